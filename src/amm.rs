@@ -0,0 +1,459 @@
+use crate::error::*;
+use crate::config::PRICE_PRECISION;
+use crate::math_safe::*;
+use crate::fixed::{mul_div, mul_div_up};
+
+// 可替换的定价引擎：市场只通过这几个函数询价，状态更新仍由 MarketData 完成
+pub trait PricingEngine {
+    /// 花费 net_amount 抵押品可买到的份额，向下取整
+    fn shares_for(&self, is_yes: bool, net_amount: u64) -> Result<u64, u32>;
+    /// 卖出 shares 份额得到的毛收益（未扣手续费），向下取整；无法定价时为 0
+    fn proceeds_for(&self, is_yes: bool, shares: u64) -> Result<u64, u32>;
+    /// 当前边际价格 (YES, NO)，以 PRICE_PRECISION 为单位，两者之和恒为 PRICE_PRECISION
+    fn prices(&self) -> Result<(u64, u64), u32>;
+    /// 以 net_amount 买入一方后该方的价格，向下取整
+    fn price_after_buy(&self, is_yes: bool, net_amount: u64) -> Result<u64, u32>;
+}
+
+/// 常量乘积 AMM：x * y = k，储备越深价格越稳
+pub struct ConstantProduct {
+    pub yes_liquidity: u64,
+    pub no_liquidity: u64,
+}
+
+impl ConstantProduct {
+    // 买入一方后的新储备 (yes, no)
+    fn reserves_after_buy(&self, is_yes: bool, net_amount: u64) -> Result<(u64, u64), u32> {
+        let k = calculate_k_safe(self.yes_liquidity, self.no_liquidity)?;
+        if is_yes {
+            let new_no = safe_add(self.no_liquidity, net_amount)?;
+            Ok((calculate_new_liquidity_safe(k, new_no)?, new_no))
+        } else {
+            let new_yes = safe_add(self.yes_liquidity, net_amount)?;
+            Ok((new_yes, calculate_new_liquidity_safe(k, new_yes)?))
+        }
+    }
+}
+
+impl PricingEngine for ConstantProduct {
+    fn shares_for(&self, is_yes: bool, net_amount: u64) -> Result<u64, u32> {
+        let (new_yes, new_no) = self.reserves_after_buy(is_yes, net_amount)?;
+        let (before, after) = if is_yes { (self.yes_liquidity, new_yes) } else { (self.no_liquidity, new_no) };
+        Ok(before.saturating_sub(after))
+    }
+
+    fn proceeds_for(&self, is_yes: bool, shares: u64) -> Result<u64, u32> {
+        // 卖出即把份额放回本方储备，从对方储备取出抵押品
+        let k = calculate_k_safe(self.yes_liquidity, self.no_liquidity)?;
+        if is_yes {
+            let new_yes = safe_add(self.yes_liquidity, shares)?;
+            let new_no = calculate_new_liquidity_safe(k, new_yes)?;
+            Ok(self.no_liquidity.saturating_sub(new_no))
+        } else {
+            let new_no = safe_add(self.no_liquidity, shares)?;
+            let new_yes = calculate_new_liquidity_safe(k, new_no)?;
+            Ok(self.yes_liquidity.saturating_sub(new_yes))
+        }
+    }
+
+    // 舍入剩余归给价格更高的一方，无储备时各 50%
+    fn prices(&self) -> Result<(u64, u64), u32> {
+        let total_liquidity = safe_add(self.yes_liquidity, self.no_liquidity)?;
+        if total_liquidity == 0 {
+            return Ok((PRICE_PRECISION / 2, PRICE_PRECISION / 2));
+        }
+        let yes = mul_div(self.no_liquidity, PRICE_PRECISION, total_liquidity)?;
+        let no = mul_div(self.yes_liquidity, PRICE_PRECISION, total_liquidity)?;
+        let leftover = safe_sub(PRICE_PRECISION, safe_add(yes, no)?)?;
+        match self.no_liquidity.cmp(&self.yes_liquidity) {
+            std::cmp::Ordering::Greater => Ok((safe_add(yes, leftover)?, no)),
+            std::cmp::Ordering::Less => Ok((yes, safe_add(no, leftover)?)),
+            std::cmp::Ordering::Equal => Ok((yes, no)),
+        }
+    }
+
+    fn price_after_buy(&self, is_yes: bool, net_amount: u64) -> Result<u64, u32> {
+        let (new_yes, new_no) = self.reserves_after_buy(is_yes, net_amount)?;
+        let total = safe_add(new_yes, new_no)?;
+        calculate_price_safe(if is_yes { new_no } else { new_yes }, total)
+    }
+}
+
+/// n 结果常量乘积 AMM：所有结果储备之积只增不减。买入结果 i 时净投入加到其余每个储备，
+/// 再从 i 的储备取出份额使乘积复原；卖出相反。价格 p_i = (1/r_i) / Σ(1/r_j)，总和恒为
+/// PRICE_PRECISION。储备按 [NO, YES] 排列、下标即 bet_type 时与 ConstantProduct 逐位相同
+pub struct MultiConstantProduct {
+    pub reserves: Vec<u64>,
+}
+
+impl MultiConstantProduct {
+    // 与 calculate_k_safe 相同的储备范围校验
+    fn check_reserves(&self, index: u64) -> Result<usize, u32> {
+        for reserve in &self.reserves {
+            if *reserve > MAX_LIQUIDITY {
+                return Err(ERROR_LIQUIDITY_TOO_HIGH);
+            }
+            if *reserve < MIN_LIQUIDITY {
+                return Err(ERROR_INVALID_CALCULATION);
+            }
+        }
+        if index as usize >= self.reserves.len() {
+            return Err(ERROR_INVALID_BET_TYPE);
+        }
+        Ok(index as usize)
+    }
+
+    // 买入结果 index 后的新储备；本方储备逐项向上取整，份额只少不多，乘积不会变小
+    pub fn reserves_after_buy(&self, index: u64, net_amount: u64) -> Result<Vec<u64>, u32> {
+        let index = self.check_reserves(index)?;
+        let mut reserves = self.reserves.clone();
+        let mut own = self.reserves[index];
+        for (j, reserve) in reserves.iter_mut().enumerate() {
+            if j == index {
+                continue;
+            }
+            let grown = safe_add(*reserve, net_amount)?;
+            own = mul_div_up(own, *reserve, grown)?;
+            *reserve = grown;
+        }
+        if !(MIN_LIQUIDITY..=MAX_LIQUIDITY).contains(&own) {
+            return Err(ERROR_INVALID_CALCULATION);
+        }
+        reserves[index] = own;
+        Ok(reserves)
+    }
+
+    pub fn shares_for(&self, index: u64, net_amount: u64) -> Result<u64, u32> {
+        let reserves = self.reserves_after_buy(index, net_amount)?;
+        Ok(self.reserves[index as usize].saturating_sub(reserves[index as usize]))
+    }
+
+    // 卖出即把份额放回本方储备，再从其余每个储备取出同样的抵押品 p；返回 (新储备, 毛收益)。
+    // 乘积逐项向下取整，二分出乘积不变小的最大 p
+    pub fn reserves_after_sell(&self, index: u64, shares: u64) -> Result<(Vec<u64>, u64), u32> {
+        let index = self.check_reserves(index)?;
+        let own = safe_add(self.reserves[index], shares)?;
+        let others = || self.reserves.iter().enumerate().filter(move |(j, _)| *j != index).map(|(_, reserve)| *reserve);
+        let keeps_product = |proceeds: u64| -> Result<bool, u32> {
+            let mut value = own;
+            for reserve in others() {
+                value = mul_div(value, reserve - proceeds, reserve)?;
+            }
+            Ok(value >= self.reserves[index])
+        };
+        // 取出全部最小储备时乘积为 0，必然不满足
+        let (mut lo, mut hi) = (0u64, others().min().ok_or(ERROR_INVALID_BET_TYPE)?);
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if keeps_product(mid)? {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        let mut reserves = self.reserves.clone();
+        for (j, reserve) in reserves.iter_mut().enumerate() {
+            *reserve = if j == index { own } else { *reserve - lo };
+            if *reserve < MIN_LIQUIDITY {
+                return Err(ERROR_INVALID_CALCULATION);
+            }
+        }
+        Ok((reserves, lo))
+    }
+
+    pub fn proceeds_for(&self, index: u64, shares: u64) -> Result<u64, u32> {
+        let (_, proceeds) = self.reserves_after_sell(index, shares)?;
+        Ok(proceeds)
+    }
+
+    // p_i = floor(P * H_i / ΣH)，H_i 为其余储备之积（超出 u128，用大整数精确计算）；
+    // 舍入剩余归给价格最高的结果，并列时取下标最小者
+    pub fn prices(&self) -> Result<Vec<u64>, u32> {
+        let weights = (0..self.reserves.len())
+            .map(|i| self.reserves.iter().enumerate()
+                .filter(|(j, _)| *j != i)
+                .fold(vec![1], |product, (_, reserve)| big_mul(&product, *reserve)))
+            .collect::<Vec<_>>();
+        let total = weights.iter().fold(vec![0], |sum, weight| big_add(&sum, weight));
+        if big_cmp(&total, &[0]) == std::cmp::Ordering::Equal {
+            return Err(ERROR_DIVISION_BY_ZERO);
+        }
+        let mut prices = Vec::with_capacity(weights.len());
+        for weight in &weights {
+            // 最大的 x 使 x * ΣH <= P * H_i
+            let scaled = big_mul(weight, PRICE_PRECISION);
+            let (mut lo, mut hi) = (0u64, PRICE_PRECISION + 1);
+            while hi - lo > 1 {
+                let mid = lo + (hi - lo) / 2;
+                if big_cmp(&big_mul(&total, mid), &scaled) != std::cmp::Ordering::Greater {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            prices.push(lo);
+        }
+        let leftover = safe_sub(PRICE_PRECISION, prices.iter().try_fold(0, |sum, price| safe_add(sum, *price))?)?;
+        let dearest = (0..prices.len()).fold(0, |best, i| if prices[i] > prices[best] { i } else { best });
+        if let Some(price) = prices.get_mut(dearest) {
+            *price = safe_add(*price, leftover)?;
+        }
+        Ok(prices)
+    }
+}
+
+// 小端 u64 limb 表示的无符号大整数，定价只需要乘以 u64、相加和比较
+fn big_mul(limbs: &[u64], factor: u64) -> Vec<u64> {
+    let mut carry = 0u128;
+    let mut product = Vec::with_capacity(limbs.len() + 1);
+    for limb in limbs {
+        let value = *limb as u128 * factor as u128 + carry;
+        product.push(value as u64);
+        carry = value >> 64;
+    }
+    product.push(carry as u64);
+    product
+}
+
+fn big_add(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut carry = 0u128;
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    for i in 0..a.len().max(b.len()) {
+        let value = *a.get(i).unwrap_or(&0) as u128 + *b.get(i).unwrap_or(&0) as u128 + carry;
+        sum.push(value as u64);
+        carry = value >> 64;
+    }
+    sum.push(carry as u64);
+    sum
+}
+
+fn big_cmp(a: &[u64], b: &[u64]) -> std::cmp::Ordering {
+    for i in (0..a.len().max(b.len())).rev() {
+        match a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)) {
+            std::cmp::Ordering::Equal => continue,
+            unequal => return unequal,
+        }
+    }
+    std::cmp::Ordering::Equal
+}
+
+// LMSR 定点运算精度：1.0 = 10^18，中间值全部在 u128 内
+const LMSR_ONE: u128 = 1_000_000_000_000_000_000;
+const LMSR_LN2: u128 = 693_147_180_559_945_309;
+// e^-44 已小于 10^-18，再大的指数直接视为 0
+const LMSR_EXP_CUTOFF: u128 = 44 * LMSR_ONE;
+
+/// 对数市场评分规则：成本 C(q) = b * ln(e^(q_yes/b) + e^(q_no/b))，b 越大价格越不易被推动，
+/// 做市方最大亏损为 b * ln2；买卖的价格都是成本函数之差，与路径无关
+pub struct Lmsr {
+    pub q_yes: u64,  // 已发行的 YES 份额
+    pub q_no: u64,   // 已发行的 NO 份额
+    pub b: u64,      // 流动性参数
+}
+
+impl Lmsr {
+    // e^-x，x 为定点数，结果向下取整
+    fn exp_neg(x: u128) -> u128 {
+        if x >= LMSR_EXP_CUTOFF {
+            return 0;
+        }
+        // x = k * ln2 + r，e^-x = e^-r / 2^k，r < ln2 时泰勒级数收敛很快
+        let k = x / LMSR_LN2;
+        let r = x - k * LMSR_LN2;
+        let (mut term, mut exp_r) = (LMSR_ONE, LMSR_ONE);
+        for n in 1..=40u128 {
+            term = term * r / (n * LMSR_ONE);
+            if term == 0 {
+                break;
+            }
+            exp_r += term;
+        }
+        (LMSR_ONE * LMSR_ONE / exp_r) >> k
+    }
+
+    // ln(1 + y)，0 <= y <= 1，用 ln((1+z)/(1-z)) = 2(z + z^3/3 + ...)，z = y/(2+y) <= 1/3
+    fn ln_1p(y: u128) -> u128 {
+        let z = y * LMSR_ONE / (2 * LMSR_ONE + y);
+        let z2 = z * z / LMSR_ONE;
+        let (mut term, mut sum, mut n) = (z, 0, 1);
+        while term > 0 {
+            sum += term / n;
+            term = term * z2 / LMSR_ONE;
+            n += 2;
+        }
+        2 * sum
+    }
+
+    // 成本函数（定点），写成 max + b * ln(1 + e^(-|q_yes - q_no| / b)) 避免指数溢出；
+    // 两边同时增加 c 时成本恰好增加 c，因此铸造完整份额组不改变价格
+    fn cost(&self, q_yes: u64, q_no: u64) -> u128 {
+        let diff = q_yes.abs_diff(q_no) as u128;
+        let tail = Self::ln_1p(Self::exp_neg(diff * LMSR_ONE / self.b as u128));
+        q_yes.max(q_no) as u128 * LMSR_ONE + self.b as u128 * tail
+    }
+
+    fn with_shares(&self, is_yes: bool, shares: u64) -> Result<(u64, u64), u32> {
+        if is_yes {
+            Ok((safe_add(self.q_yes, shares)?, self.q_no))
+        } else {
+            Ok((self.q_yes, safe_add(self.q_no, shares)?))
+        }
+    }
+
+    fn check_b(&self) -> Result<(), u32> {
+        if self.b == 0 {
+            return Err(ERROR_DIVISION_BY_ZERO);
+        }
+        Ok(())
+    }
+}
+
+impl PricingEngine for Lmsr {
+    fn shares_for(&self, is_yes: bool, net_amount: u64) -> Result<u64, u32> {
+        self.check_b()?;
+        let base = self.cost(self.q_yes, self.q_no);
+        let budget = net_amount as u128 * LMSR_ONE;
+        let held = if is_yes { self.q_yes } else { self.q_no };
+        let costs = |shares: u64| -> Result<u128, u32> {
+            let (q_yes, q_no) = self.with_shares(is_yes, shares)?;
+            Ok(self.cost(q_yes, q_no) - base)
+        };
+        // 每份价格不低于 C 的增量下界，份额不会超过 金额 + |q_yes - q_no| + b
+        let mut hi = net_amount
+            .saturating_add(self.q_yes.abs_diff(self.q_no))
+            .saturating_add(self.b)
+            .min(u64::MAX - held);
+        if costs(hi)? <= budget {
+            return Ok(hi);
+        }
+        // 成本随份额单调递增，二分出预算内最多的份额
+        let mut lo = 0u64;
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if costs(mid)? <= budget {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(lo)
+    }
+
+    fn proceeds_for(&self, is_yes: bool, shares: u64) -> Result<u64, u32> {
+        self.check_b()?;
+        let (q_yes, q_no) = if is_yes {
+            (self.q_yes.checked_sub(shares).ok_or(ERROR_INSUFFICIENT_BALANCE)?, self.q_no)
+        } else {
+            (self.q_yes, self.q_no.checked_sub(shares).ok_or(ERROR_INSUFFICIENT_BALANCE)?)
+        };
+        let released = self.cost(self.q_yes, self.q_no).saturating_sub(self.cost(q_yes, q_no));
+        u64::try_from(released / LMSR_ONE).map_err(|_| ERROR_OVERFLOW)
+    }
+
+    // 份额多的一方价格为 1 / (1 + e^(-|q_yes - q_no| / b))，另一方取余数
+    fn prices(&self) -> Result<(u64, u64), u32> {
+        self.check_b()?;
+        let diff = self.q_yes.abs_diff(self.q_no) as u128;
+        let tail = Self::exp_neg(diff * LMSR_ONE / self.b as u128);
+        let dearer = (PRICE_PRECISION as u128 * LMSR_ONE / (LMSR_ONE + tail)) as u64;
+        let cheaper = PRICE_PRECISION - dearer;
+        if self.q_yes >= self.q_no {
+            Ok((dearer, cheaper))
+        } else {
+            Ok((cheaper, dearer))
+        }
+    }
+
+    fn price_after_buy(&self, is_yes: bool, net_amount: u64) -> Result<u64, u32> {
+        let shares = self.shares_for(is_yes, net_amount)?;
+        let (q_yes, q_no) = self.with_shares(is_yes, shares)?;
+        let (yes, no) = Lmsr { q_yes, q_no, b: self.b }.prices()?;
+        Ok(if is_yes { yes } else { no })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_point_exp_and_ln() {
+        assert_eq!(Lmsr::exp_neg(0), LMSR_ONE);
+        // e^-1 = 0.367879441171442321...
+        assert!(Lmsr::exp_neg(LMSR_ONE).abs_diff(367_879_441_171_442_321) < 1_000);
+        assert_eq!(Lmsr::exp_neg(LMSR_EXP_CUTOFF), 0);
+        // ln 2 and ln 1.5 = 0.405465108108164381...
+        assert!(Lmsr::ln_1p(LMSR_ONE).abs_diff(LMSR_LN2) < 1_000);
+        assert!(Lmsr::ln_1p(LMSR_ONE / 2).abs_diff(405_465_108_108_164_381) < 1_000);
+    }
+
+    #[test]
+    fn test_two_outcome_product_matches_the_binary_reserves() {
+        // A binary market is the n = 2 case with reserves [NO, YES], indexed by bet_type
+        for (yes, no) in [(1_000_000, 1_000_000), (995_222, 1_004_802), (5_000, 2_000_000), (123_457, 98_765)] {
+            let binary = ConstantProduct { yes_liquidity: yes, no_liquidity: no };
+            let multi = MultiConstantProduct { reserves: vec![no, yes] };
+            let (yes_price, no_price) = binary.prices().unwrap();
+            assert_eq!(multi.prices(), Ok(vec![no_price, yes_price]));
+            for amount in [1, 999, 10_000, 250_000, 5_000_000] {
+                for (index, is_yes) in [(0, false), (1, true)] {
+                    assert_eq!(multi.shares_for(index, amount), binary.shares_for(is_yes, amount));
+                    assert_eq!(multi.proceeds_for(index, amount), binary.proceeds_for(is_yes, amount));
+                    if let Ok((new_yes, new_no)) = binary.reserves_after_buy(is_yes, amount) {
+                        assert_eq!(multi.reserves_after_buy(index, amount), Ok(vec![new_no, new_yes]));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_many_outcome_prices_and_round_trip() {
+        let engine = MultiConstantProduct { reserves: vec![1_000_000; 3] };
+        // Equal reserves split the price evenly, the rounding leftover going to the first outcome
+        assert_eq!(engine.prices(), Ok(vec![333_334, 333_333, 333_333]));
+
+        // Buying outcome 1 raises its price and lowers every other, still summing to 1
+        let shares = engine.shares_for(1, 10_000).unwrap();
+        let after = MultiConstantProduct { reserves: engine.reserves_after_buy(1, 10_000).unwrap() };
+        let prices = after.prices().unwrap();
+        assert!(prices[1] > 333_334 && prices[0] < 333_333 && prices[2] < 333_333);
+        assert_eq!(prices.iter().sum::<u64>(), PRICE_PRECISION);
+
+        // Selling the same shares straight back never returns more than was paid
+        let (reserves, proceeds) = after.reserves_after_sell(1, shares).unwrap();
+        assert!((9_990..=10_000).contains(&proceeds));
+        assert!(reserves.iter().zip(&engine.reserves).all(|(now, before)| now >= before));
+
+        assert_eq!(engine.shares_for(3, 10_000), Err(ERROR_INVALID_BET_TYPE));
+        assert_eq!(engine.proceeds_for(3, 10_000), Err(ERROR_INVALID_BET_TYPE));
+        let sixteen = MultiConstantProduct { reserves: (1..=16).map(|i| i * 100_000).collect() };
+        let prices = sixteen.prices().unwrap();
+        assert_eq!(prices.iter().sum::<u64>(), PRICE_PRECISION);
+        assert!(prices.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    #[test]
+    fn test_lmsr_prices_and_round_trip() {
+        let engine = Lmsr { q_yes: 0, q_no: 0, b: 100_000 };
+        assert_eq!(engine.prices(), Ok((PRICE_PRECISION / 2, PRICE_PRECISION / 2)));
+
+        // Buying YES raises its price and spends no more than the budget
+        let shares = engine.shares_for(true, 10_000).unwrap();
+        assert!(shares > 10_000 && shares < 20_000);
+        let after = Lmsr { q_yes: shares, q_no: 0, b: 100_000 };
+        let (yes, no) = after.prices().unwrap();
+        assert!(yes > PRICE_PRECISION / 2);
+        assert_eq!(yes + no, PRICE_PRECISION);
+        assert_eq!(engine.price_after_buy(true, 10_000), Ok(yes));
+
+        // Selling the same shares straight back never returns more than was paid
+        let proceeds = after.proceeds_for(true, shares).unwrap();
+        assert!((9_999..=10_000).contains(&proceeds));
+        assert_eq!(after.proceeds_for(true, shares + 1), Err(ERROR_INSUFFICIENT_BALANCE));
+
+        // A larger b moves the price less for the same spend
+        let deep = Lmsr { q_yes: 0, q_no: 0, b: 1_000_000 };
+        assert!(deep.price_after_buy(true, 10_000).unwrap() < yes);
+    }
+}
@@ -0,0 +1,513 @@
+use serde::Serialize;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::amm::MultiConstantProduct;
+use crate::config::{FEE_BASIS_POINTS, MAX_MARKET_OUTCOMES, PLATFORM_FEE_RATE};
+use crate::error::*;
+use crate::fixed::{mul_div, mul_div_up};
+use crate::math_safe::*;
+use crate::player::PlayerMarketManager;
+
+// A market on a question with 2 to MAX_MARKET_OUTCOMES answers, traded by outcome index.
+//
+// Pricing: a single MultiConstantProduct holds one reserve per outcome, so the marginal
+// prices always sum to PRICE_PRECISION and buying one outcome makes every other cheaper.
+// With two outcomes the reserves move exactly like a binary market's [NO, YES] reserves.
+//
+// Pool: every bet's collateral, net of the trade fee, goes into one prize pool shared by all
+// outcomes, and sells are paid out of it. Once resolved, holders of the winning outcome split
+// the whole pool pro rata to their shares, so stakes on the losing outcomes pay the winners.
+//
+// Fees are the flat fee_bps on every bet and sell, with no maker rebates or taker surcharges,
+// and are added to the protocol fees rather than withdrawn per market.
+// Categorical markets have no order book, liquidity providers, disputes or voiding, and are
+// not listed in market_ids, so Tick and the binary queries never see them.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CategoricalMarket {
+    pub title: Vec<u64>,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub resolution_time: u64,
+    pub reserves: Vec<u64>,       // AMM reserve per outcome, for pricing only
+    pub total_shares: Vec<u64>,   // Shares issued per outcome
+    pub prize_pool: u64,
+    pub total_volume: u64,
+    pub total_fees_collected: u64,
+    pub fee_bps: u64,             // Trading fee in FEE_BASIS_POINTS
+    pub winner: Option<u64>,      // Winning outcome index once resolved
+    pub total_claimed: u64,
+}
+
+impl CategoricalMarket {
+    // One initial reserve per outcome; unequal reserves open the market at unequal prices
+    pub fn new(title: Vec<u64>, start_time: u64, end_time: u64, resolution_time: u64, reserves: Vec<u64>) -> Result<Self, u32> {
+        if reserves.len() < 2 || reserves.len() > MAX_MARKET_OUTCOMES {
+            return Err(ERROR_INVALID_OUTCOME);
+        }
+        if start_time >= end_time || end_time > resolution_time {
+            return Err(ERROR_INVALID_MARKET_TIME);
+        }
+        for reserve in &reserves {
+            validate_liquidity(*reserve)?;
+        }
+        Ok(CategoricalMarket {
+            title,
+            start_time,
+            end_time,
+            resolution_time,
+            total_shares: vec![0; reserves.len()],
+            reserves,
+            prize_pool: 0,
+            total_volume: 0,
+            total_fees_collected: 0,
+            fee_bps: PLATFORM_FEE_RATE,
+            winner: None,
+            total_claimed: 0,
+        })
+    }
+
+    pub fn outcome_count(&self) -> u64 {
+        self.reserves.len() as u64
+    }
+
+    pub fn ensure_outcome(&self, outcome_index: u64) -> Result<usize, u32> {
+        if outcome_index >= self.outcome_count() {
+            return Err(ERROR_INVALID_BET_TYPE);
+        }
+        Ok(outcome_index as usize)
+    }
+
+    pub fn ensure_active(&self, current_time: u64) -> Result<(), u32> {
+        if self.winner.is_some() || current_time < self.start_time || current_time >= self.end_time {
+            return Err(ERROR_MARKET_NOT_ACTIVE);
+        }
+        Ok(())
+    }
+
+    fn engine(&self) -> MultiConstantProduct {
+        MultiConstantProduct { reserves: self.reserves.clone() }
+    }
+
+    // Marginal price of every outcome in PRICE_PRECISION units, in outcome order
+    pub fn prices(&self) -> Result<Vec<u64>, u32> {
+        self.engine().prices()
+    }
+
+    // Rounded up so the fee is never short
+    pub fn trade_fee(&self, gross_amount: u64) -> Result<u64, u32> {
+        if gross_amount > MAX_BET_AMOUNT {
+            return Err(ERROR_BET_TOO_LARGE);
+        }
+        mul_div_up(gross_amount, self.fee_bps, FEE_BASIS_POINTS)
+    }
+
+    // What place_bet would issue for `amount`, without changing the market
+    pub fn quote_bet(&self, outcome_index: u64, amount: u64) -> Result<u64, u32> {
+        validate_bet_amount(amount)?;
+        self.ensure_outcome(outcome_index)?;
+        let net_amount = safe_sub(amount, self.trade_fee(amount)?)?;
+        let shares = self.engine().shares_for(outcome_index, net_amount)?;
+        validate_shares(shares)?;
+        Ok(shares)
+    }
+
+    pub fn place_bet(&mut self, outcome_index: u64, amount: u64) -> Result<u64, u32> {
+        let shares = self.quote_bet(outcome_index, amount)?;
+        let fee = self.trade_fee(amount)?;
+        let net_amount = safe_sub(amount, fee)?;
+        let index = outcome_index as usize;
+
+        self.reserves = self.engine().reserves_after_buy(outcome_index, net_amount)?;
+        self.total_shares[index] = safe_add(self.total_shares[index], shares)?;
+        self.prize_pool = safe_add(self.prize_pool, net_amount)?;
+        self.total_volume = safe_add(self.total_volume, amount)?;
+        self.total_fees_collected = safe_add(self.total_fees_collected, fee)?;
+        self.check_solvency()?;
+        Ok(shares)
+    }
+
+    // What sell_shares would pay for `shares` of one outcome, as (payout, fee), without changing
+    // the market. The AMM prices the shares; the collateral comes out of the pool
+    pub fn quote_sell(&self, outcome_index: u64, shares: u64) -> Result<(u64, u64), u32> {
+        validate_shares(shares)?;
+        let index = self.ensure_outcome(outcome_index)?;
+        if shares > self.total_shares[index] {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
+        }
+        let gross_amount = self.engine().proceeds_for(outcome_index, shares)?;
+        if gross_amount == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        if gross_amount > self.prize_pool {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
+        }
+        let fee = self.trade_fee(gross_amount)?;
+        Ok((safe_sub(gross_amount, fee)?, fee))
+    }
+
+    pub fn sell_shares(&mut self, outcome_index: u64, shares: u64) -> Result<u64, u32> {
+        let (payout, fee) = self.quote_sell(outcome_index, shares)?;
+        let gross_amount = safe_add(payout, fee)?;
+        let index = outcome_index as usize;
+
+        let (reserves, _) = self.engine().reserves_after_sell(outcome_index, shares)?;
+        self.reserves = reserves;
+        self.total_shares[index] = safe_sub(self.total_shares[index], shares)?;
+        self.prize_pool = safe_sub(self.prize_pool, gross_amount)?;
+        self.total_volume = safe_add(self.total_volume, gross_amount)?;
+        self.total_fees_collected = safe_add(self.total_fees_collected, fee)?;
+        self.check_solvency()?;
+        Ok(payout)
+    }
+
+    // The admin settles the question on one outcome once the resolution time is reached
+    pub fn resolve(&mut self, outcome_index: u64, current_time: u64) -> Result<(), u32> {
+        if self.winner.is_some() {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        self.ensure_outcome(outcome_index)?;
+        if current_time < self.end_time || current_time < self.resolution_time {
+            return Err(ERROR_RESOLUTION_TOO_EARLY);
+        }
+        self.winner = Some(outcome_index);
+        Ok(())
+    }
+
+    // Pro rata share of the pool for the winning outcome's shares in `shares` (one entry per
+    // outcome); 0 while unresolved. Rounded down, so every claim together stays within the pool
+    pub fn calculate_payout(&self, shares: &[u64]) -> Result<u64, u32> {
+        match self.winner {
+            Some(winner) => self.payout_if(shares, winner),
+            None => Ok(0),
+        }
+    }
+
+    // What `shares` would claim if `outcome_index` won with the pool as it stands now
+    pub fn payout_if(&self, shares: &[u64], outcome_index: u64) -> Result<u64, u32> {
+        let index = self.ensure_outcome(outcome_index)?;
+        let held = shares.get(index).copied().unwrap_or(0);
+        if held == 0 || self.total_shares[index] == 0 {
+            return Ok(0);
+        }
+        mul_div(held, self.prize_pool, self.total_shares[index])
+    }
+
+    pub fn settle_claim(&mut self, shares: &[u64]) -> Result<u64, u32> {
+        let unclaimed_pool = safe_sub(self.prize_pool, self.total_claimed)?;
+        let payout = self.calculate_payout(shares)?.min(unclaimed_pool);
+        self.total_claimed = safe_add(self.total_claimed, payout)?;
+        self.check_solvency()?;
+        Ok(payout)
+    }
+
+    // Payouts are a pro rata split of the pool, so the pool covers every outcome at once;
+    // it only has to hold what was claimed and back any shares still open
+    pub fn check_solvency(&self) -> Result<(), u32> {
+        let unclaimed_pool = self.prize_pool.checked_sub(self.total_claimed).ok_or(ERROR_INSOLVENT)?;
+        if unclaimed_pool == 0 && self.winner.is_none() && self.total_shares.iter().any(|shares| *shares > 0) {
+            return Err(ERROR_INSOLVENT);
+        }
+        Ok(())
+    }
+}
+
+impl StorageData for CategoricalMarket {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        let title_len = *u64data.next().unwrap() as usize;
+        let title = (0..title_len).map(|_| *u64data.next().unwrap()).collect();
+        let start_time = *u64data.next().unwrap();
+        let end_time = *u64data.next().unwrap();
+        let resolution_time = *u64data.next().unwrap();
+        let count = *u64data.next().unwrap() as usize;
+        let reserves = (0..count).map(|_| *u64data.next().unwrap()).collect();
+        let total_shares = (0..count).map(|_| *u64data.next().unwrap()).collect();
+        CategoricalMarket {
+            title,
+            start_time,
+            end_time,
+            resolution_time,
+            reserves,
+            total_shares,
+            prize_pool: *u64data.next().unwrap(),
+            total_volume: *u64data.next().unwrap(),
+            total_fees_collected: *u64data.next().unwrap(),
+            fee_bps: *u64data.next().unwrap(),
+            winner: match *u64data.next().unwrap() {
+                0 => None,
+                v => Some(v - 1),
+            },
+            total_claimed: *u64data.next().unwrap(),
+        }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.title.len() as u64);
+        data.extend_from_slice(&self.title);
+        data.push(self.start_time);
+        data.push(self.end_time);
+        data.push(self.resolution_time);
+        data.push(self.reserves.len() as u64);
+        data.extend_from_slice(&self.reserves);
+        data.extend_from_slice(&self.total_shares);
+        data.push(self.prize_pool);
+        data.push(self.total_volume);
+        data.push(self.total_fees_collected);
+        data.push(self.fee_bps);
+        data.push(self.winner.map_or(0, |index| index + 1));
+        data.push(self.total_claimed);
+    }
+}
+
+// A player's holding in one categorical market: shares and the collateral paid for them,
+// one entry per outcome
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct CategoricalPosition {
+    pub shares: Vec<u64>,
+    pub costs: Vec<u64>,
+    pub claimed: bool,
+}
+
+impl CategoricalPosition {
+    pub fn new(outcome_count: u64) -> Self {
+        CategoricalPosition {
+            shares: vec![0; outcome_count as usize],
+            costs: vec![0; outcome_count as usize],
+            claimed: false,
+        }
+    }
+
+    pub fn cost_basis(&self) -> Result<u64, u32> {
+        self.costs.iter().try_fold(0, |sum, cost| safe_add(sum, *cost))
+    }
+
+    pub fn buy(&mut self, outcome_index: u64, shares: u64, cost: u64) -> Result<(), u32> {
+        let index = outcome_index as usize;
+        self.shares[index] = safe_add(self.shares[index], shares)?;
+        self.costs[index] = safe_add(self.costs[index], cost)?;
+        Ok(())
+    }
+
+    // Removes sold shares and releases their basis pro rata; returns the basis released
+    pub fn sell(&mut self, outcome_index: u64, shares: u64) -> Result<u64, u32> {
+        let index = outcome_index as usize;
+        let held = self.shares[index];
+        if held < shares {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
+        }
+        let released = mul_div(self.costs[index], shares, held)?;
+        self.shares[index] = held - shares;
+        self.costs[index] -= released;
+        Ok(released)
+    }
+
+    // Claiming uses up the position, so nothing pays out twice
+    pub fn consume(&mut self) {
+        let count = self.shares.len() as u64;
+        *self = CategoricalPosition { claimed: true, ..CategoricalPosition::new(count) };
+    }
+}
+
+impl StorageData for CategoricalPosition {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        let count = *u64data.next().unwrap() as usize;
+        let shares = (0..count).map(|_| *u64data.next().unwrap()).collect();
+        let costs = (0..count).map(|_| *u64data.next().unwrap()).collect();
+        CategoricalPosition { shares, costs, claimed: *u64data.next().unwrap() != 0 }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.shares.len() as u64);
+        data.extend_from_slice(&self.shares);
+        data.extend_from_slice(&self.costs);
+        data.push(self.claimed as u64);
+    }
+}
+
+pub struct CategoricalManager;
+
+impl CategoricalManager {
+    const MARKET_PREFIX: [u64; 2] = [5, 0];   // Prefix for categorical market storage
+    const POSITION_PREFIX: [u64; 2] = [7, 0]; // Prefix for categorical position storage
+
+    pub fn store_market(market_id: u64, market: &CategoricalMarket) {
+        let mut data = vec![];
+        market.to_data(&mut data);
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let key = [Self::MARKET_PREFIX[0], Self::MARKET_PREFIX[1], market_id, 0];
+        kvpair.set(&key, data.as_slice());
+    }
+
+    pub fn get_market(market_id: u64) -> Option<CategoricalMarket> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let key = [Self::MARKET_PREFIX[0], Self::MARKET_PREFIX[1], market_id, 0];
+        let mut data = kvpair.get(&key);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            Some(CategoricalMarket::from_data(&mut u64data))
+        } else {
+            None
+        }
+    }
+
+    // A player with no position yet gets an empty one sized to the market
+    pub fn get_position(player_id: &[u64; 2], market: &CategoricalMarket, market_id: u64) -> CategoricalPosition {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let key = [Self::POSITION_PREFIX[0], Self::POSITION_PREFIX[1], PlayerMarketManager::combine_player_id_safe(player_id), market_id];
+        let mut data = kvpair.get(&key);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            CategoricalPosition::from_data(&mut u64data)
+        } else {
+            CategoricalPosition::new(market.outcome_count())
+        }
+    }
+
+    pub fn store_position(player_id: &[u64; 2], market_id: u64, position: &CategoricalPosition) {
+        let mut data = vec![];
+        position.to_data(&mut data);
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let key = [Self::POSITION_PREFIX[0], Self::POSITION_PREFIX[1], PlayerMarketManager::combine_player_id_safe(player_id), market_id];
+        kvpair.set(&key, data.as_slice());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amm::{ConstantProduct, PricingEngine};
+    use crate::config::PRICE_PRECISION;
+    use crate::market::MarketData;
+
+    fn market(reserves: Vec<u64>) -> CategoricalMarket {
+        CategoricalMarket::new(MarketData::string_to_u64_vec("Q"), 0, 1000, 1000, reserves).unwrap()
+    }
+
+    #[test]
+    fn test_outcome_count_and_schedule_are_validated() {
+        assert_eq!(CategoricalMarket::new(vec![], 0, 1000, 1000, vec![1_000_000]), Err(ERROR_INVALID_OUTCOME));
+        assert_eq!(CategoricalMarket::new(vec![], 0, 1000, 1000, vec![1_000_000; MAX_MARKET_OUTCOMES + 1]), Err(ERROR_INVALID_OUTCOME));
+        assert_eq!(CategoricalMarket::new(vec![], 1000, 1000, 1000, vec![1_000_000; 3]), Err(ERROR_INVALID_MARKET_TIME));
+        assert_eq!(CategoricalMarket::new(vec![], 0, 1000, 1000, vec![1_000_000, 10]), Err(ERROR_INVALID_CALCULATION));
+        assert_eq!(market(vec![1_000_000; MAX_MARKET_OUTCOMES]).outcome_count(), MAX_MARKET_OUTCOMES as u64);
+    }
+
+    #[test]
+    fn test_two_outcome_market_trades_like_the_binary_amm() {
+        // Outcome i is bet_type i, so the reserves run [NO, YES]
+        let mut categorical = market(vec![1_000_000, 1_000_000]);
+        let mut binary = ConstantProduct { yes_liquidity: 1_000_000, no_liquidity: 1_000_000 };
+        for (index, amount) in [(1, 10_000), (0, 5_000), (1, 70_000)] {
+            let net = amount - categorical.trade_fee(amount).unwrap();
+            let expected = binary.shares_for(index == 1, net).unwrap();
+            assert_eq!(categorical.place_bet(index, amount), Ok(expected));
+            // The binary bet formulas: the net amount joins the other side, k is kept
+            let k = calculate_k_safe(binary.yes_liquidity, binary.no_liquidity).unwrap();
+            binary = if index == 1 {
+                let no = binary.no_liquidity + net;
+                ConstantProduct { yes_liquidity: calculate_new_liquidity_safe(k, no).unwrap(), no_liquidity: no }
+            } else {
+                let yes = binary.yes_liquidity + net;
+                ConstantProduct { yes_liquidity: yes, no_liquidity: calculate_new_liquidity_safe(k, yes).unwrap() }
+            };
+            let (yes, no) = (binary.yes_liquidity, binary.no_liquidity);
+            assert_eq!(categorical.reserves, vec![no, yes]);
+            let (yes_price, no_price) = binary.prices().unwrap();
+            assert_eq!(categorical.prices(), Ok(vec![no_price, yes_price]));
+        }
+    }
+
+    #[test]
+    fn test_losing_stakes_pay_the_winning_outcome() {
+        let mut categorical = market(vec![1_000_000; 3]);
+        let alice = categorical.place_bet(0, 30_000).unwrap();
+        let bob = categorical.place_bet(1, 20_000).unwrap();
+        let carol = categorical.place_bet(2, 10_000).unwrap();
+        // One pool for every outcome and prices that always add up to 1
+        let fees = [30_000, 20_000, 10_000].iter().map(|amount| categorical.trade_fee(*amount).unwrap()).sum::<u64>();
+        assert_eq!((categorical.prize_pool, categorical.total_fees_collected), (60_000 - fees, fees));
+        assert_eq!(categorical.prices().unwrap().iter().sum::<u64>(), PRICE_PRECISION);
+        assert_eq!(categorical.total_shares, vec![alice, bob, carol]);
+
+        assert_eq!(categorical.resolve(3, 1000), Err(ERROR_INVALID_BET_TYPE));
+        assert_eq!(categorical.resolve(1, 999), Err(ERROR_RESOLUTION_TOO_EARLY));
+        categorical.resolve(1, 1000).unwrap();
+        assert_eq!(categorical.resolve(0, 1000), Err(ERROR_MARKET_ALREADY_RESOLVED));
+        assert_eq!(categorical.ensure_active(500), Err(ERROR_MARKET_NOT_ACTIVE));
+
+        // Bob holds every winning share and takes the whole pool, Alice and Carol get nothing
+        let pool = categorical.prize_pool;
+        assert_eq!(categorical.calculate_payout(&[alice, 0, 0]), Ok(0));
+        assert_eq!(categorical.settle_claim(&[0, bob, 0]), Ok(pool));
+        assert_eq!(categorical.total_claimed, pool);
+        assert_eq!(categorical.settle_claim(&[0, bob, 0]), Ok(0));
+    }
+
+    #[test]
+    fn test_selling_an_outcome_is_paid_from_the_shared_pool() {
+        for count in [3, 8, MAX_MARKET_OUTCOMES] {
+            let mut categorical = market(vec![1_000_000; count]);
+            for index in 0..count as u64 {
+                categorical.place_bet(index, 10_000 + index * 1_000).unwrap();
+            }
+            let last = count as u64 - 1;
+            let shares = categorical.place_bet(last, 50_000).unwrap();
+            let (pool, fees, prices) = (categorical.prize_pool, categorical.total_fees_collected, categorical.prices().unwrap());
+
+            let (payout, fee) = categorical.quote_sell(last, shares).unwrap();
+            assert_eq!(categorical.sell_shares(last, shares), Ok(payout));
+            // Selling gives the bet back, less the fees on both legs and the AMM's rounding,
+            // and lowers the outcome's price while the others rise
+            assert!(payout < 50_000 && payout > 49_000);
+            assert_eq!(categorical.prize_pool, pool - payout - fee);
+            assert_eq!(categorical.total_fees_collected, fees + fee);
+            let after = categorical.prices().unwrap();
+            assert!(after[last as usize] < prices[last as usize]);
+            assert!((0..last as usize).all(|index| after[index] > prices[index]));
+            assert_eq!(after.iter().sum::<u64>(), PRICE_PRECISION);
+
+            assert_eq!(categorical.sell_shares(count as u64, 1), Err(ERROR_INVALID_BET_TYPE));
+            let open = categorical.total_shares[0];
+            assert_eq!(categorical.sell_shares(0, open + 1), Err(ERROR_INSUFFICIENT_BALANCE));
+        }
+    }
+
+    #[test]
+    fn test_payout_if_prices_every_outcome_against_the_pool() {
+        let mut categorical = market(vec![1_000_000; 3]);
+        let alice = categorical.place_bet(0, 30_000).unwrap();
+        let bob = categorical.place_bet(1, 20_000).unwrap();
+        let pool = categorical.prize_pool;
+        // Alice holds every share of outcome 0, so she would take the whole pool if it won;
+        // nobody holds outcome 2, so it pays nothing
+        assert_eq!(categorical.payout_if(&[alice, 0, 0], 0), Ok(pool));
+        assert_eq!(categorical.payout_if(&[alice, 0, 0], 1), Ok(0));
+        assert_eq!(categorical.payout_if(&[0, bob / 2, 0], 1), Ok(pool * (bob / 2) / bob));
+        assert_eq!(categorical.payout_if(&[alice, bob, 0], 2), Ok(0));
+        assert_eq!(categorical.payout_if(&[alice, bob, 0], 3), Err(ERROR_INVALID_BET_TYPE));
+
+        let mut position = CategoricalPosition::new(3);
+        position.buy(0, alice, 30_000).unwrap();
+        let released = 30_000 * (alice / 4) / alice;
+        assert_eq!(position.sell(0, alice / 4), Ok(released));
+        assert_eq!((position.shares[0], position.costs[0]), (alice - alice / 4, 30_000 - released));
+        assert_eq!(position.sell(0, alice), Err(ERROR_INSUFFICIENT_BALANCE));
+    }
+
+    #[test]
+    fn test_market_and_position_storage_round_trip() {
+        let mut categorical = market(vec![1_000_000, 2_000_000, 3_000_000, 4_000_000]);
+        categorical.place_bet(2, 10_000).unwrap();
+        categorical.resolve(2, 1000).unwrap();
+        let mut data = vec![];
+        categorical.to_data(&mut data);
+        assert_eq!(CategoricalMarket::from_data(&mut data.iter_mut()), categorical);
+
+        let mut position = CategoricalPosition::new(4);
+        position.buy(2, 9_000, 10_000).unwrap();
+        assert_eq!(position.cost_basis(), Ok(10_000));
+        let mut data = vec![];
+        position.to_data(&mut data);
+        assert_eq!(CategoricalPosition::from_data(&mut data.iter_mut()), position);
+        position.consume();
+        assert_eq!(position, CategoricalPosition { claimed: true, ..CategoricalPosition::new(4) });
+    }
+}
@@ -1,17 +1,29 @@
+use crate::config::{BASIS_POINTS_PRECISION, MAX_BATCH_ACTIVITIES, MAX_POSITIONS_PER_PLAYER, MAX_WITHDRAW_FEE_BPS, OPERATION_FEE_WAIVE_ADMIN, OPERATION_FEE_WAIVE_CLAIMS, OPERATION_FEE_WAIVE_TRADING};
 use crate::error::*;
-use crate::event::{insert_event, EVENT_BET_UPDATE, emit_market_indexed_object};
-use crate::player::Player;
-use crate::state::{GLOBAL_STATE};
+use crate::event::{insert_event, emit_balance_transfer_event, emit_claim_event, emit_fill_event, emit_order_event, emit_resolve_event, emit_sell_event, EVENT_BET_UPDATE, EVENT_DEPOSIT, EVENT_WITHDRAW, EVENT_PLAYER_FROZEN, EVENT_QUERY_RESULT, EVENT_QUOTE, emit_market_indexed_object, emit_market_state_change};
+use crate::market::{ClaimSettlement, MarketData};
+use crate::math_safe::safe_add;
+use crate::categorical::{CategoricalManager, CategoricalMarket, CategoricalPosition};
+use crate::quote::{Quote, QuoteManager};
+use crate::orders::{Fill, LimitOrder, OrderBook, OrderBookManager};
+use crate::liquidity::{self, LpManager};
+use crate::player::{load_signer, NonceCheck, Player, PlayerData, PlayerMarketManager, PlayerMarketPosition, PlayerView, SigningContext};
+use crate::state::{GlobalState, GLOBAL_STATE};
+use zkwasm_rest_abi::StorageData;
 
 #[derive(Clone)]
 pub enum Command {
     // Standard activities
     Activity(Activity),
+    // Read-only queries, never consume a nonce
+    Query(Query),
+    // Several trading activities under one nonce, applied in order and kept only if all succeed
+    BatchActivity(Vec<Activity>),
     // Standard withdraw and deposit
     Withdraw(Withdraw),
     Deposit(Deposit),
-    // Standard player install and timer
-    InstallPlayer,
+    // Standard player install and timer; the admin may also install another pid with a chosen starting balance
+    InstallPlayer(Option<([u64; 2], u64)>),
     Tick,
 }
 
@@ -19,28 +31,59 @@ pub trait CommandHandler {
     fn handle(&self, pid: &[u64; 2], nonce: u64, rand: &[u64; 4], counter: u64) -> Result<(), u32>;
 }
 
+// Withdrawal limbs in the WithdrawInfo layout: the low 32 bits of the first limb are the
+// amount, its high 32 bits and the other two limbs are the 20-byte bridge address.
 #[derive(Clone)]
 pub struct Withdraw {
     pub data: [u64; 3],
 }
 
+impl Withdraw {
+    // The amount debited, which is exactly the amount WithdrawInfo settles
+    pub fn amount(&self) -> u64 {
+        self.data[0] & 0xffffffff
+    }
+
+    // The address limbs with the amount bits cleared
+    pub fn address(&self) -> [u64; 3] {
+        [self.data[0] & !0xffffffff, self.data[1], self.data[2]]
+    }
+
+    // Splits the debited amount into (settled, fee); the fee rounds down in the player's favour
+    pub fn split_fee(&self, fee_bps: u64) -> Result<(u64, u64), u32> {
+        let amount = self.amount();
+        let fee = crate::fixed::mul_div(amount, fee_bps, BASIS_POINTS_PRECISION)?;
+        Ok((amount - fee, fee))
+    }
+
+    // The settlement record paying `net` to this withdrawal's address
+    pub fn withdraw_info(&self, net: u64) -> zkwasm_rest_abi::WithdrawInfo {
+        let [limb0, limb1, limb2] = self.address();
+        zkwasm_rest_abi::WithdrawInfo::new(&[limb0 | net, limb1, limb2], 0)
+    }
+}
+
 impl CommandHandler for Withdraw {
-    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4], _counter: u64) -> Result<(), u32> {
-        let mut player = Player::get_from_pid(pid);
-        match player.as_mut() {
-            None => Err(ERROR_PLAYER_NOT_EXIST),
-            Some(player) => {
-                player.check_and_inc_nonce(nonce);
-                let balance = player.data.balance;
-                let amount = self.data[0] & 0xffffffff;
-                unsafe { zkwasm_rust_sdk::require(balance >= amount) };
-                player.data.balance -= amount;
-                let withdrawinfo = zkwasm_rest_abi::WithdrawInfo::new(&[self.data[0], self.data[1], self.data[2]], 0);
-                crate::settlement::SettlementInfo::append_settlement(withdrawinfo);
-                player.store();
-                Ok(())
-            }
-        }
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4], counter: u64) -> Result<(), u32> {
+        let mut player = load_signer(Player::get_from_pid(pid), nonce)?;
+        player.data.ensure_not_frozen()?;
+        GLOBAL_STATE.0.borrow().ensure_transfers_open()?;
+        let balance = player.data.balance;
+        let amount = self.amount();
+        unsafe { zkwasm_rust_sdk::require(balance >= amount) };
+        // The full amount is debited; only what is left after the withdrawal fee settles
+        let (net, fee) = self.split_fee(GLOBAL_STATE.0.borrow().withdraw_fee_bps)?;
+        // Queue first so a full settlement buffer rejects the withdrawal untouched
+        crate::settlement::SettlementInfo::append_settlement(self.withdraw_info(net))?;
+        player.data.balance -= amount;
+        let mut state = GLOBAL_STATE.0.borrow_mut();
+        state.record_withdrawal(net)?;
+        state.record_protocol_fee(fee)?;
+        drop(state);
+        player.store();
+        let txid = GLOBAL_STATE.0.borrow().txcounter;
+        emit_balance_transfer_event(EVENT_WITHDRAW, txid, player.player_id, amount, counter);
+        Ok(())
     }
 }
 
@@ -50,31 +93,111 @@ pub struct Deposit {
 }
 
 impl CommandHandler for Deposit {
-    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4], _counter: u64) -> Result<(), u32> {
-        let mut admin = Player::get_from_pid(pid).unwrap();
-        admin.check_and_inc_nonce(nonce);
-        let mut player = Player::get_from_pid(&[self.data[0], self.data[1]]);
-        match player.as_mut() {
-            None => Err(ERROR_PLAYER_NOT_EXIST),
-            Some(player) => {
-                player.data.balance += self.data[2];
-                player.store();
-                admin.store();
-                Ok(())
-            }
-        }
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4], counter: u64) -> Result<(), u32> {
+        let admin = load_signer(Player::get_from_pid(pid), nonce)?;
+        GLOBAL_STATE.0.borrow().ensure_transfers_open()?;
+        let recipient = [self.data[0], self.data[1]];
+        let player = Self::credit(Player::get_from_pid(&recipient), recipient, self.data[2])?;
+        GLOBAL_STATE.0.borrow_mut().record_deposit(self.data[2])?;
+        player.store();
+        admin.store();
+        let txid = GLOBAL_STATE.0.borrow().txcounter;
+        emit_balance_transfer_event(EVENT_DEPOSIT, txid, player.player_id, self.data[2], counter);
+        Ok(())
+    }
+}
+
+impl Deposit {
+    // Funds may arrive before the recipient installs, in which case the player is created
+    // here with only the deposit: no install grant, zero nonce and no positions
+    pub fn credit(existing: Option<Player>, recipient: [u64; 2], amount: u64) -> Result<Player, u32> {
+        let mut player = existing.unwrap_or_else(|| Player::new_from_pid(recipient));
+        player.data.add_balance(amount)?;
+        Ok(player)
     }
 }
 
+pub const ARB_SIDE_BUY: u64 = 0;
+pub const ARB_SIDE_SELL: u64 = 1;
+
+// One trade of an ArbExecute: buys spend `amount` collateral, sells give up `amount` shares
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArbLeg {
+    pub market_id: u64,
+    pub outcome: u64,
+    pub amount: u64,
+    pub side: u64,
+    pub min_out: u64,  // Fewest shares (buy) or least payout (sell) the leg accepts
+}
+
+// A market a batch works on, with the signer's position and the book, all staged together
+#[derive(Clone, Debug)]
+pub struct StagedMarket {
+    pub market_id: u64,
+    pub market: MarketData,
+    pub position: PlayerMarketPosition,
+    pub book: OrderBook,
+}
+
+// What one activity of a batch did, emitted as its event once the whole batch is stored
+#[derive(Clone, Debug, PartialEq)]
+pub enum BatchEffect {
+    Bet { market_id: u64, bet_type: u64, spent: u64, shares: u64, fills: Vec<(Fill, u64)> },
+    Sell { market_id: u64, sell_type: u64, shares: u64, payout: u64 },
+    OrderPlaced { market_id: u64, order_id: u64, outcome: u64, price: u64, shares: u64 },
+    OrderCancelled { market_id: u64, order: LimitOrder },
+}
+
 #[derive(Clone)]
 pub enum Activity {
     // Prediction market activities
     Bet(u64, u64, u64),        // market_id, bet_type, amount
     Sell(u64, u64, u64),       // market_id, sell_type, shares_amount
+    BetWithSlippage(u64, u64, u64, u64),  // market_id, bet_type, amount, min_shares
+    SellWithSlippage(u64, u64, u64, u64), // market_id, sell_type, shares_amount, min_payout
+    SellForPayout(u64, u64, u64),         // market_id, sell_type, target_payout
     Resolve(u64, u64),         // market_id, outcome
     Claim(u64),                // market_id
-    WithdrawFees(u64),         // market_id
-    CreateMarket(Vec<u64>, u64, u64, u64, u64, u64), // title_u64_vec, start_time_offset, end_time_offset, resolution_time_offset, yes_liquidity, no_liquidity
+    WithdrawFees(u64, [u64; 2]), // market_id, destination pid ([0, 0] = the market's fee recipient)
+    CreateMarket(Vec<u64>, u64, u64, u64, u64, u64, Vec<(u64, u64)>), // title_u64_vec, start_time_offset, end_time_offset, resolution_time_offset, yes_liquidity, no_liquidity, options
+    HedgeToNeutral(u64, u64),  // market_id, max_cost
+    ResolveDistribution(u64, Vec<u64>), // market_id, [NO, YES] weights in bps
+    ProposeResolution(u64, u64),        // market_id, outcome
+    FinalizeResolution(u64),            // market_id
+    RequestQuote(u64, u64, u64),        // market_id, bet_type, amount
+    ExecuteQuote(u64),                  // quote_id
+    AddLiquidity(u64, u64, u64),        // market_id, amount, compounding
+    RemoveLiquidity(u64, u64),          // market_id, lp_shares
+    ClaimLpFees(u64),                   // market_id
+    ResolveBatch(bool, Vec<(u64, u64)>), // atomic, [(market_id, outcome)]
+    SetPlayerFrozen([u64; 2], bool),     // player_id, frozen
+    SetOperationFee(u64, u64),           // fee, waiver categories
+    SetWithdrawFee(u64),                 // withdraw fee in bps
+    MintSet(u64, u64),                   // market_id, amount
+    AddLiquidityFromSets(u64, u64, u64), // market_id, sets, compounding
+    ArbExecute(Vec<ArbLeg>),             // legs executed all together or not at all
+    SetWithdrawAddress([u64; 3]),        // address limbs as in Withdraw, amount bits ignored
+    VoidMarket(u64),                     // market_id
+    InternalTransfer(u64, u64, u64),     // from_sub, to_sub, amount
+    SetOracle([u64; 2], bool),           // oracle pid, enabled
+    SubmitOracleValue(u64, u64),         // market_id, value
+    ClaimAndWithdraw(u64, [u64; 3]),     // market_id, address limbs as in Withdraw
+    SubmitOracleOutcome(u64, bool),      // market_id, outcome; applied by Tick after the end once a quorum agrees
+    SetPaused(bool, bool),               // trading paused, deposits and withdrawals paused
+    CreateCategoricalMarket(Vec<u64>, u64, u64, u64, Vec<u64>), // title_u64_vec, start_time_offset, end_time_offset, resolution_time_offset, liquidity per outcome
+    BetOutcome(u64, u64, u64, u64),      // market_id, outcome_index, amount, min_shares
+    SellOutcome(u64, u64, u64, u64),     // market_id, outcome_index, shares, min_payout
+    ResolveCategorical(u64, u64),        // market_id, winning outcome_index
+    ClaimCategorical(u64),               // market_id
+    OverrideResolution(u64, bool),       // market_id, corrected outcome during the dispute window or for an escalated dispute
+    CloseMarket(u64),                    // market_id, stops trading now and leaves the outcome pending
+    PlaceLimitOrder(u64, u64, u64, u64), // market_id, outcome, price per share in PRICE_PRECISION, shares
+    CancelOrder(u64, u64),               // market_id, order_id
+    CommitBet(u64, [u64; 4]),            // market_id, bet commitment (see PlayerMarketPosition::bet_commitment)
+    RevealBet(u64, u64, u64, u64, u64),  // market_id, bet_type, amount, min_shares, salt
+    Dispute(u64, u64, u64),              // market_id, outcome claimed to be true, bond
+    ReclaimDisputeBond(u64),             // market_id
+    SweepUnclaimed(u64),                 // market_id, moves the unclaimed pool to the treasury after the claim period
 }
 
 impl CommandHandler for Activity {
@@ -83,40 +206,547 @@ impl CommandHandler for Activity {
         match player.as_mut() {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
-                player.check_and_inc_nonce(nonce);
-                match self {
+                self.admit(player, &GLOBAL_STATE.0.borrow(), pid, nonce)?;
+                player.try_inc_nonce(nonce)?;
+                // Taken up front so the activity itself sees the reduced balance
+                let operation_fee = GLOBAL_STATE.0.borrow().operation_fee_for(self);
+                Self::charge_operation_fee(&mut player.data, operation_fee)?;
+                let result = match self {
                     Activity::Bet(market_id, bet_type, amount) => {
-                        Self::handle_bet(player, *market_id, *bet_type, *amount, counter)
+                        Self::handle_bet(player, *market_id, *bet_type, *amount, 0, counter)
                     },
                     Activity::Sell(market_id, sell_type, shares) => {
-                        Self::handle_sell(player, *market_id, *sell_type, *shares, counter)
+                        Self::handle_sell(player, *market_id, *sell_type, *shares, 0, counter)
+                    },
+                    Activity::BetWithSlippage(market_id, bet_type, amount, min_shares) => {
+                        Self::handle_bet(player, *market_id, *bet_type, *amount, *min_shares, counter)
+                    },
+                    Activity::SellWithSlippage(market_id, sell_type, shares, min_payout) => {
+                        Self::handle_sell(player, *market_id, *sell_type, *shares, *min_payout, counter)
+                    },
+                    Activity::SellForPayout(market_id, sell_type, target_payout) => {
+                        Self::handle_sell_for_payout(player, *market_id, *sell_type, *target_payout, counter)
                     },
                     Activity::Resolve(market_id, outcome) => {
-                        // Only admin can resolve - we need to check this at a higher level
+                        // Only admin can resolve - checked by check_authorized
                         Self::handle_resolve(*market_id, *outcome, counter)
                     },
                     Activity::Claim(market_id) => {
-                        Self::handle_claim(player, *market_id, counter)
+                        Self::handle_claim(player, *market_id, None, counter)
+                    },
+                    Activity::ClaimAndWithdraw(market_id, address) => {
+                        Self::handle_claim(player, *market_id, Some(*address), counter)
                     },
-                    Activity::WithdrawFees(market_id) => {
-                        // Only admin can withdraw fees - we need to check this at a higher level
-                        Self::handle_withdraw_fees(player, *market_id, counter)
+                    Activity::WithdrawFees(market_id, to) => {
+                        // The market's fee recipient or the admin - checked against the market
+                        Self::handle_withdraw_fees(player, *market_id, *to, counter)
                     },
-                    Activity::CreateMarket(title_u64_vec, start_time, end_time, resolution_time, yes_liquidity, no_liquidity) => {
+                    Activity::CreateMarket(title_u64_vec, start_time, end_time, resolution_time, yes_liquidity, no_liquidity, options) => {
                         // Only admin can create markets - we need to check this at a higher level
-                        Self::handle_create_market(title_u64_vec.clone(), *start_time, *end_time, *resolution_time, *yes_liquidity, *no_liquidity, counter)
+                        Self::handle_create_market(player.player_id, title_u64_vec.clone(), *start_time, *end_time, *resolution_time, *yes_liquidity, *no_liquidity, options, counter)
+                    },
+                    Activity::HedgeToNeutral(market_id, max_cost) => {
+                        Self::handle_hedge_to_neutral(player, *market_id, *max_cost, counter)
+                    },
+                    Activity::ResolveDistribution(market_id, weights) => {
+                        // Only admin can resolve - checked in Transaction::process
+                        Self::handle_resolve_distribution(*market_id, weights.clone(), counter)
+                    },
+                    Activity::ProposeResolution(market_id, outcome) => {
+                        // Only admin can propose - checked in Transaction::process
+                        Self::handle_propose_resolution(*market_id, *outcome, counter)
+                    },
+                    Activity::FinalizeResolution(market_id) => {
+                        Self::handle_finalize_resolution(*market_id, counter)
+                    },
+                    Activity::RequestQuote(market_id, bet_type, amount) => {
+                        Self::handle_request_quote(player, *market_id, *bet_type, *amount, counter)
+                    },
+                    Activity::ExecuteQuote(quote_id) => {
+                        Self::handle_execute_quote(player, *quote_id, counter)
+                    },
+                    Activity::AddLiquidity(market_id, amount, compounding) => {
+                        Self::handle_add_liquidity(player, *market_id, *amount, *compounding != 0)
+                    },
+                    Activity::RemoveLiquidity(market_id, lp_shares) => {
+                        Self::handle_remove_liquidity(player, *market_id, *lp_shares)
+                    },
+                    Activity::ClaimLpFees(market_id) => {
+                        Self::handle_claim_lp_fees(player, *market_id)
+                    },
+                    Activity::ResolveBatch(atomic, resolutions) => {
+                        // Only admin can resolve - checked in Transaction::process
+                        Self::handle_resolve_batch(*atomic, resolutions, counter)
+                    },
+                    Activity::SetPlayerFrozen(target, frozen) => {
+                        // Only admin can freeze - checked in Transaction::process
+                        Self::handle_set_player_frozen(player, *target, *frozen)
+                    },
+                    Activity::MintSet(market_id, amount) => {
+                        Self::handle_mint_set(player, *market_id, *amount)
+                    },
+                    Activity::AddLiquidityFromSets(market_id, sets, compounding) => {
+                        Self::handle_add_liquidity_from_sets(player, *market_id, *sets, *compounding != 0)
+                    },
+                    Activity::ArbExecute(legs) => {
+                        Self::handle_arb_execute(player, legs)
+                    },
+                    Activity::CreateCategoricalMarket(title_u64_vec, start_time, end_time, resolution_time, reserves) => {
+                        // Only admin can create markets - checked in Transaction::process
+                        Self::handle_create_categorical_market(title_u64_vec.clone(), *start_time, *end_time, *resolution_time, reserves.clone(), counter)
+                    },
+                    Activity::BetOutcome(market_id, outcome_index, amount, min_shares) => {
+                        Self::handle_bet_outcome(player, *market_id, *outcome_index, *amount, *min_shares, counter)
+                    },
+                    Activity::SellOutcome(market_id, outcome_index, shares, min_payout) => {
+                        Self::handle_sell_outcome(player, *market_id, *outcome_index, *shares, *min_payout, counter)
+                    },
+                    Activity::ClaimCategorical(market_id) => {
+                        Self::handle_claim_categorical(player, *market_id, counter)
+                    },
+                    Activity::OverrideResolution(market_id, outcome) => {
+                        // Only admin can override - checked in Transaction::process
+                        Self::handle_override_resolution(*market_id, *outcome, counter)
+                    },
+                    Activity::CloseMarket(market_id) => {
+                        // Only admin can close - checked in Transaction::process
+                        Self::handle_close_market(*market_id, counter)
+                    },
+                    Activity::PlaceLimitOrder(market_id, outcome, price, shares) => {
+                        Self::handle_place_limit_order(player, *market_id, *outcome, *price, *shares, counter)
+                    },
+                    Activity::CancelOrder(market_id, order_id) => {
+                        Self::handle_cancel_order(player, *market_id, *order_id)
+                    },
+                    Activity::CommitBet(market_id, commitment) => {
+                        Self::handle_commit_bet(player, *market_id, *commitment, counter)
+                    },
+                    Activity::RevealBet(market_id, bet_type, amount, min_shares, salt) => {
+                        Self::handle_reveal_bet(player, *market_id, *bet_type, *amount, *min_shares, *salt, counter)
+                    },
+                    Activity::Dispute(market_id, outcome, bond) => {
+                        Self::handle_dispute(player, *market_id, *outcome, *bond, counter)
+                    },
+                    Activity::ReclaimDisputeBond(market_id) => {
+                        Self::handle_reclaim_dispute_bond(player, *market_id, counter)
+                    },
+                    Activity::SweepUnclaimed(market_id) => {
+                        // Only admin can sweep - checked in Transaction::process
+                        Self::handle_sweep_unclaimed(*market_id, counter)
+                    },
+                    Activity::ResolveCategorical(market_id, winning_index) => {
+                        // Only admin can resolve - checked in Transaction::process
+                        Self::handle_resolve_categorical(*market_id, *winning_index, counter)
+                    },
+                    Activity::SetWithdrawAddress(address) => {
+                        player.data.withdraw_address = [address[0] & !0xffffffff, address[1], address[2]];
+                        player.store();
+                        Ok(())
+                    },
+                    Activity::InternalTransfer(from_sub, to_sub, amount) => {
+                        player.data.internal_transfer(*from_sub, *to_sub, *amount)?;
+                        player.store();
+                        Ok(())
+                    },
+                    Activity::SetOracle(oracle, enabled) => {
+                        // Only admin can register oracles - checked in Transaction::process
+                        GLOBAL_STATE.0.borrow_mut().set_oracle(*oracle, *enabled);
+                        Ok(())
+                    },
+                    Activity::SubmitOracleOutcome(market_id, outcome) => {
+                        Self::handle_submit_oracle_outcome(player, *market_id, *outcome)
+                    },
+                    Activity::SubmitOracleValue(market_id, value) => {
+                        Self::handle_submit_oracle_value(player, *market_id, *value, counter)
+                    },
+                    Activity::VoidMarket(market_id) => {
+                        // Only admin can void - checked in Transaction::process
+                        Self::handle_void_market(*market_id, counter)
+                    },
+                    Activity::SetOperationFee(fee, waivers) => {
+                        // Only admin can configure fees - checked in Transaction::process
+                        Self::handle_set_operation_fee(*fee, *waivers)
+                    },
+                    Activity::SetWithdrawFee(fee_bps) => {
+                        // Only admin can configure fees - checked in Transaction::process
+                        if *fee_bps > MAX_WITHDRAW_FEE_BPS {
+                            return Err(ERROR_INVALID_FEE);
+                        }
+                        GLOBAL_STATE.0.borrow_mut().withdraw_fee_bps = *fee_bps;
+                        Ok(())
+                    },
+                    Activity::SetPaused(trading, transfers) => {
+                        // Only admin can pause - checked in Transaction::process
+                        let mut state = GLOBAL_STATE.0.borrow_mut();
+                        state.trading_paused = *trading;
+                        state.transfers_paused = *transfers;
+                        Ok(())
                     }
+                };
+                // Failed activities leave the player unstored, so the fee is only kept on success
+                if result.is_ok() && operation_fee > 0 {
+                    player.store();
+                    GLOBAL_STATE.0.borrow_mut().record_protocol_fee(operation_fee)?;
                 }
+                result
             }
         }
     }
 }
 
+#[derive(Clone)]
+pub enum Query {
+    Player,                    // nonce and balance of the caller
+    Market(u64),               // market_id
+    Position(u64),             // market_id
+    BetFee(u64, u64, u64),     // market_id, bet_type, amount
+    SellFee(u64, u64, u64),    // market_id, sell_type, shares
+    DroppedEvents,             // lifetime count of events lost to a full buffer
+    TopHolders(u64, u64, u64), // market_id, outcome, n
+    BridgeTotals,              // lifetime deposits, withdrawals and the value still bridged
+    PayoutMultiple(u64, u64),  // market_id, outcome
+    LiquidityPosition(u64),    // market_id
+    ClaimStatus(u64),          // market_id
+    MarginalImpact(u64, u64),  // market_id, outcome
+    Ledger(u64),               // market_id
+    SigningContext,            // nonce, balance and positions of the caller
+    MaxBetForImpact(u64, u64, u64), // market_id, outcome, max_impact_bps
+    PositionSummary(u64),      // market_id
+    QuoteBet(u64, u64, u64),   // market_id, bet_type, amount
+    QuoteSell(u64, u64, u64),  // market_id, sell_type, shares
+    PlayerView(u64, u64, u64), // pid0, pid1, market_id; any player, not just the caller
+    Pnl,                       // lifetime spent, received and realized PnL of the caller
+    CategoricalMarket(u64),    // market_id; outcome prices and the caller's shares on each outcome
+    OutcomePayouts(u64),       // market_id; what the caller would claim if each outcome won
+    Prices(u64),               // market_id; YES and NO marginal prices in PRICE_PRECISION units
+    Summary,                   // protocol-wide accounting totals over every market
+    OrderBook(u64),            // market_id; every resting order
+}
+
+pub trait QueryHandler {
+    fn query(&self, player: &Player) -> Result<Vec<u64>, u32>;
+}
+
+impl QueryHandler for Query {
+    // Queries only ever see an immutable player, so they can be retried freely
+    fn query(&self, player: &Player) -> Result<Vec<u64>, u32> {
+        match self {
+            Query::Player => Ok(vec![player.nonce, player.data.balance]),
+            Query::SigningContext => {
+                let mut data = vec![];
+                SigningContext::load(player).to_data(&mut data);
+                Ok(data)
+            },
+            Query::Market(market_id) => {
+                let market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                let mut data = vec![*market_id];
+                market.to_data(&mut data);
+                Ok(data)
+            },
+            Query::Position(market_id) => {
+                let position = PlayerMarketManager::get_position(&player.player_id, *market_id);
+                let mut data = vec![*market_id];
+                position.to_data(&mut data);
+                Ok(data)
+            },
+            Query::BetFee(market_id, bet_type, amount) => {
+                let market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                Ok(vec![*market_id, market.quote_fee(*bet_type, *amount)?])
+            },
+            Query::SellFee(market_id, sell_type, shares) => {
+                let market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                Ok(vec![*market_id, market.quote_sell_fee(*sell_type, *shares)?])
+            },
+            Query::QuoteBet(market_id, bet_type, amount) => {
+                let market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                Ok(vec![*market_id, market.quote_bet(*bet_type, *amount)?, market.quote_fee(*bet_type, *amount)?])
+            },
+            Query::QuoteSell(market_id, sell_type, shares) => {
+                let market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                Ok(vec![*market_id, market.quote_sell(*sell_type, *shares)?, market.quote_sell_fee(*sell_type, *shares)?])
+            },
+            Query::PlayerView(pid0, pid1, market_id) => {
+                let target = Player::get_from_pid(&[*pid0, *pid1]).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+                Ok(PlayerView::load(&target, *market_id).to_query_data())
+            },
+            Query::Pnl => {
+                let data = &player.data;
+                Ok(vec![data.total_spent, data.total_received, (data.realized_pnl < 0) as u64, data.realized_pnl.unsigned_abs()])
+            },
+            Query::CategoricalMarket(market_id) => {
+                // [outcome count, winner + 1 or 0, prize pool, price of each outcome, caller's shares on each outcome]
+                let market = CategoricalManager::get_market(*market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+                let position = CategoricalManager::get_position(&player.player_id, &market, *market_id);
+                let mut data = vec![market.outcome_count(), market.winner.map_or(0, |index| index + 1), market.prize_pool];
+                data.extend(market.prices()?);
+                data.extend(position.shares);
+                Ok(data)
+            },
+            Query::OutcomePayouts(market_id) => {
+                // [outcome count, caller's payout if each outcome wins]
+                let market = CategoricalManager::get_market(*market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+                let position = CategoricalManager::get_position(&player.player_id, &market, *market_id);
+                let mut data = vec![market.outcome_count()];
+                for index in 0..market.outcome_count() {
+                    data.push(market.payout_if(&position.shares, index)?);
+                }
+                Ok(data)
+            },
+            Query::DroppedEvents => Ok(vec![GLOBAL_STATE.0.borrow().dropped_events]),
+            Query::PayoutMultiple(market_id, outcome) => {
+                let market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                Ok(vec![*market_id, *outcome, market.payout_multiple(*outcome)?])
+            },
+            Query::MarginalImpact(market_id, outcome) => {
+                let market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                Ok(vec![*market_id, *outcome, market.marginal_impact(*outcome)?])
+            },
+            Query::MaxBetForImpact(market_id, outcome, max_impact_bps) => {
+                let market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                Ok(vec![*market_id, *outcome, market.max_bet_for_impact(*outcome, *max_impact_bps)?])
+            },
+            Query::Ledger(market_id) => {
+                let market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                let mut data = vec![*market_id];
+                market.ledger()?.to_data(&mut data);
+                Ok(data)
+            },
+            Query::LiquidityPosition(market_id) => {
+                let mut market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                // Settle a throwaway copy so the reply includes fees earned since the last touch
+                let mut position = LpManager::get_position(&player.player_id, *market_id);
+                liquidity::settle_lp_fees(&mut market, &mut position)?;
+                let mut data = vec![*market_id];
+                position.to_data(&mut data);
+                Ok(data)
+            },
+            Query::PositionSummary(market_id) => {
+                let mut data = vec![*market_id];
+                GlobalState::player_market_position(&player.player_id, *market_id)?.to_data(&mut data);
+                Ok(data)
+            },
+            Query::ClaimStatus(market_id) => {
+                let claimed = GlobalState::has_claimed(&player.player_id, *market_id);
+                let remaining = GlobalState::remaining_claimable(&player.player_id, *market_id)?;
+                Ok(vec![*market_id, claimed as u64, remaining])
+            },
+            Query::BridgeTotals => {
+                let state = GLOBAL_STATE.0.borrow();
+                Ok(vec![state.total_deposited, state.total_withdrawn, state.bridge_held_value()?])
+            },
+            Query::Summary => {
+                let markets = crate::state::MarketManager::get_active_market_ids().into_iter()
+                    .filter_map(crate::state::MarketManager::get_market)
+                    .collect::<Vec<_>>();
+                let mut data = vec![];
+                GLOBAL_STATE.0.borrow().summary(&markets)?.to_data(&mut data);
+                Ok(data)
+            },
+            Query::OrderBook(market_id) => {
+                // [next_id, count, (id, owner0, owner1, outcome, price, shares, cost_basis) * count]
+                let mut data = vec![];
+                OrderBookManager::get_book(*market_id).to_data(&mut data);
+                Ok(data)
+            },
+            Query::Prices(market_id) => {
+                let market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                let (yes_price, no_price) = market.current_prices()?;
+                Ok(vec![*market_id, yes_price, no_price])
+            },
+            Query::TopHolders(market_id, outcome, n) => {
+                let market = crate::state::MarketManager::get_market(*market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                let holders = market.top_holders(*outcome, *n as usize)?;
+                let mut data = vec![*market_id, *outcome, holders.len() as u64];
+                for (pid, shares) in holders {
+                    data.extend_from_slice(pid);
+                    data.push(*shares);
+                }
+                Ok(data)
+            },
+        }
+    }
+}
+
+impl Query {
+    pub fn handle(&self, pid: &[u64; 2]) -> Result<(), u32> {
+        let player = Player::get_from_pid(pid).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        let mut data = self.query(&player)?;
+        insert_event(EVENT_QUERY_RESULT, &mut data);
+        Ok(())
+    }
+}
+
 impl Activity {
+    // Checks made before the activity runs: the signer's next nonce, admin rights, freezing
+    // and the trading pause. A rejection here means nothing was attempted.
+    pub fn admit(&self, player: &Player, state: &GlobalState, pid: &[u64; 2], nonce: u64) -> Result<(), u32> {
+        if player.nonce != nonce {
+            return Err(ERROR_INVALID_NONCE);
+        }
+        self.check_authorized(state, pid)?;
+        self.check_frozen(&player.data)?;
+        self.check_paused(state)
+    }
+
+    // Admin-only activities are also gated by the ADMIN_PUBKEY check in Transaction::process;
+    // this rejects them with a proper error code for any other caller. Fee withdrawals are
+    // left to the market, which accepts its fee recipient as well as the admin.
+    pub fn check_authorized(&self, state: &GlobalState, pid: &[u64; 2]) -> Result<(), u32> {
+        match self {
+            Activity::Resolve(..)
+            | Activity::ResolveDistribution(..)
+            | Activity::ProposeResolution(..)
+            | Activity::ResolveBatch(..)
+            | Activity::VoidMarket(..)
+            | Activity::SetOracle(..)
+            | Activity::CreateMarket(..)
+            | Activity::SetPlayerFrozen(..)
+            | Activity::SetPaused(..)
+            | Activity::CreateCategoricalMarket(..)
+            | Activity::ResolveCategorical(..)
+            | Activity::OverrideResolution(..)
+            | Activity::CloseMarket(..)
+            | Activity::SweepUnclaimed(..)
+            | Activity::SetWithdrawFee(..)
+            | Activity::SetOperationFee(..) => state.require_admin(pid),
+            _ => Ok(()),
+        }
+    }
+
+    // A frozen player can no longer move funds: trading, quotes, liquidity and fee
+    // withdrawals are rejected. Claims stay open so winnings are still credited to the
+    // in-app balance, which cannot leave while frozen; admin resolution is unaffected.
+    pub fn check_frozen(&self, data: &PlayerData) -> Result<(), u32> {
+        match self {
+            Activity::Bet(..)
+            | Activity::Sell(..)
+            | Activity::BetWithSlippage(..)
+            | Activity::SellWithSlippage(..)
+            | Activity::SellForPayout(..)
+            | Activity::BetOutcome(..)
+            | Activity::SellOutcome(..)
+            | Activity::PlaceLimitOrder(..)
+            | Activity::CommitBet(..)
+            | Activity::RevealBet(..)
+            | Activity::Dispute(..)
+            | Activity::HedgeToNeutral(..)
+            | Activity::RequestQuote(..)
+            | Activity::ExecuteQuote(..)
+            | Activity::AddLiquidity(..)
+            | Activity::AddLiquidityFromSets(..)
+            | Activity::MintSet(..)
+            | Activity::ArbExecute(..)
+            | Activity::InternalTransfer(..)
+            | Activity::ClaimAndWithdraw(..)
+            | Activity::RemoveLiquidity(..)
+            | Activity::ClaimLpFees(..)
+            | Activity::WithdrawFees(..) => data.ensure_not_frozen(),
+            _ => Ok(()),
+        }
+    }
+
+    // While trading is paused nothing that moves shares, liquidity or payouts goes through;
+    // admin housekeeping, resolution and unpausing itself stay available.
+    pub fn check_paused(&self, state: &GlobalState) -> Result<(), u32> {
+        match self {
+            Activity::Bet(..)
+            | Activity::Sell(..)
+            | Activity::BetWithSlippage(..)
+            | Activity::SellWithSlippage(..)
+            | Activity::SellForPayout(..)
+            | Activity::BetOutcome(..)
+            | Activity::SellOutcome(..)
+            | Activity::PlaceLimitOrder(..)
+            | Activity::CommitBet(..)
+            | Activity::RevealBet(..)
+            | Activity::HedgeToNeutral(..)
+            | Activity::RequestQuote(..)
+            | Activity::ExecuteQuote(..)
+            | Activity::AddLiquidity(..)
+            | Activity::AddLiquidityFromSets(..)
+            | Activity::RemoveLiquidity(..)
+            | Activity::ClaimLpFees(..)
+            | Activity::MintSet(..)
+            | Activity::ArbExecute(..)
+            | Activity::Claim(..)
+            | Activity::ClaimAndWithdraw(..)
+            | Activity::ClaimCategorical(..) => state.ensure_trading_open(),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn operation_fee_category(&self) -> u64 {
+        match self {
+            Activity::Claim(..) | Activity::ClaimAndWithdraw(..) | Activity::ClaimLpFees(..) | Activity::ClaimCategorical(..) => OPERATION_FEE_WAIVE_CLAIMS,
+            Activity::Resolve(..)
+            | Activity::ResolveDistribution(..)
+            | Activity::ProposeResolution(..)
+            | Activity::FinalizeResolution(..)
+            | Activity::ResolveBatch(..)
+            | Activity::VoidMarket(..)
+            | Activity::SetOracle(..)
+            | Activity::SubmitOracleValue(..)
+            | Activity::SubmitOracleOutcome(..)
+            | Activity::CreateMarket(..)
+            | Activity::WithdrawFees(..)
+            | Activity::SetPlayerFrozen(..)
+            | Activity::SetPaused(..)
+            | Activity::CreateCategoricalMarket(..)
+            | Activity::ResolveCategorical(..)
+            | Activity::OverrideResolution(..)
+            | Activity::CloseMarket(..)
+            | Activity::SweepUnclaimed(..)
+            | Activity::SetWithdrawFee(..)
+            | Activity::SetOperationFee(..) => OPERATION_FEE_WAIVE_ADMIN,
+            _ => OPERATION_FEE_WAIVE_TRADING,
+        }
+    }
+
+    pub fn charge_operation_fee(data: &mut PlayerData, fee: u64) -> Result<(), u32> {
+        if fee > 0 {
+            data.spend_balance(fee)?;
+        }
+        Ok(())
+    }
+
+    fn handle_set_operation_fee(fee: u64, waivers: u64) -> Result<(), u32> {
+        let mut state = GLOBAL_STATE.0.borrow_mut();
+        state.operation_fee = fee;
+        state.operation_fee_waivers = waivers;
+        Ok(())
+    }
+
+    fn handle_set_player_frozen(admin: &mut Player, target: [u64; 2], frozen: bool) -> Result<(), u32> {
+        if target == admin.player_id {
+            admin.data.frozen = frozen;
+        } else {
+            let mut player = Player::get_from_pid(&target).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+            player.data.frozen = frozen;
+            player.store();
+        }
+        admin.store();
+
+        let mut data = vec![target[0], target[1], frozen as u64];
+        insert_event(EVENT_PLAYER_FROZEN, &mut data);
+        Ok(())
+    }
+
     // Note: Market IndexedObject events are now emitted directly
     // Liquidity history is only emitted during Tick (counter increment)
 
-    fn handle_bet(player: &mut Player, market_id: u64, bet_type: u64, amount: u64, _counter: u64) -> Result<(), u32> {
+    fn handle_bet(player: &mut Player, market_id: u64, bet_type: u64, amount: u64, min_shares: u64, counter: u64) -> Result<(), u32> {
         if amount == 0 {
             return Err(ERROR_INVALID_BET_AMOUNT);
         }
@@ -131,26 +761,43 @@ impl Activity {
         let current_time = global_state.ensure_market_active(market_id)?;
         let txid = global_state.txcounter;
 
-        // Check player balance
-        player.data.spend_balance(amount)?;
-
-        // Place bet using unified function
         let mut market = crate::state::MarketManager::get_market(market_id)
             .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
-        let shares = market.place_bet(bet_type, amount)?;
-        crate::state::MarketManager::update_market(market_id, &market);
-        
-        if bet_type == 1 {
-            player.data.add_yes_shares_for_market(&player.player_id, market_id, shares);
-        } else {
-            player.data.add_no_shares_for_market(&player.player_id, market_id, shares);
+        let mut position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        let mut book = OrderBookManager::get_book(market_id);
+
+        // Balance debit, fills, pool update and share credit commit together or not at all
+        let (shares, spent, fills) = Self::execute_book_bet(&mut player.data, &mut position, &mut market, &mut book, player.player_id, market_id, bet_type, amount, min_shares)?;
+        position.start_sell_cooldown(bet_type, counter, market.sell_cooldown)?;
+        let held = if bet_type == 1 { position.yes_shares } else { position.no_shares };
+        market.record_holding(player.player_id, bet_type, held)?;
+
+        // Every maker is credited before anything is stored
+        let mut makers: Vec<Player> = vec![];
+        for (fill, proceeds) in &fills {
+            if !makers.iter().any(|maker| maker.player_id == fill.owner) {
+                makers.push(Player::get_from_pid(&fill.owner).ok_or(ERROR_PLAYER_NOT_EXIST)?);
+            }
+            let maker = makers.iter_mut().find(|maker| maker.player_id == fill.owner).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+            Self::settle_fill(&mut maker.data, fill, *proceeds)?;
         }
 
         // Store updated data
+        crate::state::MarketManager::update_market(market_id, &market);
+        PlayerMarketManager::store_position(&player.player_id, market_id, &position);
         player.store();
+        if !fills.is_empty() {
+            OrderBookManager::store_book(market_id, &book);
+            for maker in &makers {
+                maker.store();
+            }
+        }
 
         // Emit events
-        Self::emit_bet_event(player.player_id, market_id, bet_type, amount, shares, txid, current_time);
+        for (fill, _) in &fills {
+            emit_fill_event(txid, market_id, fill.order_id, fill.owner, player.player_id, fill.shares, fill.cost, fill.remaining, current_time);
+        }
+        Self::emit_bet_event(player.player_id, market_id, bet_type, spent, shares, txid, current_time);
         
         // Emit IndexedObject event for updated market
         emit_market_indexed_object(&market, market_id);
@@ -158,7 +805,145 @@ impl Activity {
         Ok(())
     }
 
-    fn handle_sell(player: &mut Player, market_id: u64, sell_type: u64, shares: u64, _counter: u64) -> Result<(), u32> {
+    // Works on staged copies so a failure at any step leaves balance, position and pool untouched
+    // Nothing is committed unless the bet buys at least `min_shares`
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_bet(data: &mut PlayerData, position: &mut PlayerMarketPosition, market: &mut MarketData, market_id: u64, bet_type: u64, amount: u64, min_shares: u64) -> Result<u64, u32> {
+        let mut staged_data = data.clone();
+        let mut staged_position = position.clone();
+        let mut staged_market = market.clone();
+
+        // Only opening a new position counts against the cap
+        if staged_position.yes_shares == 0 && staged_position.no_shares == 0 {
+            staged_data.enter_market(market_id, MAX_POSITIONS_PER_PLAYER)?;
+        }
+        staged_market.check_bet_amount_limit(amount)?;
+        staged_data.spend_balance(amount)?;
+        staged_data.record_spent(amount)?;
+        let shares = staged_market.place_bet(bet_type, amount)?;
+        if shares < min_shares {
+            return Err(ERROR_SLIPPAGE_EXCEEDED);
+        }
+        let held = if bet_type == 1 { staged_position.yes_shares } else { staged_position.no_shares };
+        staged_market.check_position_limit(safe_add(held, shares)?)?;
+        staged_position.add_cost(bet_type, amount)?;
+        staged_market.record_cost(amount)?;
+        if bet_type == 1 {
+            staged_position.yes_shares = safe_add(staged_position.yes_shares, shares)?;
+        } else {
+            staged_position.no_shares = safe_add(staged_position.no_shares, shares)?;
+        }
+
+        *data = staged_data;
+        *position = staged_position;
+        *market = staged_market;
+        Ok(shares)
+    }
+
+    // Resting orders priced at or below the AMM's marginal price are bought first, cheapest
+    // first, and the rest of `amount` goes through execute_bet. `min_shares` covers both parts
+    // and nothing changes unless all of it succeeds. A leftover too small for the AMM to
+    // take stays in the balance. Returns (shares, collateral spent, (fill, maker proceeds)).
+    #[allow(clippy::too_many_arguments, clippy::type_complexity)]
+    pub fn execute_book_bet(data: &mut PlayerData, position: &mut PlayerMarketPosition, market: &mut MarketData, book: &mut OrderBook, player_id: [u64; 2], market_id: u64, bet_type: u64, amount: u64, min_shares: u64) -> Result<(u64, u64, Vec<(Fill, u64)>), u32> {
+        let mut staged_book = book.clone();
+        let amm_price = if bet_type == 1 { market.get_yes_price()? } else { market.get_no_price()? };
+        let fills = staged_book.match_buy(&player_id, bet_type, amount, amm_price)?;
+        if fills.is_empty() {
+            let shares = Self::execute_bet(data, position, market, market_id, bet_type, amount, min_shares)?;
+            return Ok((shares, amount, vec![]));
+        }
+
+        let mut staged_data = data.clone();
+        let mut staged_position = position.clone();
+        let mut staged_market = market.clone();
+        staged_market.check_bet_amount_limit(amount)?;
+        if staged_position.yes_shares == 0 && staged_position.no_shares == 0 {
+            staged_data.enter_market(market_id, MAX_POSITIONS_PER_PLAYER)?;
+        }
+
+        let (mut shares, mut spent) = (0u64, 0u64);
+        let mut settled = Vec::with_capacity(fills.len());
+        for fill in fills {
+            let fee = staged_market.collect_fill_fee(bet_type, fill.cost)?;
+            staged_market.release_cost(fill.released_basis)?;
+            staged_market.record_cost(fill.cost)?;
+            shares = safe_add(shares, fill.shares)?;
+            spent = safe_add(spent, fill.cost)?;
+            settled.push((fill.clone(), fill.cost - fee));
+        }
+        staged_data.spend_balance(spent)?;
+        staged_data.record_spent(spent)?;
+        staged_position.add_cost(bet_type, spent)?;
+        let held = if bet_type == 1 { &mut staged_position.yes_shares } else { &mut staged_position.no_shares };
+        *held = safe_add(*held, shares)?;
+        staged_market.check_position_limit(*held)?;
+
+        let remainder = amount - spent;
+        if remainder > 0 && staged_market.check_bet_amount_limit(remainder).is_ok() && staged_market.quote_bet(bet_type, remainder).is_ok() {
+            let amm_shares = Self::execute_bet(&mut staged_data, &mut staged_position, &mut staged_market, market_id, bet_type, remainder, 0)?;
+            shares = safe_add(shares, amm_shares)?;
+            spent = amount;
+        }
+        if shares < min_shares {
+            return Err(ERROR_SLIPPAGE_EXCEEDED);
+        }
+
+        *data = staged_data;
+        *position = staged_position;
+        *market = staged_market;
+        *book = staged_book;
+        Ok((shares, spent, settled))
+    }
+
+    // The maker's side of a fill: the escrowed shares are gone, the proceeds net of the fee arrive
+    pub fn settle_fill(data: &mut PlayerData, fill: &Fill, proceeds: u64) -> Result<(), u32> {
+        let mut staged_data = data.clone();
+        staged_data.add_balance(proceeds)?;
+        staged_data.realize_pnl(proceeds, fill.released_basis)?;
+        staged_data.record_received(proceeds)?;
+        *data = staged_data;
+        Ok(())
+    }
+
+    // Staged counterpart of execute_bet; selling out of a position frees its slot
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_sell(data: &mut PlayerData, position: &mut PlayerMarketPosition, market: &mut MarketData, market_id: u64, sell_type: u64, shares: u64, min_payout: u64) -> Result<u64, u32> {
+        let mut staged_data = data.clone();
+        let mut staged_position = position.clone();
+        let mut staged_market = market.clone();
+
+        // Check player has enough shares
+        let held = if sell_type == 1 { staged_position.yes_shares } else { staged_position.no_shares };
+        if held < shares {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
+        }
+
+        let payout = staged_market.sell_shares(sell_type, shares)?;
+        if payout < min_payout {
+            return Err(ERROR_SLIPPAGE_EXCEEDED);
+        }
+        let released = staged_position.reduce_cost(sell_type, shares, held)?;
+        staged_market.release_cost(released)?;
+        staged_data.realize_pnl(payout, released)?;
+        staged_data.record_received(payout)?;
+        if sell_type == 1 {
+            staged_position.yes_shares -= shares;
+        } else {
+            staged_position.no_shares -= shares;
+        }
+        staged_data.add_balance(payout)?;
+        if staged_position.yes_shares == 0 && staged_position.no_shares == 0 {
+            staged_data.exit_market(market_id);
+        }
+
+        *data = staged_data;
+        *position = staged_position;
+        *market = staged_market;
+        Ok(payout)
+    }
+
+    fn handle_sell(player: &mut Player, market_id: u64, sell_type: u64, shares: u64, min_payout: u64, counter: u64) -> Result<(), u32> {
         if shares == 0 {
             return Err(ERROR_INVALID_BET_AMOUNT);
         }
@@ -167,36 +952,27 @@ impl Activity {
         let global_state = GLOBAL_STATE.0.borrow();
         let current_time = global_state.ensure_market_active(market_id)?;
         let txid = global_state.txcounter;
+        drop(global_state);
 
-        // Check player has enough shares
-        if sell_type == 1 && player.data.get_yes_shares_for_market(&player.player_id, market_id) < shares {
-            return Err(ERROR_INSUFFICIENT_BALANCE);
-        }
-        if sell_type != 1 && player.data.get_no_shares_for_market(&player.player_id, market_id) < shares {
-            return Err(ERROR_INSUFFICIENT_BALANCE);
-        }
-
-        // Sell shares using unified function
         let mut market = crate::state::MarketManager::get_market(market_id)
             .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
-        let payout = market.sell_shares(sell_type, shares)?;
-        crate::state::MarketManager::update_market(market_id, &market);
-        
-        // Update player shares
-        if sell_type == 1 {
-            player.data.subtract_yes_shares_for_market(&player.player_id, market_id, shares)?;
-        } else {
-            player.data.subtract_no_shares_for_market(&player.player_id, market_id, shares)?;
-        }
+        let mut position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        position.check_sell_cooldown(sell_type, counter)?;
 
-        // Add payout to player balance
-        player.data.balance += payout;
+        // Share debit, pool update and payout credit commit together or not at all
+        let payout = Self::execute_sell(&mut player.data, &mut position, &mut market, market_id, sell_type, shares, min_payout)?;
+        let held = if sell_type == 1 { position.yes_shares } else { position.no_shares };
+        market.record_holding(player.player_id, sell_type, held)?;
+
+        crate::state::MarketManager::update_market(market_id, &market);
+        PlayerMarketManager::store_position(&player.player_id, market_id, &position);
 
         // Store updated data
         player.store();
+        GLOBAL_STATE.0.borrow_mut().update_pnl_leaderboard(player.player_id, player.data.realized_pnl);
 
         // Emit events
-        Self::emit_sell_event(player.player_id, market_id, sell_type, shares, payout, txid, current_time);
+        emit_sell_event(txid, player.player_id, market_id, sell_type, shares, payout, current_time);
         
         // Emit IndexedObject event for updated market
         emit_market_indexed_object(&market, market_id);
@@ -204,94 +980,1135 @@ impl Activity {
         Ok(())
     }
 
-    fn handle_resolve(market_id: u64, outcome: u64, _counter: u64) -> Result<(), u32> {
-        let current_time = GLOBAL_STATE.0.borrow().counter;
+    // Sells the fewest shares paying at least `target_payout`, or the whole holding when even
+    // that falls short; the sell event reports what was actually paid
+    fn handle_sell_for_payout(player: &mut Player, market_id: u64, sell_type: u64, target_payout: u64, counter: u64) -> Result<(), u32> {
+        let market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        let position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        let held = if sell_type == 1 { position.yes_shares } else { position.no_shares };
+        let shares = market.shares_for_payout(sell_type, target_payout, held)?;
+        Self::handle_sell(player, market_id, sell_type, shares, 0, counter)
+    }
+
+    // Only the hash is published now; the bet it hides is placed by a later RevealBet
+    fn handle_commit_bet(player: &mut Player, market_id: u64, commitment: [u64; 4], counter: u64) -> Result<(), u32> {
+        GLOBAL_STATE.0.borrow().ensure_market_active(market_id)?;
+        let mut position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        position.commit_bet(commitment, counter)?;
+        PlayerMarketManager::store_position(&player.player_id, market_id, &position);
+        Ok(())
+    }
+
+    // The revealed bet runs exactly as a BetWithSlippage would, so the committed min_shares still
+    // bounds the price once the bet is public; the commitment is spent only if it succeeds
+    fn handle_reveal_bet(player: &mut Player, market_id: u64, bet_type: u64, amount: u64, min_shares: u64, salt: u64, counter: u64) -> Result<(), u32> {
+        let reveal_window = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?
+            .reveal_window;
+        let revealed = PlayerMarketPosition::bet_commitment(&player.player_id, market_id, bet_type, amount, min_shares, salt);
+        PlayerMarketManager::get_position(&player.player_id, market_id).check_reveal(revealed, counter, reveal_window)?;
+        Self::handle_bet(player, market_id, bet_type, amount, min_shares, counter)?;
+        let mut position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        position.clear_commitment();
+        PlayerMarketManager::store_position(&player.player_id, market_id, &position);
+        Ok(())
+    }
 
+    // Stake a bond claiming `outcome` is the true result; enough stake holds claims for the admin
+    fn handle_dispute(player: &mut Player, market_id: u64, outcome: u64, bond: u64, counter: u64) -> Result<(), u32> {
         let mut market = crate::state::MarketManager::get_market(market_id)
             .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        let mut position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        Self::execute_dispute(&mut player.data, &mut position, &mut market, outcome, bond, counter)?;
+
+        crate::state::MarketManager::update_market(market_id, &market);
+        PlayerMarketManager::store_position(&player.player_id, market_id, &position);
+        player.store();
+        emit_market_indexed_object(&market, market_id);
+        Ok(())
+    }
 
-        // TODO: Uncomment this when production is ready
-        if !market.can_resolve(current_time) && false {
-             return Err(crate::error::ERROR_MARKET_NOT_RESOLVED);
+    // Staged dispute: the bond leaves the balance only if the market accepts it
+    pub fn execute_dispute(data: &mut PlayerData, position: &mut PlayerMarketPosition, market: &mut MarketData, outcome: u64, bond: u64, counter: u64) -> Result<bool, u32> {
+        if outcome > 1 {
+            return Err(ERROR_INVALID_OUTCOME);
         }
+        let mut staged_data = data.clone();
+        let mut staged_position = position.clone();
+        let mut staged_market = market.clone();
+        let escalated = staged_market.dispute(outcome == 1, bond, counter)?;
+        staged_position.add_dispute_bond(outcome, bond)?;
+        staged_data.spend_balance(bond)?;
+        *data = staged_data;
+        *position = staged_position;
+        *market = staged_market;
+        Ok(escalated)
+    }
+
+    fn handle_reclaim_dispute_bond(player: &mut Player, market_id: u64, counter: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        let mut position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        market.release_dispute_bond(position.dispute_outcome == 1, position.dispute_bond, counter)?;
+        player.data.add_balance(position.dispute_bond)?;
+        position.dispute_bond = 0;
 
-        let outcome_bool = outcome != 0;
-        market.resolve(outcome_bool)?;
         crate::state::MarketManager::update_market(market_id, &market);
-        
-        // Emit IndexedObject event for updated market
-        emit_market_indexed_object(&market, market_id);
-        
+        PlayerMarketManager::store_position(&player.player_id, market_id, &position);
+        player.store();
         Ok(())
     }
 
-    fn handle_claim(player: &mut Player, market_id: u64, _counter: u64) -> Result<(), u32> {
-        let market = crate::state::MarketManager::get_market(market_id)
+    // Escrow `shares` out of the position into a resting sell order, with the cost basis they carry
+    fn handle_place_limit_order(player: &mut Player, market_id: u64, outcome: u64, price: u64, shares: u64, counter: u64) -> Result<(), u32> {
+        GLOBAL_STATE.0.borrow().ensure_market_active(market_id)?;
+        let mut market = crate::state::MarketManager::get_market(market_id)
             .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
-        
-        if !market.resolved {
-            return Err(crate::error::ERROR_MARKET_NOT_RESOLVED);
-        }
+        let mut position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        position.check_sell_cooldown(outcome, counter)?;
 
-        // Check if already claimed
-        player.data.claim_winnings_for_market(&player.player_id, market_id)?;
+        let mut book = OrderBookManager::get_book(market_id);
+        let order_id = Self::execute_place_order(&mut position, &mut book, player.player_id, outcome, price, shares)?;
+        let held = if outcome == 1 { position.yes_shares } else { position.no_shares };
+        market.record_holding(player.player_id, outcome, held)?;
 
-        // Calculate payout
-        let yes_shares = player.data.get_yes_shares_for_market(&player.player_id, market_id);
-        let no_shares = player.data.get_no_shares_for_market(&player.player_id, market_id);
-        let payout = market.calculate_payout(yes_shares, no_shares)?;
+        crate::state::MarketManager::update_market(market_id, &market);
+        PlayerMarketManager::store_position(&player.player_id, market_id, &position);
+        OrderBookManager::store_book(market_id, &book);
+        player.store();
+        emit_order_event(market_id, order_id, player.player_id, outcome, price, shares);
+        Ok(())
+    }
 
-        if payout == 0 {
-            return Err(crate::error::ERROR_NO_WINNING_POSITION);
+    // Staged escrow of `shares` and the basis they carry out of the position into a new order
+    pub fn execute_place_order(position: &mut PlayerMarketPosition, book: &mut OrderBook, owner: [u64; 2], outcome: u64, price: u64, shares: u64) -> Result<u64, u32> {
+        let held = match outcome {
+            1 => position.yes_shares,
+            0 => position.no_shares,
+            _ => return Err(ERROR_INVALID_BET_TYPE),
+        };
+        if shares == 0 || held < shares {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
         }
+        let mut escrowed = position.clone();
+        let cost_basis = escrowed.reduce_cost(outcome, shares, held)?;
+        let order_id = book.place(owner, outcome, price, shares, cost_basis)?;
+        if outcome == 1 {
+            escrowed.yes_shares -= shares;
+        } else {
+            escrowed.no_shares -= shares;
+        }
+        *position = escrowed;
+        Ok(order_id)
+    }
 
-        // Add payout to balance
-        player.data.add_balance(payout);
-        player.store();
+    // An unfilled order's shares and basis back on the position; returns the side's new holding
+    pub fn restore_order(position: &mut PlayerMarketPosition, order: &LimitOrder) -> Result<u64, u32> {
+        position.add_cost(order.outcome, order.cost_basis)?;
+        let held = if order.outcome == 1 { &mut position.yes_shares } else { &mut position.no_shares };
+        *held = safe_add(*held, order.shares)?;
+        Ok(*held)
+    }
 
-        Ok(())
+    // Cancel every order `owner` has resting and put the shares back on the position
+    pub fn return_resting_orders(position: &mut PlayerMarketPosition, book: &mut OrderBook, owner: [u64; 2]) -> Result<Vec<LimitOrder>, u32> {
+        let mut staged_book = book.clone();
+        let mut staged_position = position.clone();
+        let returned = staged_book.cancel_all(&owner);
+        for order in &returned {
+            Self::restore_order(&mut staged_position, order)?;
+        }
+        *book = staged_book;
+        *position = staged_position;
+        Ok(returned)
     }
 
-    fn handle_withdraw_fees(player: &mut Player, market_id: u64, _counter: u64) -> Result<(), u32> {
+    // Unfilled shares and their cost basis go back to the position; open at any stage so resolved
+    // or voided markets can still be claimed
+    fn handle_cancel_order(player: &mut Player, market_id: u64, order_id: u64) -> Result<(), u32> {
         let mut market = crate::state::MarketManager::get_market(market_id)
             .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
-        
-        let fees_collected = market.total_fees_collected;
-        
-        if fees_collected == 0 {
-            return Err(crate::error::ERROR_NO_FEES_TO_WITHDRAW);
-        }
+        let mut book = OrderBookManager::get_book(market_id);
+        let order = book.cancel(&player.player_id, order_id)?;
+        let mut position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        let held = Self::restore_order(&mut position, &order)?;
+        market.record_holding(player.player_id, order.outcome, held)?;
 
-        // Transfer fees to admin's balance
-        player.data.add_balance(fees_collected);
-        
-        // Reset collected fees to zero
-        market.total_fees_collected = 0;
         crate::state::MarketManager::update_market(market_id, &market);
+        PlayerMarketManager::store_position(&player.player_id, market_id, &position);
+        OrderBookManager::store_book(market_id, &book);
+        player.store();
+        emit_order_event(market_id, order_id, player.player_id, order.outcome, order.price, 0);
+        Ok(())
+    }
 
-        // Store updated player data
+    fn handle_request_quote(player: &mut Player, market_id: u64, bet_type: u64, amount: u64, counter: u64) -> Result<(), u32> {
+        let market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        let quote = Quote::new(player.player_id, market_id, &market, bet_type, amount, counter)?;
+
+        let quote_id = {
+            let mut global_state = GLOBAL_STATE.0.borrow_mut();
+            let quote_id = global_state.next_quote_id;
+            global_state.next_quote_id += 1;
+            quote_id
+        };
+        QuoteManager::store_quote(quote_id, &quote);
         player.store();
 
+        let mut data = vec![quote_id, market_id, bet_type, amount, quote.shares, quote.expires_at];
+        insert_event(EVENT_QUOTE, &mut data);
         Ok(())
     }
 
-    fn handle_create_market(title_u64_vec: Vec<u64>, start_time_offset: u64, end_time_offset: u64, resolution_time_offset: u64, yes_liquidity: u64, no_liquidity: u64, counter: u64) -> Result<(), u32> {
-        // Calculate absolute times by adding offsets to current counter
-        let absolute_start_time = counter + start_time_offset;
-        let absolute_end_time = counter + end_time_offset;
-        let absolute_resolution_time = counter + resolution_time_offset;
-        
-        let description = format!("Prediction market created at counter {}", counter);
-        
-        let _market_id = crate::state::MarketManager::create_market_with_title_u64_and_liquidity(
-            title_u64_vec,
-            description,
-            absolute_start_time,
-            absolute_end_time,
-            absolute_resolution_time,
-            yes_liquidity,
-            no_liquidity,
-        )?;
+    fn handle_execute_quote(player: &mut Player, quote_id: u64, counter: u64) -> Result<(), u32> {
+        let quote = QuoteManager::get_quote(quote_id).ok_or(ERROR_QUOTE_EXPIRED)?;
+        let market = crate::state::MarketManager::get_market(quote.market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        quote.validate(&player.player_id, &market, counter)?;
+
+        // Prices can sit inside the tolerance while the fill still comes up short, so the
+        // shares are held to the quote as well
+        Self::handle_bet(player, quote.market_id, quote.bet_type, quote.amount, quote.min_shares(), counter)?;
+        QuoteManager::remove_quote(quote_id);
+        Ok(())
+    }
+
+    fn handle_add_liquidity(player: &mut Player, market_id: u64, amount: u64, compounding: bool) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        let mut position = LpManager::get_position(&player.player_id, market_id);
+
+        player.data.spend_balance(amount)?;
+        liquidity::add_liquidity(&mut market, &mut position, amount, compounding)?;
+
+        crate::state::MarketManager::update_market(market_id, &market);
+        LpManager::store_position(&player.player_id, market_id, &position);
+        player.store();
+        emit_market_indexed_object(&market, market_id);
+        Ok(())
+    }
+
+    // Run every leg against staged copies; the player and each touched market only change
+    // if all legs succeed and meet their slippage guards. `markets` holds each touched
+    // market once, together with the player's position in it.
+    // Legs keep the sell cooldown like standalone bets and sells, so a buy leg cannot be sold back in the same batch
+    pub fn execute_arb(data: &mut PlayerData, markets: &mut [(u64, MarketData, PlayerMarketPosition)], legs: &[ArbLeg], counter: u64) -> Result<Vec<u64>, u32> {
+        let mut staged_data = data.clone();
+        let mut staged_markets = markets.to_vec();
+        let mut received = Vec::with_capacity(legs.len());
+        for leg in legs {
+            let (market_id, market, position) = staged_markets.iter_mut()
+                .find(|(market_id, _, _)| *market_id == leg.market_id)
+                .ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+            let out = match leg.side {
+                ARB_SIDE_BUY => {
+                    let shares = Self::execute_bet(&mut staged_data, position, market, *market_id, leg.outcome, leg.amount, leg.min_out)?;
+                    position.start_sell_cooldown(leg.outcome, counter, market.sell_cooldown)?;
+                    shares
+                },
+                ARB_SIDE_SELL => {
+                    position.check_sell_cooldown(leg.outcome, counter)?;
+                    Self::execute_sell(&mut staged_data, position, market, *market_id, leg.outcome, leg.amount, leg.min_out)?
+                },
+                _ => return Err(ERROR_INVALID_BET_TYPE),
+            };
+            received.push(out);
+        }
+
+        *data = staged_data;
+        markets.clone_from_slice(&staged_markets);
+        Ok(received)
+    }
+
+    fn handle_arb_execute(player: &mut Player, legs: &[ArbLeg]) -> Result<(), u32> {
+        let (current_time, txid) = {
+            let global_state = GLOBAL_STATE.0.borrow();
+            for leg in legs {
+                global_state.ensure_market_active(leg.market_id)?;
+            }
+            (global_state.counter, global_state.txcounter)
+        };
+
+        let mut markets: Vec<(u64, MarketData, PlayerMarketPosition)> = vec![];
+        for leg in legs {
+            if markets.iter().all(|(market_id, _, _)| *market_id != leg.market_id) {
+                let market = crate::state::MarketManager::get_market(leg.market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                let position = PlayerMarketManager::get_position(&player.player_id, leg.market_id);
+                markets.push((leg.market_id, market, position));
+            }
+        }
+
+        let received = Self::execute_arb(&mut player.data, &mut markets, legs, current_time)?;
+
+        for (market_id, market, position) in markets.iter_mut() {
+            market.record_holding(player.player_id, 1, position.yes_shares)?;
+            market.record_holding(player.player_id, 0, position.no_shares)?;
+            crate::state::MarketManager::update_market(*market_id, market);
+            PlayerMarketManager::store_position(&player.player_id, *market_id, position);
+        }
+        player.store();
+        if legs.iter().any(|leg| leg.side == ARB_SIDE_SELL) {
+            GLOBAL_STATE.0.borrow_mut().update_pnl_leaderboard(player.player_id, player.data.realized_pnl);
+        }
+
+        for (leg, out) in legs.iter().zip(received) {
+            if leg.side == ARB_SIDE_BUY {
+                Self::emit_bet_event(player.player_id, leg.market_id, leg.outcome, leg.amount, out, txid, current_time);
+            } else {
+                emit_sell_event(txid, player.player_id, leg.market_id, leg.outcome, leg.amount, out, current_time);
+            }
+        }
+        for (market_id, market, _) in markets.iter() {
+            emit_market_indexed_object(market, *market_id);
+        }
+        Ok(())
+    }
+
+    // The market a batched activity trades in; only activities with a staged form can be batched
+    pub fn batch_market_id(&self) -> Result<u64, u32> {
+        match self {
+            Activity::Bet(market_id, ..)
+            | Activity::BetWithSlippage(market_id, ..)
+            | Activity::Sell(market_id, ..)
+            | Activity::SellWithSlippage(market_id, ..)
+            | Activity::PlaceLimitOrder(market_id, ..)
+            | Activity::CancelOrder(market_id, ..) => Ok(*market_id),
+            _ => Err(ERROR_NOT_BATCHABLE),
+        }
+    }
+
+    // Apply `activities` in order to staged copies of the player and `markets` (each touched
+    // market once). Every check a standalone activity makes is made here too; the player and
+    // the markets only change if all of them pass. Maker proceeds are returned in the effects.
+    pub fn execute_batch(data: &mut PlayerData, markets: &mut [StagedMarket], player_id: [u64; 2], activities: &[Activity], counter: u64) -> Result<Vec<BatchEffect>, u32> {
+        if activities.is_empty() || activities.len() > MAX_BATCH_ACTIVITIES {
+            return Err(ERROR_NOT_BATCHABLE);
+        }
+        let mut staged_data = data.clone();
+        let mut staged_markets = markets.to_vec();
+        let mut effects = Vec::with_capacity(activities.len());
+        for activity in activities {
+            let market_id = activity.batch_market_id()?;
+            let staged = staged_markets.iter_mut()
+                .find(|staged| staged.market_id == market_id)
+                .ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+            let StagedMarket { market, position, book, .. } = staged;
+            let effect = match *activity {
+                Activity::Bet(_, bet_type, amount) | Activity::BetWithSlippage(_, bet_type, amount, _) => {
+                    let min_shares = if let Activity::BetWithSlippage(_, _, _, min_shares) = *activity { min_shares } else { 0 };
+                    if amount == 0 {
+                        return Err(ERROR_INVALID_BET_AMOUNT);
+                    }
+                    if bet_type > 1 {
+                        return Err(ERROR_INVALID_BET_TYPE);
+                    }
+                    market.ensure_active(counter)?;
+                    let (shares, spent, fills) = Self::execute_book_bet(&mut staged_data, position, market, book, player_id, market_id, bet_type, amount, min_shares)?;
+                    position.start_sell_cooldown(bet_type, counter, market.sell_cooldown)?;
+                    let held = if bet_type == 1 { position.yes_shares } else { position.no_shares };
+                    market.record_holding(player_id, bet_type, held)?;
+                    BatchEffect::Bet { market_id, bet_type, spent, shares, fills }
+                },
+                Activity::Sell(_, sell_type, shares) | Activity::SellWithSlippage(_, sell_type, shares, _) => {
+                    let min_payout = if let Activity::SellWithSlippage(_, _, _, min_payout) = *activity { min_payout } else { 0 };
+                    if shares == 0 {
+                        return Err(ERROR_INVALID_BET_AMOUNT);
+                    }
+                    market.ensure_active(counter)?;
+                    position.check_sell_cooldown(sell_type, counter)?;
+                    let payout = Self::execute_sell(&mut staged_data, position, market, market_id, sell_type, shares, min_payout)?;
+                    let held = if sell_type == 1 { position.yes_shares } else { position.no_shares };
+                    market.record_holding(player_id, sell_type, held)?;
+                    BatchEffect::Sell { market_id, sell_type, shares, payout }
+                },
+                Activity::PlaceLimitOrder(_, outcome, price, shares) => {
+                    market.ensure_active(counter)?;
+                    position.check_sell_cooldown(outcome, counter)?;
+                    let order_id = Self::execute_place_order(position, book, player_id, outcome, price, shares)?;
+                    let held = if outcome == 1 { position.yes_shares } else { position.no_shares };
+                    market.record_holding(player_id, outcome, held)?;
+                    BatchEffect::OrderPlaced { market_id, order_id, outcome, price, shares }
+                },
+                Activity::CancelOrder(_, order_id) => {
+                    let order = book.cancel(&player_id, order_id)?;
+                    let held = Self::restore_order(position, &order)?;
+                    market.record_holding(player_id, order.outcome, held)?;
+                    BatchEffect::OrderCancelled { market_id, order }
+                },
+                _ => return Err(ERROR_NOT_BATCHABLE),
+            };
+            effects.push(effect);
+        }
+
+        *data = staged_data;
+        markets.clone_from_slice(&staged_markets);
+        Ok(effects)
+    }
+
+    // One nonce covers the whole batch. Each activity is admitted and charged its operation
+    // fee as if sent alone; nothing is stored unless every activity succeeds.
+    pub fn handle_batch(pid: &[u64; 2], nonce: u64, activities: &[Activity], counter: u64) -> Result<(), u32> {
+        let mut player = Player::get_from_pid(pid).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        let mut operation_fee = 0;
+        {
+            let state = GLOBAL_STATE.0.borrow();
+            for activity in activities {
+                activity.admit(&player, &state, pid, nonce)?;
+                operation_fee = safe_add(operation_fee, state.operation_fee_for(activity))?;
+            }
+        }
+        player.try_inc_nonce(nonce)?;
+        Self::charge_operation_fee(&mut player.data, operation_fee)?;
+
+        let mut markets: Vec<StagedMarket> = vec![];
+        for activity in activities {
+            let market_id = activity.batch_market_id()?;
+            if markets.iter().all(|staged| staged.market_id != market_id) {
+                let market = crate::state::MarketManager::get_market(market_id)
+                    .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+                let position = PlayerMarketManager::get_position(&player.player_id, market_id);
+                let book = OrderBookManager::get_book(market_id);
+                markets.push(StagedMarket { market_id, market, position, book });
+            }
+        }
+
+        let effects = Self::execute_batch(&mut player.data, &mut markets, player.player_id, activities, counter)?;
+
+        // Every maker is credited before anything is stored
+        let mut makers: Vec<Player> = vec![];
+        for effect in &effects {
+            if let BatchEffect::Bet { fills, .. } = effect {
+                for (fill, proceeds) in fills {
+                    if !makers.iter().any(|maker| maker.player_id == fill.owner) {
+                        makers.push(Player::get_from_pid(&fill.owner).ok_or(ERROR_PLAYER_NOT_EXIST)?);
+                    }
+                    let maker = makers.iter_mut().find(|maker| maker.player_id == fill.owner).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+                    Self::settle_fill(&mut maker.data, fill, *proceeds)?;
+                }
+            }
+        }
+
+        for staged in &markets {
+            crate::state::MarketManager::update_market(staged.market_id, &staged.market);
+            PlayerMarketManager::store_position(&player.player_id, staged.market_id, &staged.position);
+            OrderBookManager::store_book(staged.market_id, &staged.book);
+        }
+        player.store();
+        for maker in &makers {
+            maker.store();
+        }
+        let txid = {
+            let mut state = GLOBAL_STATE.0.borrow_mut();
+            if operation_fee > 0 {
+                state.record_protocol_fee(operation_fee)?;
+            }
+            if effects.iter().any(|effect| matches!(effect, BatchEffect::Sell { .. })) {
+                state.update_pnl_leaderboard(player.player_id, player.data.realized_pnl);
+            }
+            state.txcounter
+        };
+
+        // One event per activity, all under the batch's txid
+        for effect in &effects {
+            match effect {
+                BatchEffect::Bet { market_id, bet_type, spent, shares, fills } => {
+                    for (fill, _) in fills {
+                        emit_fill_event(txid, *market_id, fill.order_id, fill.owner, player.player_id, fill.shares, fill.cost, fill.remaining, counter);
+                    }
+                    Self::emit_bet_event(player.player_id, *market_id, *bet_type, *spent, *shares, txid, counter);
+                },
+                BatchEffect::Sell { market_id, sell_type, shares, payout } => {
+                    emit_sell_event(txid, player.player_id, *market_id, *sell_type, *shares, *payout, counter);
+                },
+                BatchEffect::OrderPlaced { market_id, order_id, outcome, price, shares } => {
+                    emit_order_event(*market_id, *order_id, player.player_id, *outcome, *price, *shares);
+                },
+                BatchEffect::OrderCancelled { market_id, order } => {
+                    emit_order_event(*market_id, order.id, player.player_id, order.outcome, order.price, 0);
+                },
+            }
+        }
+        for staged in &markets {
+            emit_market_indexed_object(&staged.market, staged.market_id);
+        }
+        Ok(())
+    }
+
+    // Staged like execute_bet: one YES and one NO share per unit of collateral
+    pub fn execute_mint_set(data: &mut PlayerData, position: &mut PlayerMarketPosition, market: &mut MarketData, market_id: u64, amount: u64) -> Result<(), u32> {
+        let mut staged_data = data.clone();
+        let mut staged_market = market.clone();
+        if position.yes_shares == 0 && position.no_shares == 0 {
+            staged_data.enter_market(market_id, MAX_POSITIONS_PER_PLAYER)?;
+        }
+        staged_data.spend_balance(amount)?;
+        staged_market.mint_set(amount)?;
+        let mut staged_position = position.clone();
+        staged_position.yes_shares = safe_add(position.yes_shares, amount)?;
+        staged_position.no_shares = safe_add(position.no_shares, amount)?;
+        // A complete set costs one unit per pair, split evenly between the two sides
+        staged_position.add_cost(1, amount / 2)?;
+        staged_position.add_cost(0, amount - amount / 2)?;
+        staged_market.record_cost(amount)?;
+
+        *data = staged_data;
+        *market = staged_market;
+        *position = staged_position;
+        Ok(())
+    }
+
+    fn handle_mint_set(player: &mut Player, market_id: u64, amount: u64) -> Result<(), u32> {
+        GLOBAL_STATE.0.borrow().ensure_market_active(market_id)?;
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        let mut position = PlayerMarketManager::get_position(&player.player_id, market_id);
+
+        Self::execute_mint_set(&mut player.data, &mut position, &mut market, market_id, amount)?;
+        market.record_holding(player.player_id, 1, position.yes_shares)?;
+        market.record_holding(player.player_id, 0, position.no_shares)?;
+
+        crate::state::MarketManager::update_market(market_id, &market);
+        PlayerMarketManager::store_position(&player.player_id, market_id, &position);
+        player.store();
+        emit_market_indexed_object(&market, market_id);
+        Ok(())
+    }
+
+    fn handle_add_liquidity_from_sets(player: &mut Player, market_id: u64, sets: u64, compounding: bool) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        let mut position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        let mut lp = LpManager::get_position(&player.player_id, market_id);
+
+        liquidity::add_liquidity_from_sets(&mut market, &mut lp, &mut position, sets, compounding)?;
+        market.record_holding(player.player_id, 1, position.yes_shares)?;
+        market.record_holding(player.player_id, 0, position.no_shares)?;
+        if position.yes_shares == 0 && position.no_shares == 0 {
+            player.data.exit_market(market_id);
+        }
+
+        crate::state::MarketManager::update_market(market_id, &market);
+        PlayerMarketManager::store_position(&player.player_id, market_id, &position);
+        LpManager::store_position(&player.player_id, market_id, &lp);
+        player.store();
+        emit_market_indexed_object(&market, market_id);
+        Ok(())
+    }
+
+    fn handle_remove_liquidity(player: &mut Player, market_id: u64, lp_shares: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        let mut position = LpManager::get_position(&player.player_id, market_id);
+
+        let payout = liquidity::remove_liquidity(&mut market, &mut position, lp_shares)?;
+        player.data.add_balance(payout)?;
+
+        crate::state::MarketManager::update_market(market_id, &market);
+        LpManager::store_position(&player.player_id, market_id, &position);
+        player.store();
+        emit_market_indexed_object(&market, market_id);
+        Ok(())
+    }
+
+    fn handle_claim_lp_fees(player: &mut Player, market_id: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        let mut position = LpManager::get_position(&player.player_id, market_id);
+
+        let fees = liquidity::claim_lp_fees(&mut market, &mut position)?;
+        player.data.add_balance(fees)?;
+
+        crate::state::MarketManager::update_market(market_id, &market);
+        LpManager::store_position(&player.player_id, market_id, &position);
+        player.store();
+        Ok(())
+    }
+
+    fn handle_hedge_to_neutral(player: &mut Player, market_id: u64, max_cost: u64, counter: u64) -> Result<(), u32> {
+        let market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        let position = PlayerMarketManager::get_position(&player.player_id, market_id);
+
+        // The hedge can never spend more than the player holds
+        let budget = max_cost.min(player.data.balance);
+        match market.hedge_to_neutral(position.yes_shares, position.no_shares, budget)? {
+            // The hedge was sized on these shares; a fill short of them would leave it unbalanced
+            Some((bet_type, amount)) => {
+                let shares = market.quote_bet(bet_type, amount)?;
+                Self::handle_bet(player, market_id, bet_type, amount, shares, counter)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn handle_resolve(market_id: u64, outcome: u64, _counter: u64) -> Result<(), u32> {
+        let current_time = GLOBAL_STATE.0.borrow().counter;
+
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+
+        market.ensure_resolvable(current_time)?;
+
+        let outcome_bool = outcome != 0;
+        let phase_before = market.phase(current_time);
+        market.resolve(outcome_bool)?;
+        market.resolved_at = current_time;
+        crate::state::MarketManager::update_market(market_id, &market);
+        
+        // Emit IndexedObject event for updated market
+        emit_market_indexed_object(&market, market_id);
+        emit_market_state_change(market_id, Some(phase_before), market.phase(current_time), current_time);
+        emit_resolve_event(GLOBAL_STATE.0.borrow().txcounter, market_id, &market, current_time);
+        
+        Ok(())
+    }
+
+    // Resolve several markets in one transaction. An atomic batch rejects everything if any
+    // market fails its guards; otherwise failing markets are skipped and the rest resolve.
+    pub fn resolve_markets(markets: Vec<(u64, Option<MarketData>, u64)>, atomic: bool, counter: u64) -> Result<Vec<(u64, MarketData)>, u32> {
+        let mut resolved = Vec::with_capacity(markets.len());
+        for (market_id, market, outcome) in markets {
+            let result = market.ok_or(ERROR_MARKET_NOT_ACTIVE).and_then(|mut market| {
+                market.ensure_resolvable(counter)?;
+                market.resolve(outcome != 0)?;
+                market.resolved_at = counter;
+                Ok(market)
+            });
+            match result {
+                Ok(market) => resolved.push((market_id, market)),
+                Err(e) if atomic => return Err(e),
+                Err(_) => {}
+            }
+        }
+        Ok(resolved)
+    }
+
+    // Every entry is loaded before any resolves, so a repeated market would resolve twice
+    pub fn check_distinct_resolutions(resolutions: &[(u64, u64)]) -> Result<(), u32> {
+        for (index, (market_id, _)) in resolutions.iter().enumerate() {
+            if resolutions[..index].iter().any(|(earlier, _)| earlier == market_id) {
+                return Err(ERROR_INVALID_OUTCOME);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_resolve_batch(atomic: bool, resolutions: &[(u64, u64)], counter: u64) -> Result<(), u32> {
+        Self::check_distinct_resolutions(resolutions)?;
+        let markets = resolutions.iter()
+            .map(|(market_id, outcome)| (*market_id, crate::state::MarketManager::get_market(*market_id), *outcome))
+            .collect::<Vec<_>>();
+        let phases_before = markets.iter()
+            .map(|(market_id, market, _)| (*market_id, market.as_ref().map(|m| m.phase(counter))))
+            .collect::<Vec<_>>();
+
+        let txid = GLOBAL_STATE.0.borrow().txcounter;
+        // Nothing is stored until every market in the batch has been checked
+        for (market_id, market) in Self::resolve_markets(markets, atomic, counter)? {
+            crate::state::MarketManager::update_market(market_id, &market);
+
+            // Emit IndexedObject event for updated market
+            emit_market_indexed_object(&market, market_id);
+            let phase_before = phases_before.iter()
+                .find(|(id, _)| *id == market_id)
+                .and_then(|(_, phase)| *phase);
+            emit_market_state_change(market_id, phase_before, market.phase(counter), counter);
+            emit_resolve_event(txid, market_id, &market, counter);
+        }
+
+        Ok(())
+    }
+
+    fn handle_resolve_distribution(market_id: u64, weights: Vec<u64>, counter: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+
+        let phase_before = market.phase(counter);
+        market.ensure_resolvable(counter)?;
+        market.resolve_distribution(weights)?;
+        market.resolved_at = counter;
+        crate::state::MarketManager::update_market(market_id, &market);
+
+        // Emit IndexedObject event for updated market
+        emit_market_indexed_object(&market, market_id);
+        emit_market_state_change(market_id, Some(phase_before), market.phase(counter), counter);
+        emit_resolve_event(GLOBAL_STATE.0.borrow().txcounter, market_id, &market, counter);
+
+        Ok(())
+    }
+
+    fn handle_submit_oracle_outcome(player: &mut Player, market_id: u64, outcome: bool) -> Result<(), u32> {
+        if !GLOBAL_STATE.0.borrow().is_oracle(&player.player_id) {
+            return Err(ERROR_UNAUTHORIZED);
+        }
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        market.submit_oracle_outcome(player.player_id, outcome)?;
+        crate::state::MarketManager::update_market(market_id, &market);
+        Ok(())
+    }
+
+    fn handle_submit_oracle_value(player: &mut Player, market_id: u64, value: u64, counter: u64) -> Result<(), u32> {
+        if !GLOBAL_STATE.0.borrow().is_oracle(&player.player_id) {
+            return Err(ERROR_UNAUTHORIZED);
+        }
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+
+        let phase_before = market.phase(counter);
+        market.ensure_resolvable(counter)?;
+        let settled = market.submit_oracle_value(player.player_id, value)?;
+        if settled {
+            market.resolved_at = counter;
+        }
+        crate::state::MarketManager::update_market(market_id, &market);
+
+        // Emit IndexedObject event for updated market
+        emit_market_indexed_object(&market, market_id);
+        emit_market_state_change(market_id, Some(phase_before), market.phase(counter), counter);
+        if settled {
+            emit_resolve_event(GLOBAL_STATE.0.borrow().txcounter, market_id, &market, counter);
+        }
+
+        Ok(())
+    }
+
+    // Categorical markets draw their id from the same counter as binary ones but are not
+    // listed in market_ids, so Tick and the binary queries pass them by
+    fn handle_create_categorical_market(title_u64_vec: Vec<u64>, start_time_offset: u64, end_time_offset: u64, resolution_time_offset: u64, reserves: Vec<u64>, counter: u64) -> Result<(), u32> {
+        let absolute_start_time = safe_add(counter, start_time_offset)?;
+        let absolute_end_time = safe_add(counter, end_time_offset)?;
+        let absolute_resolution_time = safe_add(counter, resolution_time_offset)?;
+        MarketData::validate_schedule(absolute_start_time, absolute_end_time, counter)?;
+        let market = CategoricalMarket::new(title_u64_vec, absolute_start_time, absolute_end_time, absolute_resolution_time, reserves)?;
+
+        let market_id = {
+            let mut global_state = GLOBAL_STATE.0.borrow_mut();
+            let market_id = global_state.next_market_id;
+            global_state.next_market_id += 1;
+            market_id
+        };
+        CategoricalManager::store_market(market_id, &market);
+        Ok(())
+    }
+
+    // Staged like execute_bet: nothing is committed unless the bet buys at least `min_shares`.
+    // Returns (shares, trade fee).
+    pub fn execute_bet_outcome(data: &mut PlayerData, position: &mut CategoricalPosition, market: &mut CategoricalMarket, outcome_index: u64, amount: u64, min_shares: u64) -> Result<(u64, u64), u32> {
+        let mut staged_data = data.clone();
+        let mut staged_position = position.clone();
+        let mut staged_market = market.clone();
+
+        staged_data.spend_balance(amount)?;
+        staged_data.record_spent(amount)?;
+        let fee = staged_market.trade_fee(amount)?;
+        let shares = staged_market.place_bet(outcome_index, amount)?;
+        if shares < min_shares {
+            return Err(ERROR_SLIPPAGE_EXCEEDED);
+        }
+        staged_position.buy(outcome_index, shares, amount)?;
+
+        *data = staged_data;
+        *position = staged_position;
+        *market = staged_market;
+        Ok((shares, fee))
+    }
+
+    fn handle_bet_outcome(player: &mut Player, market_id: u64, outcome_index: u64, amount: u64, min_shares: u64, counter: u64) -> Result<(), u32> {
+        if amount == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        let mut market = CategoricalManager::get_market(market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        market.ensure_active(counter)?;
+        let mut position = CategoricalManager::get_position(&player.player_id, &market, market_id);
+
+        let (shares, fee) = Self::execute_bet_outcome(&mut player.data, &mut position, &mut market, outcome_index, amount, min_shares)?;
+        let txid = {
+            let mut state = GLOBAL_STATE.0.borrow_mut();
+            state.record_protocol_fee(fee)?;
+            state.txcounter
+        };
+
+        CategoricalManager::store_market(market_id, &market);
+        CategoricalManager::store_position(&player.player_id, market_id, &position);
+        player.store();
+
+        Self::emit_bet_event(player.player_id, market_id, outcome_index, amount, shares, txid, counter);
+        Ok(())
+    }
+
+    // Staged sell of one outcome's shares back to the categorical AMM; returns (payout, fee)
+    pub fn execute_sell_outcome(data: &mut PlayerData, position: &mut CategoricalPosition, market: &mut CategoricalMarket, outcome_index: u64, shares: u64, min_payout: u64) -> Result<(u64, u64), u32> {
+        let mut staged_data = data.clone();
+        let mut staged_position = position.clone();
+        let mut staged_market = market.clone();
+
+        let (_, fee) = staged_market.quote_sell(outcome_index, shares)?;
+        let payout = staged_market.sell_shares(outcome_index, shares)?;
+        if payout < min_payout {
+            return Err(ERROR_SLIPPAGE_EXCEEDED);
+        }
+        let released = staged_position.sell(outcome_index, shares)?;
+        staged_data.realize_pnl(payout, released)?;
+        staged_data.record_received(payout)?;
+        staged_data.add_balance(payout)?;
+
+        *data = staged_data;
+        *position = staged_position;
+        *market = staged_market;
+        Ok((payout, fee))
+    }
+
+    fn handle_sell_outcome(player: &mut Player, market_id: u64, outcome_index: u64, shares: u64, min_payout: u64, counter: u64) -> Result<(), u32> {
+        if shares == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        let mut market = CategoricalManager::get_market(market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        market.ensure_active(counter)?;
+        let mut position = CategoricalManager::get_position(&player.player_id, &market, market_id);
+
+        let (payout, fee) = Self::execute_sell_outcome(&mut player.data, &mut position, &mut market, outcome_index, shares, min_payout)?;
+        let txid = {
+            let mut state = GLOBAL_STATE.0.borrow_mut();
+            state.record_protocol_fee(fee)?;
+            state.update_pnl_leaderboard(player.player_id, player.data.realized_pnl);
+            state.txcounter
+        };
+
+        CategoricalManager::store_market(market_id, &market);
+        CategoricalManager::store_position(&player.player_id, market_id, &position);
+        player.store();
+
+        emit_sell_event(txid, player.player_id, market_id, outcome_index, shares, payout, counter);
+        Ok(())
+    }
+
+    fn handle_resolve_categorical(market_id: u64, winning_index: u64, counter: u64) -> Result<(), u32> {
+        let mut market = CategoricalManager::get_market(market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        market.resolve(winning_index, counter)?;
+        CategoricalManager::store_market(market_id, &market);
+        Ok(())
+    }
+
+    // Staged claim of a categorical position: the winning shares' part of the pool is credited
+    // and the position is used up
+    pub fn execute_categorical_claim(data: &mut PlayerData, position: &mut CategoricalPosition, market: &mut CategoricalMarket) -> Result<u64, u32> {
+        if market.winner.is_none() {
+            return Err(ERROR_MARKET_NOT_RESOLVED);
+        }
+        if position.claimed {
+            return Err(ERROR_ALREADY_CLAIMED);
+        }
+
+        let mut staged_market = market.clone();
+        let payout = staged_market.settle_claim(&position.shares)?;
+        if payout == 0 {
+            return Err(ERROR_NO_WINNING_POSITION);
+        }
+
+        let mut staged_data = data.clone();
+        staged_data.realize_pnl(payout, position.cost_basis()?)?;
+        staged_data.record_received(payout)?;
+        staged_data.add_balance(payout)?;
+        position.consume();
+        *data = staged_data;
+        *market = staged_market;
+        Ok(payout)
+    }
+
+    fn handle_claim_categorical(player: &mut Player, market_id: u64, counter: u64) -> Result<(), u32> {
+        let mut market = CategoricalManager::get_market(market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        let mut position = CategoricalManager::get_position(&player.player_id, &market, market_id);
+        let winning_shares = market.winner.map_or(0, |winner| position.shares[winner as usize]);
+
+        let payout = Self::execute_categorical_claim(&mut player.data, &mut position, &mut market)?;
+        CategoricalManager::store_market(market_id, &market);
+        CategoricalManager::store_position(&player.player_id, market_id, &position);
+        player.store();
+
+        let txid = {
+            let mut state = GLOBAL_STATE.0.borrow_mut();
+            state.update_pnl_leaderboard(player.player_id, player.data.realized_pnl);
+            state.txcounter
+        };
+        // The winning outcome's shares are reported in the YES slot
+        emit_claim_event(txid, player.player_id, market_id, winning_shares, 0, payout, counter);
+        Ok(())
+    }
+
+    fn handle_void_market(market_id: u64, counter: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+
+        let phase_before = market.phase(counter);
+        market.void()?;
+        market.resolved_at = counter;
+        crate::state::MarketManager::update_market(market_id, &market);
+
+        // Emit IndexedObject event for updated market
+        emit_market_indexed_object(&market, market_id);
+        emit_market_state_change(market_id, Some(phase_before), market.phase(counter), counter);
+
+        Ok(())
+    }
+
+    fn handle_override_resolution(market_id: u64, outcome: bool, counter: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+
+        market.override_resolution(outcome, counter)?;
+        crate::state::MarketManager::update_market(market_id, &market);
+
+        // Emit IndexedObject event for updated market
+        emit_market_indexed_object(&market, market_id);
+        emit_resolve_event(GLOBAL_STATE.0.borrow().txcounter, market_id, &market, counter);
+
+        Ok(())
+    }
+
+    fn handle_sweep_unclaimed(market_id: u64, counter: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+
+        let swept = market.sweep_unclaimed(counter)?;
+        GLOBAL_STATE.0.borrow_mut().credit_treasury(swept)?;
+        crate::state::MarketManager::update_market(market_id, &market);
+
+        // Emit IndexedObject event for updated market
+        emit_market_indexed_object(&market, market_id);
+
+        Ok(())
+    }
+
+    fn handle_close_market(market_id: u64, counter: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+
+        let phase_before = market.phase(counter);
+        market.close(counter)?;
+        crate::state::MarketManager::update_market(market_id, &market);
+
+        // Emit IndexedObject event for updated market
+        emit_market_indexed_object(&market, market_id);
+        emit_market_state_change(market_id, Some(phase_before), market.phase(counter), counter);
+
+        Ok(())
+    }
+
+    fn handle_propose_resolution(market_id: u64, outcome: u64, counter: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+
+        market.ensure_resolvable(counter)?;
+        market.propose_resolution(outcome != 0, counter)?;
+        crate::state::MarketManager::update_market(market_id, &market);
+
+        // Emit IndexedObject event for updated market
+        emit_market_indexed_object(&market, market_id);
+
+        Ok(())
+    }
+
+    fn handle_finalize_resolution(market_id: u64, counter: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+
+        let phase_before = market.phase(counter);
+        market.finalize_resolution(counter)?;
+        crate::state::MarketManager::update_market(market_id, &market);
+
+        // Emit IndexedObject event for updated market
+        emit_market_indexed_object(&market, market_id);
+        emit_market_state_change(market_id, Some(phase_before), market.phase(counter), counter);
+        emit_resolve_event(GLOBAL_STATE.0.borrow().txcounter, market_id, &market, counter);
+
+        Ok(())
+    }
+
+    // With `withdraw_to` the payout goes straight to settlement instead of the balance
+    fn handle_claim(player: &mut Player, market_id: u64, withdraw_to: Option<[u64; 3]>, counter: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        let mut position = PlayerMarketManager::get_position(&player.player_id, market_id);
+
+        market.ensure_claims_open(counter)?;
+        market.ensure_claim_not_expired(counter)?;
+        let phase_before = market.phase(counter);
+        // Shares still escrowed in resting orders are claimed with the rest of the position
+        let mut book = OrderBookManager::get_book(market_id);
+        let returned = Self::return_resting_orders(&mut position, &mut book, player.player_id)?;
+        let (yes_shares, no_shares) = (position.yes_shares, position.no_shares);
+        let settlement = Self::execute_claim(&mut player.data, &mut position, &mut market, player.player_id, market_id)?;
+        let fee_bps = GLOBAL_STATE.0.borrow().withdraw_fee_bps;
+        let auto_settlement = match withdraw_to {
+            Some(address) => Some(Self::withdraw_payout(&mut player.data, address, settlement.payout, fee_bps)?),
+            None => Self::auto_settle_payout(&mut player.data, &market, settlement.payout, fee_bps)?,
+        };
+        if let Some((limbs, fee)) = auto_settlement {
+            // Paying out to L1 is a transfer; with transfers paused the claim waits rather than
+            // crediting the balance instead
+            GLOBAL_STATE.0.borrow().ensure_transfers_open()?;
+            let withdrawinfo = zkwasm_rest_abi::WithdrawInfo::new(&limbs, 0);
+            crate::settlement::SettlementInfo::append_settlement(withdrawinfo)?;
+            let mut state = GLOBAL_STATE.0.borrow_mut();
+            state.record_withdrawal(limbs[0] & 0xffffffff)?;
+            state.record_protocol_fee(fee)?;
+        }
+
+        if let Some((holder_id, remainder)) = settlement.remainder_recipient {
+            if holder_id == player.player_id {
+                player.data.add_balance(remainder)?;
+            } else if let Some(mut holder) = Player::get_from_pid(&holder_id) {
+                holder.data.add_balance(remainder)?;
+                holder.store();
+            }
+        }
+        crate::state::MarketManager::update_market(market_id, &market);
+        PlayerMarketManager::store_position(&player.player_id, market_id, &position);
+        if !returned.is_empty() {
+            OrderBookManager::store_book(market_id, &book);
+        }
+        player.store();
+
+        // Payout is computed and committed without holding the global state; it is borrowed
+        // here once and released before any event is emitted
+        let txid = {
+            let mut state = GLOBAL_STATE.0.borrow_mut();
+            state.update_pnl_leaderboard(player.player_id, player.data.realized_pnl);
+            state.txcounter
+        };
+        emit_market_state_change(market_id, Some(phase_before), market.phase(counter), counter);
+        for order in &returned {
+            emit_order_event(market_id, order.id, player.player_id, order.outcome, order.price, 0);
+        }
+        emit_claim_event(txid, player.player_id, market_id, yes_shares, no_shares, settlement.payout, counter);
+        if auto_settlement.is_some() {
+            emit_balance_transfer_event(EVENT_WITHDRAW, txid, player.player_id, settlement.payout, counter);
+        }
+
+        Ok(())
+    }
+
+    // On auto-settle markets the payout just credited by execute_claim leaves again as a
+    // withdrawal to the player's registered address. Returns the withdrawal limbs to queue.
+    pub fn auto_settle_payout(data: &mut PlayerData, market: &MarketData, payout: u64, fee_bps: u64) -> Result<Option<([u64; 3], u64)>, u32> {
+        if !market.auto_settle_claims {
+            return Ok(None);
+        }
+        // Frozen players may claim, but their winnings must not leave the app
+        data.ensure_not_frozen()?;
+        if !data.has_withdraw_address() {
+            return Err(ERROR_NO_WITHDRAW_ADDRESS);
+        }
+        let address = data.withdraw_address;
+        Self::withdraw_payout(data, address, payout, fee_bps).map(Some)
+    }
+
+    // Take a payout just credited by execute_claim back out of the balance as a withdrawal
+    // to `address` (limbs as in Withdraw, amount bits ignored). The withdrawal fee is taken as
+    // for a plain Withdraw; returns the limbs to queue, which carry the net amount, and the fee.
+    pub fn withdraw_payout(data: &mut PlayerData, address: [u64; 3], payout: u64, fee_bps: u64) -> Result<([u64; 3], u64), u32> {
+        // Withdrawals carry the amount in 32 bits
+        if payout > 0xffffffff {
+            return Err(ERROR_OVERFLOW);
+        }
+        let withdrawal = Withdraw { data: [(address[0] & !0xffffffff) | payout, address[1], address[2]] };
+        let (net, fee) = withdrawal.split_fee(fee_bps)?;
+        data.spend_balance(payout)?;
+        let [limb0, limb1, limb2] = withdrawal.address();
+        Ok(([limb0 | net, limb1, limb2], fee))
+    }
+
+    // Staged claim: only the position's claimed flag gates a payout, never the player's balance
+    pub fn execute_claim(data: &mut PlayerData, position: &mut PlayerMarketPosition, market: &mut MarketData, player_id: [u64; 2], market_id: u64) -> Result<ClaimSettlement, u32> {
+        if !market.resolved {
+            return Err(crate::error::ERROR_MARKET_NOT_RESOLVED);
+        }
+
+        // Check if already claimed
+        if position.claimed {
+            return Err(crate::error::ERROR_ALREADY_CLAIMED);
+        }
+
+        // Calculate payout and any rounding remainder released by this claim
+        let mut staged_market = market.clone();
+        let settlement = if staged_market.voided {
+            ClaimSettlement { payout: staged_market.settle_refund(position.cost_basis()?)?, remainder_recipient: None }
+        } else {
+            staged_market.settle_claim(player_id, position.yes_shares, position.no_shares)?
+        };
+        staged_market.check_solvency()?;
+        if settlement.payout == 0 {
+            return Err(crate::error::ERROR_NO_WINNING_POSITION);
+        }
+
+        let mut staged_data = data.clone();
+        staged_data.realize_pnl(settlement.payout, position.cost_basis()?)?;
+        staged_data.record_received(settlement.payout)?;
+        position.consume();
+        staged_data.exit_market(market_id);
+        staged_data.add_balance(settlement.payout)?;
+        *data = staged_data;
+        *market = staged_market;
+        Ok(settlement)
+    }
+
+    // Staged fee withdrawal: the market's fee counter is only reset once `destination` has been credited
+    pub fn execute_withdraw_fees(market: &mut MarketData, caller: &[u64; 2], admin: &[u64; 2], to: [u64; 2], destination: &mut PlayerData) -> Result<u64, u32> {
+        let mut staged_market = market.clone();
+        let (_, fees_collected) = staged_market.withdraw_fees(caller, admin, to)?;
+        destination.add_balance(fees_collected)?;
+        *market = staged_market;
+        Ok(fees_collected)
+    }
+
+    fn handle_withdraw_fees(player: &mut Player, market_id: u64, to: [u64; 2], _counter: u64) -> Result<(), u32> {
+        let mut market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+
+        let admin_id = GLOBAL_STATE.0.borrow().admin_id;
+        let destination = market.fee_destination(to);
+        if destination == player.player_id {
+            Self::execute_withdraw_fees(&mut market, &player.player_id, &admin_id, to, &mut player.data)?;
+        } else {
+            // A treasury account does not need to have installed a player first
+            let mut recipient = Player::get_from_pid(&destination)
+                .unwrap_or_else(|| Player::new_from_pid(destination));
+            Self::execute_withdraw_fees(&mut market, &player.player_id, &admin_id, to, &mut recipient.data)?;
+            recipient.store();
+        }
+        crate::state::MarketManager::update_market(market_id, &market);
+
+        // Store updated player data
+        player.store();
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn handle_create_market(creator: [u64; 2], title_u64_vec: Vec<u64>, start_time_offset: u64, end_time_offset: u64, resolution_time_offset: u64, yes_liquidity: u64, no_liquidity: u64, options: &[(u64, u64)], counter: u64) -> Result<(), u32> {
+        // Calculate absolute times by adding offsets to current counter
+        let absolute_start_time = safe_add(counter, start_time_offset)?;
+        let absolute_end_time = safe_add(counter, end_time_offset)?;
+        let absolute_resolution_time = safe_add(counter, resolution_time_offset)?;
+        MarketData::validate_schedule(absolute_start_time, absolute_end_time, counter)?;
+        
+        let description = format!("Prediction market created at counter {}", counter);
+        
+        let _market_id = crate::state::MarketManager::create_market_with_title_u64_and_liquidity(
+            title_u64_vec,
+            description,
+            absolute_start_time,
+            absolute_end_time,
+            absolute_resolution_time,
+            yes_liquidity,
+            no_liquidity,
+            creator,
+            options,
+        )?;
 
         Ok(())
     }
@@ -309,20 +2126,6 @@ impl Activity {
         ];
         insert_event(EVENT_BET_UPDATE, &mut data);
     }
-
-    fn emit_sell_event(player_id: [u64; 2], market_id: u64, sell_type: u64, shares: u64, payout: u64, txid: u64, counter: u64) {
-        let mut data = vec![
-            txid,
-            player_id[0],
-            player_id[1],
-            market_id,
-            sell_type + 10, // 11 = SELL_YES, 12 = SELL_NO (distinguish from bet events)
-            shares,
-            payout,
-            counter,
-        ];
-        insert_event(EVENT_BET_UPDATE, &mut data); // Reuse BET_UPDATE event for now
-    }
 }
 
 pub fn decode_error(e: u32) -> &'static str {
@@ -340,6 +2143,1081 @@ pub fn decode_error(e: u32) -> &'static str {
         ERROR_PLAYER_NOT_EXIST => "PlayerNotExist",
         ERROR_PLAYER_ALREADY_EXISTS => "PlayerAlreadyExists",
         ERROR_NO_FEES_TO_WITHDRAW => "NoFeesToWithdraw",
+        ERROR_TOO_MANY_POSITIONS => "TooManyPositions",
+        ERROR_INVALID_OUTCOME => "InvalidOutcome",
+        ERROR_RESOLUTION_TOO_EARLY => "ResolutionTooEarly",
+        ERROR_NO_PENDING_RESOLUTION => "NoPendingResolution",
+        ERROR_QUOTE_EXPIRED => "QuoteExpired",
+        ERROR_LIQUIDITY_CAP_REACHED => "LiquidityCapReached",
+        ERROR_INVALID_MARKET_OPTION => "InvalidMarketOption",
+        ERROR_PLAYER_FROZEN => "PlayerFrozen",
+        ERROR_SLIPPAGE_EXCEEDED => "SlippageExceeded",
+        ERROR_NO_WITHDRAW_ADDRESS => "NoWithdrawAddress",
+        ERROR_NO_PARTICIPANTS_ON_OUTCOME => "NoParticipantsOnOutcome",
+        ERROR_TOO_MANY_SUB_ACCOUNTS => "TooManySubAccounts",
+        ERROR_INSUFFICIENT_ORACLE_DATA => "InsufficientOracleData",
+        ERROR_POSITION_LIMIT_EXCEEDED => "PositionLimitExceeded",
+        ERROR_SETTLEMENT_FULL => "SettlementFull",
+        ERROR_MARKET_PAUSED => "MarketPaused",
+        ERROR_SELL_COOLDOWN => "SellCooldown",
+        ERROR_INVALID_NONCE => "InvalidNonce",
+        ERROR_INSOLVENT => "Insolvent",
+        ERROR_INVALID_FEE => "InvalidFee",
+        ERROR_PRICING_UNSUPPORTED => "PricingUnsupported",
+        ERROR_ORDER_NOT_FOUND => "OrderNotFound",
+        ERROR_ORDER_BOOK_FULL => "OrderBookFull",
+        ERROR_COMMITMENT_NOT_FOUND => "CommitmentNotFound",
+        ERROR_COMMITMENT_MISMATCH => "CommitmentMismatch",
+        ERROR_NO_DISPUTE_BOND => "NoDisputeBond",
+        ERROR_CLAIM_EXPIRED => "ClaimExpired",
+        ERROR_CLAIM_PERIOD_ACTIVE => "ClaimPeriodActive",
+        ERROR_NOTHING_TO_SWEEP => "NothingToSweep",
+        ERROR_NOT_BATCHABLE => "NotBatchable",
+        ERROR_CLAIM_NOT_OPEN => "ClaimNotOpen",
+        ERROR_MARKET_ENDED => "MarketEnded",
+        ERROR_OVERFLOW => "Overflow",
+        ERROR_DIVISION_BY_ZERO => "DivisionByZero",
+        ERROR_UNDERFLOW => "Underflow",
+        ERROR_BET_TOO_LARGE => "BetTooLarge",
+        ERROR_LIQUIDITY_TOO_HIGH => "LiquidityTooHigh",
+        ERROR_INVALID_CALCULATION => "InvalidCalculation",
         _ => "Unknown",
     }
-} 
+}
+
+// Coarse error classes for clients that branch on the kind of failure rather than its name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCategory {
+    Validation,    // The command itself is wrong; resending it unchanged fails again
+    Authorization, // The caller may not do this
+    MarketState,   // The market or block is not in the right state yet; may succeed later
+    Balance,       // Nothing, or not enough, to spend or collect
+    Arithmetic,    // A calculation overflowed or could not be carried out
+    Unknown,
+}
+
+pub fn error_category(e: u32) -> ErrorCategory {
+    match e {
+        ERROR_INVALID_BET_AMOUNT
+        | ERROR_INVALID_NONCE
+        | ERROR_INVALID_FEE
+        | ERROR_PRICING_UNSUPPORTED
+        | ERROR_ORDER_NOT_FOUND
+        | ERROR_COMMITMENT_NOT_FOUND
+        | ERROR_COMMITMENT_MISMATCH
+        | ERROR_NO_DISPUTE_BOND
+        | ERROR_NOTHING_TO_SWEEP
+        | ERROR_NOT_BATCHABLE
+        | ERROR_INVALID_MARKET_TIME
+        | ERROR_INVALID_BET_TYPE
+        | ERROR_INVALID_OUTCOME
+        | ERROR_INVALID_MARKET_OPTION
+        | ERROR_PLAYER_NOT_EXIST
+        | ERROR_PLAYER_ALREADY_EXISTS
+        | ERROR_TOO_MANY_POSITIONS
+        | ERROR_TOO_MANY_SUB_ACCOUNTS
+        | ERROR_POSITION_LIMIT_EXCEEDED
+        | ERROR_LIQUIDITY_CAP_REACHED
+        | ERROR_SLIPPAGE_EXCEEDED
+        | ERROR_QUOTE_EXPIRED
+        | ERROR_NO_WITHDRAW_ADDRESS
+        | ERROR_BET_TOO_LARGE
+        | ERROR_LIQUIDITY_TOO_HIGH => ErrorCategory::Validation,
+        ERROR_UNAUTHORIZED | ERROR_PLAYER_FROZEN => ErrorCategory::Authorization,
+        ERROR_MARKET_NOT_ACTIVE
+        | ERROR_MARKET_ENDED
+        | ERROR_MARKET_NOT_RESOLVED
+        | ERROR_MARKET_ALREADY_RESOLVED
+        | ERROR_RESOLUTION_TOO_EARLY
+        | ERROR_NO_PENDING_RESOLUTION
+        | ERROR_NO_PARTICIPANTS_ON_OUTCOME
+        | ERROR_INSUFFICIENT_ORACLE_DATA
+        | ERROR_SETTLEMENT_FULL
+        | ERROR_MARKET_PAUSED
+        | ERROR_SELL_COOLDOWN
+        | ERROR_CLAIM_NOT_OPEN
+        | ERROR_ORDER_BOOK_FULL
+        | ERROR_CLAIM_EXPIRED
+        | ERROR_CLAIM_PERIOD_ACTIVE => ErrorCategory::MarketState,
+        ERROR_INSUFFICIENT_BALANCE
+        | ERROR_NO_FEES_TO_WITHDRAW
+        | ERROR_NO_WINNING_POSITION
+        | ERROR_ALREADY_CLAIMED => ErrorCategory::Balance,
+        ERROR_OVERFLOW
+        | ERROR_DIVISION_BY_ZERO
+        | ERROR_UNDERFLOW
+        | ERROR_INVALID_CALCULATION
+        | ERROR_INSOLVENT => ErrorCategory::Arithmetic,
+        _ => ErrorCategory::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_error_code_has_a_name_and_category() {
+        let codes = [
+            ERROR_INSUFFICIENT_BALANCE, ERROR_MARKET_NOT_ACTIVE, ERROR_MARKET_ENDED, ERROR_INVALID_BET_AMOUNT,
+            ERROR_MARKET_NOT_RESOLVED, ERROR_ALREADY_CLAIMED, ERROR_NO_WINNING_POSITION, ERROR_UNAUTHORIZED,
+            ERROR_MARKET_ALREADY_RESOLVED, ERROR_INVALID_OUTCOME, ERROR_INVALID_BET_TYPE, ERROR_INVALID_MARKET_TIME,
+            ERROR_PLAYER_NOT_EXIST, ERROR_PLAYER_ALREADY_EXISTS, ERROR_NO_FEES_TO_WITHDRAW, ERROR_TOO_MANY_POSITIONS,
+            ERROR_RESOLUTION_TOO_EARLY, ERROR_NO_PENDING_RESOLUTION, ERROR_QUOTE_EXPIRED, ERROR_LIQUIDITY_CAP_REACHED,
+            ERROR_INVALID_MARKET_OPTION, ERROR_PLAYER_FROZEN, ERROR_SLIPPAGE_EXCEEDED, ERROR_NO_WITHDRAW_ADDRESS,
+            ERROR_NO_PARTICIPANTS_ON_OUTCOME, ERROR_TOO_MANY_SUB_ACCOUNTS, ERROR_INSUFFICIENT_ORACLE_DATA,
+            ERROR_POSITION_LIMIT_EXCEEDED, ERROR_SETTLEMENT_FULL, ERROR_MARKET_PAUSED, ERROR_SELL_COOLDOWN, ERROR_INVALID_NONCE,
+            ERROR_INSOLVENT, ERROR_INVALID_FEE, ERROR_CLAIM_NOT_OPEN, ERROR_PRICING_UNSUPPORTED, ERROR_ORDER_NOT_FOUND, ERROR_ORDER_BOOK_FULL, ERROR_COMMITMENT_NOT_FOUND, ERROR_COMMITMENT_MISMATCH, ERROR_NO_DISPUTE_BOND, ERROR_CLAIM_EXPIRED, ERROR_CLAIM_PERIOD_ACTIVE, ERROR_NOTHING_TO_SWEEP, ERROR_NOT_BATCHABLE, ERROR_OVERFLOW, ERROR_DIVISION_BY_ZERO,
+            ERROR_UNDERFLOW, ERROR_BET_TOO_LARGE, ERROR_LIQUIDITY_TOO_HIGH, ERROR_INVALID_CALCULATION,
+        ];
+        for code in codes {
+            assert_ne!(decode_error(code), "Unknown", "code {} has no name", code);
+            assert_ne!(error_category(code), ErrorCategory::Unknown, "code {} has no category", code);
+        }
+        assert_eq!(error_category(9999), ErrorCategory::Unknown);
+    }
+
+    #[test]
+    fn test_pause_blocks_trading_until_lifted() {
+        let mut state = GlobalState::new();
+        let bet = Activity::Bet(1, 1, 1_000);
+        state.trading_paused = true;
+        assert_eq!(bet.check_paused(&state), Err(ERROR_MARKET_PAUSED));
+        assert_eq!(Activity::Claim(1).check_paused(&state), Err(ERROR_MARKET_PAUSED));
+        // Transfers have their own switch, and the admin can always lift the pause
+        assert_eq!(state.ensure_transfers_open(), Ok(()));
+        assert_eq!(Activity::SetPaused(false, false).check_paused(&state), Ok(()));
+
+        state.trading_paused = false;
+        assert_eq!(bet.check_paused(&state), Ok(()));
+        let mut player = PlayerData { balance: 10_000, ..PlayerData::default() };
+        let mut market = test_market();
+        let mut position = PlayerMarketPosition::default();
+        assert!(Activity::execute_bet(&mut player, &mut position, &mut market, 1, 1, 1_000, 0).unwrap() > 0);
+    }
+
+    #[test]
+    fn test_deposit_creates_missing_recipient() {
+        let player = Deposit::credit(None, [11, 12], 5_000).unwrap();
+        assert_eq!(player.player_id, [11, 12]);
+        assert_eq!(player.data.balance, 5_000);
+        assert_eq!(player.nonce, 0);
+        assert!(player.data.active_markets.is_empty());
+
+        // An existing player is credited on top of what it holds
+        let topped_up = Deposit::credit(Some(player), [11, 12], 1_000).unwrap();
+        assert_eq!(topped_up.data.balance, 6_000);
+    }
+
+    #[test]
+    fn test_query_is_repeatable_without_nonce() {
+        let mut player = Player::new_from_pid([1, 2]);
+        player.nonce = 5;
+        player.data.balance = 1000;
+
+        // The same query issued twice succeeds both times and leaves the nonce alone
+        let first = Query::Player.query(&player);
+        let second = Query::Player.query(&player);
+        assert_eq!(first, Ok(vec![5, 1000]));
+        assert_eq!(second, first);
+        assert_eq!(player.nonce, 5);
+    }
+
+    #[test]
+    fn test_player_view_of_fresh_player_is_empty() {
+        let player = Player::new_from_pid([3, 4]);
+        let view = PlayerView::new(&player, &PlayerMarketPosition::default());
+        assert_eq!(view.to_query_data(), vec![0, 0, 0, 0, 0]);
+        assert_eq!(player.nonce, 0);
+    }
+
+    #[test]
+    fn test_signing_context_matches_individual_reads() {
+        let mut player = Player::new_from_pid([1, 2]);
+        player.data.balance = 100_000;
+        let mut market = test_market();
+        let mut position = PlayerMarketPosition::default();
+        for nonce in 0..3 {
+            player.nonce = nonce + 1;
+            Activity::execute_bet(&mut player.data, &mut position, &mut market, 1, nonce % 2, 5_000, 0).unwrap();
+        }
+
+        let context = SigningContext::new(&player, vec![(1, position.clone())]);
+        let individual = Query::Player.query(&player).unwrap();
+        assert_eq!(vec![context.nonce, context.balance], individual);
+        assert_eq!(player.data.active_markets, vec![1]);
+
+        let mut data = vec![];
+        context.to_data(&mut data);
+        assert_eq!(data, vec![3, player.data.balance, 1, 1, position.yes_shares, position.no_shares, 0, position.yes_cost, position.no_cost, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    fn test_market() -> MarketData {
+        let title = MarketData::string_to_u64_vec("Atomic Bet");
+        MarketData::new_with_title_u64_and_liquidity(
+            title, "Atomic Bet".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap()
+    }
+
+    fn assert_market_unchanged(market: &MarketData, before: &MarketData) {
+        assert_eq!(market.yes_liquidity, before.yes_liquidity);
+        assert_eq!(market.no_liquidity, before.no_liquidity);
+        assert_eq!(market.prize_pool, before.prize_pool);
+        assert_eq!(market.total_yes_shares, before.total_yes_shares);
+        assert_eq!(market.total_fees_collected, before.total_fees_collected);
+    }
+
+    #[test]
+    fn test_insufficient_balance_bet_changes_nothing() {
+        let mut market = test_market();
+        let before = market.clone();
+        let mut data = PlayerData::default();
+        data.add_balance(500).unwrap();
+        let mut position = PlayerMarketPosition::default();
+
+        let result = Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 1000, 0);
+        assert_eq!(result, Err(ERROR_INSUFFICIENT_BALANCE));
+        assert_eq!(data.balance, 500);
+        assert_eq!(position.yes_shares, 0);
+        assert_market_unchanged(&market, &before);
+    }
+
+    #[test]
+    fn test_failed_pool_update_keeps_balance() {
+        let mut market = test_market();
+        let before = market.clone();
+        let mut data = PlayerData::default();
+        data.add_balance(u64::MAX).unwrap();
+        let mut position = PlayerMarketPosition::default();
+
+        // Balance covers the bet but the pool rejects it, so the debit must not stick
+        let result = Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, crate::math_safe::MAX_BET_AMOUNT + 1, 0);
+        assert_eq!(result, Err(ERROR_BET_TOO_LARGE));
+        assert_eq!(data.balance, u64::MAX);
+        assert_eq!(position.yes_shares, 0);
+        assert_market_unchanged(&market, &before);
+    }
+
+    #[test]
+    fn test_successful_bet_commits_all_parts() {
+        let mut market = test_market();
+        let mut data = PlayerData::default();
+        data.add_balance(10_000).unwrap();
+        let mut position = PlayerMarketPosition::default();
+
+        let shares = Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 1000, 0).unwrap();
+        assert_eq!(data.balance, 9_000);
+        assert_eq!(position.yes_shares, shares);
+        assert_eq!(market.total_yes_shares, shares);
+    }
+
+    #[test]
+    fn test_position_cap_blocks_new_markets_only() {
+        let mut markets = vec![test_market(), test_market(), test_market()];
+        let mut positions = vec![PlayerMarketPosition::default(); 3];
+        let mut data = PlayerData::default();
+        data.add_balance(100_000).unwrap();
+
+        // Fill the cap with two markets
+        let mut shares = Vec::new();
+        for i in 0..2 {
+            shares.push(Activity::execute_bet(&mut data, &mut positions[i], &mut markets[i], i as u64, 1, 1000, 0).unwrap());
+        }
+        assert_eq!(data.active_markets, vec![0, 1]);
+
+        // Adding to an existing position still works, a third market does not
+        let cap = 2;
+        assert!(data.enter_market(0, cap).is_ok());
+        assert_eq!(data.enter_market(2, cap), Err(ERROR_TOO_MANY_POSITIONS));
+
+        // Exiting market 0 entirely frees a slot for market 2
+        Activity::execute_sell(&mut data, &mut positions[0], &mut markets[0], 0, 1, shares[0], 0).unwrap();
+        assert_eq!(data.active_markets, vec![1]);
+        assert!(data.enter_market(2, cap).is_ok());
+        assert_eq!(data.active_markets, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_resolve_batch_skips_or_rejects_resolved_markets() {
+        let mut already_resolved = test_market();
+        already_resolved.resolve(false).unwrap();
+        let batch = vec![
+            (1, Some(test_market()), 1),
+            (2, Some(already_resolved), 1),
+            (3, Some(test_market()), 0),
+        ];
+
+        // Atomic: the resolved market fails the whole batch
+        assert_eq!(Activity::resolve_markets(batch.clone(), true, 1000).err(), Some(ERROR_MARKET_ALREADY_RESOLVED));
+
+        // Skipping: the other two resolve to their own outcomes, the resolved one is left alone
+        let resolved = Activity::resolve_markets(batch, false, 1000).unwrap();
+        assert_eq!(resolved.len(), 2);
+        assert_eq!(resolved[0].0, 1);
+        assert_eq!(resolved[0].1.outcome, Some(true));
+        assert_eq!(resolved[1].0, 3);
+        assert_eq!(resolved[1].1.outcome, Some(false));
+        assert!(resolved.iter().all(|(_, market)| market.resolved));
+
+        // A missing market is treated like any other failing market
+        assert_eq!(Activity::resolve_markets(vec![(4, None, 1)], true, 1000).err(), Some(ERROR_MARKET_NOT_ACTIVE));
+        assert!(Activity::resolve_markets(vec![(4, None, 1)], false, 1000).unwrap().is_empty());
+
+        // The same market twice is refused whatever the outcomes, even in skipping mode
+        assert_eq!(Activity::check_distinct_resolutions(&[(5, 1), (6, 0), (5, 0)]), Err(ERROR_INVALID_OUTCOME));
+        assert_eq!(Activity::check_distinct_resolutions(&[(5, 1), (5, 1)]), Err(ERROR_INVALID_OUTCOME));
+        assert_eq!(Activity::check_distinct_resolutions(&[(5, 1), (6, 0)]), Ok(()));
+    }
+
+    #[test]
+    fn test_admission_rejects_before_anything_runs() {
+        let mut state = GlobalState::new();
+        let mut player = Player::new_from_pid([1, 2]);
+        let bet = Activity::Bet(1, 1, 1_000);
+        assert_eq!(bet.admit(&player, &state, &[1, 2], 1), Err(ERROR_INVALID_NONCE));
+        assert_eq!(bet.admit(&player, &state, &[1, 2], 0), Ok(()));
+        assert_eq!(Activity::VoidMarket(1).admit(&player, &state, &[1, 2], 0), Err(ERROR_UNAUTHORIZED));
+
+        player.data.frozen = true;
+        assert_eq!(bet.admit(&player, &state, &[1, 2], 0), Err(ERROR_PLAYER_FROZEN));
+        player.data.frozen = false;
+        state.trading_paused = true;
+        assert_eq!(bet.admit(&player, &state, &[1, 2], 0), Err(ERROR_MARKET_PAUSED));
+        // Admission only looks; the nonce moves once the activity is handled
+        assert_eq!(player.nonce, 0);
+    }
+
+    #[test]
+    fn test_frozen_player_cannot_trade_until_unfrozen() {
+        let mut market = test_market();
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+
+        data.frozen = true;
+        let bet = Activity::Bet(1, 1, 10_000);
+        assert_eq!(bet.check_frozen(&data), Err(ERROR_PLAYER_FROZEN));
+        assert_eq!(Activity::Sell(1, 1, 100).check_frozen(&data), Err(ERROR_PLAYER_FROZEN));
+        assert_eq!(Activity::AddLiquidity(1, 100, 0).check_frozen(&data), Err(ERROR_PLAYER_FROZEN));
+        // Claims and admin resolution are not blocked
+        assert_eq!(Activity::Claim(1).check_frozen(&data), Ok(()));
+        assert_eq!(Activity::Resolve(1, 1).check_frozen(&data), Ok(()));
+
+        // Once unfrozen the player trades normally
+        data.frozen = false;
+        assert_eq!(bet.check_frozen(&data), Ok(()));
+        let shares_before = position.yes_shares;
+        Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+        assert!(position.yes_shares > shares_before);
+    }
+
+    #[test]
+    fn test_operation_fee_charged_on_bets_and_waived_for_claims() {
+        let mut state = GlobalState::new();
+        state.operation_fee = 25;
+        let bet = Activity::Bet(1, 1, 10_000);
+        let claim = Activity::Claim(1);
+        assert_eq!(state.operation_fee_for(&bet), 25);
+        assert_eq!(state.operation_fee_for(&claim), 0);
+
+        let mut market = test_market();
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        Activity::charge_operation_fee(&mut data, state.operation_fee_for(&bet)).unwrap();
+        Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+        assert_eq!(data.balance, 100_000 - 10_000 - 25);
+
+        market.resolve(true).unwrap();
+        let before_claim = data.balance;
+        Activity::charge_operation_fee(&mut data, state.operation_fee_for(&claim)).unwrap();
+        let settlement = Activity::execute_claim(&mut data, &mut position, &mut market, [1, 1], 1).unwrap();
+        assert_eq!(data.balance, before_claim + settlement.payout);
+
+        // Without the waiver claims pay too, and a balance that cannot cover the fee is rejected
+        state.operation_fee_waivers = 0;
+        assert_eq!(state.operation_fee_for(&claim), 25);
+        let mut broke = PlayerData { balance: 24, ..PlayerData::default() };
+        assert_eq!(Activity::charge_operation_fee(&mut broke, 25), Err(ERROR_INSUFFICIENT_BALANCE));
+    }
+
+    #[test]
+    fn test_closed_market_rejects_trades_but_resolves_and_pays() {
+        let mut market = test_market();
+        market.resolution_time = 600;
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+
+        // Closing at 500 stops bets and sells long before the scheduled end
+        market.close(500).unwrap();
+        assert_eq!(market.ensure_active(500), Err(ERROR_MARKET_NOT_ACTIVE));
+        assert_eq!(market.ensure_active(501), Err(ERROR_MARKET_NOT_ACTIVE));
+        assert_eq!(market.close(501), Err(ERROR_MARKET_NOT_ACTIVE));
+        assert!(!market.resolved);
+
+        // The outcome still waits for the resolution time, then claims pay as usual
+        assert_eq!(market.ensure_resolvable(599), Err(ERROR_RESOLUTION_TOO_EARLY));
+        market.ensure_resolvable(600).unwrap();
+        market.resolve(true).unwrap();
+        market.ensure_claims_open(600).unwrap();
+        let before = data.balance;
+        let settlement = Activity::execute_claim(&mut data, &mut position, &mut market, [1, 1], 1).unwrap();
+        assert!(settlement.payout > 0);
+        assert_eq!(data.balance, before + settlement.payout);
+    }
+
+    #[test]
+    fn test_failed_arb_leg_reverts_every_leg() {
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let mut markets = vec![
+            (1, test_market(), PlayerMarketPosition::default()),
+            (2, test_market(), PlayerMarketPosition::default()),
+        ];
+        let before = markets.clone();
+        let first = ArbLeg { market_id: 1, outcome: 1, amount: 10_000, side: ARB_SIDE_BUY, min_out: 1 };
+        // The second leg demands more shares than the bet can buy
+        let second = ArbLeg { market_id: 2, outcome: 0, amount: 10_000, side: ARB_SIDE_BUY, min_out: 1_000_000 };
+
+        let result = Activity::execute_arb(&mut data, &mut markets, &[first.clone(), second.clone()], 0);
+        assert_eq!(result, Err(ERROR_SLIPPAGE_EXCEEDED));
+        assert_eq!(data.balance, 100_000);
+        assert!(data.active_markets.is_empty());
+        for ((_, market, position), (_, original, _)) in markets.iter().zip(before.iter()) {
+            assert_eq!(market.yes_liquidity, original.yes_liquidity);
+            assert_eq!(market.no_liquidity, original.no_liquidity);
+            assert_eq!(market.prize_pool, original.prize_pool);
+            assert_eq!(*position, PlayerMarketPosition::default());
+        }
+
+        // With a reachable guard both legs land
+        let second = ArbLeg { min_out: 1, ..second };
+        let received = Activity::execute_arb(&mut data, &mut markets, &[first, second], 0).unwrap();
+        assert_eq!(data.balance, 80_000);
+        assert_eq!(markets[0].2.yes_shares, received[0]);
+        assert_eq!(markets[1].2.no_shares, received[1]);
+    }
+
+    fn staged(market_id: u64, market: MarketData) -> StagedMarket {
+        StagedMarket { market_id, market, position: PlayerMarketPosition::default(), book: OrderBook::default() }
+    }
+
+    #[test]
+    fn test_batch_applies_activities_in_order() {
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let mut markets = vec![staged(1, test_market()), staged(2, test_market())];
+        let batch = [
+            Activity::Bet(1, 1, 10_000),
+            Activity::BetWithSlippage(2, 0, 5_000, 1),
+            Activity::PlaceLimitOrder(1, 1, 600_000, 1_000),
+            Activity::SellWithSlippage(1, 1, 2_000, 1),
+            Activity::CancelOrder(1, 0),
+        ];
+        let effects = Activity::execute_batch(&mut data, &mut markets, [1, 1], &batch, 100).unwrap();
+        assert_eq!(effects.len(), 5);
+
+        // Each activity sees what the earlier ones did, exactly as if sent one by one
+        let mut alone_data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let mut alone = test_market();
+        let mut position = PlayerMarketPosition::default();
+        let bought = Activity::execute_bet(&mut alone_data, &mut position, &mut alone, 1, 1, 10_000, 0).unwrap();
+        let payout = Activity::execute_sell(&mut alone_data, &mut position, &mut alone, 1, 1, 2_000, 1).unwrap();
+        assert_eq!(effects[0], BatchEffect::Bet { market_id: 1, bet_type: 1, spent: 10_000, shares: bought, fills: vec![] });
+        assert_eq!(effects[3], BatchEffect::Sell { market_id: 1, sell_type: 1, shares: 2_000, payout });
+        assert_eq!(markets[0].position.yes_shares, bought - 2_000);
+        assert_eq!(markets[0].market.yes_liquidity, alone.yes_liquidity);
+        assert!(markets[0].book.orders.is_empty());
+        assert!(markets[1].position.no_shares > 0);
+        assert_eq!(data.balance, alone_data.balance - 5_000);
+    }
+
+    #[test]
+    fn test_batch_rolls_back_every_activity_when_one_fails() {
+        let mut market = test_market();
+        market.sell_cooldown = 10;
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let mut markets = vec![staged(1, market), staged(2, test_market())];
+        let before = markets.clone();
+
+        // The last sell trips the cooldown its own batch's bet started
+        let batch = [Activity::Bet(2, 1, 10_000), Activity::Bet(1, 1, 10_000), Activity::Sell(1, 1, 1_000)];
+        assert_eq!(Activity::execute_batch(&mut data, &mut markets, [1, 1], &batch, 100), Err(ERROR_SELL_COOLDOWN));
+        assert_eq!(data.balance, 100_000);
+        for (after, before) in markets.iter().zip(&before) {
+            assert_eq!(after.position, before.position);
+            assert_eq!(after.market.total_volume, before.market.total_volume);
+            assert_eq!(after.book, before.book);
+        }
+
+        // Activities without a staged form, markets not loaded and empty batches are refused
+        assert_eq!(Activity::execute_batch(&mut data, &mut markets, [1, 1], &[Activity::Claim(1)], 100), Err(ERROR_NOT_BATCHABLE));
+        assert_eq!(Activity::execute_batch(&mut data, &mut markets, [1, 1], &[Activity::Bet(3, 1, 1_000)], 100), Err(ERROR_MARKET_NOT_ACTIVE));
+        assert_eq!(Activity::execute_batch(&mut data, &mut markets, [1, 1], &[], 100), Err(ERROR_NOT_BATCHABLE));
+        assert_eq!(data.balance, 100_000);
+    }
+
+    #[test]
+    fn test_arb_sell_leg_respects_the_cooldown_its_buy_leg_starts() {
+        let mut market = test_market();
+        market.sell_cooldown = 10;
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let mut markets = vec![(1, market, PlayerMarketPosition::default())];
+        let buy = ArbLeg { market_id: 1, outcome: 1, amount: 10_000, side: ARB_SIDE_BUY, min_out: 1 };
+        let sell = ArbLeg { market_id: 1, outcome: 1, amount: 1_000, side: ARB_SIDE_SELL, min_out: 0 };
+
+        // Buying and selling back in one batch is a wash trade past the cooldown
+        let before = markets.clone();
+        assert_eq!(Activity::execute_arb(&mut data, &mut markets, &[buy.clone(), sell.clone()], 100), Err(ERROR_SELL_COOLDOWN));
+        assert_eq!(markets[0].2, before[0].2);
+        assert_eq!(data.balance, 100_000);
+
+        Activity::execute_arb(&mut data, &mut markets, &[buy], 100).unwrap();
+        assert_eq!(Activity::execute_arb(&mut data, &mut markets, &[sell.clone()], 109), Err(ERROR_SELL_COOLDOWN));
+        assert!(Activity::execute_arb(&mut data, &mut markets, &[sell], 110).is_ok());
+    }
+
+    #[test]
+    fn test_auto_settle_claim_queues_withdrawal_instead_of_balance() {
+        let mut market = test_market();
+        market.apply_option(crate::market::MARKET_OPTION_AUTO_SETTLE_CLAIMS, 1).unwrap();
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+        market.resolve(true).unwrap();
+        let balance_before = data.balance;
+
+        // Without a registered address the claim cannot settle
+        let mut staged = (data.clone(), position.clone(), market.clone());
+        let settlement = Activity::execute_claim(&mut staged.0, &mut staged.1, &mut staged.2, [1, 1], 1).unwrap();
+        assert_eq!(Activity::auto_settle_payout(&mut staged.0, &staged.2, settlement.payout, 0), Err(ERROR_NO_WITHDRAW_ADDRESS));
+
+        data.withdraw_address = [0xabcd << 32, 7, 9];
+        let settlement = Activity::execute_claim(&mut data, &mut position, &mut market, [1, 1], 1).unwrap();
+        let (limbs, fee) = Activity::auto_settle_payout(&mut data, &market, settlement.payout, 0).unwrap().unwrap();
+        assert_eq!(limbs, [(0xabcd << 32) | settlement.payout, 7, 9]);
+        assert_eq!(fee, 0);
+        assert_eq!(data.balance, balance_before);
+
+        // Regular markets keep crediting the balance
+        market.auto_settle_claims = false;
+        assert_eq!(Activity::auto_settle_payout(&mut data, &market, settlement.payout, 0), Ok(None));
+    }
+
+    #[test]
+    fn test_deposit_after_claim_does_not_reopen_claim() {
+        let mut market = test_market();
+        let mut data = PlayerData::default();
+        data.add_balance(10_000).unwrap();
+        let mut position = PlayerMarketPosition::default();
+        Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 1000, 0).unwrap();
+        market.resolve(true).unwrap();
+
+        Activity::execute_claim(&mut data, &mut position, &mut market, [1, 2], 1).unwrap();
+        assert!(position.claimed);
+
+        // A later deposit only touches the balance, the claim flag stays set
+        data.add_balance(5_000).unwrap();
+        let balance = data.balance;
+        let claimed_total = market.total_claimed;
+        let result = Activity::execute_claim(&mut data, &mut position, &mut market, [1, 2], 1);
+        assert_eq!(result, Err(ERROR_ALREADY_CLAIMED));
+        assert_eq!(data.balance, balance);
+        assert_eq!(market.total_claimed, claimed_total);
+    }
+
+    #[test]
+    fn test_sells_and_claims_feed_the_pnl_leaderboard() {
+        let mut state = GlobalState::new();
+        let mut market = test_market();
+        let (alice, bob) = ([1, 0], [2, 0]);
+        let mut alice_data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let mut bob_data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let mut alice_position = PlayerMarketPosition::default();
+        let mut bob_position = PlayerMarketPosition::default();
+        let alice_shares = Activity::execute_bet(&mut alice_data, &mut alice_position, &mut market, 1, 1, 10_000, 0).unwrap();
+        Activity::execute_bet(&mut bob_data, &mut bob_position, &mut market, 1, 0, 10_000, 0).unwrap();
+
+        // Selling straight back loses the fee
+        let payout = Activity::execute_sell(&mut alice_data, &mut alice_position, &mut market, 1, 1, alice_shares, 0).unwrap();
+        assert_eq!(alice_data.realized_pnl, payout as i64 - 10_000);
+        state.update_pnl_leaderboard(alice, alice_data.realized_pnl);
+        assert_eq!(state.pnl_leaderboard, vec![(alice, alice_data.realized_pnl)]);
+
+        // Bob's winning claim books more than his cost and overtakes
+        market.resolve(false).unwrap();
+        let settlement = Activity::execute_claim(&mut bob_data, &mut bob_position, &mut market, bob, 1).unwrap();
+        assert_eq!(bob_data.realized_pnl, settlement.payout as i64 - 10_000);
+        state.update_pnl_leaderboard(bob, bob_data.realized_pnl);
+        assert_eq!(state.pnl_leaderboard, vec![(bob, bob_data.realized_pnl), (alice, alice_data.realized_pnl)]);
+    }
+
+    #[test]
+    fn test_slippage_guard_rolls_back_bet_and_sell() {
+        let mut market = test_market();
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let expected = market.calculate_shares(1, 10_000).unwrap();
+
+        // Asking for one share more than the pool gives leaves everything untouched
+        let market_before = market.clone();
+        let result = Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, expected + 1);
+        assert_eq!(result, Err(ERROR_SLIPPAGE_EXCEEDED));
+        assert_eq!(data.balance, 100_000);
+        assert!(data.active_markets.is_empty());
+        assert_eq!(position, PlayerMarketPosition::default());
+        assert_eq!(market.yes_liquidity, market_before.yes_liquidity);
+        assert_eq!(market.no_liquidity, market_before.no_liquidity);
+
+        let shares = Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, expected).unwrap();
+        assert_eq!(shares, expected);
+
+        // Sells are guarded the same way by their minimum payout
+        let payout = market.calculate_sell_details(1, shares).unwrap().0;
+        let held = position.clone();
+        let result = Activity::execute_sell(&mut data, &mut position, &mut market, 1, 1, shares, payout + 1);
+        assert_eq!(result, Err(ERROR_SLIPPAGE_EXCEEDED));
+        assert_eq!(position, held);
+        assert_eq!(Activity::execute_sell(&mut data, &mut position, &mut market, 1, 1, shares, payout), Ok(payout));
+    }
+
+    #[test]
+    fn test_only_admin_may_resolve_or_withdraw_fees() {
+        let mut state = GlobalState::new();
+        state.admin_id = [7, 7];
+        let stranger = [1, 2];
+        assert_eq!(Activity::Resolve(1, 1).check_authorized(&state, &stranger), Err(ERROR_UNAUTHORIZED));
+        assert_eq!(Activity::Resolve(1, 1).check_authorized(&state, &[7, 7]), Ok(()));
+        assert_eq!(Activity::Bet(1, 1, 100).check_authorized(&state, &stranger), Ok(()));
+
+        // Any caller but the recipient or the admin is turned away from the fees
+        let mut market = test_market();
+        market.place_bet(1, 10_000).unwrap();
+        market.fee_recipient = [3, 3];
+        assert_eq!(market.withdraw_fees(&stranger, &state.admin_id, [0, 0]), Err(ERROR_UNAUTHORIZED));
+        assert!(market.total_fees_collected > 0);
+        assert!(market.withdraw_fees(&state.admin_id, &state.admin_id, [0, 0]).is_ok());
+    }
+
+    #[test]
+    fn test_fees_withdrawn_to_a_treasury_pid() {
+        let (admin, treasury) = ([7, 7], [5, 5]);
+        let mut market = test_market();
+        market.fee_recipient = admin;
+        market.place_bet(1, 10_000).unwrap();
+        let fees = market.total_fees_collected;
+
+        // The handler credits the treasury's record, so the admin's own balance stays where it was
+        assert_eq!(market.fee_destination(treasury), treasury);
+        assert_eq!(market.fee_destination([0, 0]), admin);
+        let mut treasury_data = PlayerData::default();
+        assert_eq!(Activity::execute_withdraw_fees(&mut market, &admin, &admin, treasury, &mut treasury_data), Ok(fees));
+        assert_eq!(treasury_data.balance, fees);
+        assert_eq!(market.total_fees_collected, 0);
+
+        // A credit that cannot land leaves the fees on the market
+        market.place_bet(0, 10_000).unwrap();
+        let fees = market.total_fees_collected;
+        let mut full = PlayerData { balance: u64::MAX, ..PlayerData::default() };
+        assert_eq!(Activity::execute_withdraw_fees(&mut market, &admin, &admin, treasury, &mut full), Err(ERROR_OVERFLOW));
+        assert_eq!(market.total_fees_collected, fees);
+    }
+
+    #[test]
+    fn test_trading_and_resolution_are_gated_by_end_time() {
+        let mut market = test_market();
+        let end = market.end_time;
+
+        // Bets are open only before the end
+        assert!(market.is_active(end - 1));
+        assert!(!market.is_active(end));
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+
+        // Resolution is refused until the market has ended, in batches as well
+        assert_eq!(market.ensure_resolvable(end - 1), Err(ERROR_RESOLUTION_TOO_EARLY));
+        assert!(!market.can_resolve(end - 1));
+        let batch = vec![(1, Some(market.clone()), 1)];
+        assert_eq!(Activity::resolve_markets(batch.clone(), true, end - 1).err(), Some(ERROR_RESOLUTION_TOO_EARLY));
+        assert!(Activity::resolve_markets(batch.clone(), false, end - 1).unwrap().is_empty());
+
+        assert_eq!(market.ensure_resolvable(end), Ok(()));
+        let resolved = Activity::resolve_markets(batch, true, end).unwrap();
+        assert_eq!(resolved[0].1.outcome, Some(true));
+        market.resolve(true).unwrap();
+        assert_eq!(market.ensure_resolvable(end), Err(ERROR_MARKET_ALREADY_RESOLVED));
+    }
+
+    #[test]
+    fn test_second_claim_is_rejected_and_pays_nothing() {
+        let mut market = test_market();
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+        market.resolve(true).unwrap();
+
+        let settlement = Activity::execute_claim(&mut data, &mut position, &mut market, [1, 0], 1).unwrap();
+        assert!(settlement.payout > 0);
+        assert_eq!((position.yes_shares, position.no_shares), (0, 0));
+        assert_eq!(position.cost_basis(), Ok(0));
+
+        let balance = data.balance;
+        let claimed_before = market.total_claimed;
+        assert_eq!(Activity::execute_claim(&mut data, &mut position, &mut market, [1, 0], 1).err(), Some(ERROR_ALREADY_CLAIMED));
+        // Even with the flag cleared there are no shares left to pay out
+        position.claimed = false;
+        assert_eq!(Activity::execute_claim(&mut data, &mut position, &mut market, [1, 0], 1).err(), Some(ERROR_NO_WINNING_POSITION));
+        assert_eq!(data.balance, balance);
+        assert_eq!(market.total_claimed, claimed_before);
+    }
+
+    #[test]
+    fn test_claim_computation_leaves_global_state_unborrowed() {
+        let mut market = test_market();
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+        market.resolve(true).unwrap();
+
+        // A claim never reaches for the global state, so a caller may already hold it
+        let guard = GLOBAL_STATE.0.borrow_mut();
+        let settlement = Activity::execute_claim(&mut data, &mut position, &mut market, [1, 0], 1).unwrap();
+        drop(guard);
+
+        // ...and once the handler's own borrow is released, emitting the claim cannot double borrow
+        let txid = {
+            let mut state = GLOBAL_STATE.0.borrow_mut();
+            state.update_pnl_leaderboard([1, 0], data.realized_pnl);
+            state.txcounter
+        };
+        emit_claim_event(txid, [1, 0], 1, 0, 0, settlement.payout, 10);
+        assert!(GLOBAL_STATE.0.try_borrow_mut().is_ok());
+        let events = crate::event::clear_events(vec![]);
+        assert_eq!(events[0] >> 32, crate::event::EVENT_CLAIM);
+        assert_eq!(events[7], settlement.payout);
+    }
+
+    #[test]
+    fn test_credits_near_u64_max_fail_instead_of_wrapping() {
+        let mut near_max = PlayerData { balance: u64::MAX - 10, ..PlayerData::default() };
+        assert_eq!(near_max.add_balance(11), Err(ERROR_OVERFLOW));
+        assert_eq!(near_max.balance, u64::MAX - 10);
+        assert_eq!(near_max.add_balance(10), Ok(()));
+
+        // A sell whose payout would overflow the balance leaves everything as it was
+        let mut market = test_market();
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let shares = Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+        data.balance = u64::MAX - 1;
+        let before = market.clone();
+        let held = position.clone();
+        assert_eq!(Activity::execute_sell(&mut data, &mut position, &mut market, 1, 1, shares, 0), Err(ERROR_OVERFLOW));
+        assert_eq!(data.balance, u64::MAX - 1);
+        assert_eq!(position, held);
+        assert_market_unchanged(&market, &before);
+    }
+
+    #[test]
+    fn test_withdraw_high_bits_carry_address_not_amount() {
+        let withdraw = Withdraw { data: [(0xabcd_1234 << 32) | 500, 7, 9] };
+        assert_eq!(withdraw.amount(), 500);
+        assert_eq!(withdraw.address(), [0xabcd_1234 << 32, 7, 9]);
+        // The settled limbs are the address with the debited amount in the low bits
+        let address = withdraw.address();
+        assert_eq!([address[0] | withdraw.amount(), address[1], address[2]], withdraw.data);
+    }
+
+    #[test]
+    fn test_withdraw_fee_settles_the_net_amount() {
+        let withdraw = Withdraw { data: [(0xabcd_1234 << 32) | 10_000, 7, 9] };
+        let flushed = |info: zkwasm_rest_abi::WithdrawInfo| {
+            let mut settlement = crate::settlement::SettlementInfo::default();
+            settlement.push(info).unwrap();
+            settlement.flush()
+        };
+
+        // No fee settles exactly what was asked for
+        assert_eq!(withdraw.split_fee(0), Ok((10_000, 0)));
+        assert_eq!(flushed(withdraw.withdraw_info(10_000)), flushed(zkwasm_rest_abi::WithdrawInfo::new(&withdraw.data, 0)));
+
+        // A 2.5% fee settles the rest to the same address and is counted as a protocol fee
+        let (net, fee) = withdraw.split_fee(250).unwrap();
+        assert_eq!((net, fee), (9_750, 250));
+        let expected = zkwasm_rest_abi::WithdrawInfo::new(&[(0xabcd_1234 << 32) | 9_750, 7, 9], 0);
+        assert_eq!(flushed(withdraw.withdraw_info(net)), flushed(expected));
+        let mut state = GlobalState::new();
+        state.record_withdrawal(net).unwrap();
+        state.record_protocol_fee(fee).unwrap();
+        assert_eq!((state.total_withdrawn, state.protocol_fees), (9_750, 250));
+
+        // A fee that rounds down to nothing is not charged
+        assert_eq!(Withdraw { data: [(1 << 32) | 3, 0, 0] }.split_fee(250), Ok((3, 0)));
+    }
+
+    #[test]
+    fn test_bets_straddling_market_limits_are_rejected_atomically() {
+        let mut market = test_market();
+        market.max_bet_amount = 20_000;
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let before = market.clone();
+        assert_eq!(Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 20_001, 0), Err(ERROR_POSITION_LIMIT_EXCEEDED));
+        assert_eq!(data.balance, 100_000);
+        assert_market_unchanged(&market, &before);
+
+        // The second bet would take the position just past the cap
+        let first = Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+        market.max_position = first + market.quote_bet(1, 10_000).unwrap() - 1;
+        let (balance, held, before) = (data.balance, position.clone(), market.clone());
+        assert_eq!(Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0), Err(ERROR_POSITION_LIMIT_EXCEEDED));
+        assert_eq!(data.balance, balance);
+        assert_eq!(position, held);
+        assert_market_unchanged(&market, &before);
+
+        // The other side and smaller bets stay open; zero means unlimited
+        assert!(Activity::execute_bet(&mut data, &mut position, &mut market, 1, 0, 10_000, 0).is_ok());
+        market.max_position = 0;
+        assert!(Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).is_ok());
+    }
+
+    #[test]
+    fn test_sell_is_held_back_until_cooldown_elapses() {
+        let mut market = test_market();
+        market.sell_cooldown = 10;
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let shares = Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+        position.start_sell_cooldown(1, 100, market.sell_cooldown).unwrap();
+
+        assert_eq!(position.check_sell_cooldown(1, 109), Err(ERROR_SELL_COOLDOWN));
+        // Only the side just bought is held back
+        assert_eq!(position.check_sell_cooldown(0, 100), Ok(()));
+        assert_eq!(position.check_sell_cooldown(1, 110), Ok(()));
+        assert!(Activity::execute_sell(&mut data, &mut position, &mut market, 1, 1, shares, 0).unwrap() > 0);
+
+        // Without a cooldown a bet never blocks a sell
+        position.start_sell_cooldown(0, 200, 0).unwrap();
+        assert_eq!(position.check_sell_cooldown(0, 200), Ok(()));
+    }
+
+    #[test]
+    fn test_spent_and_received_accumulate_over_bet_and_sell() {
+        let mut market = test_market();
+        let mut player = Player::new_from_pid([1, 2]);
+        player.data.balance = 100_000;
+        let mut position = PlayerMarketPosition::default();
+        let shares = Activity::execute_bet(&mut player.data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+        let payout = Activity::execute_sell(&mut player.data, &mut position, &mut market, 1, 1, shares, 0).unwrap();
+        assert_eq!(player.data.total_spent, 10_000);
+        assert_eq!(player.data.total_received, payout);
+
+        // Selling straight back loses the fees, which shows up as a realized loss
+        let loss = (10_000 - payout) as i64;
+        assert_eq!(player.data.realized_pnl, -loss);
+        assert_eq!(Query::Pnl.query(&player), Ok(vec![10_000, payout, 1, loss as u64]));
+    }
+
+    #[test]
+    fn test_bets_outside_min_and_max_are_rejected_before_spending() {
+        let mut market = test_market();
+        market.min_bet_amount = 1_000;
+        market.max_bet_amount = 20_000;
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        assert_eq!(Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 999, 0), Err(ERROR_INVALID_BET_AMOUNT));
+        assert_eq!(Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 20_001, 0), Err(ERROR_POSITION_LIMIT_EXCEEDED));
+        assert_eq!(data.balance, 100_000);
+
+        // Both bounds are inclusive
+        assert!(Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 1_000, 0).is_ok());
+        assert!(Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 20_000, 0).is_ok());
+    }
+
+    #[test]
+    fn test_claim_and_withdraw_settles_without_touching_balance() {
+        let mut market = test_market();
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+        market.resolve(true).unwrap();
+        let balance_before = data.balance;
+
+        let settlement = Activity::execute_claim(&mut data, &mut position, &mut market, [1, 1], 1).unwrap();
+        // Any amount bits the client left in the address are replaced by the payout
+        let (limbs, fee) = Activity::withdraw_payout(&mut data, [(0x1234 << 32) | 99, 5, 6], settlement.payout, 0).unwrap();
+        assert_eq!(limbs, [(0x1234 << 32) | settlement.payout, 5, 6]);
+        assert_eq!(fee, 0);
+        assert_eq!(data.balance, balance_before);
+
+        // The position is spent, so the winnings cannot be claimed into the balance afterwards
+        assert_eq!(Activity::execute_claim(&mut data, &mut position, &mut market, [1, 1], 1).err(), Some(ERROR_ALREADY_CLAIMED));
+        data.frozen = true;
+        assert_eq!(Activity::ClaimAndWithdraw(1, [0; 3]).check_frozen(&data), Err(ERROR_PLAYER_FROZEN));
+    }
+
+    #[test]
+    fn test_quote_execution_rejects_a_short_fill_within_price_tolerance() {
+        let mut market = test_market();
+        let quote = Quote::new([1, 1], 1, &market, 1, 100_000, 100).unwrap();
+        let mut data = PlayerData { balance: 200_000, ..PlayerData::default() };
+
+        // A small move still fills at the quote
+        let mut staged = market.clone();
+        staged.place_bet(1, 500).unwrap();
+        assert_eq!(quote.validate(&[1, 1], &staged, 100), Ok(()));
+        let mut position = PlayerMarketPosition::default();
+        assert!(Activity::execute_bet(&mut data.clone(), &mut position, &mut staged, 1, 1, 100_000, quote.min_shares()).is_ok());
+
+        // A larger one keeps both prices within tolerance but buys fewer shares than allowed
+        market.place_bet(1, 1_000).unwrap();
+        assert_eq!(quote.validate(&[1, 1], &market, 100), Ok(()));
+        let mut position = PlayerMarketPosition::default();
+        assert_eq!(
+            Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 100_000, quote.min_shares()),
+            Err(ERROR_SLIPPAGE_EXCEEDED)
+        );
+        assert_eq!(data.balance, 200_000);
+    }
+
+    #[test]
+    fn test_claim_to_l1_pays_the_withdrawal_fee() {
+        let mut market = test_market();
+        market.apply_option(crate::market::MARKET_OPTION_AUTO_SETTLE_CLAIMS, 1).unwrap();
+        let mut position = PlayerMarketPosition::default();
+        let mut data = PlayerData { balance: 100_000, withdraw_address: [0xabcd << 32, 7, 9], ..PlayerData::default() };
+        Activity::execute_bet(&mut data, &mut position, &mut market, 1, 1, 10_000, 0).unwrap();
+        market.resolve(true).unwrap();
+        let balance_before = data.balance;
+        let settlement = Activity::execute_claim(&mut data, &mut position, &mut market, [1, 1], 1).unwrap();
+
+        // Both routes to L1 settle the payout less 1% and debit the whole payout
+        let fee = settlement.payout / 100;
+        let mut direct = data.clone();
+        let (limbs, direct_fee) = Activity::withdraw_payout(&mut direct, [0x1234 << 32, 5, 6], settlement.payout, 100).unwrap();
+        assert_eq!(limbs, [(0x1234 << 32) | (settlement.payout - fee), 5, 6]);
+        assert_eq!(direct_fee, fee);
+        assert_eq!(direct.balance, balance_before);
+
+        let (limbs, auto_fee) = Activity::auto_settle_payout(&mut data, &market, settlement.payout, 100).unwrap().unwrap();
+        assert_eq!(limbs, [(0xabcd << 32) | (settlement.payout - fee), 7, 9]);
+        assert_eq!(auto_fee, fee);
+        assert_eq!(data.balance, balance_before);
+    }
+
+    #[test]
+    fn test_bets_fill_resting_orders_below_the_pool_price_first() {
+        let mut market = test_market();
+        let mut book = OrderBook::default();
+        let maker = [7, 7];
+        let cheap = book.place(maker, 1, 400_000, 1_000, 300).unwrap();
+        let dear = book.place(maker, 1, 900_000, 1_000, 300).unwrap();
+        let mut data = PlayerData { balance: 10_000, ..PlayerData::default() };
+        let mut position = PlayerMarketPosition::default();
+
+        // Nothing moves if the combined fill misses the minimum
+        let book_before = book.clone();
+        let result = Activity::execute_book_bet(&mut data, &mut position, &mut market, &mut book, [1, 1], 1, 1, 1_000, u64::MAX);
+        assert_eq!(result.err(), Some(ERROR_SLIPPAGE_EXCEEDED));
+        assert_eq!((data.balance, book.clone()), (10_000, book_before));
+
+        // The order at 0.4 fills entirely, the one above the pool price rests, the rest hits the AMM
+        let amm_shares = test_market().calculate_shares(1, 600).unwrap();
+        let (shares, spent, fills) = Activity::execute_book_bet(&mut data, &mut position, &mut market, &mut book, [1, 1], 1, 1, 1_000, 0).unwrap();
+        assert_eq!((shares, spent), (1_000 + amm_shares, 1_000));
+        assert_eq!(fills.len(), 1);
+        let (fill, proceeds) = &fills[0];
+        assert_eq!((fill.order_id, fill.cost, fill.released_basis), (cheap, 400, 300));
+        assert_eq!(*proceeds, 400 - test_market().collect_fill_fee(1, 400).unwrap());
+        assert_eq!((data.balance, position.yes_shares, position.yes_cost), (9_000, shares, 1_000));
+        assert_eq!(book.orders.iter().map(|order| order.id).collect::<Vec<_>>(), vec![dear]);
+
+        let mut maker_data = PlayerData::default();
+        Activity::settle_fill(&mut maker_data, fill, *proceeds).unwrap();
+        assert_eq!(maker_data.balance, *proceeds);
+        assert_eq!(maker_data.realized_pnl, *proceeds as i64 - 300);
+    }
+
+    #[test]
+    fn test_claim_takes_back_resting_orders_so_a_later_cancel_finds_nothing() {
+        let mut market = test_market();
+        let mut book = OrderBook::default();
+        let (maker, market_id) = ([7, 7], 1);
+        let mut data = PlayerData { balance: 10_000, ..PlayerData::default() };
+        let mut position = PlayerMarketPosition::default();
+        let shares = Activity::execute_bet(&mut data, &mut position, &mut market, market_id, 1, 10_000, 0).unwrap();
+        let order_id = Activity::execute_place_order(&mut position, &mut book, maker, 1, 900_000, shares / 2).unwrap();
+        assert_eq!(position.yes_shares, shares - shares / 2);
+
+        market.resolve(true).unwrap();
+        let returned = Activity::return_resting_orders(&mut position, &mut book, maker).unwrap();
+        assert_eq!(returned.iter().map(|order| order.id).collect::<Vec<_>>(), vec![order_id]);
+        assert_eq!((position.yes_shares, position.yes_cost), (shares, 10_000));
+        Activity::execute_claim(&mut data, &mut position, &mut market, maker, market_id).unwrap();
+
+        // Every winning share was paid, so the market is settled and nothing is left to cancel
+        assert!(market.is_fully_settled());
+        assert_eq!(book.cancel(&maker, order_id), Err(ERROR_ORDER_NOT_FOUND));
+    }
+
+    #[test]
+    fn test_categorical_bets_share_one_pool_paid_to_the_winner() {
+        let title = MarketData::string_to_u64_vec("Who wins?");
+        let mut market = CategoricalMarket::new(title, 0, 1000, 1000, vec![1_000_000; 3]).unwrap();
+        let mut players = (0..3)
+            .map(|_| (PlayerData { balance: 100_000, ..PlayerData::default() }, CategoricalPosition::new(3)))
+            .collect::<Vec<_>>();
+
+        // A bet short of its minimum leaves balance, position and market untouched
+        let expected = market.quote_bet(0, 30_000).unwrap();
+        let (data, position) = &mut players[0];
+        let before = market.clone();
+        assert_eq!(Activity::execute_bet_outcome(data, position, &mut market, 0, 30_000, expected + 1), Err(ERROR_SLIPPAGE_EXCEEDED));
+        assert_eq!((data.balance, position.clone(), &market), (100_000, CategoricalPosition::new(3), &before));
+        assert_eq!(Activity::execute_bet_outcome(data, position, &mut market, 3, 30_000, 0), Err(ERROR_INVALID_BET_TYPE));
+
+        let mut fees = 0;
+        for (outcome, ((data, position), amount)) in players.iter_mut().zip([30_000, 20_000, 10_000]).enumerate() {
+            let (shares, fee) = Activity::execute_bet_outcome(data, position, &mut market, outcome as u64, amount, 0).unwrap();
+            assert_eq!((data.balance, position.shares[outcome], position.costs[outcome]), (100_000 - amount, shares, amount));
+            fees += fee;
+        }
+        assert_eq!(market.prize_pool, 60_000 - fees);
+
+        // Claims wait for the resolution, then the only backer of the winner takes the whole pool
+        let (data, position) = &mut players[1];
+        assert_eq!(Activity::execute_categorical_claim(data, position, &mut market), Err(ERROR_MARKET_NOT_RESOLVED));
+        market.resolve(1, 1000).unwrap();
+        let pool = market.prize_pool;
+        assert_eq!(Activity::execute_categorical_claim(data, position, &mut market), Ok(pool));
+        assert_eq!((data.balance, data.realized_pnl), (80_000 + pool, pool as i64 - 20_000));
+        assert_eq!(Activity::execute_categorical_claim(data, position, &mut market), Err(ERROR_ALREADY_CLAIMED));
+
+        // Backers of the losing outcomes were the ones who paid for it
+        let (data, position) = &mut players[0];
+        assert_eq!(Activity::execute_categorical_claim(data, position, &mut market), Err(ERROR_NO_WINNING_POSITION));
+        assert_eq!(data.balance, 70_000);
+    }
+
+    #[test]
+    fn test_selling_an_outcome_credits_the_payout_and_releases_its_basis() {
+        let title = MarketData::string_to_u64_vec("Which month?");
+        let mut market = CategoricalMarket::new(title, 0, 1000, 1000, vec![1_000_000; 12]).unwrap();
+        let mut data = PlayerData { balance: 100_000, ..PlayerData::default() };
+        let mut position = CategoricalPosition::new(12);
+        let (shares, _) = Activity::execute_bet_outcome(&mut data, &mut position, &mut market, 7, 40_000, 0).unwrap();
+
+        // A sell short of its minimum, or of more than is held, changes nothing
+        let (expected, fee) = market.quote_sell(7, shares / 2).unwrap();
+        let before = (data.balance, position.clone(), market.clone());
+        assert_eq!(Activity::execute_sell_outcome(&mut data, &mut position, &mut market, 7, shares / 2, expected + 1), Err(ERROR_SLIPPAGE_EXCEEDED));
+        assert_eq!(Activity::execute_sell_outcome(&mut data, &mut position, &mut market, 6, 1, 0), Err(ERROR_INSUFFICIENT_BALANCE));
+        assert_eq!((data.balance, position.clone(), market.clone()), before);
+
+        // Half the shares carry half the basis, and the difference is booked as PnL
+        assert_eq!(Activity::execute_sell_outcome(&mut data, &mut position, &mut market, 7, shares / 2, expected), Ok((expected, fee)));
+        let released = 40_000 - position.costs[7];
+        assert_eq!(released, 40_000 * (shares / 2) / shares);
+        assert_eq!((data.balance, data.realized_pnl), (60_000 + expected, expected as i64 - released as i64));
+        assert_eq!((position.shares[7], market.total_shares[7]), (shares - shares / 2, shares - shares / 2));
+    }
+}
@@ -40,6 +40,90 @@ pub const EVENT_PLAYER_UPDATE: u64 = 3;
 // Market constants
 pub const PLATFORM_FEE_RATE: u64 = 100; // 1% platform fee (100/10000)
 
+// Portion of each trade fee paid to liquidity providers while a market has any (basis points)
+pub const DEFAULT_LP_FEE_SHARE: u64 = 5000; // 50%
+
+// Bets that move the AMM reserves toward balance get a rebate, bets that widen the gap pay a surcharge
+pub const MAKER_REBATE_RATE: u64 = 50;     // 0.5% off the platform fee (capped at the fee)
+pub const TAKER_SURCHARGE_RATE: u64 = 50;  // 0.5% on top of the platform fee
+
+// Where payout rounding dust goes unless MARKET_OPTION_REMAINDER_POLICY says otherwise:
+// 0 = leave in pool, 1 = protocol fees, 2 = largest winning holder
+pub const DEFAULT_REMAINDER_POLICY: u64 = 0;
+
+// Oracle submissions needed before a scalar market resolves to their median
+pub const DEFAULT_ORACLE_QUORUM: u64 = 3;
+
+// Players kept on the realized PnL leaderboard
+pub const PNL_LEADERBOARD_SIZE: usize = 10;
+
+// Named sub-balances a player may hold besides the main balance (sub-account 0)
+pub const MAX_SUB_ACCOUNTS: usize = 8;
+
+// Flat fee charged on every state-changing activity to fund proving costs (0 = off)
+pub const DEFAULT_OPERATION_FEE: u64 = 0;
+// Activity categories the operation fee can be waived for, as a bitmask
+pub const OPERATION_FEE_WAIVE_CLAIMS: u64 = 1 << 0;   // Claim, ClaimLpFees
+pub const OPERATION_FEE_WAIVE_ADMIN: u64 = 1 << 1;    // Resolution, market creation and other admin commands
+pub const OPERATION_FEE_WAIVE_TRADING: u64 = 1 << 2;  // Everything else
+pub const DEFAULT_OPERATION_FEE_WAIVERS: u64 = OPERATION_FEE_WAIVE_CLAIMS | OPERATION_FEE_WAIVE_ADMIN;
+
+// Total bond staked against a resolution at which it is escalated to the admin for a final decision
+pub const DISPUTE_ESCALATION_STAKE: u64 = 100_000;
+
+// Ticks after a CommitBet during which it may be revealed; a reveal in the commit's own tick is refused.
+// MARKET_OPTION_REVEAL_WINDOW overrides it per market
+pub const DEFAULT_BET_REVEAL_WINDOW: u64 = 20;
+
+// Client tags remembered per player, so a resubmitted tagged command returns its first result
+pub const CLIENT_TAG_HISTORY: usize = 8;
+
+// Share of each withdrawal kept by the protocol, in basis points (0 = off)
+pub const DEFAULT_WITHDRAW_FEE_BPS: u64 = 0;
+pub const MAX_WITHDRAW_FEE_BPS: u64 = 1000; // 10%
+
+// Maximum number of markets a player can hold positions in at once (0 = unlimited)
+pub const MAX_POSITIONS_PER_PLAYER: u64 = 32;
+
+// Most outcomes a categorical market may have, bounding the reserves every trade prices
+pub const MAX_MARKET_OUTCOMES: usize = 16;
+
+// Furthest ahead of the current counter a new market may start trading
+pub const MAX_MARKET_START_HORIZON: u64 = 17280 * 90; // 90 days at 5s per tick
+
+// Highest trading fee a market can be created with (10%)
+pub const MAX_MARKET_FEE_BPS: u64 = 1000;
+
+// Minimum counter ticks between a resolution proposal and its finalization; MARKET_OPTION_RESOLUTION_DELAY overrides it per market
+pub const DEFAULT_RESOLUTION_DELAY: u64 = 720; // 1 hour at 5s per tick
+
+// Resting limit orders one market's book may hold, bounding the matching done per bet
+pub const MAX_ORDERS_PER_MARKET: usize = 64;
+// Resting orders one player may hold in one market, so no single account can fill the book
+pub const MAX_ORDERS_PER_OWNER: usize = 8;
+
+// Most activities one BatchActivity may carry, bounding the markets it loads and stores
+pub const MAX_BATCH_ACTIVITIES: usize = 16;
+
+// Quotes stay executable for this many ticks while prices stay within the drift tolerance
+pub const QUOTE_VALIDITY_TICKS: u64 = 12;       // 1 minute
+pub const QUOTE_DRIFT_TOLERANCE_BPS: u64 = 10;  // 0.1% per outcome price
+
+// Trade size the marginal price impact metric is measured over
+pub const MARGINAL_IMPACT_STEP: u64 = 1_000;
+
+// Number of largest holders tracked per outcome on each market
+pub const TOP_HOLDERS_TRACKED: usize = 5;
+
+// Per-transaction event buffer size in u64 words, oldest events are dropped beyond this
+pub const EVENT_BUFFER_CAPACITY: usize = 4096;
+
+// Withdrawals a block may queue for settlement before further ones are rejected
+pub const MAX_SETTLEMENTS_PER_BLOCK: usize = 256;
+
+// Leading byte of every flushed settlement buffer, bumped whenever the encoding changes
+pub const SETTLEMENT_FORMAT_VERSION: u8 = 1;
+
 // New player initial balance
 pub const NEW_PLAYER_INITIAL_BALANCE: u64 = 10_000_000; // 1000万代币
 
@@ -47,6 +131,7 @@ pub const NEW_PLAYER_INITIAL_BALANCE: u64 = 10_000_000; // 1000万代币
 pub const BASIS_POINTS_PRECISION: u64 = 10000;     // 10,000 basis points = 100%
 pub const PRICE_PRECISION: u64 = 1000000;          // 1,000,000 = 1.0 (higher precision for calculations)
 pub const PERCENTAGE_PRECISION: u64 = 100;         // 100 = 100%
+pub const PAYOUT_MULTIPLE_PRECISION: u64 = 1000;   // 1000 = 1.0x
 
 // Conversion helpers
 pub const BASIS_POINTS_TO_PRICE: u64 = PRICE_PRECISION / BASIS_POINTS_PRECISION; // 100
@@ -14,6 +14,36 @@ pub const ERROR_INVALID_MARKET_TIME: u32 = 1012;
 pub const ERROR_PLAYER_NOT_EXIST: u32 = 6;
 pub const ERROR_PLAYER_ALREADY_EXISTS: u32 = 8;
 pub const ERROR_NO_FEES_TO_WITHDRAW: u32 = 1013;
+pub const ERROR_TOO_MANY_POSITIONS: u32 = 1014;
+pub const ERROR_RESOLUTION_TOO_EARLY: u32 = 1015;
+pub const ERROR_NO_PENDING_RESOLUTION: u32 = 1016;
+pub const ERROR_QUOTE_EXPIRED: u32 = 1017;
+pub const ERROR_LIQUIDITY_CAP_REACHED: u32 = 1018;
+pub const ERROR_INVALID_MARKET_OPTION: u32 = 1019;
+pub const ERROR_PLAYER_FROZEN: u32 = 1020;
+pub const ERROR_SLIPPAGE_EXCEEDED: u32 = 1021;
+pub const ERROR_NO_WITHDRAW_ADDRESS: u32 = 1022;
+pub const ERROR_NO_PARTICIPANTS_ON_OUTCOME: u32 = 1023;
+pub const ERROR_TOO_MANY_SUB_ACCOUNTS: u32 = 1024;
+pub const ERROR_INSUFFICIENT_ORACLE_DATA: u32 = 1025;
+pub const ERROR_POSITION_LIMIT_EXCEEDED: u32 = 1026;
+pub const ERROR_SETTLEMENT_FULL: u32 = 1027;
+pub const ERROR_MARKET_PAUSED: u32 = 1028;
+pub const ERROR_SELL_COOLDOWN: u32 = 1029;
+pub const ERROR_INVALID_NONCE: u32 = 1030;
+pub const ERROR_INSOLVENT: u32 = 1031;
+pub const ERROR_INVALID_FEE: u32 = 1032;
+pub const ERROR_CLAIM_NOT_OPEN: u32 = 1033;
+pub const ERROR_PRICING_UNSUPPORTED: u32 = 1034;
+pub const ERROR_ORDER_NOT_FOUND: u32 = 1035;
+pub const ERROR_ORDER_BOOK_FULL: u32 = 1036;
+pub const ERROR_COMMITMENT_NOT_FOUND: u32 = 1037;
+pub const ERROR_COMMITMENT_MISMATCH: u32 = 1038;
+pub const ERROR_NO_DISPUTE_BOND: u32 = 1039;
+pub const ERROR_CLAIM_EXPIRED: u32 = 1040;
+pub const ERROR_CLAIM_PERIOD_ACTIVE: u32 = 1041;
+pub const ERROR_NOTHING_TO_SWEEP: u32 = 1042;
+pub const ERROR_NOT_BATCHABLE: u32 = 1044;
 
 // Security-related error codes
 pub const ERROR_OVERFLOW: u32 = 100;
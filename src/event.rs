@@ -1,23 +1,66 @@
 use zkwasm_rest_abi::StorageData;
 use zkwasm_rest_convention::IndexedObject;
 use std::convert::From;
-use crate::market::MarketData;
+use crate::market::{MarketData, MarketPhase};
+use crate::config::EVENT_BUFFER_CAPACITY;
 
 /// External Events that are handled by external handler
-pub static mut EVENTS: Vec<u64> = vec![];
+pub static mut EVENTS: EventBuffer = EventBuffer::new(EVENT_BUFFER_CAPACITY);
+
+/// Bounded event buffer that drops the oldest whole events once full
+pub struct EventBuffer {
+    data: Vec<u64>,
+    capacity: usize,
+    dropped: u64,
+}
+
+impl EventBuffer {
+    pub const fn new(capacity: usize) -> Self {
+        EventBuffer { data: Vec::new(), capacity, dropped: 0 }
+    }
+
+    pub fn insert(&mut self, typ: u64, data: &mut Vec<u64>) {
+        let size = 1 + data.len();
+        if size > self.capacity {
+            // Can never fit, drop the event itself
+            self.dropped += 1;
+            data.clear();
+            return;
+        }
+        while self.data.len() + size > self.capacity {
+            let oldest = 1 + (self.data[0] & 0xffffffff) as usize;
+            self.data.drain(..oldest);
+            self.dropped += 1;
+        }
+        self.data.push((typ << 32) + data.len() as u64);
+        self.data.append(data);
+    }
+
+    pub fn drain(&mut self) -> Vec<u64> {
+        std::mem::take(&mut self.data)
+    }
+
+    /// Number of events dropped since the last call
+    pub fn take_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped)
+    }
+}
 
 pub fn clear_events(a: Vec<u64>) -> Vec<u64> {
     let mut c = a;
     unsafe {
-        c.append(&mut EVENTS);
+        c.append(&mut EVENTS.drain());
     }
-    return c;
+    c
+}
+
+pub fn take_dropped_events() -> u64 {
+    unsafe { EVENTS.take_dropped() }
 }
 
 pub fn insert_event(typ: u64, data: &mut Vec<u64>) {
     unsafe {
-        EVENTS.push((typ << 32) + data.len() as u64);
-        EVENTS.append(data);
+        EVENTS.insert(typ, data);
     }
 }
 
@@ -26,6 +69,19 @@ pub const EVENT_PLAYER_UPDATE: u64 = 1;
 pub const EVENT_MARKET_UPDATE: u64 = 2;
 pub const EVENT_BET_UPDATE: u64 = 3;
 pub const EVENT_INDEXED_OBJECT: u64 = 4;
+pub const EVENT_QUERY_RESULT: u64 = 5;
+pub const EVENT_MARKET_STATE_CHANGE: u64 = 6;
+pub const EVENT_QUOTE: u64 = 7;
+pub const EVENT_TICK_SUMMARY: u64 = 8;
+pub const EVENT_PLAYER_FROZEN: u64 = 9;
+pub const EVENT_MARKET_CREATED: u64 = 10;
+pub const EVENT_RESOLVE: u64 = 11;
+pub const EVENT_CLAIM: u64 = 12;
+pub const EVENT_DEPOSIT: u64 = 13;
+pub const EVENT_WITHDRAW: u64 = 14;
+pub const EVENT_SELL_UPDATE: u64 = 15;
+pub const EVENT_ORDER_UPDATE: u64 = 16;
+pub const EVENT_ORDER_FILL: u64 = 17;
 
 // Market info constants for IndexedObject
 pub const MARKET_INFO: u64 = 1;
@@ -127,3 +183,133 @@ pub fn emit_liquidity_history(market_id: u64, counter: u64, yes_liquidity: u64,
     
     insert_event(EVENT_INDEXED_OBJECT, &mut data);
 }
+
+// [market_id, start_time, end_time, resolution_time, fee_bps, question_hash * 4]
+pub fn market_created_data(market_id: u64, market: &MarketData) -> Vec<u64> {
+    let mut data = vec![market_id, market.start_time, market.end_time, market.resolution_time, market.fee_bps];
+    data.extend_from_slice(&market.question_hash);
+    data
+}
+
+pub fn emit_market_created(market_id: u64, market: &MarketData) {
+    insert_event(EVENT_MARKET_CREATED, &mut market_created_data(market_id, market));
+}
+
+// [txid, market_id, outcome, no_weight, yes_weight, counter], weights in basis points
+pub fn resolve_event_data(txid: u64, market_id: u64, market: &MarketData, counter: u64) -> Vec<u64> {
+    let [no_weight, yes_weight] = market.settled_weights().unwrap_or([0, 0]);
+    let outcome = if market.outcome == Some(true) { 1 } else { 0 };
+    vec![txid, market_id, outcome, no_weight, yes_weight, counter]
+}
+
+pub fn emit_resolve_event(txid: u64, market_id: u64, market: &MarketData, counter: u64) {
+    insert_event(EVENT_RESOLVE, &mut resolve_event_data(txid, market_id, market, counter));
+}
+
+// [txid, pid0, pid1, market_id, yes_shares, no_shares, payout, counter], shares as held at claim time
+pub fn emit_claim_event(txid: u64, player_id: [u64; 2], market_id: u64, yes_shares: u64, no_shares: u64, payout: u64, counter: u64) {
+    let mut data = vec![txid, player_id[0], player_id[1], market_id, yes_shares, no_shares, payout, counter];
+    insert_event(EVENT_CLAIM, &mut data);
+}
+
+// [txid, pid0, pid1, market_id, sell_type, shares, payout, counter], sell_type is 0 = NO, 1 = YES
+pub fn sell_event_data(txid: u64, player_id: [u64; 2], market_id: u64, sell_type: u64, shares: u64, payout: u64, counter: u64) -> Vec<u64> {
+    vec![txid, player_id[0], player_id[1], market_id, sell_type, shares, payout, counter]
+}
+
+pub fn emit_sell_event(txid: u64, player_id: [u64; 2], market_id: u64, sell_type: u64, shares: u64, payout: u64, counter: u64) {
+    insert_event(EVENT_SELL_UPDATE, &mut sell_event_data(txid, player_id, market_id, sell_type, shares, payout, counter));
+}
+
+// [market_id, order_id, owner0, owner1, outcome, price, shares], shares left resting (0 once cancelled)
+pub fn emit_order_event(market_id: u64, order_id: u64, owner: [u64; 2], outcome: u64, price: u64, shares: u64) {
+    let mut data = vec![market_id, order_id, owner[0], owner[1], outcome, price, shares];
+    insert_event(EVENT_ORDER_UPDATE, &mut data);
+}
+
+// [txid, market_id, order_id, maker0, maker1, taker0, taker1, shares, cost, remaining, counter]
+#[allow(clippy::too_many_arguments)]
+pub fn emit_fill_event(txid: u64, market_id: u64, order_id: u64, maker: [u64; 2], taker: [u64; 2], shares: u64, cost: u64, remaining: u64, counter: u64) {
+    let mut data = vec![txid, market_id, order_id, maker[0], maker[1], taker[0], taker[1], shares, cost, remaining, counter];
+    insert_event(EVENT_ORDER_FILL, &mut data);
+}
+
+// Deposits and withdrawals share one layout: [txid, pid0, pid1, amount, counter]
+pub fn emit_balance_transfer_event(event_type: u64, txid: u64, player_id: [u64; 2], amount: u64, counter: u64) {
+    let mut data = vec![txid, player_id[0], player_id[1], amount, counter];
+    insert_event(event_type, &mut data);
+}
+
+// Payload for a lifecycle transition, None when the phase did not change
+pub fn market_state_change_data(market_id: u64, old: Option<MarketPhase>, new: MarketPhase, counter: u64) -> Option<Vec<u64>> {
+    if old == Some(new) {
+        return None;
+    }
+    Some(vec![market_id, old.map_or(0, |phase| phase.to_u64()), new.to_u64(), counter])
+}
+
+pub fn emit_market_state_change(market_id: u64, old: Option<MarketPhase>, new: MarketPhase, counter: u64) {
+    if let Some(mut data) = market_state_change_data(market_id, old, new, counter) {
+        insert_event(EVENT_MARKET_STATE_CHANGE, &mut data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_market_created_event_carries_times_fee_and_hash() {
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            MarketData::string_to_u64_vec("Created"), "Created".to_string(), 10, 1000, 1200, 1_000_000, 1_000_000
+        ).unwrap();
+        market.fee_bps = 250;
+        market.question_hash = [1, 2, 3, 4];
+        assert_eq!(market_created_data(7, &market), vec![7, 10, 1000, 1200, 250, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_resolve_event_reports_settled_weights() {
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            MarketData::string_to_u64_vec("Resolve"), "Resolve".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        market.resolve(true).unwrap();
+        assert_eq!(resolve_event_data(5, 3, &market, 1200), vec![5, 3, 1, 0, 10_000, 1200]);
+
+        let mut partial = MarketData::new_with_title_u64_and_liquidity(
+            MarketData::string_to_u64_vec("Split"), "Split".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        partial.resolve_distribution(vec![3000, 7000]).unwrap();
+        assert_eq!(resolve_event_data(6, 4, &partial, 1300), vec![6, 4, 1, 3000, 7000, 1300]);
+    }
+
+    #[test]
+    fn test_sell_event_has_its_own_type_and_raw_side() {
+        let mut buffer = EventBuffer::new(16);
+        buffer.insert(EVENT_SELL_UPDATE, &mut sell_event_data(9, [1, 2], 3, 1, 500, 480, 77));
+        let data = buffer.drain();
+        assert_eq!(data[0] >> 32, EVENT_SELL_UPDATE);
+        assert_eq!(&data[1..], &[9, 1, 2, 3, 1, 500, 480, 77]);
+        assert_eq!(sell_event_data(9, [1, 2], 3, 0, 500, 480, 77)[4], 0);
+    }
+
+    #[test]
+    fn test_full_buffer_drops_oldest_events() {
+        // Room for two events of three payload words each
+        let mut buffer = EventBuffer::new(8);
+        for i in 0..4u64 {
+            buffer.insert(EVENT_BET_UPDATE, &mut vec![i, i, i]);
+        }
+        assert_eq!(buffer.take_dropped(), 2);
+        assert_eq!(buffer.take_dropped(), 0);
+
+        // The two newest events survive intact
+        let header = (EVENT_BET_UPDATE << 32) + 3;
+        assert_eq!(buffer.drain(), vec![header, 2, 2, 2, header, 3, 3, 3]);
+
+        // An event larger than the whole buffer is dropped on its own
+        buffer.insert(EVENT_BET_UPDATE, &mut vec![0; 8]);
+        assert_eq!(buffer.take_dropped(), 1);
+        assert!(buffer.drain().is_empty());
+    }
+}
@@ -0,0 +1,112 @@
+use crate::error::*;
+use crate::config::PRICE_PRECISION;
+
+// 纯整数定点运算，zkWASM 中不使用浮点数
+pub const FIXED_SCALE: u64 = PRICE_PRECISION;  // 1.0
+
+/// a * b / c，u128 中间值，向下取整
+pub fn mul_div(a: u64, b: u64, c: u64) -> Result<u64, u32> {
+    if c == 0 {
+        return Err(ERROR_DIVISION_BY_ZERO);
+    }
+    let result = (a as u128 * b as u128) / c as u128;
+    u64::try_from(result).map_err(|_| ERROR_OVERFLOW)
+}
+
+/// a * b / c，u128 中间值，向上取整
+pub fn mul_div_up(a: u64, b: u64, c: u64) -> Result<u64, u32> {
+    if c == 0 {
+        return Err(ERROR_DIVISION_BY_ZERO);
+    }
+    let result = (a as u128 * b as u128).div_ceil(c as u128);
+    u64::try_from(result).map_err(|_| ERROR_OVERFLOW)
+}
+
+/// a * b / c，结果超出 u64 时饱和到 u64::MAX（除数为零同样饱和）
+pub fn saturating_mul_div(a: u64, b: u64, c: u64) -> u64 {
+    if c == 0 {
+        return u64::MAX;
+    }
+    let result = (a as u128 * b as u128) / c as u128;
+    u64::try_from(result).unwrap_or(u64::MAX)
+}
+
+/// 定点乘法：a * b / FIXED_SCALE，向下取整
+pub fn fixed_mul(a: u64, b: u64) -> Result<u64, u32> {
+    mul_div(a, b, FIXED_SCALE)
+}
+
+/// 定点除法：a * FIXED_SCALE / b，向下取整
+pub fn fixed_div(a: u64, b: u64) -> Result<u64, u32> {
+    mul_div(a, FIXED_SCALE, b)
+}
+
+/// 整数平方根，向下取整
+pub fn sqrt(n: u128) -> u64 {
+    if n < 2 {
+        return n as u64;
+    }
+    // 牛顿迭代，从不小于真实值的初始值单调下降
+    let mut x = 1u128 << (128 - n.leading_zeros()).div_ceil(2);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x as u64;
+        }
+        x = y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mul_div_rounding_directions() {
+        assert_eq!(mul_div(7, 1, 2), Ok(3));
+        assert_eq!(mul_div_up(7, 1, 2), Ok(4));
+        // Exact results are never rounded up
+        assert_eq!(mul_div_up(8, 1, 2), Ok(4));
+        assert_eq!(mul_div_up(0, 5, 3), Ok(0));
+        assert_eq!(mul_div(1, 1, 3), Ok(0));
+        assert_eq!(mul_div_up(1, 1, 3), Ok(1));
+    }
+
+    #[test]
+    fn test_mul_div_overflow_edges() {
+        // The intermediate product never overflows, only the final result can
+        assert_eq!(mul_div(u64::MAX, u64::MAX, u64::MAX), Ok(u64::MAX));
+        assert_eq!(mul_div(u64::MAX, 2, 2), Ok(u64::MAX));
+        assert_eq!(mul_div(u64::MAX, 2, 1), Err(ERROR_OVERFLOW));
+        assert_eq!(mul_div_up(u64::MAX, u64::MAX, u64::MAX - 1), Err(ERROR_OVERFLOW));
+        assert_eq!(mul_div(1, 1, 0), Err(ERROR_DIVISION_BY_ZERO));
+        assert_eq!(mul_div_up(1, 1, 0), Err(ERROR_DIVISION_BY_ZERO));
+
+        assert_eq!(saturating_mul_div(u64::MAX, 2, 1), u64::MAX);
+        assert_eq!(saturating_mul_div(10, 3, 4), 7);
+        assert_eq!(saturating_mul_div(1, 1, 0), u64::MAX);
+    }
+
+    #[test]
+    fn test_fixed_mul_and_div() {
+        let half = FIXED_SCALE / 2;
+        assert_eq!(fixed_mul(3 * FIXED_SCALE, half), Ok(3 * FIXED_SCALE / 2));
+        assert_eq!(fixed_div(FIXED_SCALE, 2 * FIXED_SCALE), Ok(half));
+        assert_eq!(fixed_div(1, 3), Ok(FIXED_SCALE / 3));
+        assert_eq!(fixed_div(1, 0), Err(ERROR_DIVISION_BY_ZERO));
+    }
+
+    #[test]
+    fn test_sqrt_floors() {
+        assert_eq!(sqrt(0), 0);
+        assert_eq!(sqrt(1), 1);
+        assert_eq!(sqrt(3), 1);
+        assert_eq!(sqrt(4), 2);
+        assert_eq!(sqrt(99), 9);
+        assert_eq!(sqrt(100), 10);
+        assert_eq!(sqrt(1_000_000 * 1_000_000), 1_000_000);
+        assert_eq!(sqrt(1_000_000 * 1_000_000 - 1), 999_999);
+        assert_eq!(sqrt(u128::MAX), u64::MAX);
+        assert_eq!(sqrt((u64::MAX as u128) * (u64::MAX as u128)), u64::MAX);
+    }
+}
@@ -10,7 +10,13 @@ pub mod player;
 pub mod settlement;
 pub mod state;
 pub mod market;
+pub mod liquidity;
+pub mod quote;
+pub mod orders;
+pub mod categorical;
 pub mod math_safe;
+pub mod amm;
+pub mod fixed;
 pub mod security_tests;
 
 use crate::config::Config;
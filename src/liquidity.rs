@@ -0,0 +1,354 @@
+use serde::Serialize;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::error::*;
+use crate::market::MarketData;
+use crate::fixed::mul_div;
+use crate::math_safe::*;
+use crate::player::{PlayerMarketManager, PlayerMarketPosition};
+
+// Fixed-point scale of the per-share LP fee accumulator
+pub const LP_FEE_SCALE: u64 = 1_000_000_000;
+
+// One player's liquidity in one market
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct LpPosition {
+    pub lp_shares: u64,
+    pub fee_debt: u64,      // Fees already accounted for at the last settlement
+    pub pending_fees: u64,  // Settled fees waiting to be claimed
+    pub compounding: bool,  // Reinvest fees as LP shares instead of accruing them
+}
+
+impl LpPosition {
+    fn accrued(&self, market: &MarketData) -> Result<u64, u32> {
+        safe_sub(mul_div(self.lp_shares, market.lp_fee_per_share, LP_FEE_SCALE)?, self.fee_debt)
+    }
+
+    fn reset_debt(&mut self, market: &MarketData) -> Result<(), u32> {
+        self.fee_debt = mul_div(self.lp_shares, market.lp_fee_per_share, LP_FEE_SCALE)?;
+        Ok(())
+    }
+}
+
+// Bring a position up to date with the fees its shares earned since the last settlement
+pub fn settle_lp_fees(market: &mut MarketData, position: &mut LpPosition) -> Result<(), u32> {
+    // Per-position rounding can overshoot the pool by a unit, never pay more than it holds
+    let accrued = position.accrued(market)?.min(market.lp_fees_owed);
+    if accrued > 0 {
+        market.lp_fees_owed -= accrued;
+        if position.compounding {
+            // Fees become principal at the current collateral per share
+            let minted = mul_div(accrued, market.total_lp_shares, market.lp_collateral)?;
+            if !market.resolved {
+                market.deepen_reserves(accrued)?;
+            }
+            market.lp_collateral = safe_add(market.lp_collateral, accrued)?;
+            market.total_lp_shares = safe_add(market.total_lp_shares, minted)?;
+            position.lp_shares = safe_add(position.lp_shares, minted)?;
+        } else {
+            position.pending_fees = safe_add(position.pending_fees, accrued)?;
+            market.lp_fees_settled = safe_add(market.lp_fees_settled, accrued)?;
+        }
+    }
+    position.reset_debt(market)
+}
+
+// Provide collateral for LP shares; the first LP is priced one share per unit
+pub fn add_liquidity(market: &mut MarketData, position: &mut LpPosition, amount: u64, compounding: bool) -> Result<u64, u32> {
+    if market.resolved {
+        return Err(ERROR_MARKET_ALREADY_RESOLVED);
+    }
+    validate_bet_amount(amount)?;
+    settle_lp_fees(market, position)?;
+
+    let minted = if market.total_lp_shares == 0 {
+        amount
+    } else {
+        mul_div(amount, market.total_lp_shares, market.lp_collateral)?
+    };
+    if minted == 0 {
+        return Err(ERROR_INVALID_BET_AMOUNT);
+    }
+
+    let new_collateral = safe_add(market.lp_collateral, amount)?;
+    if market.lp_cap_total != 0 && new_collateral > market.lp_cap_total {
+        return Err(ERROR_LIQUIDITY_CAP_REACHED);
+    }
+    if market.lp_cap_per_provider != 0 {
+        let provided = if market.total_lp_shares == 0 {
+            0
+        } else {
+            mul_div(position.lp_shares, market.lp_collateral, market.total_lp_shares)?
+        };
+        if safe_add(provided, amount)? > market.lp_cap_per_provider {
+            return Err(ERROR_LIQUIDITY_CAP_REACHED);
+        }
+    }
+
+    market.deepen_reserves(amount)?;
+    market.lp_collateral = new_collateral;
+    market.total_lp_shares = safe_add(market.total_lp_shares, minted)?;
+    position.lp_shares = safe_add(position.lp_shares, minted)?;
+    position.compounding = compounding;
+    position.reset_debt(market)?;
+    Ok(minted)
+}
+
+// Provide liquidity with complete sets: burning one YES and one NO share frees one unit
+// of collateral, which is added exactly like a collateral deposit, so the price is unchanged
+pub fn add_liquidity_from_sets(market: &mut MarketData, lp: &mut LpPosition, position: &mut PlayerMarketPosition, sets: u64, compounding: bool) -> Result<u64, u32> {
+    if sets > position.yes_shares || sets > position.no_shares {
+        return Err(ERROR_INSUFFICIENT_BALANCE);
+    }
+    let mut staged_market = market.clone();
+    let mut staged_lp = lp.clone();
+    let mut staged_position = position.clone();
+    staged_market.burn_set(sets)?;
+    let minted = add_liquidity(&mut staged_market, &mut staged_lp, sets, compounding)?;
+
+    // The sets' cost basis leaves the position along with the shares
+    let released = safe_add(
+        staged_position.reduce_cost(1, sets, position.yes_shares)?,
+        staged_position.reduce_cost(0, sets, position.no_shares)?,
+    )?;
+    staged_market.release_cost(released)?;
+    staged_position.yes_shares -= sets;
+    staged_position.no_shares -= sets;
+
+    *market = staged_market;
+    *lp = staged_lp;
+    *position = staged_position;
+    Ok(minted)
+}
+
+// Burn LP shares for their principal plus any settled fees
+pub fn remove_liquidity(market: &mut MarketData, position: &mut LpPosition, lp_shares: u64) -> Result<u64, u32> {
+    if lp_shares == 0 {
+        return Err(ERROR_INVALID_BET_AMOUNT);
+    }
+    if lp_shares > position.lp_shares {
+        return Err(ERROR_INSUFFICIENT_BALANCE);
+    }
+    settle_lp_fees(market, position)?;
+
+    let principal = mul_div(lp_shares, market.lp_collateral, market.total_lp_shares)?;
+    // Reserves only price trades before resolution
+    if !market.resolved {
+        market.thin_reserves(principal)?;
+    }
+    market.lp_collateral = safe_sub(market.lp_collateral, principal)?;
+    market.total_lp_shares = safe_sub(market.total_lp_shares, lp_shares)?;
+    position.lp_shares = safe_sub(position.lp_shares, lp_shares)?;
+    position.reset_debt(market)?;
+
+    let fees = take_pending_fees(market, position)?;
+    safe_add(principal, fees)
+}
+
+// Pay out the settled fees of a non-compounding LP
+pub fn claim_lp_fees(market: &mut MarketData, position: &mut LpPosition) -> Result<u64, u32> {
+    settle_lp_fees(market, position)?;
+    let fees = take_pending_fees(market, position)?;
+    if fees == 0 {
+        return Err(ERROR_NO_FEES_TO_WITHDRAW);
+    }
+    Ok(fees)
+}
+
+fn take_pending_fees(market: &mut MarketData, position: &mut LpPosition) -> Result<u64, u32> {
+    let fees = std::mem::take(&mut position.pending_fees);
+    market.lp_fees_settled = safe_sub(market.lp_fees_settled, fees)?;
+    Ok(fees)
+}
+
+impl StorageData for LpPosition {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        LpPosition {
+            lp_shares: *u64data.next().unwrap(),
+            fee_debt: *u64data.next().unwrap(),
+            pending_fees: *u64data.next().unwrap(),
+            compounding: *u64data.next().unwrap() != 0,
+        }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.lp_shares);
+        data.push(self.fee_debt);
+        data.push(self.pending_fees);
+        data.push(if self.compounding { 1 } else { 0 });
+    }
+}
+
+pub struct LpManager;
+
+impl LpManager {
+    const LP_PREFIX: [u64; 2] = [4, 0]; // Prefix for LP position storage
+
+    pub fn get_position(player_id: &[u64; 2], market_id: u64) -> LpPosition {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let combined_player_id = PlayerMarketManager::combine_player_id_safe(player_id);
+        let key = [Self::LP_PREFIX[0], Self::LP_PREFIX[1], combined_player_id, market_id];
+        let mut data = kvpair.get(&key);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            LpPosition::from_data(&mut u64data)
+        } else {
+            LpPosition::default()
+        }
+    }
+
+    pub fn store_position(player_id: &[u64; 2], market_id: u64, position: &LpPosition) {
+        let mut data = vec![];
+        position.to_data(&mut data);
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let combined_player_id = PlayerMarketManager::combine_player_id_safe(player_id);
+        let key = [Self::LP_PREFIX[0], Self::LP_PREFIX[1], combined_player_id, market_id];
+        kvpair.set(&key, data.as_slice());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lp_market() -> MarketData {
+        let title = MarketData::string_to_u64_vec("Liquidity");
+        MarketData::new_with_title_u64_and_liquidity(
+            title, "Liquidity".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_compounding_lp_share_grows_against_accruing_lp() {
+        let mut market = lp_market();
+        let mut compounding = LpPosition::default();
+        let mut accruing = LpPosition::default();
+        add_liquidity(&mut market, &mut compounding, 100_000, true).unwrap();
+        add_liquidity(&mut market, &mut accruing, 100_000, false).unwrap();
+        assert_eq!(compounding.lp_shares, accruing.lp_shares);
+
+        let mut last_share = compounding.lp_shares;
+        for round in 0..4 {
+            market.place_bet(round % 2, 50_000).unwrap();
+            settle_lp_fees(&mut market, &mut compounding).unwrap();
+            settle_lp_fees(&mut market, &mut accruing).unwrap();
+
+            // Compounded fees mint new shares; accrued fees wait to be claimed
+            assert!(compounding.lp_shares > last_share);
+            assert_eq!(compounding.pending_fees, 0);
+            assert_eq!(accruing.lp_shares, 100_000);
+            last_share = compounding.lp_shares;
+        }
+        assert!(accruing.pending_fees > 0);
+        assert!(compounding.lp_shares as u128 * 2 > market.total_lp_shares as u128);
+
+        // Both recover what they are owed and the market owes nothing more
+        let accruing_shares = accruing.lp_shares;
+        let accruing_total = remove_liquidity(&mut market, &mut accruing, accruing_shares).unwrap();
+        assert!(accruing_total > 100_000);
+        let compounding_shares = compounding.lp_shares;
+        let compounding_total = remove_liquidity(&mut market, &mut compounding, compounding_shares).unwrap();
+        assert!(compounding_total > 100_000);
+        assert_eq!(market.total_lp_shares, 0);
+        assert_eq!(market.lp_collateral, 0);
+        assert_eq!(market.lp_fees_settled, 0);
+        assert!(market.lp_fees_owed <= 4);
+    }
+
+    #[test]
+    fn test_lp_exit_before_resolution_leaves_the_prize_pool_whole() {
+        let mut market = lp_market();
+        let mut lp = LpPosition::default();
+        add_liquidity(&mut market, &mut lp, 200_000, false).unwrap();
+        market.place_bet(1, 40_000).unwrap();
+        market.place_bet(0, 10_000).unwrap();
+        let pool = market.prize_pool;
+
+        // Principal comes out of LP collateral and fees out of the LP fee pool, never the bettors' pool
+        let returned = remove_liquidity(&mut market, &mut lp, 200_000).unwrap();
+        assert!(returned > 200_000);
+        assert_eq!(market.prize_pool, pool);
+        assert_eq!(market.lp_collateral, 0);
+        market.resolve(true).unwrap();
+        assert!(market.calculate_payout(market.total_yes_shares, 0).unwrap() <= market.prize_pool);
+    }
+
+    #[test]
+    fn test_complete_sets_add_liquidity_at_collateral_value() {
+        let mut market = lp_market();
+        market.place_bet(1, 30_000).unwrap();
+        let mut by_collateral = LpPosition::default();
+        add_liquidity(&mut market, &mut by_collateral, 50_000, false).unwrap();
+
+        // Mint a complete set, then contribute it as liquidity
+        let mut position = PlayerMarketPosition::default();
+        market.mint_set(50_000).unwrap();
+        position.yes_shares += 50_000;
+        position.no_shares += 50_000;
+        let price_before = market.get_yes_price().unwrap();
+        let pool_before = market.prize_pool;
+
+        let mut by_sets = LpPosition::default();
+        let minted = add_liquidity_from_sets(&mut market, &mut by_sets, &mut position, 50_000, false).unwrap();
+        assert_eq!(minted, by_collateral.lp_shares);
+        assert_eq!(position.yes_shares, 0);
+        assert_eq!(position.no_shares, 0);
+        assert_eq!(market.prize_pool, pool_before - 50_000);
+        // Deepening both reserves in proportion can only move the price by rounding
+        assert!(market.get_yes_price().unwrap().abs_diff(price_before) <= 1);
+
+        // A position missing either side cannot be contributed
+        position.yes_shares = 10;
+        assert_eq!(add_liquidity_from_sets(&mut market, &mut by_sets, &mut position, 10, false), Err(ERROR_INSUFFICIENT_BALANCE));
+    }
+
+    #[test]
+    fn test_void_without_trades_returns_full_provision() {
+        let mut market = lp_market();
+        let mut lp = LpPosition::default();
+        add_liquidity(&mut market, &mut lp, 150_000, false).unwrap();
+        market.void().unwrap();
+        assert_eq!(market.total_fees_collected, 0);
+
+        // Nobody traded, so there is nothing to refund and no division by a zero cost basis
+        assert_eq!(market.settle_refund(0), Ok(0));
+        assert_eq!(market.settle_refund(1_000), Ok(0));
+
+        let shares = lp.lp_shares;
+        assert_eq!(remove_liquidity(&mut market, &mut lp, shares), Ok(150_000));
+        assert_eq!(market.lp_collateral, 0);
+        assert_eq!(market.lp_fees_owed + market.lp_fees_settled, 0);
+    }
+
+    #[test]
+    fn test_provider_cap_limits_a_single_lp() {
+        let mut market = lp_market();
+        market.apply_option(crate::market::MARKET_OPTION_LP_CAP_PER_PROVIDER, 100_000).unwrap();
+        let mut capped = LpPosition::default();
+        let mut other = LpPosition::default();
+
+        add_liquidity(&mut market, &mut capped, 60_000, false).unwrap();
+        assert_eq!(add_liquidity(&mut market, &mut capped, 50_000, false), Err(ERROR_LIQUIDITY_CAP_REACHED));
+        add_liquidity(&mut market, &mut capped, 40_000, false).unwrap();
+
+        // The cap is per provider, others can still add
+        add_liquidity(&mut market, &mut other, 100_000, false).unwrap();
+        assert_eq!(market.lp_collateral, 200_000);
+    }
+
+    #[test]
+    fn test_total_cap_limits_the_market() {
+        let mut market = lp_market();
+        market.apply_option(crate::market::MARKET_OPTION_LP_CAP_TOTAL, 150_000).unwrap();
+        let mut first = LpPosition::default();
+        let mut second = LpPosition::default();
+
+        add_liquidity(&mut market, &mut first, 100_000, false).unwrap();
+        let before = market.clone();
+        assert_eq!(add_liquidity(&mut market, &mut second, 60_000, false), Err(ERROR_LIQUIDITY_CAP_REACHED));
+        assert_eq!(market.yes_liquidity, before.yes_liquidity);
+        add_liquidity(&mut market, &mut second, 50_000, false).unwrap();
+        assert_eq!(market.lp_collateral, 150_000);
+
+        assert_eq!(market.apply_option(99, 1), Err(ERROR_INVALID_MARKET_OPTION));
+    }
+}
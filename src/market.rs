@@ -1,10 +1,165 @@
 use serde::Serialize;
 use zkwasm_rest_abi::StorageData;
 use zkwasm_rest_convention::IndexedObject;
-use crate::config::PRICE_PRECISION;
+use crate::config::{PRICE_PRECISION, BASIS_POINTS_PRECISION, DEFAULT_BET_REVEAL_WINDOW, DEFAULT_REMAINDER_POLICY, DEFAULT_RESOLUTION_DELAY, DEFAULT_ORACLE_QUORUM, DEFAULT_LP_FEE_SHARE, DISPUTE_ESCALATION_STAKE, MAX_MARKET_START_HORIZON, MAX_MARKET_FEE_BPS, TOP_HOLDERS_TRACKED, FEE_BASIS_POINTS, PAYOUT_MULTIPLE_PRECISION, MARGINAL_IMPACT_STEP, PLATFORM_FEE_RATE, MAKER_REBATE_RATE, TAKER_SURCHARGE_RATE};
 use crate::error::*;
 use crate::math_safe::*;
+use crate::fixed::{mul_div, mul_div_up};
+use crate::amm::{ConstantProduct, Lmsr, PricingEngine};
+use crate::liquidity::LP_FEE_SCALE;
+use crate::player::PlayerMarketPosition;
 
+// What happens to the rounding dust left once every winning share has claimed
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum RemainderPolicy {
+    LeaveInPool,
+    ToFees,
+    ToLargestHolder,
+}
+
+impl RemainderPolicy {
+    pub fn from_u64(value: u64) -> Self {
+        match value {
+            1 => RemainderPolicy::ToFees,
+            2 => RemainderPolicy::ToLargestHolder,
+            _ => RemainderPolicy::LeaveInPool,
+        }
+    }
+
+    pub fn to_u64(self) -> u64 {
+        match self {
+            RemainderPolicy::LeaveInPool => 0,
+            RemainderPolicy::ToFees => 1,
+            RemainderPolicy::ToLargestHolder => 2,
+        }
+    }
+}
+
+// Lifecycle phase reported in EVENT_MARKET_STATE_CHANGE (0 is reserved for "no previous phase")
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum MarketPhase {
+    Created,
+    Active,
+    Ended,
+    Resolved,
+    Voided,
+    Cancelled,
+    FullySettled,
+    PendingResolution,
+}
+
+impl MarketPhase {
+    pub fn to_u64(self) -> u64 {
+        match self {
+            MarketPhase::Created => 1,
+            MarketPhase::Active => 2,
+            MarketPhase::Ended => 3,
+            MarketPhase::Resolved => 4,
+            MarketPhase::Voided => 5,
+            MarketPhase::Cancelled => 6,
+            MarketPhase::FullySettled => 7,
+            MarketPhase::PendingResolution => 8,
+        }
+    }
+}
+
+// Coarse lifecycle status that gates trading and resolution. Like MarketPhase it is derived
+// from the resolution flags and the trading window, so it never drifts from the times; the
+// transitions below move those times or flags rather than storing the status itself.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum MarketStatus {
+    Pending,   // Created, trading has not started
+    Active,    // Accepting bets and sells
+    Closed,    // Trading over, outcome not yet known
+    Resolved,
+    Cancelled, // Voided; positions are refunded
+}
+
+// Optional per-market settings, passed to CreateMarket as trailing (key, value) pairs
+pub const MARKET_OPTION_LP_CAP_PER_PROVIDER: u64 = 1;
+pub const MARKET_OPTION_LP_CAP_TOTAL: u64 = 2;
+pub const MARKET_OPTION_TICK_SUMMARY: u64 = 3;
+pub const MARKET_OPTION_FEE_RECIPIENT_0: u64 = 4;  // First word of the fee recipient pid
+pub const MARKET_OPTION_FEE_RECIPIENT_1: u64 = 5;  // Second word of the fee recipient pid
+pub const MARKET_OPTION_AUTO_SETTLE_CLAIMS: u64 = 6;
+pub const MARKET_OPTION_REQUIRE_NONZERO_WINNING_SIDE: u64 = 7;
+pub const MARKET_OPTION_SCALAR_LOW: u64 = 8;       // Value at which NO takes the whole pool
+pub const MARKET_OPTION_SCALAR_HIGH: u64 = 9;      // Value at which YES takes the whole pool
+pub const MARKET_OPTION_ORACLE_QUORUM: u64 = 10;
+pub const MARKET_OPTION_FEE_BPS: u64 = 11;
+pub const MARKET_OPTION_QUESTION_HASH_0: u64 = 12; // ..=15, one limb each
+pub const MARKET_OPTION_MAX_POSITION: u64 = 16;
+pub const MARKET_OPTION_MAX_BET_AMOUNT: u64 = 17;
+pub const MARKET_OPTION_MIN_BET_AMOUNT: u64 = 18;
+pub const MARKET_OPTION_SELL_COOLDOWN: u64 = 19;
+pub const MARKET_OPTION_DISPUTE_WINDOW: u64 = 20;
+pub const MARKET_OPTION_LMSR_B: u64 = 21;
+pub const MARKET_OPTION_CLAIM_PERIOD: u64 = 22;
+pub const MARKET_OPTION_RESOLUTION_DELAY: u64 = 23; // Minimum wait between ProposeResolution and FinalizeResolution
+pub const MARKET_OPTION_REMAINDER_POLICY: u64 = 24; // RemainderPolicy::to_u64 value
+pub const MARKET_OPTION_REVEAL_WINDOW: u64 = 25;     // Ticks a CommitBet stays revealable, at least 1
+
+// Result of settling one claim against the market
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClaimSettlement {
+    pub payout: u64,
+    pub remainder_recipient: Option<([u64; 2], u64)>, // (pid, amount) when the remainder goes to a holder
+}
+
+// Full accounting snapshot of a market for off-chain reconciliation. It balances as
+//   outstanding_claims + residual + platform_fees + lp_fees_owed + lp_fees_settled + lp_collateral = held
+//   outstanding_claims + residual + total_claimed = prize_pool
+// where `held` is everything the market still owes someone out of the funds it took in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MarketLedger {
+    pub yes_liquidity: u64,
+    pub no_liquidity: u64,
+    pub total_yes_shares: u64,
+    pub total_no_shares: u64,
+    pub total_volume: u64,
+    pub platform_fees: u64,       // Withdrawable by the fee recipient
+    pub lp_fees_owed: u64,
+    pub lp_fees_settled: u64,
+    pub maker_rebates: u64,       // Informational, never collected
+    pub taker_surcharges: u64,    // Informational, already part of the fees
+    pub lp_collateral: u64,
+    pub prize_pool: u64,
+    pub total_claimed: u64,
+    pub outstanding_claims: u64,  // Most that holders can still claim, worst case before resolution
+    pub residual: u64,            // Rounding dust left in the pool
+    pub held: u64,
+}
+
+impl MarketLedger {
+    pub fn to_data(&self, data: &mut Vec<u64>) {
+        data.extend_from_slice(&[
+            self.yes_liquidity, self.no_liquidity, self.total_yes_shares, self.total_no_shares,
+            self.total_volume, self.platform_fees, self.lp_fees_owed, self.lp_fees_settled,
+            self.maker_rebates, self.taker_surcharges, self.lp_collateral, self.prize_pool,
+            self.total_claimed, self.outstanding_claims, self.residual, self.held,
+        ]);
+    }
+}
+
+// One player's standing in one market, marked against the current reserves
+// (or against what is still claimable once resolved)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PositionSummary {
+    pub yes_shares: u64,
+    pub no_shares: u64,
+    pub value: u64,
+    pub cost_basis: u64,
+    pub unrealized_pnl: i64,
+}
+
+impl PositionSummary {
+    pub fn to_data(&self, data: &mut Vec<u64>) {
+        data.extend_from_slice(&[self.yes_shares, self.no_shares, self.value, self.cost_basis]);
+        // Sign and magnitude, the reply is unsigned
+        data.push((self.unrealized_pnl < 0) as u64);
+        data.push(self.unrealized_pnl.unsigned_abs());
+    }
+}
 
 #[derive(Serialize, Clone, Debug)]
 pub struct MarketData {
@@ -23,7 +178,64 @@ pub struct MarketData {
     pub total_no_shares: u64,   // Total NO shares issued
     pub resolved: bool,
     pub outcome: Option<bool>, // None = unresolved, Some(true) = Yes wins, Some(false) = No wins
+    pub resolution_weights: Vec<u64>, // [NO, YES] bps for fractional resolutions, empty = winner takes all
     pub total_fees_collected: u64,
+    pub fee_recipient: [u64; 2],       // Credited by WithdrawFees, defaults to the creator
+    pub auto_settle_claims: bool,      // Claims are queued as bridge withdrawals instead of credited
+    pub require_nonzero_winning_side: bool, // Refuse to resolve to an outcome nobody holds
+    // Voiding: positions are refunded the pool pro rata to their cost basis
+    pub voided: bool,
+    pub total_cost_basis: u64,         // Sum of every open position's cost basis
+    pub refunded_cost_basis: u64,      // Cost basis already refunded after a void
+    // Scalar markets: oracles report a value, the pool splits linearly between low and high
+    pub scalar_low: u64,
+    pub scalar_high: u64,              // Not a scalar market unless above scalar_low
+    pub oracle_quorum: u64,
+    pub oracle_submissions: Vec<([u64; 2], u64)>, // (oracle pid, value), one per oracle
+    pub oracle_median: u64,            // Value the market settled at
+    pub fee_bps: u64,                  // Trading fee in FEE_BASIS_POINTS
+    pub question_hash: [u64; 4],       // Binds the market to its off-chain question text
+    pub max_position: u64,             // Most shares one player may hold on a side, 0 = unlimited
+    pub max_bet_amount: u64,           // Largest single bet, 0 = unlimited
+    pub oracle_outcome: Option<bool>,  // Outcome reported by an oracle, applied by Tick once resolvable
+    pub min_bet_amount: u64,           // Smallest single bet, 0 = no minimum
+    pub sell_cooldown: u64,            // Ticks after a bet before that side can be sold, 0 = none
+    pub resolved_at: u64,              // When the outcome was set, opening the dispute window
+    pub dispute_window: u64,           // Ticks the outcome may still be overridden before claims open, 0 = none
+    pub lmsr_b: u64,                   // LMSR liquidity parameter pricing the market, 0 = the constant-product reserves
+    pub dispute_stake: [u64; 2],       // Bonds held for disputes claiming NO / YES is the true outcome
+    pub dispute_escalated: bool,       // Disputes reached DISPUTE_ESCALATION_STAKE; claims wait for the admin
+    pub dispute_decided: bool,         // The admin settled an escalated dispute, the outcome is final
+    pub claim_period: u64,             // Ticks claims stay open once they open, 0 = they never expire
+    pub swept: u64,                    // Unclaimed pool moved to the treasury after the claim period
+    pub reveal_window: u64,            // Ticks after a CommitBet during which it may be revealed
+    pub total_maker_rebates: u64,      // Discounts given to bets that rebalanced the reserves
+    pub total_taker_surcharges: u64,   // Extra fees taken from bets that worsened the imbalance
+    // Claim accounting
+    pub total_claimed: u64,            // Pool already distributed (payouts plus any routed remainder)
+    pub claimed_winning_shares: u64,
+    pub remainder_policy: RemainderPolicy,
+    pub largest_claim_pid: [u64; 2],
+    pub largest_claim_shares: u64,
+    // Two-step resolution: a proposal only takes effect once the delay has passed
+    pub proposed_outcome: Option<bool>,
+    pub proposed_at: u64,
+    pub resolution_delay: u64,
+    // Liquidity providers: collateral deepens the reserves, LPs earn a cut of trade fees
+    pub lp_fee_share_bps: u64,         // Portion of each fee owed to LPs while any exist
+    pub total_lp_shares: u64,
+    pub lp_collateral: u64,            // LP principal, including compounded fees
+    pub lp_fee_per_share: u64,         // Accumulated LP fees per share, scaled by LP_FEE_SCALE
+    pub lp_fees_owed: u64,             // LP fees accrued but not yet settled into a position
+    pub lp_fees_settled: u64,          // LP fees settled into positions, awaiting claim
+    pub lp_cap_per_provider: u64,      // Max collateral a single LP may hold, 0 = unlimited
+    pub lp_cap_total: u64,             // Max total LP collateral, 0 = unlimited
+    // Periodic snapshot emitted by Tick
+    pub tick_summary_enabled: bool,
+    pub last_summary_volume: u64,      // total_volume when the last summary was taken
+    // Largest holders per outcome as (pid, shares), sorted descending
+    pub top_no_holders: Vec<([u64; 2], u64)>,
+    pub top_yes_holders: Vec<([u64; 2], u64)>,
 }
 
 impl MarketData {
@@ -64,12 +276,112 @@ impl MarketData {
             total_no_shares: 0,
             resolved: false,
             outcome: None,
+            resolution_weights: vec![],
             total_fees_collected: 0,
+            fee_recipient: [0, 0],
+            auto_settle_claims: false,
+            require_nonzero_winning_side: false,
+            voided: false,
+            total_cost_basis: 0,
+            refunded_cost_basis: 0,
+            scalar_low: 0,
+            scalar_high: 0,
+            oracle_quorum: DEFAULT_ORACLE_QUORUM,
+            oracle_submissions: vec![],
+            oracle_median: 0,
+            fee_bps: PLATFORM_FEE_RATE,
+            question_hash: [0; 4],
+            max_position: 0,
+            max_bet_amount: 0,
+            oracle_outcome: None,
+            min_bet_amount: 0,
+            sell_cooldown: 0,
+            resolved_at: 0,
+            dispute_window: 0,
+            lmsr_b: 0,
+            dispute_stake: [0, 0],
+            dispute_escalated: false,
+            dispute_decided: false,
+            claim_period: 0,
+            swept: 0,
+            reveal_window: DEFAULT_BET_REVEAL_WINDOW,
+            total_maker_rebates: 0,
+            total_taker_surcharges: 0,
+            total_claimed: 0,
+            claimed_winning_shares: 0,
+            remainder_policy: RemainderPolicy::from_u64(DEFAULT_REMAINDER_POLICY),
+            largest_claim_pid: [0, 0],
+            largest_claim_shares: 0,
+            proposed_outcome: None,
+            proposed_at: 0,
+            resolution_delay: DEFAULT_RESOLUTION_DELAY,
+            lp_fee_share_bps: DEFAULT_LP_FEE_SHARE,
+            total_lp_shares: 0,
+            lp_collateral: 0,
+            lp_fee_per_share: 0,
+            lp_fees_owed: 0,
+            lp_fees_settled: 0,
+            lp_cap_per_provider: 0,
+            lp_cap_total: 0,
+            tick_summary_enabled: false,
+            last_summary_volume: 0,
+            top_no_holders: vec![],
+            top_yes_holders: vec![],
         })
     }
 
 
 
+    pub fn apply_option(&mut self, key: u64, value: u64) -> Result<(), u32> {
+        match key {
+            MARKET_OPTION_LP_CAP_PER_PROVIDER => self.lp_cap_per_provider = value,
+            MARKET_OPTION_LP_CAP_TOTAL => self.lp_cap_total = value,
+            MARKET_OPTION_TICK_SUMMARY => self.tick_summary_enabled = value != 0,
+            MARKET_OPTION_FEE_RECIPIENT_0 => self.fee_recipient[0] = value,
+            MARKET_OPTION_FEE_RECIPIENT_1 => self.fee_recipient[1] = value,
+            MARKET_OPTION_AUTO_SETTLE_CLAIMS => self.auto_settle_claims = value != 0,
+            MARKET_OPTION_REQUIRE_NONZERO_WINNING_SIDE => self.require_nonzero_winning_side = value != 0,
+            MARKET_OPTION_SCALAR_LOW => self.scalar_low = value,
+            MARKET_OPTION_SCALAR_HIGH => self.scalar_high = value,
+            MARKET_OPTION_ORACLE_QUORUM => self.oracle_quorum = value.max(1),
+            MARKET_OPTION_FEE_BPS => {
+                if value > MAX_MARKET_FEE_BPS {
+                    return Err(ERROR_INVALID_MARKET_OPTION);
+                }
+                self.fee_bps = value;
+            }
+            MARKET_OPTION_QUESTION_HASH_0..=15 => self.question_hash[(key - MARKET_OPTION_QUESTION_HASH_0) as usize] = value,
+            MARKET_OPTION_MAX_POSITION => self.max_position = value,
+            MARKET_OPTION_MAX_BET_AMOUNT => self.max_bet_amount = value,
+            MARKET_OPTION_MIN_BET_AMOUNT => self.min_bet_amount = value,
+            MARKET_OPTION_SELL_COOLDOWN => self.sell_cooldown = value,
+            MARKET_OPTION_DISPUTE_WINDOW => self.dispute_window = value,
+            MARKET_OPTION_CLAIM_PERIOD => self.claim_period = value,
+            MARKET_OPTION_RESOLUTION_DELAY => self.resolution_delay = value,
+            MARKET_OPTION_REMAINDER_POLICY => {
+                if value > RemainderPolicy::ToLargestHolder.to_u64() {
+                    return Err(ERROR_INVALID_MARKET_OPTION);
+                }
+                self.remainder_policy = RemainderPolicy::from_u64(value);
+            }
+            MARKET_OPTION_REVEAL_WINDOW => {
+                // A zero window would leave every commitment unrevealable
+                if value == 0 {
+                    return Err(ERROR_INVALID_MARKET_OPTION);
+                }
+                self.reveal_window = value;
+            }
+            MARKET_OPTION_LMSR_B => {
+                if value != 0 {
+                    validate_liquidity(value).map_err(|_| ERROR_INVALID_MARKET_OPTION)?;
+                }
+                self.lmsr_b = value;
+            }
+            _ => return Err(ERROR_INVALID_MARKET_OPTION),
+        }
+        Ok(())
+    }
+
     // Helper function to convert string to Vec<u64>
     pub fn string_to_u64_vec(s: &str) -> Vec<u64> {
         let bytes = s.as_bytes();
@@ -104,20 +416,171 @@ impl MarketData {
         String::from_utf8_lossy(&bytes).to_string()
     }
 
+    // 创建时相对当前 counter 检查时间：已经结束或开始时间远超 MAX_MARKET_START_HORIZON 的市场无法使用
+    pub fn validate_schedule(start_time: u64, end_time: u64, current_time: u64) -> Result<(), u32> {
+        if end_time <= current_time {
+            return Err(crate::error::ERROR_INVALID_MARKET_TIME);
+        }
+        if start_time > safe_add(current_time, MAX_MARKET_START_HORIZON)? {
+            return Err(crate::error::ERROR_INVALID_MARKET_TIME);
+        }
+        Ok(())
+    }
+
     pub fn get_title_string(&self) -> String {
         Self::u64_vec_to_string(&self.title)
     }
 
     pub fn is_active(&self, current_time: u64) -> bool {
-        current_time >= self.start_time && current_time < self.end_time && !self.resolved
+        self.status(current_time) == MarketStatus::Active
+    }
+
+    // 当前状态：作废、已结算、未开始、交易中、已收盘
+    pub fn status(&self, current_time: u64) -> MarketStatus {
+        if self.voided {
+            MarketStatus::Cancelled
+        } else if self.resolved {
+            MarketStatus::Resolved
+        } else if current_time < self.start_time {
+            MarketStatus::Pending
+        } else if current_time < self.end_time {
+            MarketStatus::Active
+        } else {
+            MarketStatus::Closed
+        }
+    }
+
+    // 只有交易中的市场可以下注和卖出
+    pub fn ensure_active(&self, current_time: u64) -> Result<(), u32> {
+        match self.status(current_time) {
+            MarketStatus::Active => Ok(()),
+            _ => Err(ERROR_MARKET_NOT_ACTIVE),
+        }
+    }
+
+    // 提前开盘：Pending -> Active
+    pub fn open(&mut self, current_time: u64) -> Result<(), u32> {
+        match self.status(current_time) {
+            MarketStatus::Pending => {
+                self.start_time = current_time;
+                Ok(())
+            }
+            MarketStatus::Resolved | MarketStatus::Cancelled => Err(ERROR_MARKET_ALREADY_RESOLVED),
+            _ => Err(ERROR_MARKET_NOT_ACTIVE),
+        }
+    }
+
+    // 提前收盘：Active -> Closed，停止交易但结果仍待定
+    pub fn close(&mut self, current_time: u64) -> Result<(), u32> {
+        match self.status(current_time) {
+            MarketStatus::Active => {
+                self.end_time = current_time;
+                Ok(())
+            }
+            MarketStatus::Resolved | MarketStatus::Cancelled => Err(ERROR_MARKET_ALREADY_RESOLVED),
+            _ => Err(ERROR_MARKET_NOT_ACTIVE),
+        }
     }
 
     pub fn can_resolve(&self, current_time: u64) -> bool {
-        current_time >= self.resolution_time && !self.resolved
+        self.ensure_resolvable(current_time).is_ok()
+    }
+
+    // 只有已收盘且到达结算时间的市场才能结算：Closed -> Resolved
+    pub fn ensure_resolvable(&self, current_time: u64) -> Result<(), u32> {
+        match self.status(current_time) {
+            MarketStatus::Resolved | MarketStatus::Cancelled => Err(ERROR_MARKET_ALREADY_RESOLVED),
+            MarketStatus::Pending | MarketStatus::Active => Err(ERROR_RESOLUTION_TOO_EARLY),
+            MarketStatus::Closed if current_time < self.resolution_time => Err(ERROR_RESOLUTION_TOO_EARLY),
+            MarketStatus::Closed => Ok(()),
+        }
+    }
+
+    // 当前所处的生命周期阶段
+    pub fn phase(&self, current_time: u64) -> MarketPhase {
+        if self.voided {
+            MarketPhase::Voided
+        } else if self.resolved {
+            if self.is_fully_settled() {
+                MarketPhase::FullySettled
+            } else {
+                MarketPhase::Resolved
+            }
+        } else if current_time < self.start_time {
+            MarketPhase::Created
+        } else if current_time < self.end_time {
+            MarketPhase::Active
+        } else if current_time < self.resolution_time {
+            MarketPhase::Ended
+        } else {
+            // 已到结算时间但尚未结算，等待预言机或管理员
+            MarketPhase::PendingResolution
+        }
+    }
+
+    // 所有获胜份额都已领取
+    pub fn is_fully_settled(&self) -> bool {
+        self.resolved && self.total_winning_shares().is_ok_and(|total| self.claimed_winning_shares >= total)
+    }
+
+    // Shares on any outcome with a nonzero settled weight
+    fn total_winning_shares(&self) -> Result<u64, u32> {
+        let weights = self.settled_weights().unwrap_or([0, 0]);
+        let mut total = 0;
+        if weights[0] > 0 {
+            total = safe_add(total, self.total_no_shares)?;
+        }
+        if weights[1] > 0 {
+            total = safe_add(total, self.total_yes_shares)?;
+        }
+        Ok(total)
+    }
+
+    // 交易后更新持仓排行；跌出榜单的持有者要等下一次交易才会重新计入
+    pub fn record_holding(&mut self, pid: [u64; 2], outcome: u64, shares: u64) -> Result<(), u32> {
+        let holders = if Self::validate_bet_type(outcome)? {
+            &mut self.top_yes_holders
+        } else {
+            &mut self.top_no_holders
+        };
+        holders.retain(|(holder, _)| *holder != pid);
+        if shares > 0 {
+            // Insert after equal holdings so earlier holders keep their rank on ties
+            let index = holders.partition_point(|(_, held)| *held >= shares);
+            holders.insert(index, (pid, shares));
+            holders.truncate(TOP_HOLDERS_TRACKED);
+        }
+        Ok(())
+    }
+
+    // 某一结果的前 n 名持有者
+    pub fn top_holders(&self, outcome: u64, n: usize) -> Result<&[([u64; 2], u64)], u32> {
+        let holders = if Self::validate_bet_type(outcome)? {
+            &self.top_yes_holders
+        } else {
+            &self.top_no_holders
+        };
+        Ok(&holders[..n.min(holders.len())])
+    }
+
+    // 定价引擎：设置了 lmsr_b 的市场按已发行份额走 LMSR，其余按储备走常量乘积
+    pub fn pricing(&self) -> Box<dyn PricingEngine> {
+        if self.lmsr_b > 0 {
+            Box::new(Lmsr { q_yes: self.total_yes_shares, q_no: self.total_no_shares, b: self.lmsr_b })
+        } else {
+            Box::new(ConstantProduct { yes_liquidity: self.yes_liquidity, no_liquidity: self.no_liquidity })
+        }
+    }
+
+    pub fn is_lmsr(&self) -> bool {
+        self.lmsr_b > 0
     }
 
     // 安全的 YES 价格计算
     pub fn get_yes_price(&self) -> Result<u64, u32> {
+        if self.is_lmsr() {
+            return Ok(self.pricing().prices()?.0);
+        }
         let total_liquidity = safe_add(self.yes_liquidity, self.no_liquidity)?;
         if total_liquidity == 0 {
             return Ok(PRICE_PRECISION / 2); // 50% if no liquidity
@@ -127,6 +590,9 @@ impl MarketData {
 
     // 安全的 NO 价格计算
     pub fn get_no_price(&self) -> Result<u64, u32> {
+        if self.is_lmsr() {
+            return Ok(self.pricing().prices()?.1);
+        }
         let total_liquidity = safe_add(self.yes_liquidity, self.no_liquidity)?;
         if total_liquidity == 0 {
             return Ok(PRICE_PRECISION / 2); // 50% if no liquidity
@@ -134,6 +600,131 @@ impl MarketData {
         calculate_price_safe(self.yes_liquidity, total_liquidity)
     }
 
+    // 当前边际价格 (YES, NO)，以 PRICE_PRECISION（百万分之一）为单位，两者之和恒为 PRICE_PRECISION，
+    // 除以 10_000 即为百分比；舍入剩余与 implied_probability 相同，归给价格更高的一方，无储备时各 50%
+    pub fn current_prices(&self) -> Result<(u64, u64), u32> {
+        self.pricing().prices()
+    }
+
+    // 隐含概率（基点），YES 与 NO 之和恒为 BASIS_POINTS_PRECISION
+    // 两边各自向下取整，舍入剩下的 1 个基点确定性地归给储备比例更高的一方；
+    // 储备相等时两边都恰好是 5000，不存在剩余，因此没有偏向
+    pub fn implied_probability(&self, outcome: u64) -> Result<u64, u32> {
+        let is_yes = Self::validate_bet_type(outcome)?;
+        if self.is_lmsr() {
+            // LMSR 价格本身已保证两边之和为 PRICE_PRECISION，按同样规则折算为基点
+            let (yes, no) = self.current_prices()?;
+            let (own, other) = if is_yes { (yes, no) } else { (no, yes) };
+            let own_bps = mul_div(own, BASIS_POINTS_PRECISION, PRICE_PRECISION)?;
+            let other_bps = mul_div(other, BASIS_POINTS_PRECISION, PRICE_PRECISION)?;
+            let leftover = safe_sub(BASIS_POINTS_PRECISION, safe_add(own_bps, other_bps)?)?;
+            return if own > other { safe_add(own_bps, leftover) } else { Ok(own_bps) };
+        }
+        let total_liquidity = safe_add(self.yes_liquidity, self.no_liquidity)?;
+        if total_liquidity == 0 {
+            return Ok(BASIS_POINTS_PRECISION / 2);
+        }
+        // YES 的概率来自 NO 储备，反之亦然
+        let (own_reserve, other_reserve) = if is_yes {
+            (self.no_liquidity, self.yes_liquidity)
+        } else {
+            (self.yes_liquidity, self.no_liquidity)
+        };
+        let own = mul_div(own_reserve, BASIS_POINTS_PRECISION, total_liquidity)?;
+        let other = mul_div(other_reserve, BASIS_POINTS_PRECISION, total_liquidity)?;
+        let leftover = safe_sub(BASIS_POINTS_PRECISION, safe_add(own, other)?)?;
+        if own_reserve > other_reserve {
+            safe_add(own, leftover)
+        } else {
+            Ok(own)
+        }
+    }
+
+    // 价差：买入一个 MARGINAL_IMPACT_STEP 该结果后的价格（ask）与买入同量对手结果后该结果的价格（bid）之差
+    pub fn spread(&self, outcome: u64) -> Result<u64, u32> {
+        let is_yes = Self::validate_bet_type(outcome)?;
+        let price_now = if is_yes { self.get_yes_price()? } else { self.get_no_price()? };
+        let opposite = if is_yes { 0 } else { 1 };
+        let ask = safe_add(price_now, self.marginal_impact(outcome)?)?;
+        // 对手价格上涨多少，该结果价格就下跌多少（两者之和保持为 PRICE_PRECISION）
+        let bid = price_now.saturating_sub(self.marginal_impact(opposite)?);
+        safe_sub(ask, bid)
+    }
+
+    // 按当前价格押注一单位、该结果获胜时的赔付倍数（扣除平台费，PAYOUT_MULTIPLE_PRECISION = 1.0x）
+    pub fn payout_multiple(&self, outcome: u64) -> Result<u64, u32> {
+        let price = if Self::validate_bet_type(outcome)? {
+            self.get_yes_price()?
+        } else {
+            self.get_no_price()?
+        };
+        if price == 0 {
+            return Err(ERROR_DIVISION_BY_ZERO);
+        }
+        let net_rate = safe_sub(FEE_BASIS_POINTS, self.fee_bps)?;
+        mul_div(
+            safe_mul(net_rate, PAYOUT_MULTIPLE_PRECISION)?,
+            PRICE_PRECISION,
+            safe_mul(FEE_BASIS_POINTS, price)?,
+        )
+    }
+
+    // 边际价格冲击：按当前储备买入 MARGINAL_IMPACT_STEP（不计手续费）后该结果价格的上涨幅度
+    // 储备越深数值越小，用于衡量市场深度
+    pub fn marginal_impact(&self, outcome: u64) -> Result<u64, u32> {
+        let is_yes = Self::validate_bet_type(outcome)?;
+        let price_before = if is_yes { self.get_yes_price()? } else { self.get_no_price()? };
+        let price_after = self.pricing().price_after_buy(is_yes, MARGINAL_IMPACT_STEP)?;
+        Ok(price_after.saturating_sub(price_before))
+    }
+
+    // 单笔下注后该结果价格上涨的幅度（基点，按 PRICE_PRECISION 折算），包含手续费的影响
+    pub fn price_impact_bps(&self, outcome: u64, amount: u64) -> Result<u64, u32> {
+        let is_yes = Self::validate_bet_type(outcome)?;
+        let price_of = |market: &MarketData| if is_yes { market.get_yes_price() } else { market.get_no_price() };
+        let mut simulated = self.clone();
+        simulated.place_bet(outcome, amount)?;
+        let moved = price_of(&simulated)?.saturating_sub(price_of(self)?);
+        mul_div(moved, BASIS_POINTS_PRECISION, PRICE_PRECISION)
+    }
+
+    // 当前储备下价格冲击不超过 max_impact_bps 的最大单笔下注，0 表示连最小下注都超出
+    pub fn max_bet_for_impact(&self, outcome: u64, max_impact_bps: u64) -> Result<u64, u32> {
+        Self::validate_bet_type(outcome)?;
+        let within = |amount: u64| self.price_impact_bps(outcome, amount).is_ok_and(|impact| impact <= max_impact_bps);
+        if within(MAX_BET_AMOUNT) {
+            return Ok(MAX_BET_AMOUNT);
+        }
+        // Impact only grows with size, so bisect for the last amount within the bound
+        let (mut lo, mut hi) = (0u64, MAX_BET_AMOUNT);
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if within(mid) {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(lo)
+    }
+
+    // 每个 tick 的快照 [yes_price, yes_liquidity, no_liquidity, volume_since_last, open_interest]
+    // 未开启时返回 None；open interest 为尚未领取的奖池
+    pub fn take_tick_summary(&mut self) -> Result<Option<[u64; 5]>, u32> {
+        if !self.tick_summary_enabled {
+            return Ok(None);
+        }
+        let volume_since_last = safe_sub(self.total_volume, self.last_summary_volume)?;
+        self.last_summary_volume = self.total_volume;
+        Ok(Some([
+            self.get_yes_price()?,
+            self.yes_liquidity,
+            self.no_liquidity,
+            volume_since_last,
+            safe_sub(self.prize_pool, self.total_claimed)?,
+        ]))
+    }
+
     // 验证投注类型的辅助函数
     fn validate_bet_type(bet_type: u64) -> Result<bool, u32> {
         match bet_type {
@@ -143,33 +734,77 @@ impl MarketData {
         }
     }
 
+    // 唯一的手续费来源，下注、卖出和报价都经过这里
+    fn trade_fee(&self, _bet_type: u64, gross_amount: u64) -> Result<u64, u32> {
+        if gross_amount > MAX_BET_AMOUNT {
+            return Err(ERROR_BET_TOO_LARGE);
+        }
+        // 向上取整
+        mul_div_up(gross_amount, self.fee_bps, FEE_BASIS_POINTS)
+    }
+
+    // 挂单成交的手续费：按卖出同样的费率从成交额中扣给平台和 LP，成交计入交易量
+    pub fn collect_fill_fee(&mut self, outcome: u64, cost: u64) -> Result<u64, u32> {
+        let fee = self.trade_fee(outcome, cost)?;
+        self.distribute_fee(fee)?;
+        self.total_volume = safe_add(self.total_volume, cost)?;
+        Ok(fee)
+    }
+
+    // 下注手续费：基础费用减去 maker 返佣或加上 taker 附加费，返回 (fee, rebate, surcharge)
+    // 按基础费用模拟成交，比较前后 AMM 储备的差距判断是否改善平衡
+    fn bet_fee(&self, bet_type: u64, bet_amount: u64) -> Result<(u64, u64, u64), u32> {
+        let base_fee = self.trade_fee(bet_type, bet_amount)?;
+        let net_amount = safe_sub(bet_amount, base_fee)?;
+
+        // 以价格偏离 0.5 的程度衡量失衡，两种定价引擎都适用（LMSR 市场的储备不会变动）
+        let is_yes = Self::validate_bet_type(bet_type)?;
+        let pricing = self.pricing();
+        let (yes_before, _) = pricing.prices()?;
+        let own_after = pricing.price_after_buy(is_yes, net_amount)?;
+        let yes_after = if is_yes { own_after } else { PRICE_PRECISION.saturating_sub(own_after) };
+        let imbalance_before = yes_before.abs_diff(PRICE_PRECISION / 2);
+        let imbalance_after = yes_after.abs_diff(PRICE_PRECISION / 2);
+
+        let rate_of = |rate: u64| mul_div(bet_amount, rate, FEE_BASIS_POINTS);
+        match imbalance_after.cmp(&imbalance_before) {
+            std::cmp::Ordering::Less => {
+                let rebate = rate_of(MAKER_REBATE_RATE)?.min(base_fee);
+                Ok((base_fee - rebate, rebate, 0))
+            }
+            std::cmp::Ordering::Greater => {
+                let surcharge = rate_of(TAKER_SURCHARGE_RATE)?;
+                Ok((safe_add(base_fee, surcharge)?, 0, surcharge))
+            }
+            std::cmp::Ordering::Equal => Ok((base_fee, 0, 0)),
+        }
+    }
+
+    // 下注前报价：place_bet 对该金额实际收取的手续费
+    pub fn quote_fee(&self, bet_type: u64, bet_amount: u64) -> Result<u64, u32> {
+        validate_bet_amount(bet_amount)?;
+        Self::validate_bet_type(bet_type)?;
+        let (fee, _, _) = self.bet_fee(bet_type, bet_amount)?;
+        Ok(fee)
+    }
+
+    // 卖出前报价：sell_shares 对该份额实际收取的手续费
+    pub fn quote_sell_fee(&self, sell_type: u64, shares_to_sell: u64) -> Result<u64, u32> {
+        let (_, fee) = self.quote_sell_details(sell_type, shares_to_sell)?;
+        Ok(fee)
+    }
+
     // 统一的份额计算函数（bet_type: 1=YES, 0=NO）
     pub fn calculate_shares(&self, bet_type: u64, bet_amount: u64) -> Result<u64, u32> {
         validate_bet_amount(bet_amount)?;
         let is_yes_bet = Self::validate_bet_type(bet_type)?;
         
-        let net_amount = calculate_net_amount_safe(bet_amount)?;
-        
-        // 安全的 AMM 计算
-        let k = calculate_k_safe(self.yes_liquidity, self.no_liquidity)?;
-        
-        let (new_yes_liquidity, new_no_liquidity, original_liquidity) = if is_yes_bet {
-            let new_no = safe_add(self.no_liquidity, net_amount)?;
-            let new_yes = calculate_new_liquidity_safe(k, new_no)?;
-            (new_yes, new_no, self.yes_liquidity)
-        } else {
-            let new_yes = safe_add(self.yes_liquidity, net_amount)?;
-            let new_no = calculate_new_liquidity_safe(k, new_yes)?;
-            (new_yes, new_no, self.no_liquidity)
-        };
+        let (fee, _, _) = self.bet_fee(bet_type, bet_amount)?;
+        let net_amount = safe_sub(bet_amount, fee)?;
         
-        if original_liquidity >= if is_yes_bet { new_yes_liquidity } else { new_no_liquidity } {
-            let shares = safe_sub(original_liquidity, if is_yes_bet { new_yes_liquidity } else { new_no_liquidity })?;
-            validate_shares(shares)?;
-            Ok(shares)
-        } else {
-            Ok(0)
-        }
+        let shares = self.pricing().shares_for(is_yes_bet, net_amount)?;
+        validate_shares(shares)?;
+        Ok(shares)
     }
 
 
@@ -189,33 +824,12 @@ impl MarketData {
             return Ok((0, 0));
         }
         
-        // 安全的 AMM 计算
-        let k = calculate_k_safe(self.yes_liquidity, self.no_liquidity)?;
-        let (new_yes_liquidity, new_no_liquidity) = if is_yes_sell {
-            let new_yes = safe_add(self.yes_liquidity, shares_to_sell)?;
-            let new_no = calculate_new_liquidity_safe(k, new_yes)?;
-            (new_yes, new_no)
-        } else {
-            let new_no = safe_add(self.no_liquidity, shares_to_sell)?;
-            let new_yes = calculate_new_liquidity_safe(k, new_no)?;
-            (new_yes, new_no)
-        };
-        
-        let gross_amount = if is_yes_sell {
-            if self.no_liquidity >= new_no_liquidity {
-                safe_sub(self.no_liquidity, new_no_liquidity)?
-            } else {
-                return Ok((0, 0));
-            }
-        } else {
-            if self.yes_liquidity >= new_yes_liquidity {
-                safe_sub(self.yes_liquidity, new_yes_liquidity)?
-            } else {
-                return Ok((0, 0));
-            }
-        };
+        let gross_amount = self.pricing().proceeds_for(is_yes_sell, shares_to_sell)?;
+        if gross_amount == 0 {
+            return Ok((0, 0));
+        }
         
-        let fee = calculate_fee_safe(gross_amount)?;
+        let fee = self.trade_fee(sell_type, gross_amount)?;
         let net_payout = safe_sub(gross_amount, fee)?;
         
         Ok((net_payout, fee))
@@ -279,47 +893,113 @@ impl MarketData {
 
     // 统一的投注函数（bet_type: 1=YES, 0=NO）
     pub fn place_bet(&mut self, bet_type: u64, bet_amount: u64) -> Result<u64, u32> {
-        validate_bet_amount(bet_amount)?;
-
-        let shares = self.calculate_shares(bet_type, bet_amount)?;
-        if shares == 0 {
-            return Err(ERROR_INVALID_BET_AMOUNT);
-        }
+        let shares = self.quote_bet(bet_type, bet_amount)?;
 
-        let fee = calculate_fee_safe(bet_amount)?;
+        let (fee, rebate, surcharge) = self.bet_fee(bet_type, bet_amount)?;
         let net_amount = safe_sub(bet_amount, fee)?;
         let is_yes_bet = bet_type == 1;
         
-        // 安全更新 AMM 流动性
-        let k = calculate_k_safe(self.yes_liquidity, self.no_liquidity)?;
+        // 安全更新 AMM 流动性；LMSR 的状态就是已发行份额，不动储备
+        if !self.is_lmsr() {
+            let k = calculate_k_safe(self.yes_liquidity, self.no_liquidity)?;
+            if is_yes_bet {
+                self.no_liquidity = safe_add(self.no_liquidity, net_amount)?;
+                self.yes_liquidity = calculate_new_liquidity_safe(k, self.no_liquidity)?;
+            } else {
+                self.yes_liquidity = safe_add(self.yes_liquidity, net_amount)?;
+                self.no_liquidity = calculate_new_liquidity_safe(k, self.yes_liquidity)?;
+            }
+        }
         if is_yes_bet {
-            self.no_liquidity = safe_add(self.no_liquidity, net_amount)?;
-            self.yes_liquidity = calculate_new_liquidity_safe(k, self.no_liquidity)?;
             self.total_yes_shares = safe_add(self.total_yes_shares, shares)?;
         } else {
-            self.yes_liquidity = safe_add(self.yes_liquidity, net_amount)?;
-            self.no_liquidity = calculate_new_liquidity_safe(k, self.yes_liquidity)?;
             self.total_no_shares = safe_add(self.total_no_shares, shares)?;
         }
         
         // 安全更新状态
         self.prize_pool = safe_add(self.prize_pool, net_amount)?;
         self.total_volume = safe_add(self.total_volume, bet_amount)?;
-        self.total_fees_collected = safe_add(self.total_fees_collected, fee)?;
+        self.distribute_fee(fee)?;
+        self.total_maker_rebates = safe_add(self.total_maker_rebates, rebate)?;
+        self.total_taker_surcharges = safe_add(self.total_taker_surcharges, surcharge)?;
+        self.check_solvency()?;
         
         Ok(shares)
     }
 
 
 
-    // 统一的卖出函数（sell_type: 1=YES, 0=NO）
-    pub fn sell_shares(&mut self, sell_type: u64, shares_to_sell: u64) -> Result<u64, u32> {
+    // 单笔下注上下限，0 表示不限
+    pub fn check_bet_amount_limit(&self, bet_amount: u64) -> Result<(), u32> {
+        if bet_amount < self.min_bet_amount {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        if self.max_bet_amount > 0 && bet_amount > self.max_bet_amount {
+            return Err(ERROR_POSITION_LIMIT_EXCEEDED);
+        }
+        Ok(())
+    }
+
+    // 单边持仓上限，0 表示不限
+    pub fn check_position_limit(&self, held_after: u64) -> Result<(), u32> {
+        if self.max_position > 0 && held_after > self.max_position {
+            return Err(ERROR_POSITION_LIMIT_EXCEEDED);
+        }
+        Ok(())
+    }
+
+    // 下注报价：与 place_bet 的定价和校验完全一致，不修改储备和费用
+    pub fn quote_bet(&self, bet_type: u64, bet_amount: u64) -> Result<u64, u32> {
+        validate_bet_amount(bet_amount)?;
+
+        let shares = self.calculate_shares(bet_type, bet_amount)?;
+        if shares == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        Ok(shares)
+    }
+
+    // 卖出报价：sell_shares 将支付的净额
+    pub fn quote_sell(&self, sell_type: u64, shares_to_sell: u64) -> Result<u64, u32> {
+        let (payout, _) = self.quote_sell_details(sell_type, shares_to_sell)?;
+        Ok(payout)
+    }
+
+    // 拿到至少 target_payout 所需卖出的最少份额（份额向上取整，到手金额不低于目标）；
+    // 全部 max_shares 卖出仍不够时返回 max_shares
+    pub fn shares_for_payout(&self, sell_type: u64, target_payout: u64, max_shares: u64) -> Result<u64, u32> {
+        Self::validate_bet_type(sell_type)?;
+        if target_payout == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        if max_shares == 0 {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
+        }
+        let reaches = |shares: u64| self.quote_sell(sell_type, shares).is_ok_and(|payout| payout >= target_payout);
+        if !reaches(max_shares) {
+            return Ok(max_shares);
+        }
+        // Payout never shrinks as more shares are sold, so bisect for the fewest that reach the target
+        let (mut lo, mut hi) = (0u64, max_shares);
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if reaches(mid) {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        Ok(hi)
+    }
+
+    // 卖出的全部校验与定价，返回 (payout, fee)
+    fn quote_sell_details(&self, sell_type: u64, shares_to_sell: u64) -> Result<(u64, u64), u32> {
         validate_shares(shares_to_sell)?;
 
-        let (total_shares, is_yes_sell) = if sell_type == 1 {
-            (self.total_yes_shares, true)
+        let total_shares = if sell_type == 1 {
+            self.total_yes_shares
         } else {
-            (self.total_no_shares, false)
+            self.total_no_shares
         };
 
         if shares_to_sell > total_shares {
@@ -335,83 +1015,741 @@ impl MarketData {
         if payout > self.prize_pool {
             return Err(ERROR_INSUFFICIENT_BALANCE);
         }
+        // The fee leaves the pool along with the payout; AMM rounding can leave slightly less
+        // than the full fee behind, and only what is there can be collected
+        Ok((payout, fee.min(self.prize_pool - payout)))
+    }
+
+    // 统一的卖出函数（sell_type: 1=YES, 0=NO）
+    pub fn sell_shares(&mut self, sell_type: u64, shares_to_sell: u64) -> Result<u64, u32> {
+        let (payout, fee) = self.quote_sell_details(sell_type, shares_to_sell)?;
+        let is_yes_sell = sell_type == 1;
 
-        // 安全更新 AMM 流动性
-        let k = calculate_k_safe(self.yes_liquidity, self.no_liquidity)?;
+        // 安全更新 AMM 流动性；LMSR 的状态就是已发行份额，不动储备
+        if !self.is_lmsr() {
+            let k = calculate_k_safe(self.yes_liquidity, self.no_liquidity)?;
+            if is_yes_sell {
+                self.yes_liquidity = safe_add(self.yes_liquidity, shares_to_sell)?;
+                self.no_liquidity = calculate_new_liquidity_safe(k, self.yes_liquidity)?;
+            } else {
+                self.no_liquidity = safe_add(self.no_liquidity, shares_to_sell)?;
+                self.yes_liquidity = calculate_new_liquidity_safe(k, self.no_liquidity)?;
+            }
+        }
         if is_yes_sell {
-            self.yes_liquidity = safe_add(self.yes_liquidity, shares_to_sell)?;
-            self.no_liquidity = calculate_new_liquidity_safe(k, self.yes_liquidity)?;
             self.total_yes_shares = safe_sub(self.total_yes_shares, shares_to_sell)?;
         } else {
-            self.no_liquidity = safe_add(self.no_liquidity, shares_to_sell)?;
-            self.yes_liquidity = calculate_new_liquidity_safe(k, self.no_liquidity)?;
             self.total_no_shares = safe_sub(self.total_no_shares, shares_to_sell)?;
         }
 
         // 安全更新状态
-        self.prize_pool = safe_sub(self.prize_pool, payout)?;
-        self.total_fees_collected = safe_add(self.total_fees_collected, fee)?;
+        self.prize_pool = safe_sub(self.prize_pool, safe_add(payout, fee)?)?;
+        self.distribute_fee(fee)?;
         // 将卖出金额（payout + fee）计入总交易量
         let total_transaction_value = safe_add(payout, fee)?;
         self.total_volume = safe_add(self.total_volume, total_transaction_value)?;
+        self.check_solvency()?;
 
         Ok(payout)
     }
 
 
 
-    // 市场解决
-    pub fn resolve(&mut self, outcome: bool) -> Result<(), u32> {
+    // 铸造完整份额组：每单位抵押品各发行一份 YES 和 NO，不经过 AMM，价格不变，不收手续费
+    pub fn mint_set(&mut self, amount: u64) -> Result<(), u32> {
         if self.resolved {
             return Err(ERROR_MARKET_ALREADY_RESOLVED);
         }
-        
-        self.resolved = true;
-        self.outcome = Some(outcome);
+        validate_bet_amount(amount)?;
+        self.total_yes_shares = safe_add(self.total_yes_shares, amount)?;
+        self.total_no_shares = safe_add(self.total_no_shares, amount)?;
+        self.prize_pool = safe_add(self.prize_pool, amount)?;
         Ok(())
     }
 
-    // 安全计算奖金
-    pub fn calculate_payout(&self, yes_shares: u64, no_shares: u64) -> Result<u64, u32> {
-        if !self.resolved || self.prize_pool == 0 {
-            return Ok(0);
+    // 销毁完整份额组，取回等额抵押品，是 mint_set 的逆操作
+    pub fn burn_set(&mut self, amount: u64) -> Result<(), u32> {
+        if self.resolved {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        if amount == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
         }
+        self.total_yes_shares = safe_sub(self.total_yes_shares, amount)?;
+        self.total_no_shares = safe_sub(self.total_no_shares, amount)?;
+        self.prize_pool = safe_sub(self.prize_pool, amount)?;
+        Ok(())
+    }
 
-        match self.outcome {
-            Some(true) => {
-                // YES 获胜
-                if self.total_yes_shares == 0 {
-                    return Ok(0);
-                }
-                safe_div_high_precision(yes_shares, self.prize_pool, self.total_yes_shares)
-            },
-            Some(false) => {
-                // NO 获胜
-                if self.total_no_shares == 0 {
-                    return Ok(0);
-                }
-                safe_div_high_precision(no_shares, self.prize_pool, self.total_no_shares)
-            },
-            None => Ok(0),
+    // 手续费分给 LP 的部分计入每份额累计值，其余归平台
+    fn distribute_fee(&mut self, fee: u64) -> Result<(), u32> {
+        let mut platform_fee = fee;
+        if self.total_lp_shares > 0 {
+            let lp_cut = mul_div(fee, self.lp_fee_share_bps, BASIS_POINTS_PRECISION)?;
+            let per_share = mul_div(lp_cut, LP_FEE_SCALE, self.total_lp_shares)?;
+            // Only what the accumulator can actually pay out leaves the platform's share
+            let distributed = mul_div(per_share, self.total_lp_shares, LP_FEE_SCALE)?;
+            self.lp_fee_per_share = safe_add(self.lp_fee_per_share, per_share)?;
+            self.lp_fees_owed = safe_add(self.lp_fees_owed, distributed)?;
+            platform_fee = safe_sub(platform_fee, distributed)?;
         }
+        self.total_fees_collected = safe_add(self.total_fees_collected, platform_fee)?;
+        Ok(())
     }
 
-    // // 获取份额价值（解决前估算）- 前端分析用，后端不使用
-    // pub fn get_share_value(&self, is_yes_share: bool) -> Result<u64, u32> {
-    //     if self.prize_pool == 0 {
-    //         return Ok(0);
-    //     }
-    //     
-    //     if is_yes_share {
-    //         if self.total_yes_shares == 0 { return Ok(0); }
-    //         let total_shares = safe_add(self.total_yes_shares, self.total_no_shares)?;
-    //         safe_div(self.prize_pool, total_shares)
-    //     } else {
-    //         if self.total_no_shares == 0 { return Ok(0); }
-    //         let total_shares = safe_add(self.total_yes_shares, self.total_no_shares)?;
-    //         safe_div(self.prize_pool, total_shares)
-    //     }
-    // }
+    // 按当前比例加深两侧储备，价格不变
+    pub fn deepen_reserves(&mut self, amount: u64) -> Result<(), u32> {
+        self.ensure_constant_product()?;
+        let (yes_part, no_part) = self.split_by_reserves(amount)?;
+        self.yes_liquidity = safe_add(self.yes_liquidity, yes_part)?;
+        self.no_liquidity = safe_add(self.no_liquidity, no_part)?;
+        validate_liquidity(self.yes_liquidity)?;
+        validate_liquidity(self.no_liquidity)
+    }
+
+    // deepen_reserves 的逆操作，储备不能低于最小流动性
+    pub fn thin_reserves(&mut self, amount: u64) -> Result<(), u32> {
+        self.ensure_constant_product()?;
+        let (yes_part, no_part) = self.split_by_reserves(amount)?;
+        self.yes_liquidity = safe_sub(self.yes_liquidity, yes_part).map_err(|_| ERROR_INVALID_CALCULATION)?;
+        self.no_liquidity = safe_sub(self.no_liquidity, no_part).map_err(|_| ERROR_INVALID_CALCULATION)?;
+        validate_liquidity(self.yes_liquidity)?;
+        validate_liquidity(self.no_liquidity)
+    }
+
+    // LMSR 市场的深度由 b 决定，没有可供 LP 加深的储备
+    fn ensure_constant_product(&self) -> Result<(), u32> {
+        if self.is_lmsr() {
+            return Err(ERROR_PRICING_UNSUPPORTED);
+        }
+        Ok(())
+    }
+
+    fn split_by_reserves(&self, amount: u64) -> Result<(u64, u64), u32> {
+        let total = safe_add(self.yes_liquidity, self.no_liquidity)?;
+        let yes_part = mul_div(amount, self.yes_liquidity, total)?;
+        Ok((yes_part, amount - yes_part))
+    }
+
+    // 手续费的去向：目标为零时记给接收者，否则记给指定账户
+    pub fn fee_destination(&self, to: [u64; 2]) -> [u64; 2] {
+        if to == [0, 0] { self.fee_recipient } else { to }
+    }
+
+    // 提取平台手续费：只有手续费接收者或管理员可以触发；只有接收者本人可以把资金转给其他账户
+    pub fn withdraw_fees(&mut self, caller: &[u64; 2], admin: &[u64; 2], to: [u64; 2]) -> Result<([u64; 2], u64), u32> {
+        if caller != &self.fee_recipient && caller != admin {
+            return Err(ERROR_UNAUTHORIZED);
+        }
+        let destination = self.fee_destination(to);
+        if destination != self.fee_recipient && caller != &self.fee_recipient {
+            return Err(ERROR_UNAUTHORIZED);
+        }
+        if self.total_fees_collected == 0 {
+            return Err(ERROR_NO_FEES_TO_WITHDRAW);
+        }
+        Ok((destination, std::mem::take(&mut self.total_fees_collected)))
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        self.scalar_high > self.scalar_low
+    }
+
+    // 记录一位预言机的数值（重复提交覆盖旧值），达到法定数量后按中位数结算；返回是否已结算
+    pub fn submit_oracle_value(&mut self, oracle: [u64; 2], value: u64) -> Result<bool, u32> {
+        if self.resolved {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        if !self.is_scalar() {
+            return Err(ERROR_INVALID_OUTCOME);
+        }
+        match self.oracle_submissions.iter_mut().find(|(pid, _)| *pid == oracle) {
+            Some(submission) => submission.1 = value,
+            None => self.oracle_submissions.push((oracle, value)),
+        }
+        if (self.oracle_submissions.len() as u64) < self.oracle_quorum {
+            return Ok(false);
+        }
+        let median = self.oracle_median()?;
+        self.resolve_scalar(median)?;
+        Ok(true)
+    }
+
+    // 提交值的整数中位数，偶数个时取中间两个的平均值（向下取整）
+    pub fn oracle_median(&self) -> Result<u64, u32> {
+        if self.oracle_submissions.is_empty() || (self.oracle_submissions.len() as u64) < self.oracle_quorum {
+            return Err(ERROR_INSUFFICIENT_ORACLE_DATA);
+        }
+        let mut values: Vec<u64> = self.oracle_submissions.iter().map(|(_, value)| *value).collect();
+        values.sort_unstable();
+        let mid = values.len() / 2;
+        if values.len() % 2 == 1 {
+            Ok(values[mid])
+        } else {
+            let (a, b) = (values[mid - 1], values[mid]);
+            // Average without overflowing
+            Ok(a / 2 + b / 2 + (a % 2 + b % 2) / 2)
+        }
+    }
+
+    // 按数值在 [scalar_low, scalar_high] 中的位置线性分配 YES/NO 权重
+    pub fn resolve_scalar(&mut self, value: u64) -> Result<(), u32> {
+        if !self.is_scalar() {
+            return Err(ERROR_INVALID_OUTCOME);
+        }
+        let clamped = value.clamp(self.scalar_low, self.scalar_high);
+        let yes_weight = mul_div(clamped - self.scalar_low, BASIS_POINTS_PRECISION, self.scalar_high - self.scalar_low)?;
+        self.resolve_distribution(vec![BASIS_POINTS_PRECISION - yes_weight, yes_weight])?;
+        self.oracle_median = value;
+        Ok(())
+    }
+
+    // 作废市场（任何未结算状态 -> Cancelled）：不再交易，也没有获胜结果，持仓按成本比例退还奖池
+    pub fn void(&mut self) -> Result<(), u32> {
+        if self.resolved {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        self.resolved = true;
+        self.voided = true;
+        self.outcome = None;
+        Ok(())
+    }
+
+    pub fn record_cost(&mut self, amount: u64) -> Result<(), u32> {
+        self.total_cost_basis = safe_add(self.total_cost_basis, amount)?;
+        Ok(())
+    }
+
+    pub fn release_cost(&mut self, amount: u64) -> Result<(), u32> {
+        // Rounding in per-position releases can never take the total below zero
+        self.total_cost_basis = self.total_cost_basis.saturating_sub(amount);
+        Ok(())
+    }
+
+    // 作废后的一次退款：剩余奖池按该持仓成本占尚未退款成本的比例分配，无人交易时为 0
+    pub fn settle_refund(&mut self, cost_basis: u64) -> Result<u64, u32> {
+        if !self.voided {
+            return Err(ERROR_MARKET_NOT_RESOLVED);
+        }
+        let outstanding_cost = safe_sub(self.total_cost_basis, self.refunded_cost_basis)?;
+        if outstanding_cost == 0 || cost_basis == 0 {
+            return Ok(0);
+        }
+        let unclaimed_pool = safe_sub(self.prize_pool, self.total_claimed)?;
+        let refund = mul_div(unclaimed_pool, cost_basis.min(outstanding_cost), outstanding_cost)?;
+        self.refunded_cost_basis = safe_add(self.refunded_cost_basis, cost_basis.min(outstanding_cost))?;
+        self.total_claimed = safe_add(self.total_claimed, refund)?;
+        Ok(refund)
+    }
+
+    // 开启 require_nonzero_winning_side 时，获得权重的结果必须有人持有份额，否则应改为作废
+    fn check_participation(&self, weights: [u64; 2]) -> Result<(), u32> {
+        if !self.require_nonzero_winning_side {
+            return Ok(());
+        }
+        let held = [self.total_no_shares, self.total_yes_shares];
+        if weights.iter().zip(held.iter()).all(|(weight, shares)| *weight == 0 || *shares == 0) {
+            return Err(ERROR_NO_PARTICIPANTS_ON_OUTCOME);
+        }
+        Ok(())
+    }
+
+    // 市场解决
+    pub fn resolve(&mut self, outcome: bool) -> Result<(), u32> {
+        if self.resolved {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        let weights = if outcome { [0, BASIS_POINTS_PRECISION] } else { [BASIS_POINTS_PRECISION, 0] };
+        self.check_participation(weights)?;
+        
+        self.resolved = true;
+        self.outcome = Some(outcome);
+        Ok(())
+    }
+
+    // 争议期结束前不能领奖；作废市场的退款不受争议期限制
+    pub fn ensure_claims_open(&self, current_time: u64) -> Result<(), u32> {
+        if self.dispute_escalated && !self.voided {
+            return if self.dispute_decided { Ok(()) } else { Err(ERROR_CLAIM_NOT_OPEN) };
+        }
+        if self.resolved && !self.voided && current_time < safe_add(self.resolved_at, self.dispute_window)? {
+            return Err(ERROR_CLAIM_NOT_OPEN);
+        }
+        Ok(())
+    }
+
+    // 争议期内由管理员更正结果；期满后结果为最终结果。期内没有人能领奖，所以奖池尚未分配。
+    // 已升级的争议在期满后仍等待管理员裁决，裁决即为最终结果，押错一方的保证金计入平台费
+    pub fn override_resolution(&mut self, outcome: bool, current_time: u64) -> Result<(), u32> {
+        if !self.resolved {
+            return Err(ERROR_MARKET_NOT_RESOLVED);
+        }
+        let open = if self.dispute_escalated {
+            !self.dispute_decided
+        } else {
+            current_time < safe_add(self.resolved_at, self.dispute_window)?
+        };
+        if self.voided || !open {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        let weights = if outcome { [0, BASIS_POINTS_PRECISION] } else { [BASIS_POINTS_PRECISION, 0] };
+        self.check_participation(weights)?;
+        if self.dispute_escalated {
+            let forfeited = std::mem::take(&mut self.dispute_stake[!outcome as usize]);
+            self.total_fees_collected = safe_add(self.total_fees_collected, forfeited)?;
+            self.dispute_decided = true;
+            // 裁决时领奖才开放，领奖期从此刻算起
+            self.resolved_at = current_time;
+        }
+        self.outcome = Some(outcome);
+        self.resolution_weights.clear();
+        Ok(())
+    }
+
+    // 领奖开放的时间：作废或争议已裁决时立即开放，否则等争议期结束
+    pub fn claims_open_at(&self) -> Result<u64, u32> {
+        if self.voided || self.dispute_decided {
+            Ok(self.resolved_at)
+        } else {
+            safe_add(self.resolved_at, self.dispute_window)
+        }
+    }
+
+    // 领奖截止时间，未设置领奖期时没有截止
+    pub fn claim_deadline(&self) -> Result<Option<u64>, u32> {
+        if self.claim_period == 0 {
+            return Ok(None);
+        }
+        Ok(Some(safe_add(self.claims_open_at()?, self.claim_period)?))
+    }
+
+    pub fn ensure_claim_not_expired(&self, current_time: u64) -> Result<(), u32> {
+        match self.claim_deadline()? {
+            Some(deadline) if self.resolved && current_time >= deadline => Err(ERROR_CLAIM_EXPIRED),
+            _ => Ok(()),
+        }
+    }
+
+    // 领奖期结束后把未领取的奖池转入国库，只能转一次；记入 total_claimed 使账目保持平衡。
+    // 已升级但未裁决的争议期间领奖被冻结，领奖期不算数
+    pub fn sweep_unclaimed(&mut self, current_time: u64) -> Result<u64, u32> {
+        if !self.resolved {
+            return Err(ERROR_MARKET_NOT_RESOLVED);
+        }
+        if self.dispute_escalated && !self.dispute_decided && !self.voided {
+            return Err(ERROR_CLAIM_NOT_OPEN);
+        }
+        match self.claim_deadline()? {
+            Some(deadline) if current_time >= deadline => {}
+            _ => return Err(ERROR_CLAIM_PERIOD_ACTIVE),
+        }
+        let unclaimed = safe_sub(self.prize_pool, self.total_claimed)?;
+        if self.swept > 0 || unclaimed == 0 {
+            return Err(ERROR_NOTHING_TO_SWEEP);
+        }
+        self.total_claimed = safe_add(self.total_claimed, unclaimed)?;
+        self.swept = unclaimed;
+        Ok(unclaimed)
+    }
+
+    // 争议期内押保证金主张另一结果，累计保证金达到阈值即升级给管理员裁决；返回是否已升级。
+    // 只适用于二元结果，分布式结算没有单一的“另一结果”
+    pub fn dispute(&mut self, outcome: bool, bond: u64, current_time: u64) -> Result<bool, u32> {
+        if !self.resolved {
+            return Err(ERROR_MARKET_NOT_RESOLVED);
+        }
+        if self.voided || self.dispute_decided || current_time >= safe_add(self.resolved_at, self.dispute_window)? {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        if !self.resolution_weights.is_empty() || self.outcome == Some(outcome) || self.outcome.is_none() {
+            return Err(ERROR_INVALID_OUTCOME);
+        }
+        if bond == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        let stake = &mut self.dispute_stake[outcome as usize];
+        *stake = safe_add(*stake, bond)?;
+        if safe_add(self.dispute_stake[0], self.dispute_stake[1])? >= DISPUTE_ESCALATION_STAKE {
+            self.dispute_escalated = true;
+        }
+        Ok(self.dispute_escalated)
+    }
+
+    // 退还保证金：未升级的争议在争议期结束后全部退还；已升级的只退给管理员裁决支持的一方；
+    // 市场作废时全部退还
+    pub fn release_dispute_bond(&mut self, outcome: bool, bond: u64, current_time: u64) -> Result<(), u32> {
+        if bond == 0 {
+            return Err(ERROR_NO_DISPUTE_BOND);
+        }
+        if !self.voided {
+            if self.dispute_escalated {
+                if !self.dispute_decided {
+                    return Err(ERROR_CLAIM_NOT_OPEN);
+                }
+                if self.outcome != Some(outcome) {
+                    return Err(ERROR_NO_DISPUTE_BOND);
+                }
+            } else {
+                self.ensure_claims_open(current_time)?;
+            }
+        }
+        let stake = &mut self.dispute_stake[outcome as usize];
+        *stake = stake.checked_sub(bond).ok_or(ERROR_INSOLVENT)?;
+        Ok(())
+    }
+
+    // 提议结算结果，重新提议会重新开始等待期
+    pub fn propose_resolution(&mut self, outcome: bool, current_time: u64) -> Result<(), u32> {
+        if self.resolved {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        self.proposed_outcome = Some(outcome);
+        self.proposed_at = current_time;
+        Ok(())
+    }
+
+    // 记录一位预言机的二元结果（重复提交覆盖旧值）；多数一方达到法定数量才成为 oracle_outcome，
+    // 到达结算时间后由 Tick 自动结算；结算前可以更正。返回当前是否已有达成一致的结果
+    pub fn submit_oracle_outcome(&mut self, oracle: [u64; 2], outcome: bool) -> Result<bool, u32> {
+        if self.resolved {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        if self.is_scalar() {
+            return Err(ERROR_INVALID_OUTCOME);
+        }
+        match self.oracle_submissions.iter_mut().find(|(pid, _)| *pid == oracle) {
+            Some(submission) => submission.1 = outcome as u64,
+            None => self.oracle_submissions.push((oracle, outcome as u64)),
+        }
+        let yes = self.oracle_submissions.iter().filter(|(_, value)| *value == 1).count() as u64;
+        let no = self.oracle_submissions.len() as u64 - yes;
+        // 平票或多数一方未达法定数量时没有结果
+        self.oracle_outcome = match yes.cmp(&no) {
+            std::cmp::Ordering::Greater if yes >= self.oracle_quorum => Some(true),
+            std::cmp::Ordering::Less if no >= self.oracle_quorum => Some(false),
+            _ => None,
+        };
+        Ok(self.oracle_outcome.is_some())
+    }
+
+    // 有预言机结果且已可结算时结算，返回是否结算；失败时市场保持不变
+    pub fn try_auto_resolve(&mut self, current_time: u64) -> Result<bool, u32> {
+        let outcome = match self.oracle_outcome {
+            Some(outcome) if !self.resolved => outcome,
+            _ => return Ok(false),
+        };
+        self.ensure_resolvable(current_time)?;
+        let mut staged = self.clone();
+        staged.resolve(outcome)?;
+        staged.resolved_at = current_time;
+        *self = staged;
+        Ok(true)
+    }
+
+    // 等待期结束后确认提议的结果
+    pub fn finalize_resolution(&mut self, current_time: u64) -> Result<(), u32> {
+        let outcome = self.proposed_outcome.ok_or(ERROR_NO_PENDING_RESOLUTION)?;
+        if current_time < safe_add(self.proposed_at, self.resolution_delay)? {
+            return Err(ERROR_RESOLUTION_TOO_EARLY);
+        }
+        self.resolve(outcome)?;
+        self.proposed_outcome = None;
+        self.resolved_at = current_time;
+        Ok(())
+    }
+
+    // 按概率分布结算（[NO, YES] 基点，总和必须为 10000）
+    pub fn resolve_distribution(&mut self, weights: Vec<u64>) -> Result<(), u32> {
+        if self.resolved {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        if weights.len() != 2 || weights.iter().try_fold(0u64, |acc, w| acc.checked_add(*w)) != Some(BASIS_POINTS_PRECISION) {
+            return Err(ERROR_INVALID_OUTCOME);
+        }
+        self.check_participation([weights[0], weights[1]])?;
+
+        self.resolved = true;
+        // The heavier side is reported as the outcome for display purposes
+        self.outcome = Some(weights[1] >= weights[0]);
+        self.resolution_weights = weights;
+        Ok(())
+    }
+
+    // 安全计算奖金：奖池按获胜份额比例分配（部分结算时按权重），失败份额价值为 0
+    // 每份获胜份额价值 prize_pool / 获胜总份额，而非固定 1 单位：AMM 发行的份额多于投入，
+    // 固定赎回价会超出奖池。向下取整保证所有赔付之和不超过奖池（已扣除手续费的投注减去卖出支付）
+    pub fn calculate_payout(&self, yes_shares: u64, no_shares: u64) -> Result<u64, u32> {
+        if self.prize_pool == 0 {
+            return Ok(0);
+        }
+
+        match self.settled_weights() {
+            Some(weights) => self.weighted_value(yes_shares, no_shares, weights),
+            None => Ok(0),
+        }
+    }
+
+    // 持仓还能领取的金额：已领取或未结算为 0，否则为 settle_claim 将支付的数额
+    pub fn remaining_claimable(&self, position: &PlayerMarketPosition) -> Result<u64, u32> {
+        if position.claimed || !self.resolved {
+            return Ok(0);
+        }
+        if self.voided {
+            return self.clone().settle_refund(position.cost_basis()?);
+        }
+        let unclaimed_pool = safe_sub(self.prize_pool, self.total_claimed)?;
+        Ok(self.calculate_payout(position.yes_shares, position.no_shares)?.min(unclaimed_pool))
+    }
+
+    // 已结算市场每个结果的权重（基点，[NO, YES]），未结算返回 None
+    pub fn settled_weights(&self) -> Option<[u64; 2]> {
+        if !self.resolved {
+            return None;
+        }
+        if self.resolution_weights.len() == 2 {
+            return Some([self.resolution_weights[0], self.resolution_weights[1]]);
+        }
+        match self.outcome {
+            Some(true) => Some([0, BASIS_POINTS_PRECISION]),
+            Some(false) => Some([BASIS_POINTS_PRECISION, 0]),
+            None => None,
+        }
+    }
+
+    // 假设市场此刻以 outcome 结算时持仓的价值
+    pub fn position_value(&self, yes_shares: u64, no_shares: u64, outcome: bool) -> Result<u64, u32> {
+        let weights = if outcome { [0, BASIS_POINTS_PRECISION] } else { [BASIS_POINTS_PRECISION, 0] };
+        self.weighted_value(yes_shares, no_shares, weights)
+    }
+
+    // 持仓在最差与最好结果下的赔付 (min, max)，shares_per_outcome 为 [NO, YES]
+    pub fn position_extremes(&self, shares_per_outcome: [u64; 2]) -> Result<(u64, u64), u32> {
+        let [no_shares, yes_shares] = shares_per_outcome;
+        let if_no = self.position_value(yes_shares, no_shares, false)?;
+        let if_yes = self.position_value(yes_shares, no_shares, true)?;
+        Ok((if_no.min(if_yes), if_no.max(if_yes)))
+    }
+
+    // 每个结果按权重分得奖池的一部分，再按份额比例分给该结果的持有者（向下取整）
+    pub fn weighted_value(&self, yes_shares: u64, no_shares: u64, weights: [u64; 2]) -> Result<u64, u32> {
+        let sides = [(no_shares, self.total_no_shares), (yes_shares, self.total_yes_shares)];
+        let mut value: u128 = 0;
+        for (weight, (shares, total_shares)) in weights.iter().zip(sides.iter()) {
+            if *weight == 0 || *total_shares == 0 {
+                continue;
+            }
+            let numerator = (*shares as u128)
+                .checked_mul(self.prize_pool as u128)
+                .and_then(|v| v.checked_mul(*weight as u128))
+                .ok_or(ERROR_OVERFLOW)?;
+            value += numerator / (*total_shares as u128 * BASIS_POINTS_PRECISION as u128);
+        }
+        if value > u64::MAX as u128 {
+            return Err(ERROR_OVERFLOW);
+        }
+        Ok(value as u64)
+    }
+
+    // 计算使持仓在两种结果下价值相等的对冲投注 (bet_type, amount)，花费不超过 max_cost
+    pub fn hedge_to_neutral(&self, yes_shares: u64, no_shares: u64, max_cost: u64) -> Result<Option<(u64, u64)>, u32> {
+        let yes_value = self.position_value(yes_shares, no_shares, true)?;
+        let no_value = self.position_value(yes_shares, no_shares, false)?;
+        if yes_value == no_value {
+            return Ok(None);
+        }
+        // Buy the side the position is short on
+        let bet_type = if yes_value > no_value { 0 } else { 1 };
+
+        // Signed gap (long side value - hedged side value) after simulating a hedge of `amount`
+        let gap_after = |amount: u64| -> Option<i128> {
+            let mut simulated = self.clone();
+            let shares = simulated.place_bet(bet_type, amount).ok()?;
+            let (yes, no) = if bet_type == 1 { (yes_shares.checked_add(shares)?, no_shares) } else { (yes_shares, no_shares.checked_add(shares)?) };
+            let yes_value = simulated.position_value(yes, no, true).ok()? as i128;
+            let no_value = simulated.position_value(yes, no, false).ok()? as i128;
+            Some(if bet_type == 1 { no_value - yes_value } else { yes_value - no_value })
+        };
+
+        let max_amount = max_cost.min(MAX_BET_AMOUNT);
+        if max_amount == 0 {
+            return Ok(None);
+        }
+        if gap_after(max_amount).map_or(true, |gap| gap > 0) {
+            // Even the full budget does not reach neutral, hedge as far as allowed
+            return Ok(Some((bet_type, max_amount)));
+        }
+
+        // Smallest amount that closes the gap
+        let (mut lo, mut hi) = (0u64, max_amount);
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if gap_after(mid).map_or(true, |gap| gap > 0) {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+
+        // Pick whichever side of the crossing lands closer to neutral
+        let amount = match (lo, gap_after(lo), gap_after(hi)) {
+            (lo, Some(under), Some(over)) if lo > 0 && under < -over => lo,
+            _ => hi,
+        };
+        Ok(Some((bet_type, amount)))
+    }
+
+    // 持仓汇总：未结算时按储备价格估值，结算后按剩余可领取金额估值
+    pub fn position_summary(&self, position: &PlayerMarketPosition) -> Result<PositionSummary, u32> {
+        let value = if self.resolved {
+            self.remaining_claimable(position)?
+        } else {
+            safe_add(
+                mul_div(position.yes_shares, self.get_yes_price()?, PRICE_PRECISION)?,
+                mul_div(position.no_shares, self.get_no_price()?, PRICE_PRECISION)?,
+            )?
+        };
+        let cost_basis = position.cost_basis()?;
+        let unrealized_pnl = i64::try_from(value as i128 - cost_basis as i128).map_err(|_| ERROR_OVERFLOW)?;
+        Ok(PositionSummary {
+            yes_shares: position.yes_shares,
+            no_shares: position.no_shares,
+            value,
+            cost_basis,
+            unrealized_pnl,
+        })
+    }
+
+    // 审计用账本快照，见 MarketLedger 的平衡关系
+    pub fn ledger(&self) -> Result<MarketLedger, u32> {
+        let unclaimed_pool = safe_sub(self.prize_pool, self.total_claimed)?;
+        let outstanding_claims = match self.settled_weights() {
+            // A voided pool is owed back in full while any cost basis is unrefunded
+            _ if self.voided => if self.refunded_cost_basis < self.total_cost_basis { unclaimed_pool } else { 0 },
+            // Every winning share claimed, whatever is left is dust
+            Some(_) if self.claimed_winning_shares >= self.total_winning_shares()? => 0,
+            Some(weights) => self.weighted_value(self.total_yes_shares, self.total_no_shares, weights)?
+                .saturating_sub(self.total_claimed),
+            None => {
+                let (_, worst_case) = self.position_extremes([self.total_no_shares, self.total_yes_shares])?;
+                worst_case
+            }
+        }.min(unclaimed_pool);
+        let residual = unclaimed_pool - outstanding_claims;
+
+        let mut held = safe_add(unclaimed_pool, self.total_fees_collected)?;
+        held = safe_add(held, self.lp_fees_owed)?;
+        held = safe_add(held, self.lp_fees_settled)?;
+        held = safe_add(held, self.lp_collateral)?;
+        held = safe_add(held, safe_add(self.dispute_stake[0], self.dispute_stake[1])?)?;
+
+        Ok(MarketLedger {
+            yes_liquidity: self.yes_liquidity,
+            no_liquidity: self.no_liquidity,
+            total_yes_shares: self.total_yes_shares,
+            total_no_shares: self.total_no_shares,
+            total_volume: self.total_volume,
+            platform_fees: self.total_fees_collected,
+            lp_fees_owed: self.lp_fees_owed,
+            lp_fees_settled: self.lp_fees_settled,
+            maker_rebates: self.total_maker_rebates,
+            taker_surcharges: self.total_taker_surcharges,
+            lp_collateral: self.lp_collateral,
+            prize_pool: self.prize_pool,
+            total_claimed: self.total_claimed,
+            outstanding_claims,
+            residual,
+            held,
+        })
+    }
+
+    // 偿付能力校验：剩余奖池必须覆盖未结算份额在最差结果下的全部赔付。
+    // 失败时市场已被修改，调用方应在副本上操作并丢弃它
+    pub fn check_solvency(&self) -> Result<(), u32> {
+        let unclaimed_pool = self.prize_pool.checked_sub(self.total_claimed).ok_or(ERROR_INSOLVENT)?;
+        // Shares with nothing left behind them can never be paid
+        if unclaimed_pool == 0 && !self.resolved && (self.total_yes_shares > 0 || self.total_no_shares > 0) {
+            return Err(ERROR_INSOLVENT);
+        }
+        let owed = match self.settled_weights() {
+            // Refunds are capped at the unclaimed pool when they are paid
+            _ if self.voided => 0,
+            Some(_) if self.claimed_winning_shares > self.total_winning_shares()? => return Err(ERROR_INSOLVENT),
+            Some(weights) => self.weighted_value(self.total_yes_shares, self.total_no_shares, weights)?
+                .saturating_sub(self.total_claimed),
+            None => {
+                let (_, worst_case) = self.position_extremes([self.total_no_shares, self.total_yes_shares])?;
+                worst_case
+            }
+        };
+        if owed > unclaimed_pool {
+            return Err(ERROR_INSOLVENT);
+        }
+        // Every bucket the market holds must still add up
+        self.ledger().map(|_| ()).map_err(|_| ERROR_INSOLVENT)
+    }
+
+    // 结算一次领奖，所有获胜份额领完后按配置分配余数
+    pub fn settle_claim(&mut self, pid: [u64; 2], yes_shares: u64, no_shares: u64) -> Result<ClaimSettlement, u32> {
+        // Shares on any outcome with a nonzero settled weight are paid
+        let weights = self.settled_weights().unwrap_or([0, 0]);
+        let mut winning_shares = 0;
+        if weights[0] > 0 {
+            winning_shares = safe_add(winning_shares, no_shares)?;
+        }
+        if weights[1] > 0 {
+            winning_shares = safe_add(winning_shares, yes_shares)?;
+        }
+        let total_winning_shares = self.total_winning_shares()?;
+
+        // Never hand out more than what is left of the pool
+        let unclaimed_pool = safe_sub(self.prize_pool, self.total_claimed)?;
+        let payout = self.calculate_payout(yes_shares, no_shares)?.min(unclaimed_pool);
+
+        self.total_claimed = safe_add(self.total_claimed, payout)?;
+        self.claimed_winning_shares = safe_add(self.claimed_winning_shares, winning_shares)?;
+        // Strictly greater keeps the earliest claimer on ties
+        if winning_shares > self.largest_claim_shares {
+            self.largest_claim_shares = winning_shares;
+            self.largest_claim_pid = pid;
+        }
+
+        let mut settlement = ClaimSettlement { payout, remainder_recipient: None };
+        if winning_shares > 0 && self.claimed_winning_shares == total_winning_shares {
+            let remainder = safe_sub(self.prize_pool, self.total_claimed)?;
+            if remainder > 0 {
+                match self.remainder_policy {
+                    RemainderPolicy::LeaveInPool => {},
+                    RemainderPolicy::ToFees => {
+                        self.total_fees_collected = safe_add(self.total_fees_collected, remainder)?;
+                        self.total_claimed = safe_add(self.total_claimed, remainder)?;
+                    },
+                    RemainderPolicy::ToLargestHolder => {
+                        self.total_claimed = safe_add(self.total_claimed, remainder)?;
+                        settlement.remainder_recipient = Some((self.largest_claim_pid, remainder));
+                    },
+                }
+            }
+        }
+
+        Ok(settlement)
+    }
+
+    // // 获取份额价值（解决前估算）- 前端分析用，后端不使用
+    // pub fn get_share_value(&self, is_yes_share: bool) -> Result<u64, u32> {
+    //     if self.prize_pool == 0 {
+    //         return Ok(0);
+    //     }
+    //     
+    //     if is_yes_share {
+    //         if self.total_yes_shares == 0 { return Ok(0); }
+    //         let total_shares = safe_add(self.total_yes_shares, self.total_no_shares)?;
+    //         safe_div(self.prize_pool, total_shares)
+    //     } else {
+    //         if self.total_no_shares == 0 { return Ok(0); }
+    //         let total_shares = safe_add(self.total_yes_shares, self.total_no_shares)?;
+    //         safe_div(self.prize_pool, total_shares)
+    //     }
+    // }
 }
 
 impl StorageData for MarketData {
@@ -442,6 +1780,66 @@ impl StorageData for MarketData {
                 else { Some(true) }
             },
             total_fees_collected: *u64data.next().unwrap(),
+            total_maker_rebates: *u64data.next().unwrap(),
+            total_taker_surcharges: *u64data.next().unwrap(),
+            total_claimed: *u64data.next().unwrap(),
+            claimed_winning_shares: *u64data.next().unwrap(),
+            remainder_policy: RemainderPolicy::from_u64(*u64data.next().unwrap()),
+            largest_claim_pid: [*u64data.next().unwrap(), *u64data.next().unwrap()],
+            largest_claim_shares: *u64data.next().unwrap(),
+            resolution_weights: {
+                let weight_count = *u64data.next().unwrap();
+                (0..weight_count).map(|_| *u64data.next().unwrap()).collect()
+            },
+            proposed_outcome: {
+                let proposed_val = *u64data.next().unwrap();
+                if proposed_val == 0 { None }
+                else { Some(proposed_val == 2) }
+            },
+            proposed_at: *u64data.next().unwrap(),
+            resolution_delay: *u64data.next().unwrap(),
+            lp_fee_share_bps: *u64data.next().unwrap(),
+            total_lp_shares: *u64data.next().unwrap(),
+            lp_collateral: *u64data.next().unwrap(),
+            lp_fee_per_share: *u64data.next().unwrap(),
+            lp_fees_owed: *u64data.next().unwrap(),
+            lp_fees_settled: *u64data.next().unwrap(),
+            lp_cap_per_provider: *u64data.next().unwrap(),
+            lp_cap_total: *u64data.next().unwrap(),
+            tick_summary_enabled: *u64data.next().unwrap() != 0,
+            last_summary_volume: *u64data.next().unwrap(),
+            fee_recipient: [*u64data.next().unwrap(), *u64data.next().unwrap()],
+            auto_settle_claims: *u64data.next().unwrap() != 0,
+            require_nonzero_winning_side: *u64data.next().unwrap() != 0,
+            voided: *u64data.next().unwrap() != 0,
+            total_cost_basis: *u64data.next().unwrap(),
+            refunded_cost_basis: *u64data.next().unwrap(),
+            scalar_low: *u64data.next().unwrap(),
+            scalar_high: *u64data.next().unwrap(),
+            oracle_quorum: *u64data.next().unwrap(),
+            oracle_submissions: read_holders(u64data),
+            oracle_median: *u64data.next().unwrap(),
+            fee_bps: *u64data.next().unwrap(),
+            question_hash: [*u64data.next().unwrap(), *u64data.next().unwrap(), *u64data.next().unwrap(), *u64data.next().unwrap()],
+            max_position: *u64data.next().unwrap(),
+            max_bet_amount: *u64data.next().unwrap(),
+            oracle_outcome: match *u64data.next().unwrap() {
+                0 => None,
+                v => Some(v == 2),
+            },
+            min_bet_amount: *u64data.next().unwrap(),
+            sell_cooldown: *u64data.next().unwrap(),
+            resolved_at: *u64data.next().unwrap(),
+            dispute_window: *u64data.next().unwrap(),
+            lmsr_b: *u64data.next().unwrap(),
+            dispute_stake: [*u64data.next().unwrap(), *u64data.next().unwrap()],
+            dispute_escalated: *u64data.next().unwrap() != 0,
+            dispute_decided: *u64data.next().unwrap() != 0,
+            claim_period: *u64data.next().unwrap(),
+            swept: *u64data.next().unwrap(),
+            reveal_window: *u64data.next().unwrap(),
+            top_no_holders: read_holders(u64data),
+            top_yes_holders: read_holders(u64data),
         }
     }
 
@@ -464,6 +1862,82 @@ impl StorageData for MarketData {
             Some(true) => 2,
         });
         data.push(self.total_fees_collected);
+        data.push(self.total_maker_rebates);
+        data.push(self.total_taker_surcharges);
+        data.push(self.total_claimed);
+        data.push(self.claimed_winning_shares);
+        data.push(self.remainder_policy.to_u64());
+        data.push(self.largest_claim_pid[0]);
+        data.push(self.largest_claim_pid[1]);
+        data.push(self.largest_claim_shares);
+        data.push(self.resolution_weights.len() as u64);
+        data.extend_from_slice(&self.resolution_weights);
+        data.push(match self.proposed_outcome {
+            None => 0,
+            Some(false) => 1,
+            Some(true) => 2,
+        });
+        data.push(self.proposed_at);
+        data.push(self.resolution_delay);
+        data.push(self.lp_fee_share_bps);
+        data.push(self.total_lp_shares);
+        data.push(self.lp_collateral);
+        data.push(self.lp_fee_per_share);
+        data.push(self.lp_fees_owed);
+        data.push(self.lp_fees_settled);
+        data.push(self.lp_cap_per_provider);
+        data.push(self.lp_cap_total);
+        data.push(if self.tick_summary_enabled { 1 } else { 0 });
+        data.push(self.last_summary_volume);
+        data.extend_from_slice(&self.fee_recipient);
+        data.push(if self.auto_settle_claims { 1 } else { 0 });
+        data.push(if self.require_nonzero_winning_side { 1 } else { 0 });
+        data.push(if self.voided { 1 } else { 0 });
+        data.push(self.total_cost_basis);
+        data.push(self.refunded_cost_basis);
+        data.push(self.scalar_low);
+        data.push(self.scalar_high);
+        data.push(self.oracle_quorum);
+        write_holders(&self.oracle_submissions, data);
+        data.push(self.oracle_median);
+        data.push(self.fee_bps);
+        data.extend_from_slice(&self.question_hash);
+        data.push(self.max_position);
+        data.push(self.max_bet_amount);
+        data.push(match self.oracle_outcome {
+            None => 0,
+            Some(false) => 1,
+            Some(true) => 2,
+        });
+        data.push(self.min_bet_amount);
+        data.push(self.sell_cooldown);
+        data.push(self.resolved_at);
+        data.push(self.dispute_window);
+        data.push(self.lmsr_b);
+        data.extend_from_slice(&self.dispute_stake);
+        data.push(self.dispute_escalated as u64);
+        data.push(self.dispute_decided as u64);
+        data.push(self.claim_period);
+        data.push(self.swept);
+        data.push(self.reveal_window);
+        write_holders(&self.top_no_holders, data);
+        write_holders(&self.top_yes_holders, data);
+    }
+}
+
+fn read_holders(u64data: &mut std::slice::IterMut<u64>) -> Vec<([u64; 2], u64)> {
+    let count = *u64data.next().unwrap();
+    (0..count).map(|_| {
+        let pid = [*u64data.next().unwrap(), *u64data.next().unwrap()];
+        (pid, *u64data.next().unwrap())
+    }).collect()
+}
+
+fn write_holders(holders: &[([u64; 2], u64)], data: &mut Vec<u64>) {
+    data.push(holders.len() as u64);
+    for (pid, shares) in holders {
+        data.extend_from_slice(pid);
+        data.push(*shares);
     }
 }
 
@@ -471,4 +1945,1192 @@ impl IndexedObject<MarketData> for MarketData {
     const PREFIX: u64 = 0x1ee3;
     const POSTFIX: u64 = 0xfee3;
     const EVENT_NAME: u64 = 0x02;
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Resolved YES market whose pool does not divide evenly among 1 + 2 + 4 shares
+    fn uneven_market(policy: RemainderPolicy) -> MarketData {
+        let title = MarketData::string_to_u64_vec("Remainder");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Remainder".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        market.prize_pool = 1000;
+        market.total_yes_shares = 7;
+        market.apply_option(MARKET_OPTION_REMAINDER_POLICY, policy.to_u64()).unwrap();
+        market.resolve(true).unwrap();
+        market
+    }
+
+    fn claim_all(market: &mut MarketData) -> Vec<ClaimSettlement> {
+        vec![
+            market.settle_claim([1, 1], 1, 0).unwrap(),
+            market.settle_claim([4, 4], 4, 0).unwrap(),
+            market.settle_claim([2, 2], 2, 0).unwrap(),
+        ]
+    }
+
+    fn total_paid(settlements: &[ClaimSettlement]) -> u64 {
+        settlements.iter()
+            .map(|s| s.payout + s.remainder_recipient.map_or(0, |(_, amount)| amount))
+            .sum()
+    }
+
+    #[test]
+    fn test_binary_market_keeps_its_numbers() {
+        // Numbers from the binary market before categorical markets existed
+        let title = MarketData::string_to_u64_vec("Binary");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Binary".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        let yes = market.place_bet(1, 10_000).unwrap();
+        assert_eq!(yes, 9_753);
+        assert_eq!(market.place_bet(0, 5_000), Ok(5_048));
+        assert_eq!((market.yes_liquidity, market.no_liquidity), (995_222, 1_004_802));
+        assert_eq!(market.sell_shares(1, yes / 2), Ok(4_849));
+        assert_eq!(market.prize_pool, 9_927);
+        market.resolve(true).unwrap();
+        assert_eq!(market.calculate_payout(yes - yes / 2, 0), Ok(9_927));
+    }
+
+    #[test]
+    fn test_remainder_policy_option_rejects_unknown_policies() {
+        let mut market = uneven_market(RemainderPolicy::ToFees);
+        assert_eq!(market.remainder_policy, RemainderPolicy::ToFees);
+        assert_eq!(market.apply_option(MARKET_OPTION_REMAINDER_POLICY, 3), Err(ERROR_INVALID_MARKET_OPTION));
+        assert_eq!(market.remainder_policy, RemainderPolicy::ToFees);
+        market.apply_option(MARKET_OPTION_REMAINDER_POLICY, 0).unwrap();
+        assert_eq!(market.remainder_policy, RemainderPolicy::LeaveInPool);
+    }
+
+    #[test]
+    fn test_remainder_left_in_pool() {
+        let mut market = uneven_market(RemainderPolicy::LeaveInPool);
+        let settlements = claim_all(&mut market);
+        // 142 + 571 + 285 = 998, 2 units of dust remain
+        assert_eq!(total_paid(&settlements), 998);
+        assert_eq!(market.prize_pool - market.total_claimed, 2);
+        assert_eq!(market.total_fees_collected, 0);
+    }
+
+    #[test]
+    fn test_remainder_routed_to_fees() {
+        let mut market = uneven_market(RemainderPolicy::ToFees);
+        let settlements = claim_all(&mut market);
+        assert_eq!(total_paid(&settlements), 998);
+        assert_eq!(market.total_fees_collected, 2);
+        assert_eq!(market.total_claimed, market.prize_pool);
+    }
+
+    #[test]
+    fn test_remainder_to_largest_holder() {
+        let mut market = uneven_market(RemainderPolicy::ToLargestHolder);
+        let settlements = claim_all(&mut market);
+        // The remainder is released on the final claim, to the 4-share holder
+        assert_eq!(settlements[2].remainder_recipient, Some(([4, 4], 2)));
+        assert_eq!(total_paid(&settlements), 1000);
+        assert!(total_paid(&settlements) <= market.prize_pool);
+        assert_eq!(market.total_fees_collected, 0);
+    }
+
+    #[test]
+    fn test_hedge_to_neutral_balances_outcomes() {
+        let title = MarketData::string_to_u64_vec("Hedge");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Hedge".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+
+        // Our lopsided YES position alongside another YES holder and a NO holder
+        let yes_shares = market.place_bet(1, 100_000).unwrap();
+        market.place_bet(1, 100_000).unwrap();
+        market.place_bet(0, 50_000).unwrap();
+        assert_eq!(market.position_value(yes_shares, 0, false).unwrap(), 0);
+
+        let (bet_type, amount) = market.hedge_to_neutral(yes_shares, 0, 1_000_000).unwrap().unwrap();
+        assert_eq!(bet_type, 0);
+        // The quoted shares are what the hedge executes against as its minimum
+        let quoted = market.quote_bet(bet_type, amount).unwrap();
+        let no_shares = market.place_bet(bet_type, amount).unwrap();
+        assert_eq!(no_shares, quoted);
+
+        let if_yes = market.position_value(yes_shares, no_shares, true).unwrap();
+        let if_no = market.position_value(yes_shares, no_shares, false).unwrap();
+        let gap = (if_yes as i128 - if_no as i128).unsigned_abs();
+        assert!(gap * 100 < if_yes as u128, "yes {} no {}", if_yes, if_no);
+
+        // Already neutral positions need no trade
+        assert_eq!(market.hedge_to_neutral(0, 0, 1_000_000).unwrap(), None);
+    }
+
+    #[test]
+    fn test_quoted_fee_matches_realized_fee() {
+        let title = MarketData::string_to_u64_vec("Fee Quote");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Fee Quote".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+
+        let quoted = market.quote_fee(1, 1960).unwrap();
+        let before = market.total_fees_collected;
+        let shares = market.place_bet(1, 1960).unwrap();
+        assert_eq!(market.total_fees_collected - before, quoted);
+
+        let quoted = market.quote_sell_fee(1, shares / 2).unwrap();
+        let before = market.total_fees_collected;
+        market.sell_shares(1, shares / 2).unwrap();
+        assert_eq!(market.total_fees_collected - before, quoted);
+
+        assert_eq!(market.quote_fee(2, 1000), Err(ERROR_INVALID_BET_TYPE));
+    }
+
+    #[test]
+    fn test_distribution_resolution_pays_blended_amount() {
+        let title = MarketData::string_to_u64_vec("Partial");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Partial".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        market.prize_pool = 10_000;
+        market.total_yes_shares = 100;
+        market.total_no_shares = 50;
+
+        assert_eq!(market.resolve_distribution(vec![5000, 4000]), Err(ERROR_INVALID_OUTCOME));
+        assert_eq!(market.resolve_distribution(vec![10000]), Err(ERROR_INVALID_OUTCOME));
+        market.resolve_distribution(vec![4000, 6000]).unwrap();
+
+        // 30 YES of 100 on the 60% leg, 10 NO of 50 on the 40% leg
+        let expected = 30 * 10_000 * 6000 / (100 * 10_000) + 10 * 10_000 * 4000 / (50 * 10_000);
+        assert_eq!(market.calculate_payout(30, 10).unwrap(), expected);
+        assert_eq!(expected, 1800 + 800);
+
+        // Every share gets paid something, and holders of everything receive the whole pool
+        assert_eq!(market.calculate_payout(100, 50).unwrap(), 10_000);
+        assert_eq!(market.resolve_distribution(vec![4000, 6000]), Err(ERROR_MARKET_ALREADY_RESOLVED));
+    }
+
+    #[test]
+    fn test_position_extremes_match_each_resolution() {
+        let title = MarketData::string_to_u64_vec("Extremes");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Extremes".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        market.prize_pool = 9_000;
+        market.total_yes_shares = 300;
+        market.total_no_shares = 100;
+
+        // 60 of 300 YES pays 1800 on YES, 25 of 100 NO pays 2250 on NO
+        let (min, max) = market.position_extremes([25, 60]).unwrap();
+        assert_eq!((min, max), (1800, 2250));
+
+        let mut resolved_yes = market.clone();
+        resolved_yes.resolve(true).unwrap();
+        let mut resolved_no = market.clone();
+        resolved_no.resolve(false).unwrap();
+        assert_eq!(resolved_yes.calculate_payout(60, 25).unwrap(), min);
+        assert_eq!(resolved_no.calculate_payout(60, 25).unwrap(), max);
+    }
+
+    #[test]
+    fn test_balancing_bet_pays_less_than_imbalancing_bet() {
+        let title = MarketData::string_to_u64_vec("Rebate");
+        // YES is the cheap side, so a YES bet pulls the reserves together
+        let market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Rebate".to_string(), 0, 1000, 1000, 1_500_000, 1_000_000
+        ).unwrap();
+
+        let balancing_fee = market.quote_fee(1, 10_000).unwrap();
+        let imbalancing_fee = market.quote_fee(0, 10_000).unwrap();
+        assert!(balancing_fee < imbalancing_fee);
+
+        let mut balancing = market.clone();
+        balancing.place_bet(1, 10_000).unwrap();
+        assert_eq!(balancing.total_fees_collected, balancing_fee);
+        assert!(balancing.total_maker_rebates > 0);
+        assert_eq!(balancing.total_taker_surcharges, 0);
+
+        let mut imbalancing = market.clone();
+        imbalancing.place_bet(0, 10_000).unwrap();
+        assert_eq!(imbalancing.total_fees_collected, imbalancing_fee);
+        assert_eq!(imbalancing.total_maker_rebates, 0);
+        assert!(imbalancing.total_taker_surcharges > 0);
+    }
+
+    #[test]
+    fn test_lifecycle_emits_each_state_change() {
+        use crate::event::{market_state_change_data, EventBuffer, EVENT_MARKET_STATE_CHANGE};
+
+        let title = MarketData::string_to_u64_vec("Lifecycle");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Lifecycle".to_string(), 10, 20, 30, 1_000_000, 1_000_000
+        ).unwrap();
+        let mut buffer = EventBuffer::new(1024);
+        let mut record = |old: Option<MarketPhase>, market: &MarketData, counter: u64| {
+            if let Some(mut data) = market_state_change_data(7, old, market.phase(counter), counter) {
+                buffer.insert(EVENT_MARKET_STATE_CHANGE, &mut data);
+            }
+        };
+
+        record(None, &market, 0);
+        // Ticks only report the counters where the phase actually moves
+        for counter in 1..=30 {
+            let before = market.phase(counter - 1);
+            record(Some(before), &market, counter);
+        }
+
+        market.total_yes_shares = 10;
+        market.prize_pool = 1000;
+        let before = market.phase(30);
+        market.resolve(true).unwrap();
+        record(Some(before), &market, 30);
+
+        let before = market.phase(31);
+        market.settle_claim([1, 1], 10, 0).unwrap();
+        record(Some(before), &market, 31);
+
+        let header = (EVENT_MARKET_STATE_CHANGE << 32) + 4;
+        assert_eq!(buffer.drain(), vec![
+            header, 7, 0, MarketPhase::Created.to_u64(), 0,
+            header, 7, MarketPhase::Created.to_u64(), MarketPhase::Active.to_u64(), 10,
+            header, 7, MarketPhase::Active.to_u64(), MarketPhase::Ended.to_u64(), 20,
+            header, 7, MarketPhase::Ended.to_u64(), MarketPhase::PendingResolution.to_u64(), 30,
+            header, 7, MarketPhase::PendingResolution.to_u64(), MarketPhase::Resolved.to_u64(), 30,
+            header, 7, MarketPhase::Resolved.to_u64(), MarketPhase::FullySettled.to_u64(), 31,
+        ]);
+    }
+
+    #[test]
+    fn test_top_holders_follow_position_changes() {
+        let title = MarketData::string_to_u64_vec("Holders");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Holders".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+
+        for (i, shares) in [300u64, 500, 100, 400, 200, 600].iter().enumerate() {
+            market.record_holding([i as u64, 0], 1, *shares).unwrap();
+        }
+        market.record_holding([9, 0], 0, 50).unwrap();
+
+        // Only the tracked number of holders is kept, largest first
+        let top: Vec<u64> = market.top_holders(1, 10).unwrap().iter().map(|(pid, _)| pid[0]).collect();
+        assert_eq!(top, vec![5, 1, 3, 0, 4]);
+        assert_eq!(market.top_holders(1, 2).unwrap(), &[([5, 0], 600), ([1, 0], 500)]);
+        assert_eq!(market.top_holders(0, 3).unwrap(), &[([9, 0], 50)]);
+
+        // A holder selling down drops in rank, selling out removes them
+        market.record_holding([5, 0], 1, 150).unwrap();
+        market.record_holding([1, 0], 1, 0).unwrap();
+        let top: Vec<u64> = market.top_holders(1, 10).unwrap().iter().map(|(pid, _)| pid[0]).collect();
+        assert_eq!(top, vec![3, 0, 4, 5]);
+
+        // The list survives a storage round trip
+        let mut stored = vec![];
+        market.to_data(&mut stored);
+        let loaded = MarketData::from_data(&mut stored.iter_mut());
+        assert_eq!(loaded.top_yes_holders, market.top_yes_holders);
+        assert_eq!(loaded.top_no_holders, market.top_no_holders);
+
+        assert_eq!(market.top_holders(2, 1), Err(ERROR_INVALID_BET_TYPE));
+    }
+
+    #[test]
+    fn test_finalize_waits_for_resolution_delay() {
+        let title = MarketData::string_to_u64_vec("Cooldown");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Cooldown".to_string(), 0, 100, 100, 1_000_000, 1_000_000
+        ).unwrap();
+        assert_eq!(market.resolution_delay, DEFAULT_RESOLUTION_DELAY);
+        market.apply_option(MARKET_OPTION_RESOLUTION_DELAY, 50).unwrap();
+        assert_eq!(market.resolution_delay, 50);
+
+        assert_eq!(market.finalize_resolution(100), Err(ERROR_NO_PENDING_RESOLUTION));
+        market.propose_resolution(true, 100).unwrap();
+        assert_eq!(market.finalize_resolution(100), Err(ERROR_RESOLUTION_TOO_EARLY));
+        assert_eq!(market.finalize_resolution(149), Err(ERROR_RESOLUTION_TOO_EARLY));
+        assert!(!market.resolved);
+
+        market.finalize_resolution(150).unwrap();
+        assert!(market.resolved);
+        assert_eq!(market.outcome, Some(true));
+        assert_eq!(market.proposed_outcome, None);
+        assert_eq!(market.propose_resolution(false, 200), Err(ERROR_MARKET_ALREADY_RESOLVED));
+    }
+
+    #[test]
+    fn test_payout_multiple_moves_against_probability() {
+        let title = MarketData::string_to_u64_vec("Odds");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Odds".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+
+        // Even odds pay just under 2x after the platform fee
+        assert_eq!(market.payout_multiple(1).unwrap(), 1980);
+        assert_eq!(market.payout_multiple(0).unwrap(), 1980);
+
+        // Buying YES raises its probability, so YES pays less and NO pays more
+        let yes_before = market.payout_multiple(1).unwrap();
+        let no_before = market.payout_multiple(0).unwrap();
+        market.place_bet(1, 200_000).unwrap();
+        assert!(market.get_yes_price().unwrap() > PRICE_PRECISION / 2);
+        assert!(market.payout_multiple(1).unwrap() < yes_before);
+        assert!(market.payout_multiple(0).unwrap() > no_before);
+
+        assert_eq!(market.payout_multiple(2), Err(ERROR_INVALID_BET_TYPE));
+    }
+
+    #[test]
+    fn test_claim_status_before_and_after_claim() {
+        let mut market = uneven_market(RemainderPolicy::LeaveInPool);
+        let mut first = PlayerMarketPosition { yes_shares: 4, ..PlayerMarketPosition::default() };
+        let second = PlayerMarketPosition { yes_shares: 2, ..PlayerMarketPosition::default() };
+
+        let unresolved = MarketData { resolved: false, ..market.clone() };
+        assert_eq!(unresolved.remaining_claimable(&first), Ok(0));
+
+        assert_eq!(market.remaining_claimable(&first), Ok(571));
+        assert_eq!(market.remaining_claimable(&second), Ok(285));
+
+        // Once the first holder claims, only the other holder has anything left
+        let settlement = market.settle_claim([1, 0], first.yes_shares, first.no_shares).unwrap();
+        first.claimed = true;
+        assert_eq!(settlement.payout, 571);
+        assert_eq!(market.remaining_claimable(&first), Ok(0));
+        assert_eq!(market.remaining_claimable(&second), Ok(285));
+    }
+
+    #[test]
+    fn test_tick_summary_reports_volume_between_ticks() {
+        let title = MarketData::string_to_u64_vec("Summary");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Summary".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        assert_eq!(market.take_tick_summary(), Ok(None));
+        market.apply_option(MARKET_OPTION_TICK_SUMMARY, 1).unwrap();
+
+        market.place_bet(1, 1000).unwrap();
+        market.place_bet(0, 2000).unwrap();
+        let summary = market.take_tick_summary().unwrap().unwrap();
+        assert_eq!(summary[0], market.get_yes_price().unwrap());
+        assert_eq!(summary[3], 3000);
+        assert_eq!(summary[4], market.prize_pool);
+
+        // A quiet tick reports no volume, the next trade only counts once
+        assert_eq!(market.take_tick_summary().unwrap().unwrap()[3], 0);
+        let shares = market.place_bet(1, 500).unwrap();
+        let (payout, fee) = market.calculate_sell_details(1, shares).unwrap();
+        market.sell_shares(1, shares).unwrap();
+        assert_eq!(market.take_tick_summary().unwrap().unwrap()[3], 500 + payout + fee);
+    }
+
+    #[test]
+    fn test_only_recipient_or_admin_withdraws_fees() {
+        let title = MarketData::string_to_u64_vec("Treasury");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Treasury".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        let (admin, treasury, stranger) = ([1, 1], [7, 7], [9, 9]);
+        market.fee_recipient = admin;
+        market.apply_option(MARKET_OPTION_FEE_RECIPIENT_0, treasury[0]).unwrap();
+        market.apply_option(MARKET_OPTION_FEE_RECIPIENT_1, treasury[1]).unwrap();
+        market.place_bet(1, 10_000).unwrap();
+        let fees = market.total_fees_collected;
+
+        assert_eq!(market.withdraw_fees(&stranger, &admin, [0, 0]), Err(ERROR_UNAUTHORIZED));
+        assert_eq!(market.total_fees_collected, fees);
+
+        // The treasury is credited whoever triggers the withdrawal
+        assert_eq!(market.clone().withdraw_fees(&treasury, &admin, [0, 0]), Ok((treasury, fees)));
+        // Only the recipient itself may send its fees somewhere else
+        assert_eq!(market.withdraw_fees(&admin, &admin, stranger), Err(ERROR_UNAUTHORIZED));
+        assert_eq!(market.clone().withdraw_fees(&treasury, &admin, stranger), Ok((stranger, fees)));
+        assert_eq!(market.withdraw_fees(&admin, &admin, [0, 0]), Ok((treasury, fees)));
+        assert_eq!(market.withdraw_fees(&admin, &admin, [0, 0]), Err(ERROR_NO_FEES_TO_WITHDRAW));
+    }
+
+    #[test]
+    fn test_marginal_impact_tracks_depth() {
+        let title = MarketData::string_to_u64_vec("Depth");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Depth".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        let yes_impact = market.marginal_impact(1).unwrap();
+        assert!(yes_impact > 0);
+        assert_eq!(market.marginal_impact(0).unwrap(), yes_impact);
+        assert_eq!(market.marginal_impact(2), Err(ERROR_INVALID_BET_TYPE));
+
+        // Added liquidity absorbs the same trade with less movement
+        let mut deeper = market.clone();
+        deeper.deepen_reserves(1_000_000).unwrap();
+        assert!(deeper.marginal_impact(1).unwrap() < yes_impact);
+
+        // A drained pool moves more
+        market.thin_reserves(500_000).unwrap();
+        assert!(market.marginal_impact(1).unwrap() > yes_impact);
+    }
+
+    #[test]
+    fn test_equal_reserves_price_symmetrically() {
+        // Even and odd totals alike must not favour either side
+        for reserve in [1_000_000u64, 1_000_001, 333_333] {
+            let title = MarketData::string_to_u64_vec("Even");
+            let market = MarketData::new_with_title_u64_and_liquidity(
+                title, "Even".to_string(), 0, 1000, 1000, reserve, reserve
+            ).unwrap();
+            assert_eq!(market.get_yes_price().unwrap(), PRICE_PRECISION / 2);
+            assert_eq!(market.get_no_price().unwrap(), PRICE_PRECISION / 2);
+            assert_eq!(market.implied_probability(1).unwrap(), 5000);
+            assert_eq!(market.implied_probability(0).unwrap(), 5000);
+            assert_eq!(market.marginal_impact(1).unwrap(), market.marginal_impact(0).unwrap());
+            assert_eq!(market.spread(1).unwrap(), market.spread(0).unwrap());
+            assert_eq!(market.calculate_shares(1, 10_000).unwrap(), market.calculate_shares(0, 10_000).unwrap());
+            assert_eq!(market.quote_fee(1, 10_000).unwrap(), market.quote_fee(0, 10_000).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_implied_probabilities_always_sum_to_one() {
+        let title = MarketData::string_to_u64_vec("Skew");
+        let market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Skew".to_string(), 0, 1000, 1000, 1_000_000, 2_000_000
+        ).unwrap();
+        // 2/3 and 1/3 do not divide evenly, the leftover goes to the favourite
+        assert_eq!(market.implied_probability(1).unwrap(), 6667);
+        assert_eq!(market.implied_probability(0).unwrap(), 3333);
+        assert_eq!(market.implied_probability(2), Err(ERROR_INVALID_BET_TYPE));
+    }
+
+    #[test]
+    fn test_ledger_balances_through_settlement() {
+        let title = MarketData::string_to_u64_vec("Audit");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Audit".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        let mut lp = crate::liquidity::LpPosition::default();
+        crate::liquidity::add_liquidity(&mut market, &mut lp, 200_000, false).unwrap();
+        let yes_a = market.place_bet(1, 30_001).unwrap();
+        let yes_b = market.place_bet(1, 17_777).unwrap();
+        market.place_bet(0, 25_000).unwrap();
+        market.sell_shares(1, yes_b / 3).unwrap();
+        let yes_b = yes_b - yes_b / 3;
+
+        let balanced = |market: &MarketData| {
+            let ledger = market.ledger().unwrap();
+            assert_eq!(
+                ledger.outstanding_claims + ledger.residual + ledger.platform_fees
+                    + ledger.lp_fees_owed + ledger.lp_fees_settled + ledger.lp_collateral,
+                ledger.held
+            );
+            assert_eq!(ledger.outstanding_claims + ledger.residual + ledger.total_claimed, ledger.prize_pool);
+            ledger
+        };
+        balanced(&market);
+
+        market.resolve(true).unwrap();
+        let ledger = balanced(&market);
+        assert_eq!(ledger.total_claimed, 0);
+        assert!(ledger.outstanding_claims > 0);
+
+        // Part claimed, then fully claimed: obligations shrink by exactly what was paid
+        let first = market.settle_claim([1, 1], yes_a, 0).unwrap();
+        let ledger_after = balanced(&market);
+        assert_eq!(ledger_after.outstanding_claims, ledger.outstanding_claims - first.payout);
+        market.settle_claim([2, 2], yes_b, 0).unwrap();
+        let ledger = balanced(&market);
+        assert_eq!(ledger.outstanding_claims, 0);
+        assert!(ledger.residual < 2);
+    }
+
+    #[test]
+    fn test_max_bet_for_impact_sits_on_the_bound() {
+        let title = MarketData::string_to_u64_vec("Size");
+        let market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Size".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        for outcome in [0, 1] {
+            let size = market.max_bet_for_impact(outcome, 100).unwrap();
+            assert!(size > 0);
+            assert!(market.price_impact_bps(outcome, size).unwrap() <= 100);
+            assert!(market.price_impact_bps(outcome, size + 1).unwrap() > 100);
+        }
+        // A looser bound allows a larger bet
+        assert!(market.max_bet_for_impact(1, 500).unwrap() > market.max_bet_for_impact(1, 100).unwrap());
+        assert_eq!(market.max_bet_for_impact(2, 100), Err(ERROR_INVALID_BET_TYPE));
+    }
+
+    #[test]
+    fn test_schedule_checked_against_current_counter() {
+        let now = 1_000;
+        // Already ended
+        assert_eq!(MarketData::validate_schedule(0, now, now), Err(ERROR_INVALID_MARKET_TIME));
+        assert_eq!(MarketData::validate_schedule(0, 500, now), Err(ERROR_INVALID_MARKET_TIME));
+        // Starts beyond the horizon
+        let far = now + MAX_MARKET_START_HORIZON + 1;
+        assert_eq!(MarketData::validate_schedule(far, far + 100, now), Err(ERROR_INVALID_MARKET_TIME));
+        // Normal markets, including one starting right at the horizon
+        assert_eq!(MarketData::validate_schedule(now, now + 100, now), Ok(()));
+        let edge = now + MAX_MARKET_START_HORIZON;
+        assert_eq!(MarketData::validate_schedule(edge, edge + 100, now), Ok(()));
+    }
+
+    #[test]
+    fn test_position_summary_marks_against_reserves() {
+        let title = MarketData::string_to_u64_vec("Summary");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Summary".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        let yes_shares = market.place_bet(1, 40_000).unwrap();
+        let no_shares = market.place_bet(0, 10_000).unwrap();
+        let mut position = PlayerMarketPosition { yes_shares, no_shares, ..PlayerMarketPosition::default() };
+        position.add_cost(1, 40_000).unwrap();
+        position.add_cost(0, 10_000).unwrap();
+
+        let summary = market.position_summary(&position).unwrap();
+        let manual_value = position.yes_shares as u128 * market.no_liquidity as u128 / (market.yes_liquidity + market.no_liquidity) as u128
+            + position.no_shares as u128 * market.yes_liquidity as u128 / (market.yes_liquidity + market.no_liquidity) as u128;
+        assert!((summary.value as i128 - manual_value as i128).abs() <= 2);
+        assert_eq!(summary.cost_basis, 50_000);
+        assert_eq!(summary.unrealized_pnl, summary.value as i64 - 50_000);
+        // Fees and price impact put a fresh position under water
+        assert!(summary.unrealized_pnl < 0);
+
+        // Selling half the YES shares releases half their basis
+        let held = position.yes_shares;
+        position.reduce_cost(1, held / 2, held).unwrap();
+        assert_eq!(position.yes_cost, 40_000 - 40_000 * (held / 2) / held);
+
+        let mut data = vec![];
+        summary.to_data(&mut data);
+        assert_eq!(data[4..], [1, summary.unrealized_pnl.unsigned_abs()]);
+    }
+
+    #[test]
+    fn test_resolving_to_unheld_outcome_needs_flag_off() {
+        let title = MarketData::string_to_u64_vec("Lonely");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Lonely".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        market.place_bet(1, 10_000).unwrap();
+
+        let mut guarded = market.clone();
+        guarded.apply_option(MARKET_OPTION_REQUIRE_NONZERO_WINNING_SIDE, 1).unwrap();
+        assert_eq!(guarded.clone().resolve(false), Err(ERROR_NO_PARTICIPANTS_ON_OUTCOME));
+        assert_eq!(guarded.clone().resolve_distribution(vec![BASIS_POINTS_PRECISION, 0]), Err(ERROR_NO_PARTICIPANTS_ON_OUTCOME));
+        assert!(!guarded.resolved);
+        // The held side still resolves
+        assert_eq!(guarded.resolve(true), Ok(()));
+
+        // Without the flag nobody has to hold the winning side
+        assert_eq!(market.resolve(false), Ok(()));
+    }
+
+    #[test]
+    fn test_scalar_market_settles_at_oracle_median() {
+        let title = MarketData::string_to_u64_vec("Scalar");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Scalar".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        market.apply_option(MARKET_OPTION_SCALAR_LOW, 100).unwrap();
+        market.apply_option(MARKET_OPTION_SCALAR_HIGH, 200).unwrap();
+        market.apply_option(MARKET_OPTION_ORACLE_QUORUM, 5).unwrap();
+        market.place_bet(1, 10_000).unwrap();
+        market.place_bet(0, 10_000).unwrap();
+
+        let values = [180, 130, 900, 150, 155];
+        for (i, value) in values.iter().enumerate().take(4) {
+            assert_eq!(market.submit_oracle_value([i as u64, 0], *value), Ok(false));
+        }
+        assert_eq!(market.oracle_median(), Err(ERROR_INSUFFICIENT_ORACLE_DATA));
+        // A resubmission replaces the oracle's earlier value without counting twice
+        assert_eq!(market.submit_oracle_value([2, 0], 170), Ok(false));
+        assert_eq!(market.submit_oracle_value([4, 0], values[4]), Ok(true));
+
+        // Sorted: 130 150 155 170 180, an outlier would not have moved it far either
+        assert!(market.resolved);
+        assert_eq!(market.oracle_median, 155);
+        assert_eq!(market.resolution_weights, vec![4500, 5500]);
+        assert_eq!(market.submit_oracle_value([5, 0], 100), Err(ERROR_MARKET_ALREADY_RESOLVED));
+
+        // Even counts average the middle pair
+        let mut even = MarketData::new_with_title_u64_and_liquidity(
+            MarketData::string_to_u64_vec("Even"), "Even".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        assert_eq!(even.submit_oracle_value([1, 0], 5), Err(ERROR_INVALID_OUTCOME));
+        even.scalar_high = 10;
+        even.oracle_quorum = 4;
+        for (i, value) in [1u64, 8, 3, 4].iter().enumerate() {
+            even.oracle_submissions.push(([i as u64, 0], *value));
+        }
+        assert_eq!(even.oracle_median(), Ok(3));
+    }
+
+    #[test]
+    fn test_market_fee_and_question_hash_options() {
+        let title = MarketData::string_to_u64_vec("Fee");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Fee".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        assert_eq!(market.trade_fee(1, 10_000), Ok(100));
+
+        market.apply_option(MARKET_OPTION_FEE_BPS, 300).unwrap();
+        for (i, limb) in [11, 22, 33, 44].iter().enumerate() {
+            market.apply_option(MARKET_OPTION_QUESTION_HASH_0 + i as u64, *limb).unwrap();
+        }
+        assert_eq!(market.question_hash, [11, 22, 33, 44]);
+        // The base fee, before maker rebates or taker surcharges, follows the market's rate
+        assert_eq!(market.trade_fee(1, 10_000), Ok(300));
+        assert_eq!(market.apply_option(MARKET_OPTION_FEE_BPS, MAX_MARKET_FEE_BPS + 1), Err(ERROR_INVALID_MARKET_OPTION));
+
+        let mut data = vec![];
+        market.to_data(&mut data);
+        let loaded = MarketData::from_data(&mut data.iter_mut());
+        assert_eq!(loaded.fee_bps, 300);
+        assert_eq!(loaded.question_hash, [11, 22, 33, 44]);
+    }
+
+    #[test]
+    fn test_reveal_window_is_set_per_market() {
+        let title = MarketData::string_to_u64_vec("Reveal");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Reveal".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        assert_eq!(market.reveal_window, DEFAULT_BET_REVEAL_WINDOW);
+        assert_eq!(market.apply_option(MARKET_OPTION_REVEAL_WINDOW, 0), Err(ERROR_INVALID_MARKET_OPTION));
+        market.apply_option(MARKET_OPTION_REVEAL_WINDOW, 5).unwrap();
+
+        let mut data = vec![];
+        market.to_data(&mut data);
+        let loaded = MarketData::from_data(&mut data.iter_mut());
+        assert_eq!(loaded.reveal_window, 5);
+
+        // A commitment made at tick 100 is revealable through tick 105 only
+        let commitment = PlayerMarketPosition::bet_commitment(&[1, 2], 3, 1, 500, 480, 42);
+        let mut position = PlayerMarketPosition::default();
+        position.commit_bet(commitment, 100).unwrap();
+        assert_eq!(position.check_reveal(commitment, 105, loaded.reveal_window), Ok(()));
+        assert_eq!(position.check_reveal(commitment, 106, loaded.reveal_window), Err(ERROR_COMMITMENT_NOT_FOUND));
+    }
+
+    #[test]
+    fn test_quotes_match_execution_without_mutating() {
+        let title = MarketData::string_to_u64_vec("Preview");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Preview".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        let before = (market.yes_liquidity, market.no_liquidity, market.total_fees_collected, market.prize_pool);
+
+        let quoted_shares = market.quote_bet(1, 25_000).unwrap();
+        assert_eq!((market.yes_liquidity, market.no_liquidity, market.total_fees_collected, market.prize_pool), before);
+        assert_eq!(market.place_bet(1, 25_000), Ok(quoted_shares));
+
+        let after_bet = (market.yes_liquidity, market.no_liquidity, market.total_fees_collected);
+        let quoted_payout = market.quote_sell(1, quoted_shares / 2).unwrap();
+        assert_eq!((market.yes_liquidity, market.no_liquidity, market.total_fees_collected), after_bet);
+        assert_eq!(market.sell_shares(1, quoted_shares / 2), Ok(quoted_payout));
+
+        // Quotes reject exactly what execution would reject
+        assert_eq!(market.quote_bet(1, 0), Err(ERROR_INVALID_BET_AMOUNT));
+        assert_eq!(market.quote_sell(0, 1_000), Err(ERROR_INSUFFICIENT_BALANCE));
+    }
+
+    #[test]
+    fn test_payouts_never_exceed_collected_stakes() {
+        for weights in [None, Some(vec![2500, 7500])] {
+            let title = MarketData::string_to_u64_vec("Solvency");
+            let mut market = MarketData::new_with_title_u64_and_liquidity(
+                title, "Solvency".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+            ).unwrap();
+            let mut holders = vec![];
+            let mut staked = 0;
+            for (i, amount) in [12_345u64, 70_001, 3_333, 99_999, 41_000, 7].iter().enumerate() {
+                let side = (i % 2) as u64;
+                match market.place_bet(side, *amount) {
+                    Ok(shares) => {
+                        staked += amount;
+                        holders.push(if side == 1 { (shares, 0) } else { (0, shares) });
+                    }
+                    Err(e) => assert_eq!(e, ERROR_INVALID_BET_AMOUNT),
+                }
+            }
+            let (sold, _) = holders[1];
+            let sell_payout = market.sell_shares(1, sold / 2).unwrap();
+            holders[1].0 -= sold / 2;
+
+            match weights {
+                Some(weights) => market.resolve_distribution(weights).unwrap(),
+                None => market.resolve(true).unwrap(),
+            }
+            let owed: u64 = holders.iter().map(|(yes, no)| market.calculate_payout(*yes, *no).unwrap()).sum();
+            assert!(owed <= market.prize_pool);
+            assert!(market.prize_pool + market.total_fees_collected + sell_payout <= staked);
+        }
+    }
+
+    #[test]
+    fn test_sell_fee_follows_market_fee_rate() {
+        let title = MarketData::string_to_u64_vec("Sell Fee");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Sell Fee".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        let shares = market.place_bet(1, 50_000).unwrap();
+
+        let mut free = market.clone();
+        free.fee_bps = 0;
+        let gross = free.quote_sell(1, shares / 2).unwrap();
+        assert_eq!(free.quote_sell_fee(1, shares / 2), Ok(0));
+
+        // The same rate as bets is deducted from the gross proceeds and collected
+        market.fee_bps = 500;
+        let fee = mul_div_up(gross, 500, FEE_BASIS_POINTS).unwrap();
+        let before = market.total_fees_collected;
+        assert_eq!(market.sell_shares(1, shares / 2), Ok(gross - fee));
+        assert_eq!(market.total_fees_collected - before, fee);
+    }
+
+    #[test]
+    fn test_binary_oracle_outcome_needs_a_quorum_majority() {
+        let title = MarketData::string_to_u64_vec("Quorum");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Quorum".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        assert_eq!(market.oracle_quorum, 3);
+
+        // Two of three agreeing is not enough, and a changed vote replaces the old one
+        assert_eq!(market.submit_oracle_outcome([1, 1], true), Ok(false));
+        assert_eq!(market.submit_oracle_outcome([2, 2], true), Ok(false));
+        assert_eq!(market.submit_oracle_outcome([1, 1], false), Ok(false));
+        assert_eq!(market.try_auto_resolve(1000), Ok(false));
+
+        assert_eq!(market.submit_oracle_outcome([1, 1], true), Ok(false));
+        assert_eq!(market.submit_oracle_outcome([3, 3], true), Ok(true));
+        // A dissenting fourth oracle does not undo the majority
+        assert_eq!(market.submit_oracle_outcome([4, 4], false), Ok(true));
+        assert_eq!(market.try_auto_resolve(1000), Ok(true));
+        assert_eq!(market.outcome, Some(true));
+
+        // A tie never settles, whatever the quorum
+        let mut split = MarketData::new_with_title_u64_and_liquidity(
+            MarketData::string_to_u64_vec("Split"), "Split".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        split.apply_option(MARKET_OPTION_ORACLE_QUORUM, 1).unwrap();
+        split.submit_oracle_outcome([1, 1], true).unwrap();
+        assert_eq!(split.submit_oracle_outcome([2, 2], false), Ok(false));
+        assert_eq!(split.try_auto_resolve(1000), Ok(false));
+    }
+
+    #[test]
+    fn test_oracle_outcome_resolves_only_after_end() {
+        let title = MarketData::string_to_u64_vec("Auto");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Auto".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        market.apply_option(MARKET_OPTION_ORACLE_QUORUM, 1).unwrap();
+        // Nothing reported yet: tick leaves the market alone
+        assert_eq!(market.try_auto_resolve(2000), Ok(false));
+
+        market.submit_oracle_outcome([1, 1], false).unwrap();
+        assert_eq!(market.try_auto_resolve(999), Err(ERROR_RESOLUTION_TOO_EARLY));
+        assert!(!market.resolved);
+
+        assert_eq!(market.try_auto_resolve(1000), Ok(true));
+        assert_eq!(market.outcome, Some(false));
+        assert_eq!(market.submit_oracle_outcome([1, 1], true), Err(ERROR_MARKET_ALREADY_RESOLVED));
+
+        // Voided markets are skipped
+        let mut voided = MarketData::new_with_title_u64_and_liquidity(
+            MarketData::string_to_u64_vec("Void"), "Void".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        voided.apply_option(MARKET_OPTION_ORACLE_QUORUM, 1).unwrap();
+        voided.submit_oracle_outcome([1, 1], true).unwrap();
+        voided.void().unwrap();
+        assert_eq!(voided.try_auto_resolve(2000), Ok(false));
+        assert!(voided.voided);
+    }
+
+    #[test]
+    fn test_solvency_holds_under_random_trading() {
+        // Deterministic LCG so a failure replays exactly
+        let mut seed: u64 = 0x5eed_1234;
+        let mut next = move |bound: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % bound
+        };
+        for round in 0..4 {
+            let title = MarketData::string_to_u64_vec("Fuzz");
+            let mut market = MarketData::new_with_title_u64_and_liquidity(
+                title, "Fuzz".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+            ).unwrap();
+            market.fee_bps = next(MAX_MARKET_FEE_BPS + 1);
+            let mut holders: Vec<(u64, u64)> = vec![];
+            for _ in 0..300 {
+                if holders.is_empty() || next(3) > 0 {
+                    let side = next(2);
+                    if let Ok(shares) = market.place_bet(side, 1 + next(200_000)) {
+                        holders.push(if side == 1 { (shares, 0) } else { (0, shares) });
+                    }
+                } else {
+                    let holder = next(holders.len() as u64) as usize;
+                    let (yes, no) = holders[holder];
+                    let (side, held) = if yes > 0 { (1, yes) } else { (0, no) };
+                    let shares = 1 + next(held);
+                    if market.sell_shares(side, shares).is_ok() {
+                        if side == 1 { holders[holder].0 -= shares } else { holders[holder].1 -= shares }
+                    }
+                }
+                assert_eq!(market.check_solvency(), Ok(()));
+            }
+
+            market.resolve(round % 2 == 0).unwrap();
+            assert_eq!(market.check_solvency(), Ok(()));
+            for (i, (yes, no)) in holders.iter().enumerate() {
+                market.settle_claim([i as u64, 0], *yes, *no).unwrap();
+                assert_eq!(market.check_solvency(), Ok(()));
+            }
+            assert!(market.total_claimed <= market.prize_pool);
+        }
+    }
+
+    #[test]
+    fn test_insolvent_market_rejects_trades() {
+        let title = MarketData::string_to_u64_vec("Insolvent");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Insolvent".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        let shares = market.place_bet(1, 10_000).unwrap();
+
+        // A pool that no longer covers the shares on issue fails every trade
+        market.total_claimed = market.prize_pool;
+        assert_eq!(market.check_solvency(), Err(ERROR_INSOLVENT));
+        assert_eq!(market.place_bet(0, 10_000), Err(ERROR_INSOLVENT));
+        assert_eq!(market.sell_shares(1, shares / 2), Err(ERROR_INSOLVENT));
+    }
+
+    #[test]
+    fn test_status_transition_matrix() {
+        use MarketStatus::*;
+        let at = |status: MarketStatus| {
+            let title = MarketData::string_to_u64_vec("Lifecycle");
+            let mut market = MarketData::new_with_title_u64_and_liquidity(
+                title, "Lifecycle".to_string(), 100, 200, 200, 1_000_000, 1_000_000
+            ).unwrap();
+            let time = match status { Pending => 50, Active => 150, _ => 250 };
+            match status {
+                Resolved => market.resolve(true).unwrap(),
+                Cancelled => market.void().unwrap(),
+                _ => {}
+            }
+            assert_eq!(market.status(time), status);
+            (market, time)
+        };
+        let done = Err(ERROR_MARKET_ALREADY_RESOLVED);
+        let not_active = Err(ERROR_MARKET_NOT_ACTIVE);
+        let early = Err(ERROR_RESOLUTION_TOO_EARLY);
+        // (from, open, close, resolve, cancel, status after a successful open/close)
+        let matrix = [
+            (Pending, Ok(()), not_active, early, Ok(()), Active),
+            (Active, not_active, Ok(()), early, Ok(()), Closed),
+            (Closed, not_active, not_active, Ok(()), Ok(()), Closed),
+            (Resolved, done, done, done, done, Resolved),
+            (Cancelled, done, done, done, done, Cancelled),
+        ];
+        for (from, open, close, resolve, cancel, moved) in matrix {
+            let (mut market, time) = at(from);
+            assert_eq!(market.open(time), open, "open from {:?}", from);
+            if open.is_ok() {
+                assert_eq!(market.status(time), moved);
+            }
+            let (mut market, _) = at(from);
+            assert_eq!(market.close(time), close, "close from {:?}", from);
+            if close.is_ok() {
+                assert_eq!(market.status(time), moved);
+            }
+            let (mut market, _) = at(from);
+            assert_eq!(market.ensure_resolvable(time).and_then(|_| market.resolve(true)), resolve, "resolve from {:?}", from);
+            let (mut market, _) = at(from);
+            assert_eq!(market.void(), cancel, "cancel from {:?}", from);
+            // Only an Active market trades
+            assert_eq!(at(from).0.ensure_active(time).is_ok(), from == Active);
+        }
+
+        // A closed market stops trading at once but resolves only at its resolution time
+        let (mut market, _) = at(Active);
+        market.close(150).unwrap();
+        assert_eq!(market.ensure_active(150), not_active);
+        assert_eq!(market.ensure_resolvable(150), early);
+        assert_eq!(market.ensure_resolvable(200), Ok(()));
+    }
+
+    #[test]
+    fn test_shares_for_payout_rounds_up_to_the_target() {
+        let title = MarketData::string_to_u64_vec("Target");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Target".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        market.place_bet(0, 80_000).unwrap();
+        let held = market.place_bet(1, 50_000).unwrap();
+        let everything = market.quote_sell(1, held).unwrap();
+
+        // The fewest shares that pay at least the target, one fewer falls short
+        let target = everything / 3;
+        let shares = market.shares_for_payout(1, target, held).unwrap();
+        assert!(market.quote_sell(1, shares).unwrap() >= target);
+        assert!(market.quote_sell(1, shares - 1).map_or(true, |payout| payout < target));
+
+        // Exactly the whole holding's payout needs at most the whole holding
+        assert!(market.shares_for_payout(1, everything, held).unwrap() <= held);
+        // Just short: everything is sold and the achieved payout is what the holding was worth
+        assert_eq!(market.shares_for_payout(1, everything + 1, held), Ok(held));
+        assert_eq!(market.clone().sell_shares(1, held), Ok(everything));
+
+        assert_eq!(market.shares_for_payout(1, target, 0), Err(ERROR_INSUFFICIENT_BALANCE));
+        assert_eq!(market.shares_for_payout(1, 0, held), Err(ERROR_INVALID_BET_AMOUNT));
+    }
+
+    #[test]
+    fn test_current_prices_sum_to_one() {
+        let title = MarketData::string_to_u64_vec("Prices");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Prices".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        assert_eq!(market.current_prices(), Ok((500_000, 500_000)));
+
+        // A thinner YES reserve makes YES dearer; the rounding leftover goes to the dearer side
+        market.yes_liquidity = 1_000_000;
+        market.no_liquidity = 2_000_000;
+        assert_eq!(market.current_prices(), Ok((666_667, 333_333)));
+        market.yes_liquidity = 2_000_000;
+        market.no_liquidity = 1_000_000;
+        assert_eq!(market.current_prices(), Ok((333_333, 666_667)));
+        let (yes, no) = market.current_prices().unwrap();
+        assert_eq!(yes + no, PRICE_PRECISION);
+
+        // An unseeded market reads as even odds
+        market.yes_liquidity = 0;
+        market.no_liquidity = 0;
+        assert_eq!(market.current_prices(), Ok((500_000, 500_000)));
+    }
+
+    #[test]
+    fn test_fees_stay_with_the_market_that_earned_them() {
+        let open_market = |name: &str| MarketData::new_with_title_u64_and_liquidity(
+            MarketData::string_to_u64_vec(name), name.to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        let admin = [1, 1];
+        let (mut market_a, mut market_b) = (open_market("A"), open_market("B"));
+        market_a.place_bet(1, 50_000).unwrap();
+        let earned = market_a.total_fees_collected;
+        assert!(earned > 0);
+
+        // B has earned nothing, whatever A holds
+        assert_eq!(market_b.withdraw_fees(&admin, &admin, [0, 0]), Err(ERROR_NO_FEES_TO_WITHDRAW));
+
+        // A cancelled market still releases its fees, once
+        market_a.void().unwrap();
+        assert_eq!(market_a.withdraw_fees(&admin, &admin, [0, 0]).map(|(_, fees)| fees), Ok(earned));
+        assert_eq!(market_a.withdraw_fees(&admin, &admin, [0, 0]), Err(ERROR_NO_FEES_TO_WITHDRAW));
+    }
+
+    #[test]
+    fn test_buy_then_sell_never_profits_from_rounding() {
+        for (yes_liquidity, no_liquidity) in [(1_000_000, 1_000_000), (1_234_567, 7_654_321), (9_999_991, 1_000_003)] {
+            let title = MarketData::string_to_u64_vec("Round Trip");
+            let mut market = MarketData::new_with_title_u64_and_liquidity(
+                title, "Round Trip".to_string(), 0, 1000, 1000, yes_liquidity, no_liquidity
+            ).unwrap();
+            // No fee, so only rounding could hand the trader anything back
+            market.fee_bps = 0;
+            for amount in (1..400).chain([1_001, 33_333, 250_000]) {
+                let side = amount % 2;
+                let k_before = calculate_k_safe(market.yes_liquidity, market.no_liquidity).unwrap();
+                let shares = match market.place_bet(side, amount) {
+                    Ok(shares) => shares,
+                    Err(_) => continue,
+                };
+                let payout = market.sell_shares(side, shares).unwrap_or(0);
+                assert!(payout <= amount, "bet {} on {} sold back for {}", amount, side, payout);
+                // The dust stays in the pool: the product of the reserves never shrinks
+                assert!(calculate_k_safe(market.yes_liquidity, market.no_liquidity).unwrap() >= k_before);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bonded_disputes_escalate_and_settle_bonds_by_the_decision() {
+        let disputed = |title: &str| {
+            let mut market = MarketData::new_with_title_u64_and_liquidity(
+                MarketData::string_to_u64_vec(title), title.to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+            ).unwrap();
+            market.apply_option(MARKET_OPTION_DISPUTE_WINDOW, 100).unwrap();
+            market.place_bet(1, 10_000).unwrap();
+            market.place_bet(0, 10_000).unwrap();
+            market.resolve(true).unwrap();
+            market.resolved_at = 1000;
+            market
+        };
+
+        // Below the threshold nothing is escalated and bonds come back once the window ends
+        let mut quiet = disputed("Quiet");
+        assert_eq!(quiet.dispute(true, 1, 1010), Err(ERROR_INVALID_OUTCOME));
+        assert_eq!(quiet.dispute(false, 1_000, 1010), Ok(false));
+        assert_eq!(quiet.release_dispute_bond(false, 1_000, 1050), Err(ERROR_CLAIM_NOT_OPEN));
+        assert_eq!(quiet.dispute(false, 1_000, 1100), Err(ERROR_MARKET_ALREADY_RESOLVED));
+        assert_eq!(quiet.release_dispute_bond(false, 1_000, 1100), Ok(()));
+
+        // Enough stake holds claims past the window until the admin decides
+        let mut upheld = disputed("Upheld");
+        assert_eq!(upheld.dispute(false, DISPUTE_ESCALATION_STAKE / 2, 1010), Ok(false));
+        assert_eq!(upheld.dispute(false, DISPUTE_ESCALATION_STAKE / 2, 1020), Ok(true));
+        assert_eq!(upheld.ensure_claims_open(5000), Err(ERROR_CLAIM_NOT_OPEN));
+        upheld.override_resolution(false, 5000).unwrap();
+        assert_eq!(upheld.ensure_claims_open(5000), Ok(()));
+        assert_eq!(upheld.override_resolution(true, 5000), Err(ERROR_MARKET_ALREADY_RESOLVED));
+        assert_eq!(upheld.release_dispute_bond(false, DISPUTE_ESCALATION_STAKE / 2, 5000), Ok(()));
+        assert_eq!(upheld.dispute_stake, [DISPUTE_ESCALATION_STAKE / 2, 0]);
+
+        // A rejected dispute forfeits its bonds to the platform fees
+        let mut rejected = disputed("Rejected");
+        rejected.dispute(false, DISPUTE_ESCALATION_STAKE, 1010).unwrap();
+        let fees = rejected.total_fees_collected;
+        rejected.override_resolution(true, 1200).unwrap();
+        assert_eq!(rejected.total_fees_collected, fees + DISPUTE_ESCALATION_STAKE);
+        assert_eq!(rejected.dispute_stake, [0, 0]);
+        assert_eq!(rejected.release_dispute_bond(false, DISPUTE_ESCALATION_STAKE, 1200), Err(ERROR_NO_DISPUTE_BOND));
+    }
+
+    #[test]
+    fn test_unclaimed_pool_is_swept_once_the_claim_period_ends() {
+        let title = MarketData::string_to_u64_vec("Sweep");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Sweep".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        market.apply_option(MARKET_OPTION_DISPUTE_WINDOW, 10).unwrap();
+        market.apply_option(MARKET_OPTION_CLAIM_PERIOD, 50).unwrap();
+        let early = market.place_bet(1, 10_000).unwrap();
+        let late = market.place_bet(1, 10_000).unwrap();
+        assert_eq!(market.sweep_unclaimed(2000), Err(ERROR_MARKET_NOT_RESOLVED));
+        market.resolve(true).unwrap();
+        market.resolved_at = 1000;
+
+        // Claims open after the dispute window and expire claim_period later
+        assert_eq!(market.claim_deadline(), Ok(Some(1060)));
+        assert_eq!(market.ensure_claim_not_expired(1059), Ok(()));
+        let paid = market.settle_claim([1, 1], early, 0).unwrap().payout;
+        assert_eq!(market.sweep_unclaimed(1059), Err(ERROR_CLAIM_PERIOD_ACTIVE));
+        assert_eq!(market.ensure_claim_not_expired(1060), Err(ERROR_CLAIM_EXPIRED));
+
+        let unclaimed = market.prize_pool - paid;
+        assert!(market.calculate_payout(late, 0).unwrap() <= unclaimed);
+        assert_eq!(market.sweep_unclaimed(1060), Ok(unclaimed));
+        assert_eq!(market.swept, unclaimed);
+        assert_eq!(market.check_solvency(), Ok(()));
+        assert_eq!(market.ledger().unwrap().outstanding_claims, 0);
+        assert_eq!(market.sweep_unclaimed(2000), Err(ERROR_NOTHING_TO_SWEEP));
+
+        // Without a claim period nothing ever expires
+        let mut open = MarketData::new_with_title_u64_and_liquidity(
+            MarketData::string_to_u64_vec("Open"), "Open".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        open.place_bet(1, 10_000).unwrap();
+        open.resolve(true).unwrap();
+        assert_eq!(open.ensure_claim_not_expired(u64::MAX), Ok(()));
+        assert_eq!(open.sweep_unclaimed(u64::MAX), Err(ERROR_CLAIM_PERIOD_ACTIVE));
+
+        // An escalated dispute freezes claims, so the pool stays put until the admin decides
+        let mut escalated = MarketData::new_with_title_u64_and_liquidity(
+            MarketData::string_to_u64_vec("Escalated"), "Escalated".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        escalated.apply_option(MARKET_OPTION_DISPUTE_WINDOW, 10).unwrap();
+        escalated.apply_option(MARKET_OPTION_CLAIM_PERIOD, 50).unwrap();
+        let winner = escalated.place_bet(1, 10_000).unwrap();
+        escalated.place_bet(0, 10_000).unwrap();
+        escalated.resolve(true).unwrap();
+        escalated.resolved_at = 1000;
+        assert_eq!(escalated.dispute(false, DISPUTE_ESCALATION_STAKE, 1005), Ok(true));
+        assert_eq!(escalated.claim_deadline(), Ok(Some(1060)));
+        assert_eq!(escalated.sweep_unclaimed(5000), Err(ERROR_CLAIM_NOT_OPEN));
+        escalated.override_resolution(true, 5000).unwrap();
+        assert_eq!(escalated.sweep_unclaimed(5000), Err(ERROR_CLAIM_PERIOD_ACTIVE));
+        assert!(escalated.calculate_payout(winner, 0).unwrap() > 0);
+    }
+
+    #[test]
+    fn test_dispute_window_delays_claims_and_allows_override() {
+        let title = MarketData::string_to_u64_vec("Dispute");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Dispute".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        market.apply_option(MARKET_OPTION_DISPUTE_WINDOW, 100).unwrap();
+        let yes_shares = market.place_bet(1, 10_000).unwrap();
+        let no_shares = market.place_bet(0, 10_000).unwrap();
+        assert_eq!(market.override_resolution(false, 900), Err(ERROR_MARKET_NOT_RESOLVED));
+
+        market.apply_option(MARKET_OPTION_ORACLE_QUORUM, 1).unwrap();
+        market.submit_oracle_outcome([1, 1], true).unwrap();
+        assert_eq!(market.try_auto_resolve(1000), Ok(true));
+        assert_eq!(market.resolved_at, 1000);
+        assert!(market.calculate_payout(yes_shares, 0).unwrap() > 0);
+
+        // Inside the window claims wait and the admin can flip the outcome
+        assert_eq!(market.ensure_claims_open(1050), Err(ERROR_CLAIM_NOT_OPEN));
+        market.override_resolution(false, 1050).unwrap();
+        assert_eq!(market.calculate_payout(yes_shares, 0), Ok(0));
+        assert!(market.calculate_payout(0, no_shares).unwrap() > 0);
+
+        // Once it ends the outcome is final and claims proceed
+        assert_eq!(market.ensure_claims_open(1100), Ok(()));
+        assert_eq!(market.override_resolution(true, 1100), Err(ERROR_MARKET_ALREADY_RESOLVED));
+
+        // Without a window claims open as soon as the market resolves
+        let mut immediate = MarketData::new_with_title_u64_and_liquidity(
+            MarketData::string_to_u64_vec("Immediate"), "Immediate".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        immediate.resolve(true).unwrap();
+        immediate.resolved_at = 1000;
+        assert_eq!(immediate.ensure_claims_open(1000), Ok(()));
+        assert_eq!(immediate.override_resolution(false, 1000), Err(ERROR_MARKET_ALREADY_RESOLVED));
+    }
+
+    #[test]
+    fn test_lmsr_market_trades_through_its_cost_function() {
+        let title = MarketData::string_to_u64_vec("LMSR");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "LMSR".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        assert_eq!(market.apply_option(MARKET_OPTION_LMSR_B, 1), Err(ERROR_INVALID_MARKET_OPTION));
+        market.apply_option(MARKET_OPTION_LMSR_B, 200_000).unwrap();
+        assert_eq!(market.current_prices(), Ok((PRICE_PRECISION / 2, PRICE_PRECISION / 2)));
+
+        // Bets move the LMSR price and leave the constant-product reserves alone
+        let shares = market.place_bet(1, 50_000).unwrap();
+        assert_eq!((market.yes_liquidity, market.no_liquidity), (1_000_000, 1_000_000));
+        let (yes, no) = market.current_prices().unwrap();
+        assert!(yes > no);
+        assert_eq!(market.get_yes_price(), Ok(yes));
+        assert_eq!(market.implied_probability(1).unwrap() + market.implied_probability(0).unwrap(), BASIS_POINTS_PRECISION);
+        assert!(market.marginal_impact(1).unwrap() > 0);
+        assert!(market.total_taker_surcharges > 0);
+
+        // A bet back toward even odds earns the maker rebate instead of the surcharge
+        let (rebates, surcharges) = (market.total_maker_rebates, market.total_taker_surcharges);
+        let no_shares = market.place_bet(0, 10_000).unwrap();
+        assert!(market.total_maker_rebates > rebates);
+        assert_eq!(market.total_taker_surcharges, surcharges);
+        market.sell_shares(0, no_shares).unwrap();
+        let (yes, no) = market.current_prices().unwrap();
+
+        // Complete sets shift both quantities equally, so the price holds
+        market.mint_set(10_000).unwrap();
+        assert_eq!(market.current_prices(), Ok((yes, no)));
+        market.burn_set(10_000).unwrap();
+
+        // Selling everything back returns at most the stake, net of fees on both legs
+        let payout = market.sell_shares(1, shares).unwrap();
+        assert!(payout < 50_000);
+        assert_eq!(market.current_prices(), Ok((PRICE_PRECISION / 2, PRICE_PRECISION / 2)));
+        market.check_solvency().unwrap();
+
+        // LPs have no reserves to deepen, and the parameter survives storage
+        assert_eq!(market.deepen_reserves(1_000), Err(ERROR_PRICING_UNSUPPORTED));
+        let mut data = vec![];
+        market.to_data(&mut data);
+        assert_eq!(MarketData::from_data(&mut data.iter_mut()).lmsr_b, 200_000);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::error::*;
 use crate::config::{PRICE_PRECISION, FEE_BASIS_POINTS, PLATFORM_FEE_RATE};
+use crate::fixed::{mul_div, mul_div_up};
 
 // 安全限制常量
 pub const MAX_LIQUIDITY: u64 = 1_000_000_000_000;  // 1万亿 - 防止溢出
@@ -32,14 +33,7 @@ pub fn safe_add(a: u64, b: u64) -> Result<u64, u32> {
 
 /// 使用 u128 进行高精度乘法，然后安全转换回 u64
 pub fn safe_mul_high_precision(a: u64, b: u64) -> Result<u64, u32> {
-    let result = (a as u128).checked_mul(b as u128)
-        .ok_or(ERROR_OVERFLOW)?;
-    
-    if result > u64::MAX as u128 {
-        return Err(ERROR_OVERFLOW);
-    }
-    
-    Ok(result as u64)
+    mul_div(a, b, 1)
 }
 
 /// 使用 u128 进行高精度除法计算
@@ -47,17 +41,7 @@ pub fn safe_div_high_precision(a: u64, b: u64, c: u64) -> Result<u64, u32> {
     if b == 0 || c == 0 {
         return Err(ERROR_DIVISION_BY_ZERO);
     }
-    
-    let numerator = (a as u128).checked_mul(b as u128)
-        .ok_or(ERROR_OVERFLOW)?;
-    
-    let result = numerator / (c as u128);
-    
-    if result > u64::MAX as u128 {
-        return Err(ERROR_OVERFLOW);
-    }
-    
-    Ok(result as u64)
+    mul_div(a, b, c)
 }
 
 /// 安全计算 AMM 常量乘积 k = x * y
@@ -79,12 +63,14 @@ pub fn calculate_k_safe(yes_liquidity: u64, no_liquidity: u64) -> Result<u128, u
 }
 
 /// 安全计算新的流动性值
+/// 向上取整：交易流出一侧的储备多留一点，买入得到的份额和卖出得到的金额都向下取整，
+/// k 只增不减，舍入的零头始终留在池中，先买后卖不可能无风险获利
 pub fn calculate_new_liquidity_safe(k: u128, other_liquidity: u64) -> Result<u64, u32> {
     if other_liquidity == 0 {
         return Err(ERROR_DIVISION_BY_ZERO);
     }
     
-    let new_liquidity = k / (other_liquidity as u128);
+    let new_liquidity = k.div_ceil(other_liquidity as u128);
     
     if new_liquidity > u64::MAX as u128 {
         return Err(ERROR_OVERFLOW);
@@ -106,23 +92,8 @@ pub fn calculate_fee_safe(amount: u64) -> Result<u64, u32> {
         return Err(ERROR_BET_TOO_LARGE);
     }
     
-    // 计算 (amount * PLATFORM_FEE_RATE + FEE_BASIS_POINTS - 1) / FEE_BASIS_POINTS
-    // 这样可以实现向上取整
-    let numerator = (amount as u128)
-        .checked_mul(PLATFORM_FEE_RATE as u128)
-        .ok_or(ERROR_OVERFLOW)?;
-    
-    let rounded_numerator = numerator
-        .checked_add((FEE_BASIS_POINTS - 1) as u128)
-        .ok_or(ERROR_OVERFLOW)?;
-    
-    let result = rounded_numerator / FEE_BASIS_POINTS as u128;
-    
-    if result > u64::MAX as u128 {
-        return Err(ERROR_OVERFLOW);
-    }
-    
-    Ok(result as u64)
+    // 向上取整
+    mul_div_up(amount, PLATFORM_FEE_RATE, FEE_BASIS_POINTS)
 }
 
 /// 安全计算净金额（扣除费用后）
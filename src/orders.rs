@@ -0,0 +1,236 @@
+use serde::Serialize;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::config::{MAX_ORDERS_PER_MARKET, MAX_ORDERS_PER_OWNER, PRICE_PRECISION};
+use crate::error::*;
+use crate::fixed::{mul_div, mul_div_up};
+
+// A resting offer to sell `shares` of one outcome at `price` collateral per share, in
+// PRICE_PRECISION units. The shares, and the cost basis they carried, are escrowed out of
+// the owner's position while the order rests and go back to it on cancel.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct LimitOrder {
+    pub id: u64,
+    pub owner: [u64; 2],
+    pub outcome: u64,
+    pub price: u64,
+    pub shares: u64,
+    pub cost_basis: u64,
+}
+
+// One order's part in a buy: `cost` moves from the taker to the owner for `shares`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    pub order_id: u64,
+    pub owner: [u64; 2],
+    pub shares: u64,
+    pub cost: u64,
+    pub released_basis: u64,  // Share of the order's cost basis the filled shares carried
+    pub remaining: u64,       // Shares still resting on the order afterwards
+}
+
+// Every resting order of one market, matched before incoming bets reach the AMM
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct OrderBook {
+    pub next_id: u64,
+    pub orders: Vec<LimitOrder>,
+}
+
+impl OrderBook {
+    pub fn place(&mut self, owner: [u64; 2], outcome: u64, price: u64, shares: u64, cost_basis: u64) -> Result<u64, u32> {
+        if outcome > 1 {
+            return Err(ERROR_INVALID_BET_TYPE);
+        }
+        if price == 0 || price >= PRICE_PRECISION || shares == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        if self.orders.len() >= MAX_ORDERS_PER_MARKET
+            || self.orders.iter().filter(|order| order.owner == owner).count() >= MAX_ORDERS_PER_OWNER {
+            return Err(ERROR_ORDER_BOOK_FULL);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.orders.push(LimitOrder { id, owner, outcome, price, shares, cost_basis });
+        Ok(id)
+    }
+
+    // Only the owner may take an order down; the escrowed shares are returned in it
+    pub fn cancel(&mut self, owner: &[u64; 2], order_id: u64) -> Result<LimitOrder, u32> {
+        let index = self.orders.iter().position(|order| order.id == order_id).ok_or(ERROR_ORDER_NOT_FOUND)?;
+        if self.orders[index].owner != *owner {
+            return Err(ERROR_UNAUTHORIZED);
+        }
+        Ok(self.orders.remove(index))
+    }
+
+    // Take down every order `owner` has resting, oldest first
+    pub fn cancel_all(&mut self, owner: &[u64; 2]) -> Vec<LimitOrder> {
+        let (cancelled, kept) = std::mem::take(&mut self.orders).into_iter().partition(|order| order.owner == *owner);
+        self.orders = kept;
+        cancelled
+    }
+
+    // Buy `outcome` from orders priced at or below `limit_price` for at most `budget`, cheapest
+    // first and oldest first within a price. The taker's own orders are never matched.
+    // Costs round up, so makers always receive at least their price.
+    pub fn match_buy(&mut self, taker: &[u64; 2], outcome: u64, budget: u64, limit_price: u64) -> Result<Vec<Fill>, u32> {
+        let mut fills = Vec::new();
+        let mut budget = budget;
+        loop {
+            let best = self.orders.iter().enumerate()
+                .filter(|(_, order)| order.outcome == outcome && order.owner != *taker && order.price <= limit_price)
+                .min_by_key(|(_, order)| (order.price, order.id))
+                .map(|(index, _)| index);
+            let Some(index) = best else { break };
+            let order = &mut self.orders[index];
+            let shares = mul_div(budget, PRICE_PRECISION, order.price)?.min(order.shares);
+            if shares == 0 {
+                break;
+            }
+            let cost = mul_div_up(shares, order.price, PRICE_PRECISION)?;
+            let released_basis = mul_div(order.cost_basis, shares, order.shares)?;
+            order.shares -= shares;
+            order.cost_basis -= released_basis;
+            budget -= cost;
+            fills.push(Fill { order_id: order.id, owner: order.owner, shares, cost, released_basis, remaining: order.shares });
+            if order.shares == 0 {
+                self.orders.remove(index);
+            }
+        }
+        Ok(fills)
+    }
+}
+
+impl StorageData for OrderBook {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        let next_id = *u64data.next().unwrap();
+        let count = *u64data.next().unwrap();
+        let mut orders = Vec::new();
+        for _ in 0..count {
+            orders.push(LimitOrder {
+                id: *u64data.next().unwrap(),
+                owner: [*u64data.next().unwrap(), *u64data.next().unwrap()],
+                outcome: *u64data.next().unwrap(),
+                price: *u64data.next().unwrap(),
+                shares: *u64data.next().unwrap(),
+                cost_basis: *u64data.next().unwrap(),
+            });
+        }
+        OrderBook { next_id, orders }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.next_id);
+        data.push(self.orders.len() as u64);
+        for order in &self.orders {
+            data.push(order.id);
+            data.extend_from_slice(&order.owner);
+            data.push(order.outcome);
+            data.push(order.price);
+            data.push(order.shares);
+            data.push(order.cost_basis);
+        }
+    }
+}
+
+pub struct OrderBookManager;
+
+impl OrderBookManager {
+    const ORDER_PREFIX: [u64; 2] = [6, 0]; // Prefix for order book storage, one book per market
+
+    pub fn store_book(market_id: u64, book: &OrderBook) {
+        let mut data = vec![];
+        book.to_data(&mut data);
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let key = [Self::ORDER_PREFIX[0], Self::ORDER_PREFIX[1], market_id, 0];
+        kvpair.set(&key, data.as_slice());
+    }
+
+    pub fn get_book(market_id: u64) -> OrderBook {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let key = [Self::ORDER_PREFIX[0], Self::ORDER_PREFIX[1], market_id, 0];
+        let mut data = kvpair.get(&key);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            OrderBook::from_data(&mut u64data)
+        } else {
+            OrderBook::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: [u64; 2] = [1, 1];
+    const BOB: [u64; 2] = [2, 2];
+    const CAROL: [u64; 2] = [3, 3];
+
+    #[test]
+    fn test_buys_fill_cheapest_then_oldest_within_the_limit() {
+        let mut book = OrderBook::default();
+        let dear = book.place(ALICE, 1, 600_000, 1_000, 500).unwrap();
+        let cheap = book.place(BOB, 1, 400_000, 1_000, 400).unwrap();
+        let cheap_later = book.place(ALICE, 1, 400_000, 1_000, 300).unwrap();
+        book.place(BOB, 0, 100_000, 1_000, 100).unwrap();
+
+        // 600 collateral at 0.4 buys Bob's whole order and half of Alice's at the same price;
+        // the order above the limit and the NO order are left alone
+        let fills = book.match_buy(&CAROL, 1, 600, 500_000).unwrap();
+        assert_eq!(fills, vec![
+            Fill { order_id: cheap, owner: BOB, shares: 1_000, cost: 400, released_basis: 400, remaining: 0 },
+            Fill { order_id: cheap_later, owner: ALICE, shares: 500, cost: 200, released_basis: 150, remaining: 500 },
+        ]);
+        assert_eq!(book.orders.iter().map(|order| order.id).collect::<Vec<_>>(), vec![dear, cheap_later, 3]);
+
+        // Nobody trades against their own orders
+        assert_eq!(book.match_buy(&ALICE, 1, 10_000, PRICE_PRECISION), Ok(vec![]));
+
+        // Storage round trip keeps ids counting on
+        let mut data = vec![];
+        book.to_data(&mut data);
+        let mut loaded = OrderBook::from_data(&mut data.iter_mut());
+        assert_eq!(loaded, book);
+        assert_eq!(loaded.place(CAROL, 0, 1, 1, 0), Ok(4));
+    }
+
+    #[test]
+    fn test_costs_round_up_and_dust_buys_nothing() {
+        let mut book = OrderBook::default();
+        book.place(ALICE, 0, 333_333, 10, 0).unwrap();
+        // 1 unit of collateral buys 3 shares at 0.333333, paying the full unit
+        let fills = book.match_buy(&BOB, 0, 1, 333_333).unwrap();
+        assert_eq!((fills[0].shares, fills[0].cost), (3, 1));
+        // Too little for a single share leaves the order untouched
+        let mut thin = OrderBook::default();
+        thin.place(ALICE, 0, 500_000, 10, 0).unwrap();
+        assert_eq!(thin.match_buy(&BOB, 0, 0, PRICE_PRECISION), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_only_the_owner_cancels_and_the_book_is_bounded() {
+        let mut book = OrderBook::default();
+        let id = book.place(ALICE, 1, 500_000, 100, 40).unwrap();
+        assert_eq!(book.cancel(&BOB, id), Err(ERROR_UNAUTHORIZED));
+        assert_eq!(book.cancel(&ALICE, id).map(|order| (order.shares, order.cost_basis)), Ok((100, 40)));
+        assert_eq!(book.cancel(&ALICE, id), Err(ERROR_ORDER_NOT_FOUND));
+
+        assert_eq!(book.place(ALICE, 2, 500_000, 100, 0), Err(ERROR_INVALID_BET_TYPE));
+        assert_eq!(book.place(ALICE, 1, PRICE_PRECISION, 100, 0), Err(ERROR_INVALID_BET_AMOUNT));
+
+        // One owner runs out of slots long before the book does
+        for _ in 0..MAX_ORDERS_PER_OWNER {
+            book.place(ALICE, 1, 500_000, 1, 0).unwrap();
+        }
+        assert_eq!(book.place(ALICE, 1, 500_000, 1, 0), Err(ERROR_ORDER_BOOK_FULL));
+        assert!(book.place(BOB, 1, 500_000, 1, 0).is_ok());
+        assert_eq!(book.cancel_all(&ALICE).len(), MAX_ORDERS_PER_OWNER);
+        assert_eq!(book.orders.iter().map(|order| order.owner).collect::<Vec<_>>(), vec![BOB]);
+
+        for owner in 0..(MAX_ORDERS_PER_MARKET / MAX_ORDERS_PER_OWNER) as u64 {
+            while book.place([10, owner], 1, 500_000, 1, 0).is_ok() {}
+        }
+        assert_eq!(book.orders.len(), MAX_ORDERS_PER_MARKET);
+        assert_eq!(book.place(CAROL, 1, 500_000, 1, 0), Err(ERROR_ORDER_BOOK_FULL));
+    }
+}
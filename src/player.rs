@@ -1,12 +1,123 @@
 use serde::Serialize;
 use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
 use crate::error::*;
+use crate::config::{CLIENT_TAG_HISTORY, MAX_SUB_ACCOUNTS};
+use sha2::{Digest, Sha256};
 
-#[derive(Serialize, Clone, Debug, Default)]
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
 pub struct PlayerMarketPosition {
     pub yes_shares: u64,
     pub no_shares: u64,
     pub claimed: bool,
+    pub yes_cost: u64,  // Collateral paid for the YES shares still held
+    pub no_cost: u64,   // Collateral paid for the NO shares still held
+    pub yes_sell_unlock: u64, // Counter from which YES shares may be sold again after a bet
+    pub no_sell_unlock: u64,  // Counter from which NO shares may be sold again after a bet
+    pub commitment: [u64; 4], // Hash of a bet committed but not yet revealed, all zero if none
+    pub committed_at: u64,    // Counter of the CommitBet that set `commitment`
+    pub dispute_bond: u64,    // Bond staked against the market's resolution, 0 if none
+    pub dispute_outcome: u64, // Outcome that bond claims is the true one
+}
+
+impl PlayerMarketPosition {
+    pub fn add_cost(&mut self, outcome: u64, amount: u64) -> Result<(), u32> {
+        let cost = if outcome == 1 { &mut self.yes_cost } else { &mut self.no_cost };
+        *cost = cost.checked_add(amount).ok_or(ERROR_OVERFLOW)?;
+        Ok(())
+    }
+
+    // Release the basis of `removed` shares out of `held_before`, pro rata; returns the amount released
+    pub fn reduce_cost(&mut self, outcome: u64, removed: u64, held_before: u64) -> Result<u64, u32> {
+        let cost = if outcome == 1 { &mut self.yes_cost } else { &mut self.no_cost };
+        if held_before == 0 {
+            return Ok(0);
+        }
+        let released = crate::fixed::mul_div(*cost, removed, held_before)?;
+        *cost -= released;
+        Ok(released)
+    }
+
+    // A claimed position is used up: its shares and basis are cleared so nothing can pay out twice.
+    // A dispute bond is settled separately and survives the claim.
+    pub fn consume(&mut self) {
+        *self = PlayerMarketPosition {
+            claimed: true,
+            dispute_bond: self.dispute_bond,
+            dispute_outcome: self.dispute_outcome,
+            ..PlayerMarketPosition::default()
+        };
+    }
+
+    // Further bonds from the same player must back the same outcome
+    pub fn add_dispute_bond(&mut self, outcome: u64, bond: u64) -> Result<(), u32> {
+        if self.dispute_bond > 0 && self.dispute_outcome != outcome {
+            return Err(ERROR_INVALID_OUTCOME);
+        }
+        self.dispute_bond = self.dispute_bond.checked_add(bond).ok_or(ERROR_OVERFLOW)?;
+        self.dispute_outcome = outcome;
+        Ok(())
+    }
+
+    // A bet holds back sells of the same side for `cooldown` ticks
+    pub fn start_sell_cooldown(&mut self, outcome: u64, counter: u64, cooldown: u64) -> Result<(), u32> {
+        let unlock = if outcome == 1 { &mut self.yes_sell_unlock } else { &mut self.no_sell_unlock };
+        *unlock = counter.checked_add(cooldown).ok_or(ERROR_OVERFLOW)?;
+        Ok(())
+    }
+
+    pub fn check_sell_cooldown(&self, outcome: u64, counter: u64) -> Result<(), u32> {
+        let unlock = if outcome == 1 { self.yes_sell_unlock } else { self.no_sell_unlock };
+        if counter < unlock {
+            return Err(ERROR_SELL_COOLDOWN);
+        }
+        Ok(())
+    }
+
+    // sha256 over the little-endian words [pid0, pid1, market_id, bet_type, amount, min_shares, salt],
+    // read back as four little-endian words. Binding the pid stops anyone replaying another player's
+    // commitment, and binding min_shares stops the revealed bet being filled at a worse price.
+    pub fn bet_commitment(player_id: &[u64; 2], market_id: u64, bet_type: u64, amount: u64, min_shares: u64, salt: u64) -> [u64; 4] {
+        let mut hasher = Sha256::new();
+        for word in [player_id[0], player_id[1], market_id, bet_type, amount, min_shares, salt] {
+            hasher.update(word.to_le_bytes());
+        }
+        let digest = hasher.finalize();
+        let mut hash = [0u64; 4];
+        for (limb, bytes) in hash.iter_mut().zip(digest.chunks(8)) {
+            *limb = u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        hash
+    }
+
+    // A new commitment replaces any earlier one that was never revealed
+    pub fn commit_bet(&mut self, commitment: [u64; 4], counter: u64) -> Result<(), u32> {
+        if commitment == [0; 4] {
+            return Err(ERROR_COMMITMENT_MISMATCH);
+        }
+        self.commitment = commitment;
+        self.committed_at = counter;
+        Ok(())
+    }
+
+    // A reveal must come after the commit's tick and within `window` ticks of it
+    pub fn check_reveal(&self, revealed: [u64; 4], counter: u64, window: u64) -> Result<(), u32> {
+        if self.commitment == [0; 4] || counter <= self.committed_at || counter - self.committed_at > window {
+            return Err(ERROR_COMMITMENT_NOT_FOUND);
+        }
+        if self.commitment != revealed {
+            return Err(ERROR_COMMITMENT_MISMATCH);
+        }
+        Ok(())
+    }
+
+    pub fn clear_commitment(&mut self) {
+        self.commitment = [0; 4];
+        self.committed_at = 0;
+    }
+
+    pub fn cost_basis(&self) -> Result<u64, u32> {
+        self.yes_cost.checked_add(self.no_cost).ok_or(ERROR_OVERFLOW)
+    }
 }
 
 impl StorageData for PlayerMarketPosition {
@@ -15,6 +126,15 @@ impl StorageData for PlayerMarketPosition {
             yes_shares: *u64data.next().unwrap(),
             no_shares: *u64data.next().unwrap(),
             claimed: *u64data.next().unwrap() != 0,
+            // Positions stored before cost tracking have no recorded basis
+            yes_cost: u64data.next().map_or(0, |v| *v),
+            no_cost: u64data.next().map_or(0, |v| *v),
+            yes_sell_unlock: u64data.next().map_or(0, |v| *v),
+            no_sell_unlock: u64data.next().map_or(0, |v| *v),
+            commitment: [(); 4].map(|_| u64data.next().map_or(0, |v| *v)),
+            committed_at: u64data.next().map_or(0, |v| *v),
+            dispute_bond: u64data.next().map_or(0, |v| *v),
+            dispute_outcome: u64data.next().map_or(0, |v| *v),
         }
     }
 
@@ -22,6 +142,14 @@ impl StorageData for PlayerMarketPosition {
         data.push(self.yes_shares);
         data.push(self.no_shares);
         data.push(if self.claimed { 1 } else { 0 });
+        data.push(self.yes_cost);
+        data.push(self.no_cost);
+        data.push(self.yes_sell_unlock);
+        data.push(self.no_sell_unlock);
+        data.extend_from_slice(&self.commitment);
+        data.push(self.committed_at);
+        data.push(self.dispute_bond);
+        data.push(self.dispute_outcome);
     }
 }
 
@@ -31,7 +159,7 @@ impl PlayerMarketManager {
     const POSITION_PREFIX: [u64; 2] = [2, 0]; // Prefix for player market position storage
     
     // 安全的 player_id 组合方法
-    fn combine_player_id_safe(player_id: &[u64; 2]) -> u64 {
+    pub(crate) fn combine_player_id_safe(player_id: &[u64; 2]) -> u64 {
         // 使用更安全的组合方式，确保不会溢出
         // 取高32位和低32位，但限制在合理范围内
         let high = (player_id[0] & 0xFFFFFFFF) << 32;
@@ -65,6 +193,81 @@ impl PlayerMarketManager {
 #[derive(Serialize, Clone, Debug, Default)]
 pub struct PlayerData {
     pub balance: u64,
+    pub active_markets: Vec<u64>,  // Markets the player currently holds a position in
+    pub frozen: bool,              // Set by the admin, blocks trading and withdrawals
+    pub withdraw_address: [u64; 3], // Bridge address in withdrawal limb layout (low 32 bits of the first limb unused), zero = unset
+    pub sub_balances: Vec<(u64, u64)>, // (sub-account id, balance); id 0 is the main balance and never listed
+    pub realized_pnl: i64,         // Proceeds from sells and claims minus the cost basis they closed
+    pub total_spent: u64,          // Lifetime collateral paid into bets, across all markets
+    pub total_received: u64,       // Lifetime sell and claim proceeds, across all markets
+    pub recent_tags: Vec<(u64, u32)>, // (client tag, result code) of the last tagged commands, oldest first
+}
+
+// Everything a client needs to build its next command, taken from one read of the player.
+// Funds are never locked in this app: bets turn straight into shares, listed per market.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SigningContext {
+    pub nonce: u64,
+    pub balance: u64,
+    pub positions: Vec<(u64, PlayerMarketPosition)>, // (market_id, position) for each active market
+}
+
+impl SigningContext {
+    pub fn new(player: &Player, positions: Vec<(u64, PlayerMarketPosition)>) -> Self {
+        SigningContext {
+            nonce: player.nonce,
+            balance: player.data.balance,
+            positions,
+        }
+    }
+
+    pub fn load(player: &Player) -> Self {
+        let positions = player.data.active_markets.iter()
+            .map(|market_id| (*market_id, PlayerMarketManager::get_position(&player.player_id, *market_id)))
+            .collect();
+        Self::new(player, positions)
+    }
+
+    pub fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.nonce);
+        data.push(self.balance);
+        data.push(self.positions.len() as u64);
+        for (market_id, position) in &self.positions {
+            data.push(*market_id);
+            position.to_data(data);
+        }
+    }
+}
+
+// Read-only view of any player's standing in one market, for frontends that would
+// otherwise rebuild it from events
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PlayerView {
+    pub balance: u64,
+    pub yes_shares: u64,
+    pub no_shares: u64,
+    pub nonce: u64,
+    pub claimed: bool,
+}
+
+impl PlayerView {
+    pub fn new(player: &Player, position: &PlayerMarketPosition) -> Self {
+        PlayerView {
+            balance: player.data.balance,
+            yes_shares: position.yes_shares,
+            no_shares: position.no_shares,
+            nonce: player.nonce,
+            claimed: position.claimed,
+        }
+    }
+
+    pub fn load(player: &Player, market_id: u64) -> Self {
+        Self::new(player, &PlayerMarketManager::get_position(&player.player_id, market_id))
+    }
+
+    pub fn to_query_data(&self) -> Vec<u64> {
+        vec![self.balance, self.yes_shares, self.no_shares, self.nonce, self.claimed as u64]
+    }
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -98,8 +301,9 @@ impl PredictionMarketPlayer {
 }
 
 impl PlayerData {
-    pub fn add_balance(&mut self, amount: u64) {
-        self.balance += amount;
+    pub fn add_balance(&mut self, amount: u64) -> Result<(), u32> {
+        self.balance = self.balance.checked_add(amount).ok_or(ERROR_OVERFLOW)?;
+        Ok(())
     }
 
     pub fn spend_balance(&mut self, amount: u64) -> Result<(), u32> {
@@ -110,6 +314,105 @@ impl PlayerData {
         Ok(())
     }
 
+    // Track a newly opened position, rejecting it once `max_positions` markets are held
+    pub fn enter_market(&mut self, market_id: u64, max_positions: u64) -> Result<(), u32> {
+        if self.active_markets.contains(&market_id) {
+            return Ok(());
+        }
+        if max_positions != 0 && self.active_markets.len() as u64 >= max_positions {
+            return Err(ERROR_TOO_MANY_POSITIONS);
+        }
+        self.active_markets.push(market_id);
+        Ok(())
+    }
+
+    pub fn sub_balance(&self, sub: u64) -> u64 {
+        if sub == 0 {
+            return self.balance;
+        }
+        self.sub_balances.iter().find(|(id, _)| *id == sub).map_or(0, |(_, amount)| *amount)
+    }
+
+    // Main balance plus every sub-balance
+    pub fn total_balance(&self) -> Result<u64, u32> {
+        self.sub_balances.iter().try_fold(self.balance, |total, (_, amount)| total.checked_add(*amount).ok_or(ERROR_OVERFLOW))
+    }
+
+    // Move free balance between two of the player's own sub-accounts; emptied sub-accounts are dropped
+    pub fn internal_transfer(&mut self, from_sub: u64, to_sub: u64, amount: u64) -> Result<(), u32> {
+        if amount == 0 || from_sub == to_sub {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        if self.sub_balance(from_sub) < amount {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
+        }
+        let to_balance = self.sub_balance(to_sub).checked_add(amount).ok_or(ERROR_OVERFLOW)?;
+        if to_sub != 0 && self.sub_balance(to_sub) == 0 && self.sub_balances.len() >= MAX_SUB_ACCOUNTS {
+            return Err(ERROR_TOO_MANY_SUB_ACCOUNTS);
+        }
+        let from_balance = self.sub_balance(from_sub) - amount;
+        self.set_sub_balance(from_sub, from_balance);
+        self.set_sub_balance(to_sub, to_balance);
+        Ok(())
+    }
+
+    fn set_sub_balance(&mut self, sub: u64, amount: u64) {
+        if sub == 0 {
+            self.balance = amount;
+            return;
+        }
+        self.sub_balances.retain(|(id, _)| *id != sub);
+        if amount > 0 {
+            self.sub_balances.push((sub, amount));
+        }
+    }
+
+    pub fn has_withdraw_address(&self) -> bool {
+        self.withdraw_address != [0, 0, 0]
+    }
+
+    // Book the gain or loss of closing `cost` of basis for `proceeds`
+    pub fn realize_pnl(&mut self, proceeds: u64, cost: u64) -> Result<(), u32> {
+        let delta = i64::try_from(proceeds as i128 - cost as i128).map_err(|_| ERROR_OVERFLOW)?;
+        self.realized_pnl = self.realized_pnl.checked_add(delta).ok_or(ERROR_OVERFLOW)?;
+        Ok(())
+    }
+
+    pub fn record_spent(&mut self, amount: u64) -> Result<(), u32> {
+        self.total_spent = self.total_spent.checked_add(amount).ok_or(ERROR_OVERFLOW)?;
+        Ok(())
+    }
+
+    pub fn record_received(&mut self, amount: u64) -> Result<(), u32> {
+        self.total_received = self.total_received.checked_add(amount).ok_or(ERROR_OVERFLOW)?;
+        Ok(())
+    }
+
+    pub fn ensure_not_frozen(&self) -> Result<(), u32> {
+        if self.frozen {
+            return Err(ERROR_PLAYER_FROZEN);
+        }
+        Ok(())
+    }
+
+    // Result recorded for `tag` if it is still among the remembered tags
+    pub fn cached_result(&self, tag: u64) -> Option<u32> {
+        self.recent_tags.iter().find(|(seen, _)| *seen == tag).map(|(_, result)| *result)
+    }
+
+    // Remember the result of a tagged command, evicting the oldest tag once the history is full
+    pub fn record_tag(&mut self, tag: u64, result: u32) {
+        self.recent_tags.retain(|(seen, _)| *seen != tag);
+        if self.recent_tags.len() >= CLIENT_TAG_HISTORY {
+            self.recent_tags.remove(0);
+        }
+        self.recent_tags.push((tag, result));
+    }
+
+    pub fn exit_market(&mut self, market_id: u64) {
+        self.active_markets.retain(|id| *id != market_id);
+    }
+
     // Multi-market support methods using indexed storage
     pub fn get_yes_shares_for_market(&self, player_id: &[u64; 2], market_id: u64) -> u64 {
         let position = PlayerMarketManager::get_position(player_id, market_id);
@@ -121,16 +424,18 @@ impl PlayerData {
         position.no_shares
     }
 
-    pub fn add_yes_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64) {
+    pub fn add_yes_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64) -> Result<(), u32> {
         let mut position = PlayerMarketManager::get_position(player_id, market_id);
-        position.yes_shares += shares;
+        position.yes_shares = position.yes_shares.checked_add(shares).ok_or(ERROR_OVERFLOW)?;
         PlayerMarketManager::store_position(player_id, market_id, &position);
+        Ok(())
     }
 
-    pub fn add_no_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64) {
+    pub fn add_no_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64) -> Result<(), u32> {
         let mut position = PlayerMarketManager::get_position(player_id, market_id);
-        position.no_shares += shares;
+        position.no_shares = position.no_shares.checked_add(shares).ok_or(ERROR_OVERFLOW)?;
         PlayerMarketManager::store_position(player_id, market_id, &position);
+        Ok(())
     }
 
     pub fn subtract_yes_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64) -> Result<(), u32> {
@@ -158,22 +463,394 @@ impl PlayerData {
         if position.claimed {
             return Err(ERROR_ALREADY_CLAIMED);
         }
-        position.claimed = true;
+        position.consume();
         PlayerMarketManager::store_position(player_id, market_id, &position);
         Ok(())
     }
 }
 
+// Stored layouts: v1 = [balance], v2 = [balance, n, market ids...], v3+ = [tag | version, ...]
+// v4 appends the frozen flag, v5 the withdraw address, v6 the sub-balances, v7 the realized PnL,
+// v8 the lifetime spent and received totals, v9 the recent client tags
+// Untagged records predate versioning and are told apart by their length
+pub const PLAYER_DATA_VERSION: u64 = 9;
+const PLAYER_DATA_TAG: u64 = 0x5044 << 48; // "PD" in the top bits, far above any real balance
+const PLAYER_DATA_TAG_MASK: u64 = 0xffff << 48;
+
+impl PlayerData {
+    // Default the fields that did not exist in `from_version`, decoding leaves them zeroed
+    fn migrate(&mut self, from_version: u64) {
+        if from_version < 2 {
+            // Positions opened before tracking existed are not counted against the cap
+            self.active_markets.clear();
+        }
+    }
+}
+
 impl StorageData for PlayerData {
     fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
-        PlayerData {
-            balance: *u64data.next().unwrap(),
+        let first = *u64data.next().unwrap();
+        let (version, balance) = if first & PLAYER_DATA_TAG_MASK == PLAYER_DATA_TAG {
+            (first & !PLAYER_DATA_TAG_MASK, *u64data.next().unwrap())
+        } else if u64data.len() == 0 {
+            (1, first)
+        } else {
+            (2, first)
+        };
+
+        let mut active_markets = Vec::new();
+        if version >= 2 {
+            let market_count = *u64data.next().unwrap();
+            for _ in 0..market_count {
+                active_markets.push(*u64data.next().unwrap());
+            }
+        }
+        let frozen = version >= 4 && *u64data.next().unwrap() != 0;
+        let mut withdraw_address = [0; 3];
+        if version >= 5 {
+            for limb in withdraw_address.iter_mut() {
+                *limb = *u64data.next().unwrap();
+            }
+        }
+        let mut sub_balances = Vec::new();
+        if version >= 6 {
+            let sub_count = *u64data.next().unwrap();
+            for _ in 0..sub_count {
+                sub_balances.push((*u64data.next().unwrap(), *u64data.next().unwrap()));
+            }
+        }
+        let mut realized_pnl = 0;
+        if version >= 7 {
+            let negative = *u64data.next().unwrap() != 0;
+            let magnitude = *u64data.next().unwrap() as i64;
+            realized_pnl = if negative { -magnitude } else { magnitude };
         }
+        let (mut total_spent, mut total_received) = (0, 0);
+        if version >= 8 {
+            total_spent = *u64data.next().unwrap();
+            total_received = *u64data.next().unwrap();
+        }
+        let mut recent_tags = Vec::new();
+        if version >= 9 {
+            let tag_count = *u64data.next().unwrap();
+            for _ in 0..tag_count {
+                recent_tags.push((*u64data.next().unwrap(), *u64data.next().unwrap() as u32));
+            }
+        }
+        let mut player = PlayerData {
+            balance,
+            active_markets,
+            frozen,
+            withdraw_address,
+            sub_balances,
+            realized_pnl,
+            total_spent,
+            total_received,
+            recent_tags,
+        };
+        player.migrate(version);
+        player
     }
 
     fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(PLAYER_DATA_TAG | PLAYER_DATA_VERSION);
         data.push(self.balance);
+        data.push(self.active_markets.len() as u64);
+        data.extend(self.active_markets.iter().cloned());
+        data.push(if self.frozen { 1 } else { 0 });
+        data.extend_from_slice(&self.withdraw_address);
+        data.push(self.sub_balances.len() as u64);
+        for (sub, amount) in &self.sub_balances {
+            data.push(*sub);
+            data.push(*amount);
+        }
+        data.push((self.realized_pnl < 0) as u64);
+        data.push(self.realized_pnl.unsigned_abs());
+        data.push(self.total_spent);
+        data.push(self.total_received);
+        data.push(self.recent_tags.len() as u64);
+        for (tag, result) in &self.recent_tags {
+            data.push(*tag);
+            data.push(*result as u64);
+        }
+    }
+}
+
+pub type Player = zkwasm_rest_abi::Player<PlayerData>;
+
+// Nonce handling that reports a stale or replayed command instead of aborting the proof
+pub trait NonceCheck {
+    fn try_inc_nonce(&mut self, nonce: u64) -> Result<(), u32>;
+}
+
+impl NonceCheck for Player {
+    // A mismatch leaves the nonce where it was
+    fn try_inc_nonce(&mut self, nonce: u64) -> Result<(), u32> {
+        if self.nonce != nonce {
+            return Err(ERROR_INVALID_NONCE);
+        }
+        self.nonce += 1;
+        Ok(())
+    }
+}
+
+// Where a signer's recent client tags are remembered
+pub trait TagHistory {
+    fn cached_result(&self, tag: u64) -> Option<u32>;
+    fn record_tag(&mut self, tag: u64, result: u32);
+}
+
+impl TagHistory for Player {
+    fn cached_result(&self, tag: u64) -> Option<u32> {
+        self.data.cached_result(tag)
+    }
+
+    fn record_tag(&mut self, tag: u64, result: u32) {
+        self.data.record_tag(tag, result);
+    }
+}
+
+// A recently seen tag returns its recorded result without running the command again. Otherwise the
+// command runs, and its result is remembered only if the command was admitted: one rejected before
+// it ran (stale nonce, missing signer, pause) leaves the tag free, so a corrected resubmission still
+// executes. Tag 0 is untagged. Returns the result and whether it was replayed.
+pub fn run_tagged<H: TagHistory>(
+    history: &mut H,
+    tag: u64,
+    admit: impl FnOnce(&H) -> Result<(), u32>,
+    run: impl FnOnce(&mut H) -> u32,
+) -> (u32, bool) {
+    if tag != 0 {
+        if let Some(result) = history.cached_result(tag) {
+            return (result, true);
+        }
+    }
+    let admitted = admit(history);
+    let result = run(history);
+    if tag != 0 && admitted.is_ok() {
+        history.record_tag(tag, result);
+    }
+    (result, false)
+}
+
+// The signer of a command must exist and present its next nonce
+pub fn load_signer(player: Option<Player>, nonce: u64) -> Result<Player, u32> {
+    let mut player = player.ok_or(ERROR_PLAYER_NOT_EXIST)?;
+    player.try_inc_nonce(nonce)?;
+    Ok(player)
+}
+
+// A fresh player holding only `balance`: zero nonce, no positions, PnL or history
+pub fn install(existing: Option<Player>, player_id: [u64; 2], balance: u64) -> Result<Player, u32> {
+    if existing.is_some() {
+        return Err(ERROR_PLAYER_ALREADY_EXISTS);
     }
+    let mut player = Player::new_from_pid(player_id);
+    player.data.balance = balance;
+    Ok(player)
 }
 
-pub type Player = zkwasm_rest_abi::Player<PlayerData>; 
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(mut stored: Vec<u64>) -> PlayerData {
+        PlayerData::from_data(&mut stored.iter_mut())
+    }
+
+    #[test]
+    fn test_legacy_player_layouts_upgrade_on_load() {
+        // v1 only stored the balance
+        let v1 = load(vec![500]);
+        assert_eq!(v1.balance, 500);
+        assert!(v1.active_markets.is_empty());
+
+        // v2 added the active market list without a version tag
+        let v2 = load(vec![500, 2, 7, 9]);
+        assert_eq!(v2.balance, 500);
+        assert_eq!(v2.active_markets, vec![7, 9]);
+
+        // Re-storing writes the current tagged layout, which loads back unchanged
+        let mut stored = vec![];
+        v1.to_data(&mut stored);
+        assert_eq!(stored, vec![PLAYER_DATA_TAG | PLAYER_DATA_VERSION, 500, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let current = load(stored);
+        assert_eq!(current.balance, 500);
+        assert!(current.active_markets.is_empty());
+        assert!(!current.frozen);
+
+        // v3 was tagged but had no frozen flag
+        let v3 = load(vec![PLAYER_DATA_TAG | 3, 500, 1, 7]);
+        assert_eq!(v3.active_markets, vec![7]);
+        assert!(!v3.frozen);
+        assert!(!v3.has_withdraw_address());
+    }
+
+    #[test]
+    fn test_stale_nonce_and_missing_signer_are_errors() {
+        let mut player = Player::new_from_pid([1, 2]);
+        assert_eq!(player.try_inc_nonce(0), Ok(()));
+        // Replaying the same nonce is rejected and does not move it
+        assert_eq!(player.try_inc_nonce(0), Err(ERROR_INVALID_NONCE));
+        assert_eq!(player.nonce, 1);
+
+        assert_eq!(load_signer(Some(player), 1).map(|p| p.nonce), Ok(2));
+        assert_eq!(load_signer(None, 0).map(|p| p.nonce), Err(ERROR_PLAYER_NOT_EXIST));
+    }
+
+    #[test]
+    fn test_install_starts_clean_and_only_once() {
+        let player = install(None, [1, 2], 500).unwrap();
+        assert_eq!(player.nonce, 0);
+        let mut stored = vec![];
+        player.data.to_data(&mut stored);
+        let mut fresh = vec![];
+        PlayerData { balance: 500, ..PlayerData::default() }.to_data(&mut fresh);
+        assert_eq!(stored, fresh);
+
+        // A second install is refused rather than wiping the first
+        assert_eq!(install(Some(install(None, [1, 2], 500).unwrap()), [1, 2], 900).map(|p| p.data.balance), Err(ERROR_PLAYER_ALREADY_EXISTS));
+
+        // Deposits land on top of the starting balance
+        let deposited = crate::command::Deposit::credit(Some(player), [1, 2], 250).unwrap();
+        assert_eq!(deposited.data.balance, 750);
+        assert_eq!(deposited.nonce, 0);
+    }
+
+    #[test]
+    fn test_internal_transfer_between_sub_accounts() {
+        let mut player = PlayerData { balance: 1_000, ..PlayerData::default() };
+        player.internal_transfer(0, 7, 300).unwrap();
+        player.internal_transfer(0, 9, 200).unwrap();
+        player.internal_transfer(7, 9, 100).unwrap();
+        assert_eq!(player.sub_balance(0), 500);
+        assert_eq!(player.sub_balance(7), 200);
+        assert_eq!(player.sub_balance(9), 300);
+        assert_eq!(player.total_balance(), Ok(1_000));
+
+        // Overdrawing the source fails and changes nothing
+        assert_eq!(player.internal_transfer(7, 9, 201), Err(ERROR_INSUFFICIENT_BALANCE));
+        assert_eq!(player.sub_balance(7), 200);
+
+        // Emptied sub-accounts disappear and survive a storage round trip otherwise
+        player.internal_transfer(7, 0, 200).unwrap();
+        let mut stored = vec![];
+        player.to_data(&mut stored);
+        let loaded = load(stored);
+        assert_eq!(loaded.sub_balances, vec![(9, 300)]);
+        assert_eq!(loaded.balance, 700);
+    }
+
+    // A tagged activity signed with `nonce`, admitted as Transaction::process admits it, with a
+    // spend standing in for the command
+    fn submit(player: &mut Player, tag: u64, nonce: u64, amount: u64) -> u32 {
+        let state = crate::state::GlobalState::new();
+        let cmd = crate::command::Activity::Bet(1, 1, amount);
+        let pid = player.player_id;
+        run_tagged(player, tag, |player| cmd.admit(player, &state, &pid, nonce), |player| {
+            player.try_inc_nonce(nonce)
+                .and_then(|_| player.data.spend_balance(amount))
+                .map_or_else(|e| e, |_| 0)
+        }).0
+    }
+
+    // The same, signed with the player's next nonce
+    fn submit_next(player: &mut Player, tag: u64, amount: u64) -> u32 {
+        let nonce = player.nonce;
+        submit(player, tag, nonce, amount)
+    }
+
+    fn funded(balance: u64) -> Player {
+        let mut player = Player::new_from_pid([1, 2]);
+        player.data.balance = balance;
+        player
+    }
+
+    #[test]
+    fn test_resubmitted_tag_does_not_spend_twice() {
+        let mut player = funded(1_000);
+        assert_eq!(submit(&mut player, 42, 0, 300), 0);
+        assert_eq!(submit(&mut player, 42, 1, 300), 0);
+        assert_eq!(player.data.balance, 700);
+        assert_eq!(player.nonce, 1);
+
+        // Errors are replayed too, even once the command would now succeed
+        assert_eq!(submit(&mut player, 43, 1, 5_000), ERROR_INSUFFICIENT_BALANCE);
+        player.data.add_balance(5_000).unwrap();
+        assert_eq!(submit(&mut player, 43, 2, 5_000), ERROR_INSUFFICIENT_BALANCE);
+        assert_eq!(player.data.balance, 5_700);
+
+        // The history survives a storage round trip
+        let mut stored = vec![];
+        player.data.to_data(&mut stored);
+        player.data = load(stored);
+        assert_eq!(player.data.recent_tags, vec![(42, 0), (43, ERROR_INSUFFICIENT_BALANCE)]);
+        assert_eq!(submit(&mut player, 42, 2, 300), 0);
+        assert_eq!(player.data.balance, 5_700);
+
+        // Untagged commands are never replayed
+        assert_eq!(run_tagged(&mut player, 0, |_| Ok(()), |_| 0), (0, false));
+        assert_eq!(player.data.cached_result(0), None);
+    }
+
+    #[test]
+    fn test_stale_nonce_leaves_the_tag_free() {
+        let mut player = funded(1_000);
+        assert_eq!(submit(&mut player, 7, 5, 300), ERROR_INVALID_NONCE);
+        assert_eq!(player.data.cached_result(7), None);
+        // The corrected resubmission with the same tag still executes, once
+        assert_eq!(submit(&mut player, 7, 0, 300), 0);
+        assert_eq!(submit(&mut player, 7, 1, 300), 0);
+        assert_eq!(player.data.balance, 700);
+        assert_eq!(player.nonce, 1);
+    }
+
+    #[test]
+    fn test_evicted_tag_runs_again() {
+        let mut player = funded(1_000);
+        submit_next(&mut player, 1, 100);
+        for tag in 2..=CLIENT_TAG_HISTORY as u64 {
+            submit_next(&mut player, tag, 0);
+        }
+        // Still remembered with the history exactly full
+        assert_eq!(submit_next(&mut player, 1, 100), 0);
+        assert_eq!(player.data.balance, 900);
+
+        // One more tag pushes the oldest out, so it executes again
+        submit_next(&mut player, 100, 0);
+        assert_eq!(player.data.recent_tags.len(), CLIENT_TAG_HISTORY);
+        assert_eq!(player.data.cached_result(1), None);
+        assert_eq!(submit_next(&mut player, 1, 100), 0);
+        assert_eq!(player.data.balance, 800);
+    }
+
+    #[test]
+    fn test_bet_reveal_must_match_and_follow_its_commit() {
+        let pid = [1, 2];
+        let commitment = PlayerMarketPosition::bet_commitment(&pid, 3, 1, 500, 480, 42);
+        // Clients hash the same little-endian words, so the layout is pinned
+        assert_eq!(commitment, [0x7c4ed4b7a146fb24, 0x0cd020ee90620888, 0x572972cdac3f7fa7, 0x1c3dc61d9c33fde5]);
+
+        let mut position = PlayerMarketPosition::default();
+        assert_eq!(position.check_reveal(commitment, 1, 10), Err(ERROR_COMMITMENT_NOT_FOUND));
+        position.commit_bet(commitment, 100).unwrap();
+
+        // Same tick, past the window, a different bet, a looser bound or another player's pid all fail
+        assert_eq!(position.check_reveal(commitment, 100, 10), Err(ERROR_COMMITMENT_NOT_FOUND));
+        assert_eq!(position.check_reveal(commitment, 111, 10), Err(ERROR_COMMITMENT_NOT_FOUND));
+        let larger = PlayerMarketPosition::bet_commitment(&pid, 3, 1, 501, 480, 42);
+        assert_eq!(position.check_reveal(larger, 101, 10), Err(ERROR_COMMITMENT_MISMATCH));
+        let unbounded = PlayerMarketPosition::bet_commitment(&pid, 3, 1, 500, 0, 42);
+        assert_eq!(position.check_reveal(unbounded, 101, 10), Err(ERROR_COMMITMENT_MISMATCH));
+        let stolen = PlayerMarketPosition::bet_commitment(&[9, 9], 3, 1, 500, 480, 42);
+        assert_eq!(position.check_reveal(stolen, 101, 10), Err(ERROR_COMMITMENT_MISMATCH));
+        assert_eq!(position.check_reveal(commitment, 110, 10), Ok(()));
+
+        let mut data = vec![];
+        position.to_data(&mut data);
+        let mut loaded = PlayerMarketPosition::from_data(&mut data.iter_mut());
+        assert_eq!(loaded, position);
+        loaded.clear_commitment();
+        assert_eq!(loaded.check_reveal(commitment, 101, 10), Err(ERROR_COMMITMENT_NOT_FOUND));
+    }
+}
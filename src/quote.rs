@@ -0,0 +1,164 @@
+use serde::Serialize;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::config::{QUOTE_VALIDITY_TICKS, QUOTE_DRIFT_TOLERANCE_BPS, BASIS_POINTS_PRECISION};
+use crate::error::*;
+use crate::fixed::saturating_mul_div;
+use crate::market::MarketData;
+use crate::math_safe::safe_add;
+
+// A bet priced against the market at quote time, executable by its owner until it expires
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Quote {
+    pub player_id: [u64; 2],
+    pub market_id: u64,
+    pub bet_type: u64,
+    pub amount: u64,
+    pub shares: u64,         // Shares the bet bought when quoted
+    pub yes_price: u64,      // Marginal prices at quote time, from whichever engine prices the market
+    pub no_price: u64,
+    pub expires_at: u64,     // Last counter the quote can be executed at
+}
+
+impl Quote {
+    pub fn new(player_id: [u64; 2], market_id: u64, market: &MarketData, bet_type: u64, amount: u64, counter: u64) -> Result<Self, u32> {
+        let shares = market.calculate_shares(bet_type, amount)?;
+        if shares == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        let (yes_price, no_price) = market.current_prices()?;
+        Ok(Quote {
+            player_id,
+            market_id,
+            bet_type,
+            amount,
+            shares,
+            yes_price,
+            no_price,
+            expires_at: safe_add(counter, QUOTE_VALIDITY_TICKS)?,
+        })
+    }
+
+    // The quote still holds if it is unexpired and neither price moved beyond the tolerance
+    pub fn validate(&self, player_id: &[u64; 2], market: &MarketData, counter: u64) -> Result<(), u32> {
+        if self.player_id != *player_id {
+            return Err(ERROR_UNAUTHORIZED);
+        }
+        if counter > self.expires_at {
+            return Err(ERROR_QUOTE_EXPIRED);
+        }
+        let within_tolerance = |quoted: u64, current: u64| {
+            quoted.abs_diff(current) <= saturating_mul_div(quoted, QUOTE_DRIFT_TOLERANCE_BPS, BASIS_POINTS_PRECISION)
+        };
+        let (yes_price, no_price) = market.current_prices()?;
+        if !within_tolerance(self.yes_price, yes_price) || !within_tolerance(self.no_price, no_price) {
+            return Err(ERROR_QUOTE_EXPIRED);
+        }
+        Ok(())
+    }
+
+    // The fewest shares an execution may buy: the quoted shares less the same drift tolerance
+    pub fn min_shares(&self) -> u64 {
+        self.shares - saturating_mul_div(self.shares, QUOTE_DRIFT_TOLERANCE_BPS, BASIS_POINTS_PRECISION)
+    }
+}
+
+impl StorageData for Quote {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        Quote {
+            player_id: [*u64data.next().unwrap(), *u64data.next().unwrap()],
+            market_id: *u64data.next().unwrap(),
+            bet_type: *u64data.next().unwrap(),
+            amount: *u64data.next().unwrap(),
+            shares: *u64data.next().unwrap(),
+            yes_price: *u64data.next().unwrap(),
+            no_price: *u64data.next().unwrap(),
+            expires_at: *u64data.next().unwrap(),
+        }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.extend_from_slice(&self.player_id);
+        data.push(self.market_id);
+        data.push(self.bet_type);
+        data.push(self.amount);
+        data.push(self.shares);
+        data.push(self.yes_price);
+        data.push(self.no_price);
+        data.push(self.expires_at);
+    }
+}
+
+pub struct QuoteManager;
+
+impl QuoteManager {
+    const QUOTE_PREFIX: [u64; 2] = [3, 0]; // Prefix for pending quote storage
+
+    pub fn store_quote(quote_id: u64, quote: &Quote) {
+        let mut data = vec![];
+        quote.to_data(&mut data);
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let key = [Self::QUOTE_PREFIX[0], Self::QUOTE_PREFIX[1], quote_id, 0];
+        kvpair.set(&key, data.as_slice());
+    }
+
+    pub fn get_quote(quote_id: u64) -> Option<Quote> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let key = [Self::QUOTE_PREFIX[0], Self::QUOTE_PREFIX[1], quote_id, 0];
+        let mut data = kvpair.get(&key);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            Some(Quote::from_data(&mut u64data))
+        } else {
+            None
+        }
+    }
+
+    // Quotes are single use
+    pub fn remove_quote(quote_id: u64) {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let key = [Self::QUOTE_PREFIX[0], Self::QUOTE_PREFIX[1], quote_id, 0];
+        kvpair.set(&key, &[]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_executes_only_while_valid() {
+        let title = MarketData::string_to_u64_vec("Quote");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Quote".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        let quote = Quote::new([1, 2], 1, &market, 1, 10_000, 100).unwrap();
+        assert_eq!(quote.shares, market.calculate_shares(1, 10_000).unwrap());
+
+        // Within validity the quote holds, for its owner only
+        assert_eq!(quote.validate(&[1, 2], &market, 100 + QUOTE_VALIDITY_TICKS), Ok(()));
+        assert_eq!(quote.validate(&[3, 4], &market, 100), Err(ERROR_UNAUTHORIZED));
+
+        // Past the expiry counter it is rejected
+        assert_eq!(quote.validate(&[1, 2], &market, 101 + QUOTE_VALIDITY_TICKS), Err(ERROR_QUOTE_EXPIRED));
+
+        // A trade that moves the price past the tolerance also invalidates it
+        market.place_bet(0, 100_000).unwrap();
+        assert_eq!(quote.validate(&[1, 2], &market, 100), Err(ERROR_QUOTE_EXPIRED));
+    }
+
+    #[test]
+    fn test_lmsr_quote_is_invalidated_by_price_moves() {
+        let title = MarketData::string_to_u64_vec("Lmsr Quote");
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
+            title, "Lmsr Quote".to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        market.apply_option(crate::market::MARKET_OPTION_LMSR_B, 200_000).unwrap();
+        let quote = Quote::new([1, 2], 1, &market, 1, 10_000, 100).unwrap();
+        assert_eq!(quote.validate(&[1, 2], &market, 100), Ok(()));
+
+        // LMSR reserves never move, so only the prices can show the trade
+        market.place_bet(0, 100_000).unwrap();
+        assert_eq!((market.yes_liquidity, market.no_liquidity), (1_000_000, 1_000_000));
+        assert_eq!(quote.validate(&[1, 2], &market, 100), Err(ERROR_QUOTE_EXPIRED));
+    }
+}
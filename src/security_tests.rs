@@ -267,9 +267,9 @@ mod security_tests {
         // 验证常量乘积保持（使用新的流动性计算新的k）
         let new_k = calculate_k_safe(new_yes_liquidity, new_no_liquidity).unwrap();
         
-        // 由于费用的存在，新的k会略小于原始k，这是正常的
-        assert!(new_k <= k);
-        assert!(new_k > 0);
+        // 流出一侧向上取整，新的k不小于原始k，且最多多出一个单位的储备
+        assert!(new_k >= k);
+        assert!(new_k - k < new_no_liquidity as u128);
         
         // 验证份额计算
         let shares = safe_sub(initial_yes, new_yes_liquidity).unwrap();
@@ -1,24 +1,110 @@
+use std::cell::RefCell;
 use zkwasm_rest_abi::WithdrawInfo;
+use crate::config::{MAX_SETTLEMENTS_PER_BLOCK, SETTLEMENT_FORMAT_VERSION};
+use crate::error::ERROR_SETTLEMENT_FULL;
+
+#[derive(Default)]
 pub struct SettlementInfo(Vec<WithdrawInfo>);
 
-pub static mut SETTLEMENT: SettlementInfo = SettlementInfo(vec![]);
+// Single-threaded like GLOBAL_STATE, so a RefCell is all the guarding it needs
+pub struct SafeSettlement(pub RefCell<SettlementInfo>);
+unsafe impl Sync for SafeSettlement {}
+
+// Version byte plus little-endian u32 record count, ahead of the 32-byte records
+pub const SETTLEMENT_HEADER_SIZE: usize = 5;
+pub const SETTLEMENT_RECORD_SIZE: usize = 32;
+
+lazy_static::lazy_static! {
+    pub static ref SETTLEMENT: SafeSettlement = SafeSettlement(RefCell::new(SettlementInfo::default()));
+}
 
 impl SettlementInfo {
-    pub fn append_settlement(info: WithdrawInfo) {
-        unsafe { SETTLEMENT.0.push(info) };
+    // Refuses once the block already holds MAX_SETTLEMENTS_PER_BLOCK withdrawals
+    pub fn push(&mut self, info: WithdrawInfo) -> Result<(), u32> {
+        if self.0.len() >= MAX_SETTLEMENTS_PER_BLOCK {
+            return Err(ERROR_SETTLEMENT_FULL);
+        }
+        self.0.push(info);
+        Ok(())
+    }
+    // Drain the queued withdrawals as settlement bytes; an empty queue flushes to nothing
+    pub fn flush(&mut self) -> Vec<u8> {
+        if self.0.is_empty() {
+            return vec![];
+        }
+        let mut bytes: Vec<u8> = Vec::with_capacity(SETTLEMENT_HEADER_SIZE + self.0.len() * SETTLEMENT_RECORD_SIZE);
+        bytes.push(SETTLEMENT_FORMAT_VERSION);
+        bytes.extend_from_slice(&(self.0.len() as u32).to_le_bytes());
+        for s in &self.0 {
+            s.flush(&mut bytes);
+        }
+        self.0 = vec![];
+        bytes
+    }
+    pub fn append_settlement(info: WithdrawInfo) -> Result<(), u32> {
+        SETTLEMENT.0.borrow_mut().push(info)
     }
     pub fn settlement_size() -> usize {
-        let sinfo = unsafe { &mut SETTLEMENT };
-        return sinfo.0.len()
+        SETTLEMENT.0.borrow().0.len()
+    }
+    pub fn settlement_capacity() -> usize {
+        MAX_SETTLEMENTS_PER_BLOCK
     }
     pub fn flush_settlement() -> Vec<u8> {
         zkwasm_rust_sdk::dbg!("flush settlement\n");
-        let sinfo = unsafe { &mut SETTLEMENT };
-        let mut bytes: Vec<u8> = Vec::with_capacity(sinfo.0.len() * 32);
-        for s in &sinfo.0 {
-            s.flush(&mut bytes);
+        SETTLEMENT.0.borrow_mut().flush()
+    }
+}
+
+// Split a flushed buffer into its version and records, None if the count does not match the length
+pub fn decode_settlement(bytes: &[u8]) -> Option<(u8, Vec<&[u8]>)> {
+    if bytes.len() < SETTLEMENT_HEADER_SIZE {
+        return None;
+    }
+    let count = u32::from_le_bytes(bytes[1..SETTLEMENT_HEADER_SIZE].try_into().unwrap()) as usize;
+    let records = &bytes[SETTLEMENT_HEADER_SIZE..];
+    if records.len() != count.checked_mul(SETTLEMENT_RECORD_SIZE)? {
+        return None;
+    }
+    Some((bytes[0], records.chunks(SETTLEMENT_RECORD_SIZE).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settlement_rejects_withdrawals_past_capacity() {
+        let mut settlement = SettlementInfo::default();
+        for i in 0..MAX_SETTLEMENTS_PER_BLOCK as u64 {
+            settlement.push(WithdrawInfo::new(&[i, 0, 0], 0)).unwrap();
         }
-        sinfo.0 = vec![];
-        bytes
+        assert_eq!(settlement.push(WithdrawInfo::new(&[1, 0, 0], 0)), Err(ERROR_SETTLEMENT_FULL));
+        assert_eq!(settlement.0.len(), SettlementInfo::settlement_capacity());
+    }
+
+    #[test]
+    fn test_flush_writes_versioned_header_before_records() {
+        let mut settlement = SettlementInfo::default();
+        let infos = [[7u64 << 32 | 100, 1, 2], [9u64 << 32 | 250, 3, 4]];
+        let mut expected = vec![];
+        for limbs in &infos {
+            WithdrawInfo::new(limbs, 0).flush(&mut expected);
+            settlement.push(WithdrawInfo::new(limbs, 0)).unwrap();
+        }
+        let bytes = settlement.flush();
+        assert_eq!(bytes.len(), SETTLEMENT_HEADER_SIZE + 2 * SETTLEMENT_RECORD_SIZE);
+        assert_eq!(&bytes[SETTLEMENT_HEADER_SIZE..], expected.as_slice());
+        assert!(settlement.flush().is_empty());
+
+        // The header round-trips to the version and the original records
+        let (version, records) = decode_settlement(&bytes).unwrap();
+        assert_eq!(version, SETTLEMENT_FORMAT_VERSION);
+        assert_eq!(records, vec![&expected[..32], &expected[32..]]);
+
+        // A count that disagrees with the payload is rejected
+        let mut truncated = bytes.clone();
+        truncated.truncate(bytes.len() - 1);
+        assert_eq!(decode_settlement(&truncated), None);
     }
-} 
\ No newline at end of file
+}
@@ -1,9 +1,13 @@
 use serde::Serialize;
 use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
 use std::cell::RefCell;
-use crate::market::MarketData;
-use crate::error::ERROR_MARKET_NOT_ACTIVE;
-use crate::event::{emit_market_indexed_object, emit_liquidity_history};
+use crate::market::{MarketData, PositionSummary};
+use crate::error::{ERROR_MARKET_NOT_ACTIVE, ERROR_MARKET_PAUSED, ERROR_PLAYER_NOT_EXIST, ERROR_UNAUTHORIZED};
+use crate::math_safe::{safe_add, safe_sub};
+use crate::player::{Player, PlayerMarketManager, TagHistory};
+use crate::command::Activity;
+use crate::config::{ADMIN_PUBKEY, DEFAULT_OPERATION_FEE, DEFAULT_OPERATION_FEE_WAIVERS, DEFAULT_WITHDRAW_FEE_BPS, PNL_LEADERBOARD_SIZE};
+use crate::event::{insert_event, EVENT_TICK_SUMMARY, emit_market_created, emit_market_indexed_object, emit_resolve_event, emit_liquidity_history, emit_market_state_change};
 
 
 #[derive(Serialize)]
@@ -21,6 +25,20 @@ pub struct GlobalState {
     pub total_players: u64,
     pub txsize: u64,
     pub txcounter: u64,
+    pub dropped_events: u64,   // Lifetime count of events lost to a full event buffer
+    pub total_deposited: u64,  // Lifetime bridge deposits credited to players
+    pub total_withdrawn: u64,  // Lifetime withdrawals handed to settlement
+    pub next_quote_id: u64,    // Auto-incrementing quote ID
+    pub operation_fee: u64,    // Flat fee per state-changing activity
+    pub operation_fee_waivers: u64, // OPERATION_FEE_WAIVE_* categories exempt from the fee
+    pub protocol_fees: u64,    // Operation and withdrawal fees collected
+    pub oracles: Vec<[u64; 2]>, // Players allowed to submit oracle values
+    pub pnl_leaderboard: Vec<([u64; 2], i64)>, // Top players by realized PnL, best first, ties by pid
+    pub admin_id: [u64; 2],    // Pid of ADMIN_PUBKEY, derived at init rather than stored
+    pub trading_paused: bool,  // Emergency stop for trading, liquidity and claims
+    pub transfers_paused: bool, // Emergency stop for deposits and withdrawals
+    pub withdraw_fee_bps: u64, // Share of each withdrawal kept as a protocol fee
+    pub treasury: u64,         // Unclaimed winnings swept from markets past their claim period
 }
 
 impl GlobalState {
@@ -32,6 +50,20 @@ impl GlobalState {
             total_players: 0,
             txsize: 0,
             txcounter: 0,
+            dropped_events: 0,
+            total_deposited: 0,
+            total_withdrawn: 0,
+            next_quote_id: 1,
+            operation_fee: DEFAULT_OPERATION_FEE,
+            operation_fee_waivers: DEFAULT_OPERATION_FEE_WAIVERS,
+            protocol_fees: 0,
+            oracles: vec![],
+            pnl_leaderboard: vec![],
+            admin_id: Player::pkey_to_pid(&ADMIN_PUBKEY),
+            trading_paused: false,
+            transfers_paused: false,
+            withdraw_fee_bps: DEFAULT_WITHDRAW_FEE_BPS,
+            treasury: 0,
         }
     }
 
@@ -46,14 +78,26 @@ impl GlobalState {
         serde_json::to_string(&player).unwrap()
     }
 
+    pub fn ensure_trading_open(&self) -> Result<(), u32> {
+        if self.trading_paused {
+            return Err(ERROR_MARKET_PAUSED);
+        }
+        Ok(())
+    }
+
+    pub fn ensure_transfers_open(&self) -> Result<(), u32> {
+        if self.transfers_paused {
+            return Err(ERROR_MARKET_PAUSED);
+        }
+        Ok(())
+    }
+
     pub fn ensure_market_active(&self, market_id: u64) -> Result <u64, u32> {
+        self.ensure_trading_open()?;
         let current_time = self.counter;
         if let Some(market) = MarketManager::get_market(market_id) {
-            if !market.is_active(current_time) {
-                return Err(ERROR_MARKET_NOT_ACTIVE);
-            } else {
-                Ok(current_time)
-            }
+            market.ensure_active(current_time)?;
+            Ok(current_time)
         } else {
             Err(ERROR_MARKET_NOT_ACTIVE)
         }
@@ -99,6 +143,133 @@ impl GlobalState {
     pub fn get_counter() -> u64 {
         GLOBAL_STATE.0.borrow().counter
     }
+
+    pub fn has_claimed(pid: &[u64; 2], market_id: u64) -> bool {
+        PlayerMarketManager::get_position(pid, market_id).claimed
+    }
+
+    pub fn remaining_claimable(pid: &[u64; 2], market_id: u64) -> Result<u64, u32> {
+        let market = MarketManager::get_market(market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        market.remaining_claimable(&PlayerMarketManager::get_position(pid, market_id))
+    }
+
+    pub fn player_market_position(pid: &[u64; 2], market_id: u64) -> Result<PositionSummary, u32> {
+        let market = MarketManager::get_market(market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        market.position_summary(&PlayerMarketManager::get_position(pid, market_id))
+    }
+
+    pub fn record_deposit(&mut self, amount: u64) -> Result<(), u32> {
+        self.total_deposited = safe_add(self.total_deposited, amount)?;
+        Ok(())
+    }
+
+    pub fn record_withdrawal(&mut self, amount: u64) -> Result<(), u32> {
+        self.total_withdrawn = safe_add(self.total_withdrawn, amount)?;
+        Ok(())
+    }
+
+    pub fn credit_treasury(&mut self, amount: u64) -> Result<(), u32> {
+        self.treasury = safe_add(self.treasury, amount)?;
+        Ok(())
+    }
+
+    // Operation fee owed for an activity under the current waivers
+    pub fn operation_fee_for(&self, activity: &Activity) -> u64 {
+        if self.operation_fee_waivers & activity.operation_fee_category() != 0 {
+            0
+        } else {
+            self.operation_fee
+        }
+    }
+
+    pub fn require_admin(&self, pid: &[u64; 2]) -> Result<(), u32> {
+        if *pid != self.admin_id {
+            return Err(ERROR_UNAUTHORIZED);
+        }
+        Ok(())
+    }
+
+    pub fn is_oracle(&self, pid: &[u64; 2]) -> bool {
+        self.oracles.contains(pid)
+    }
+
+    pub fn set_oracle(&mut self, pid: [u64; 2], enabled: bool) {
+        self.oracles.retain(|oracle| *oracle != pid);
+        if enabled {
+            self.oracles.push(pid);
+        }
+    }
+
+    // Re-rank a player whose realized PnL just changed. Only players who realize PnL after
+    // being pushed out can climb back in, so the board never needs a scan of all players.
+    pub fn update_pnl_leaderboard(&mut self, pid: [u64; 2], realized_pnl: i64) {
+        self.pnl_leaderboard.retain(|(entry, _)| *entry != pid);
+        self.pnl_leaderboard.push((pid, realized_pnl));
+        self.pnl_leaderboard.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        self.pnl_leaderboard.truncate(PNL_LEADERBOARD_SIZE);
+    }
+
+    pub fn record_protocol_fee(&mut self, fee: u64) -> Result<(), u32> {
+        self.protocol_fees = safe_add(self.protocol_fees, fee)?;
+        Ok(())
+    }
+
+    // Value the bridge should still be holding for this rollup
+    pub fn bridge_held_value(&self) -> Result<u64, u32> {
+        safe_sub(self.total_deposited, self.total_withdrawn)
+    }
+
+    // Protocol-wide audit totals over `markets`, normally every id in market_ids
+    pub fn summary(&self, markets: &[MarketData]) -> Result<AccountingSummary, u32> {
+        let mut summary = AccountingSummary {
+            txcounter: self.txcounter,
+            market_count: markets.len() as u64,
+            protocol_fees: self.protocol_fees,
+            treasury: self.treasury,
+            ..AccountingSummary::default()
+        };
+        for market in markets {
+            let ledger = market.ledger()?;
+            summary.yes_reserves = safe_add(summary.yes_reserves, ledger.yes_liquidity)?;
+            summary.no_reserves = safe_add(summary.no_reserves, ledger.no_liquidity)?;
+            summary.platform_fees = safe_add(summary.platform_fees, ledger.platform_fees)?;
+            summary.yes_shares = safe_add(summary.yes_shares, ledger.total_yes_shares)?;
+            summary.no_shares = safe_add(summary.no_shares, ledger.total_no_shares)?;
+            summary.claimed_shares = safe_add(summary.claimed_shares, market.claimed_winning_shares)?;
+            summary.unclaimed_pools = safe_add(summary.unclaimed_pools, safe_sub(ledger.prize_pool, ledger.total_claimed)?)?;
+            summary.held = safe_add(summary.held, ledger.held)?;
+        }
+        Ok(summary)
+    }
+}
+
+// Aggregate of every market's ledger for off-chain solvency monitoring. Share totals are
+// what bets and sets minted less what sells and burns returned; claimed winning shares are
+// reported separately since claims leave the totals in place to keep pro-rata payouts fixed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccountingSummary {
+    pub txcounter: u64,
+    pub market_count: u64,
+    pub yes_reserves: u64,
+    pub no_reserves: u64,
+    pub platform_fees: u64,   // Market trading fees not yet withdrawn
+    pub protocol_fees: u64,   // Operation and withdrawal fees
+    pub yes_shares: u64,
+    pub no_shares: u64,
+    pub claimed_shares: u64,
+    pub unclaimed_pools: u64,
+    pub held: u64,            // Everything the markets still owe out of the funds they took in
+    pub treasury: u64,        // Unclaimed winnings swept out of the markets
+}
+
+impl AccountingSummary {
+    pub fn to_data(&self, data: &mut Vec<u64>) {
+        data.extend_from_slice(&[
+            self.txcounter, self.market_count, self.yes_reserves, self.no_reserves,
+            self.platform_fees, self.protocol_fees, self.yes_shares, self.no_shares,
+            self.claimed_shares, self.unclaimed_pools, self.held, self.treasury,
+        ]);
+    }
 }
 
 impl StorageData for GlobalState {
@@ -117,6 +288,31 @@ impl StorageData for GlobalState {
             }
         }
         
+        let dropped_events = *u64data.next().unwrap();
+        let total_deposited = *u64data.next().unwrap();
+        let total_withdrawn = *u64data.next().unwrap();
+        let next_quote_id = *u64data.next().unwrap();
+        let operation_fee = *u64data.next().unwrap();
+        let operation_fee_waivers = *u64data.next().unwrap();
+        let protocol_fees = *u64data.next().unwrap();
+        let oracle_count = *u64data.next().unwrap();
+        let mut oracles = Vec::new();
+        for _ in 0..oracle_count {
+            oracles.push([*u64data.next().unwrap(), *u64data.next().unwrap()]);
+        }
+        let leaderboard_len = *u64data.next().unwrap();
+        let mut pnl_leaderboard = Vec::new();
+        for _ in 0..leaderboard_len {
+            let pid = [*u64data.next().unwrap(), *u64data.next().unwrap()];
+            let negative = *u64data.next().unwrap() != 0;
+            let magnitude = *u64data.next().unwrap() as i64;
+            pnl_leaderboard.push((pid, if negative { -magnitude } else { magnitude }));
+        }
+        let trading_paused = *u64data.next().unwrap() != 0;
+        let transfers_paused = *u64data.next().unwrap() != 0;
+        let withdraw_fee_bps = *u64data.next().unwrap();
+        let treasury = *u64data.next().unwrap();
+        
         GlobalState {
             counter,
             total_players,
@@ -124,6 +320,20 @@ impl StorageData for GlobalState {
             txcounter,
             next_market_id,
             market_ids,
+            dropped_events,
+            total_deposited,
+            total_withdrawn,
+            next_quote_id,
+            operation_fee,
+            operation_fee_waivers,
+            protocol_fees,
+            oracles,
+            pnl_leaderboard,
+            admin_id: Player::pkey_to_pid(&ADMIN_PUBKEY),
+            trading_paused,
+            transfers_paused,
+            withdraw_fee_bps,
+            treasury,
         }
     }
 
@@ -135,6 +345,27 @@ impl StorageData for GlobalState {
         data.push(self.next_market_id);
         data.push(self.market_ids.len() as u64);
         data.extend(self.market_ids.iter().cloned());
+        data.push(self.dropped_events);
+        data.push(self.total_deposited);
+        data.push(self.total_withdrawn);
+        data.push(self.next_quote_id);
+        data.push(self.operation_fee);
+        data.push(self.operation_fee_waivers);
+        data.push(self.protocol_fees);
+        data.push(self.oracles.len() as u64);
+        for oracle in &self.oracles {
+            data.extend_from_slice(oracle);
+        }
+        data.push(self.pnl_leaderboard.len() as u64);
+        for (pid, pnl) in &self.pnl_leaderboard {
+            data.extend_from_slice(pid);
+            data.push((*pnl < 0) as u64);
+            data.push(pnl.unsigned_abs());
+        }
+        data.push(self.trading_paused as u64);
+        data.push(self.transfers_paused as u64);
+        data.push(self.withdraw_fee_bps);
+        data.push(self.treasury);
     }
 }
 
@@ -156,10 +387,96 @@ const RESOLVE: u64 = 6;
 const CLAIM: u64 = 7;
 const WITHDRAW_FEES: u64 = 8;
 const CREATE_MARKET: u64 = 9;
+const QUERY_PLAYER: u64 = 10;
+const QUERY_MARKET: u64 = 11;
+const QUERY_POSITION: u64 = 12;
+const HEDGE_TO_NEUTRAL: u64 = 13;
+const QUERY_BET_FEE: u64 = 14;
+const QUERY_SELL_FEE: u64 = 15;
+const RESOLVE_DISTRIBUTION: u64 = 16;
+const QUERY_DROPPED_EVENTS: u64 = 17;
+const QUERY_TOP_HOLDERS: u64 = 18;
+const PROPOSE_RESOLUTION: u64 = 19;
+const FINALIZE_RESOLUTION: u64 = 20;
+const QUERY_BRIDGE_TOTALS: u64 = 21;
+const REQUEST_QUOTE: u64 = 22;
+const EXECUTE_QUOTE: u64 = 23;
+const QUERY_PAYOUT_MULTIPLE: u64 = 24;
+const ADD_LIQUIDITY: u64 = 25;
+const REMOVE_LIQUIDITY: u64 = 26;
+const CLAIM_LP_FEES: u64 = 27;
+const QUERY_LIQUIDITY_POSITION: u64 = 28;
+const QUERY_CLAIM_STATUS: u64 = 29;
+const RESOLVE_BATCH: u64 = 30;
+const QUERY_MARGINAL_IMPACT: u64 = 31;
+const SET_PLAYER_FROZEN: u64 = 32;
+const QUERY_LEDGER: u64 = 33;
+const SET_OPERATION_FEE: u64 = 34;
+const QUERY_SIGNING_CONTEXT: u64 = 35;
+const MINT_SET: u64 = 36;
+const ADD_LIQUIDITY_FROM_SETS: u64 = 37;
+const QUERY_MAX_BET_FOR_IMPACT: u64 = 38;
+const ARB_EXECUTE: u64 = 39;
+const SET_WITHDRAW_ADDRESS: u64 = 40;
+const QUERY_POSITION_SUMMARY: u64 = 41;
+const VOID_MARKET: u64 = 42;
+const INTERNAL_TRANSFER: u64 = 43;
+const SET_ORACLE: u64 = 44;
+const SUBMIT_ORACLE_VALUE: u64 = 45;
+const BET_WITH_SLIPPAGE: u64 = 46;
+const SELL_WITH_SLIPPAGE: u64 = 47;
+const QUERY_QUOTE_BET: u64 = 48;
+const QUERY_QUOTE_SELL: u64 = 49;
+const CLAIM_AND_WITHDRAW: u64 = 50;
+const SUBMIT_ORACLE_OUTCOME: u64 = 51;
+const QUERY_PLAYER_VIEW: u64 = 52;
+const SET_PAUSED: u64 = 53;
+const QUERY_PNL: u64 = 54;
+const CREATE_CATEGORICAL_MARKET: u64 = 55;
+const BET_OUTCOME: u64 = 56;
+const SELL_OUTCOME: u64 = 57;
+const RESOLVE_CATEGORICAL: u64 = 58;
+const QUERY_CATEGORICAL_MARKET: u64 = 59;
+const SELL_FOR_PAYOUT: u64 = 60;
+const SET_WITHDRAW_FEE: u64 = 61;
+const QUERY_PRICES: u64 = 62;
+const QUERY_SUMMARY: u64 = 63;
+const OVERRIDE_RESOLUTION: u64 = 64;
+const TAGGED: u64 = 65;
+const CLOSE_MARKET: u64 = 66;
+const PLACE_LIMIT_ORDER: u64 = 67;
+const CANCEL_ORDER: u64 = 68;
+const QUERY_ORDER_BOOK: u64 = 69;
+const COMMIT_BET: u64 = 70;
+const REVEAL_BET: u64 = 71;
+const DISPUTE: u64 = 72;
+const RECLAIM_DISPUTE_BOND: u64 = 73;
+const SWEEP_UNCLAIMED: u64 = 74;
+const QUERY_OUTCOME_PAYOUTS: u64 = 75;
+const BATCH_ACTIVITY: u64 = 76;
+const CLAIM_CATEGORICAL: u64 = 77;
 
 pub struct Transaction {
     command: crate::command::Command,
     nonce: u64,
+    client_tag: u64, // Idempotency key chosen by the client, 0 = untagged
+}
+
+// The signer's tag history as kept in storage
+struct StoredTags([u64; 2]);
+
+impl TagHistory for StoredTags {
+    fn cached_result(&self, tag: u64) -> Option<u32> {
+        Player::get_from_pid(&self.0).and_then(|player| player.data.cached_result(tag))
+    }
+
+    fn record_tag(&mut self, tag: u64, result: u32) {
+        // A signer that does not exist has nowhere to remember the tag
+        if let Some(mut player) = Player::get_from_pid(&self.0) {
+            player.data.record_tag(tag, result);
+            player.store();
+        }
+    }
 }
 
 impl Transaction {
@@ -168,12 +485,41 @@ impl Transaction {
     }
 
     pub fn decode(params: &[u64]) -> Self {
-        use crate::command::{Command, Activity, Withdraw, Deposit};
+        use crate::command::{Command, Activity, Query, Withdraw, Deposit};
         use zkwasm_rest_abi::enforce;
         
         let command = params[0] & 0xff;
         let nonce = params[0] >> 16;
+
+        if command == TAGGED {
+            // [tag, inner command, inner params...]: the inner activity runs once per recent tag
+            enforce(params.len() >= 3 && params[1] != 0, "tagged needs a nonzero tag and an inner command");
+            let mut inner = vec![(params[0] & !0xff) | (params[2] & 0xff)];
+            inner.extend_from_slice(&params[3..]);
+            let inner = Self::decode(&inner);
+            enforce(matches!(inner.command, Command::Activity(_)) && inner.client_tag == 0, "tagged wraps a single activity");
+            return Transaction { client_tag: params[1], ..inner };
+        }
         
+        if command == BATCH_ACTIVITY {
+            // [(length, command, params...) * n]: each activity encoded as on its own, any nonce bits ignored
+            let mut activities = vec![];
+            let mut rest = &params[1..];
+            while !rest.is_empty() {
+                let length = rest[0] as usize;
+                enforce(length >= 1 && rest.len() > length, "batch_activity entry length mismatch");
+                let mut inner = vec![rest[1] & 0xff];
+                inner.extend_from_slice(&rest[2..=length]);
+                match Self::decode(&inner) {
+                    Transaction { command: Command::Activity(activity), client_tag: 0, .. } => activities.push(activity),
+                    _ => enforce(false, "batch_activity entries must be single activities"),
+                }
+                rest = &rest[length + 1..];
+            }
+            enforce(!activities.is_empty() && activities.len() <= crate::config::MAX_BATCH_ACTIVITIES, "batch_activity needs between 1 and MAX_BATCH_ACTIVITIES activities");
+            return Transaction { command: Command::BatchActivity(activities), nonce, client_tag: 0 };
+        }
+
         let command = if command == WITHDRAW {
             enforce(params.len() == 5, "withdraw needs 5 params");
             Command::Withdraw(Withdraw {
@@ -191,53 +537,269 @@ impl Transaction {
         } else if command == SELL {
             enforce(params.len() == 4, "sell needs 4 params");
             Command::Activity(Activity::Sell(params[1], params[2], params[3]))
+        } else if command == BET_WITH_SLIPPAGE {
+            enforce(params.len() == 5, "bet_with_slippage needs 5 params");
+            Command::Activity(Activity::BetWithSlippage(params[1], params[2], params[3], params[4]))
+        } else if command == SELL_WITH_SLIPPAGE {
+            enforce(params.len() == 5, "sell_with_slippage needs 5 params");
+            Command::Activity(Activity::SellWithSlippage(params[1], params[2], params[3], params[4]))
         } else if command == RESOLVE {
             enforce(params.len() == 3, "resolve needs 3 params");
             Command::Activity(Activity::Resolve(params[1], params[2]))
         } else if command == CLAIM {
             enforce(params.len() == 2, "claim needs 2 params");
             Command::Activity(Activity::Claim(params[1]))
+        } else if command == CLAIM_AND_WITHDRAW {
+            enforce(params.len() == 5, "claim_and_withdraw needs 5 params");
+            Command::Activity(Activity::ClaimAndWithdraw(params[1], [params[2], params[3], params[4]]))
         } else if command == WITHDRAW_FEES {
-            enforce(params.len() == 2, "withdraw_fees needs 2 params");
-            Command::Activity(Activity::WithdrawFees(params[1]))
+            // [market_id] pays the market's fee recipient, [market_id, pid0, pid1] a chosen account
+            enforce(params.len() == 2 || params.len() == 4, "withdraw_fees needs 2 or 4 params");
+            let to = if params.len() == 4 { [params[2], params[3]] } else { [0, 0] };
+            Command::Activity(Activity::WithdrawFees(params[1], to))
         } else if command == CREATE_MARKET {
             enforce(params.len() >= 7, "create_market needs at least 7 params");
             let title_len = params[1] as usize;
-            enforce(params.len() == 2 + title_len + 5, "create_market param length mismatch");
+            // Anything after the fixed params is (option key, value) pairs
+            enforce(params.len() >= 2 + title_len + 5, "create_market param length mismatch");
+            enforce((params.len() - (2 + title_len + 5)) % 2 == 0, "create_market options must be key/value pairs");
             let title_u64_vec = params[2..2+title_len].to_vec();
             let start_time = params[2+title_len];
             let end_time = params[2+title_len+1];
             let resolution_time = params[2+title_len+2];
             let yes_liquidity = params[2+title_len+3];
             let no_liquidity = params[2+title_len+4];
-            Command::Activity(Activity::CreateMarket(title_u64_vec, start_time, end_time, resolution_time, yes_liquidity, no_liquidity))
+            let options = params[2+title_len+5..].chunks(2).map(|pair| (pair[0], pair[1])).collect();
+            Command::Activity(Activity::CreateMarket(title_u64_vec, start_time, end_time, resolution_time, yes_liquidity, no_liquidity, options))
+        } else if command == HEDGE_TO_NEUTRAL {
+            enforce(params.len() == 3, "hedge_to_neutral needs 3 params");
+            Command::Activity(Activity::HedgeToNeutral(params[1], params[2]))
+        } else if command == RESOLVE_DISTRIBUTION {
+            enforce(params.len() >= 3, "resolve_distribution needs at least 3 params");
+            let weight_count = params[2] as usize;
+            enforce(params.len() == 3 + weight_count, "resolve_distribution param length mismatch");
+            Command::Activity(Activity::ResolveDistribution(params[1], params[3..3+weight_count].to_vec()))
+        } else if command == RESOLVE_BATCH {
+            // [atomic, count, (market_id, outcome) * count]
+            enforce(params.len() >= 3, "resolve_batch needs at least 3 params");
+            let count = params[2] as usize;
+            enforce(params.len() == 3 + 2 * count, "resolve_batch param length mismatch");
+            let resolutions = params[3..].chunks(2).map(|pair| (pair[0], pair[1])).collect();
+            Command::Activity(Activity::ResolveBatch(params[1] != 0, resolutions))
+        } else if command == SET_PLAYER_FROZEN {
+            enforce(params.len() == 4, "set_player_frozen needs 4 params");
+            Command::Activity(Activity::SetPlayerFrozen([params[1], params[2]], params[3] != 0))
+        } else if command == SET_PAUSED {
+            enforce(params.len() == 3, "set_paused needs 3 params");
+            Command::Activity(Activity::SetPaused(params[1] != 0, params[2] != 0))
+        } else if command == SET_OPERATION_FEE {
+            enforce(params.len() == 3, "set_operation_fee needs 3 params");
+            Command::Activity(Activity::SetOperationFee(params[1], params[2]))
+        } else if command == SET_WITHDRAW_ADDRESS {
+            enforce(params.len() == 4, "set_withdraw_address needs 4 params");
+            Command::Activity(Activity::SetWithdrawAddress([params[1], params[2], params[3]]))
+        } else if command == VOID_MARKET {
+            enforce(params.len() == 2, "void_market needs 2 params");
+            Command::Activity(Activity::VoidMarket(params[1]))
+        } else if command == INTERNAL_TRANSFER {
+            enforce(params.len() == 4, "internal_transfer needs 4 params");
+            Command::Activity(Activity::InternalTransfer(params[1], params[2], params[3]))
+        } else if command == SET_ORACLE {
+            enforce(params.len() == 4, "set_oracle needs 4 params");
+            Command::Activity(Activity::SetOracle([params[1], params[2]], params[3] != 0))
+        } else if command == SUBMIT_ORACLE_OUTCOME {
+            enforce(params.len() == 3, "submit_oracle_outcome needs 3 params");
+            Command::Activity(Activity::SubmitOracleOutcome(params[1], params[2] != 0))
+        } else if command == SUBMIT_ORACLE_VALUE {
+            enforce(params.len() == 3, "submit_oracle_value needs 3 params");
+            Command::Activity(Activity::SubmitOracleValue(params[1], params[2]))
+        } else if command == QUERY_PLAYER {
+            enforce(params.len() == 1, "query_player needs 1 param");
+            Command::Query(Query::Player)
+        } else if command == QUERY_PLAYER_VIEW {
+            enforce(params.len() == 4, "query_player_view needs 4 params");
+            Command::Query(Query::PlayerView(params[1], params[2], params[3]))
+        } else if command == QUERY_PNL {
+            enforce(params.len() == 1, "query_pnl needs 1 param");
+            Command::Query(Query::Pnl)
+        } else if command == QUERY_SIGNING_CONTEXT {
+            enforce(params.len() == 1, "query_signing_context needs 1 param");
+            Command::Query(Query::SigningContext)
+        } else if command == QUERY_POSITION_SUMMARY {
+            enforce(params.len() == 2, "query_position_summary needs 2 params");
+            Command::Query(Query::PositionSummary(params[1]))
+        } else if command == QUERY_QUOTE_BET {
+            enforce(params.len() == 4, "query_quote_bet needs 4 params");
+            Command::Query(Query::QuoteBet(params[1], params[2], params[3]))
+        } else if command == QUERY_QUOTE_SELL {
+            enforce(params.len() == 4, "query_quote_sell needs 4 params");
+            Command::Query(Query::QuoteSell(params[1], params[2], params[3]))
+        } else if command == QUERY_MARKET {
+            enforce(params.len() == 2, "query_market needs 2 params");
+            Command::Query(Query::Market(params[1]))
+        } else if command == QUERY_POSITION {
+            enforce(params.len() == 2, "query_position needs 2 params");
+            Command::Query(Query::Position(params[1]))
+        } else if command == QUERY_BET_FEE {
+            enforce(params.len() == 4, "query_bet_fee needs 4 params");
+            Command::Query(Query::BetFee(params[1], params[2], params[3]))
+        } else if command == QUERY_SELL_FEE {
+            enforce(params.len() == 4, "query_sell_fee needs 4 params");
+            Command::Query(Query::SellFee(params[1], params[2], params[3]))
+        } else if command == QUERY_DROPPED_EVENTS {
+            enforce(params.len() == 1, "query_dropped_events needs 1 param");
+            Command::Query(Query::DroppedEvents)
+        } else if command == QUERY_TOP_HOLDERS {
+            enforce(params.len() == 4, "query_top_holders needs 4 params");
+            Command::Query(Query::TopHolders(params[1], params[2], params[3]))
+        } else if command == PROPOSE_RESOLUTION {
+            enforce(params.len() == 3, "propose_resolution needs 3 params");
+            Command::Activity(Activity::ProposeResolution(params[1], params[2]))
+        } else if command == FINALIZE_RESOLUTION {
+            enforce(params.len() == 2, "finalize_resolution needs 2 params");
+            Command::Activity(Activity::FinalizeResolution(params[1]))
+        } else if command == QUERY_BRIDGE_TOTALS {
+            enforce(params.len() == 1, "query_bridge_totals needs 1 param");
+            Command::Query(Query::BridgeTotals)
+        } else if command == REQUEST_QUOTE {
+            enforce(params.len() == 4, "request_quote needs 4 params");
+            Command::Activity(Activity::RequestQuote(params[1], params[2], params[3]))
+        } else if command == EXECUTE_QUOTE {
+            enforce(params.len() == 2, "execute_quote needs 2 params");
+            Command::Activity(Activity::ExecuteQuote(params[1]))
+        } else if command == QUERY_PAYOUT_MULTIPLE {
+            enforce(params.len() == 3, "query_payout_multiple needs 3 params");
+            Command::Query(Query::PayoutMultiple(params[1], params[2]))
+        } else if command == QUERY_MARGINAL_IMPACT {
+            enforce(params.len() == 3, "query_marginal_impact needs 3 params");
+            Command::Query(Query::MarginalImpact(params[1], params[2]))
+        } else if command == QUERY_MAX_BET_FOR_IMPACT {
+            enforce(params.len() == 4, "query_max_bet_for_impact needs 4 params");
+            Command::Query(Query::MaxBetForImpact(params[1], params[2], params[3]))
+        } else if command == QUERY_LEDGER {
+            enforce(params.len() == 2, "query_ledger needs 2 params");
+            Command::Query(Query::Ledger(params[1]))
+        } else if command == ADD_LIQUIDITY {
+            enforce(params.len() == 4, "add_liquidity needs 4 params");
+            Command::Activity(Activity::AddLiquidity(params[1], params[2], params[3]))
+        } else if command == MINT_SET {
+            enforce(params.len() == 3, "mint_set needs 3 params");
+            Command::Activity(Activity::MintSet(params[1], params[2]))
+        } else if command == ADD_LIQUIDITY_FROM_SETS {
+            enforce(params.len() == 4, "add_liquidity_from_sets needs 4 params");
+            Command::Activity(Activity::AddLiquidityFromSets(params[1], params[2], params[3]))
+        } else if command == CREATE_CATEGORICAL_MARKET {
+            // [title_len, title * title_len, start, end, resolution, count, liquidity * count]
+            enforce(params.len() >= 6, "create_categorical_market needs at least 6 params");
+            let title_len = params[1] as usize;
+            enforce(params.len() >= 2 + title_len + 4, "create_categorical_market param length mismatch");
+            let times = &params[2 + title_len..2 + title_len + 3];
+            let count = params[2 + title_len + 3] as usize;
+            enforce(params.len() == 2 + title_len + 4 + count, "create_categorical_market liquidity count mismatch");
+            Command::Activity(Activity::CreateCategoricalMarket(
+                params[2..2 + title_len].to_vec(), times[0], times[1], times[2], params[2 + title_len + 4..].to_vec()
+            ))
+        } else if command == BET_OUTCOME {
+            enforce(params.len() == 5, "bet_outcome needs 5 params");
+            Command::Activity(Activity::BetOutcome(params[1], params[2], params[3], params[4]))
+        } else if command == SELL_OUTCOME {
+            enforce(params.len() == 5, "sell_outcome needs 5 params");
+            Command::Activity(Activity::SellOutcome(params[1], params[2], params[3], params[4]))
+        } else if command == RESOLVE_CATEGORICAL {
+            enforce(params.len() == 3, "resolve_categorical needs 3 params");
+            Command::Activity(Activity::ResolveCategorical(params[1], params[2]))
+        } else if command == CLAIM_CATEGORICAL {
+            enforce(params.len() == 2, "claim_categorical needs 2 params");
+            Command::Activity(Activity::ClaimCategorical(params[1]))
+        } else if command == PLACE_LIMIT_ORDER {
+            enforce(params.len() == 5, "place_limit_order needs 5 params");
+            Command::Activity(Activity::PlaceLimitOrder(params[1], params[2], params[3], params[4]))
+        } else if command == CANCEL_ORDER {
+            enforce(params.len() == 3, "cancel_order needs 3 params");
+            Command::Activity(Activity::CancelOrder(params[1], params[2]))
+        } else if command == QUERY_ORDER_BOOK {
+            enforce(params.len() == 2, "query_order_book needs 2 params");
+            Command::Query(Query::OrderBook(params[1]))
+        } else if command == COMMIT_BET {
+            enforce(params.len() == 6, "commit_bet needs 6 params");
+            Command::Activity(Activity::CommitBet(params[1], [params[2], params[3], params[4], params[5]]))
+        } else if command == REVEAL_BET {
+            enforce(params.len() == 6, "reveal_bet needs 6 params");
+            Command::Activity(Activity::RevealBet(params[1], params[2], params[3], params[4], params[5]))
+        } else if command == DISPUTE {
+            enforce(params.len() == 4, "dispute needs 4 params");
+            Command::Activity(Activity::Dispute(params[1], params[2], params[3]))
+        } else if command == RECLAIM_DISPUTE_BOND {
+            enforce(params.len() == 2, "reclaim_dispute_bond needs 2 params");
+            Command::Activity(Activity::ReclaimDisputeBond(params[1]))
+        } else if command == SWEEP_UNCLAIMED {
+            enforce(params.len() == 2, "sweep_unclaimed needs 2 params");
+            Command::Activity(Activity::SweepUnclaimed(params[1]))
+        } else if command == CLOSE_MARKET {
+            enforce(params.len() == 2, "close_market needs 2 params");
+            Command::Activity(Activity::CloseMarket(params[1]))
+        } else if command == OVERRIDE_RESOLUTION {
+            enforce(params.len() == 3, "override_resolution needs 3 params");
+            Command::Activity(Activity::OverrideResolution(params[1], params[2] != 0))
+        } else if command == QUERY_SUMMARY {
+            enforce(params.len() == 1, "query_summary needs 1 param");
+            Command::Query(Query::Summary)
+        } else if command == QUERY_PRICES {
+            enforce(params.len() == 2, "query_prices needs 2 params");
+            Command::Query(Query::Prices(params[1]))
+        } else if command == SET_WITHDRAW_FEE {
+            enforce(params.len() == 2, "set_withdraw_fee needs 2 params");
+            Command::Activity(Activity::SetWithdrawFee(params[1]))
+        } else if command == SELL_FOR_PAYOUT {
+            enforce(params.len() == 4, "sell_for_payout needs 4 params");
+            Command::Activity(Activity::SellForPayout(params[1], params[2], params[3]))
+        } else if command == QUERY_CATEGORICAL_MARKET {
+            enforce(params.len() == 2, "query_categorical_market needs 2 params");
+            Command::Query(Query::CategoricalMarket(params[1]))
+        } else if command == QUERY_OUTCOME_PAYOUTS {
+            enforce(params.len() == 2, "query_outcome_payouts needs 2 params");
+            Command::Query(Query::OutcomePayouts(params[1]))
+        } else if command == ARB_EXECUTE {
+            // [count, (market_id, outcome, amount, side, min_out) * count]
+            enforce(params.len() >= 2, "arb_execute needs at least 2 params");
+            let count = params[1] as usize;
+            enforce(params.len() == 2 + 5 * count, "arb_execute param length mismatch");
+            let legs = params[2..].chunks(5).map(|leg| crate::command::ArbLeg {
+                market_id: leg[0],
+                outcome: leg[1],
+                amount: leg[2],
+                side: leg[3],
+                min_out: leg[4],
+            }).collect();
+            Command::Activity(Activity::ArbExecute(legs))
+        } else if command == REMOVE_LIQUIDITY {
+            enforce(params.len() == 3, "remove_liquidity needs 3 params");
+            Command::Activity(Activity::RemoveLiquidity(params[1], params[2]))
+        } else if command == CLAIM_LP_FEES {
+            enforce(params.len() == 2, "claim_lp_fees needs 2 params");
+            Command::Activity(Activity::ClaimLpFees(params[1]))
+        } else if command == QUERY_LIQUIDITY_POSITION {
+            enforce(params.len() == 2, "query_liquidity_position needs 2 params");
+            Command::Query(Query::LiquidityPosition(params[1]))
+        } else if command == QUERY_CLAIM_STATUS {
+            enforce(params.len() == 2, "query_claim_status needs 2 params");
+            Command::Query(Query::ClaimStatus(params[1]))
         } else if command == INSTALL_PLAYER {
-            Command::InstallPlayer
+            // [] installs the signer with the default grant, [pid0, pid1, balance] (admin only) another pid
+            enforce(params.len() == 1 || params.len() == 4, "install_player needs 1 or 4 params");
+            Command::InstallPlayer((params.len() == 4).then(|| ([params[1], params[2]], params[3])))
         } else {
             unsafe { zkwasm_rust_sdk::require(command == TICK) };
             Command::Tick
         };
         
-        Transaction { command, nonce }
+        Transaction { command, nonce, client_tag: 0 }
     }
 
-    pub fn create_player(&self, pkey: &[u64; 4]) -> Result<(), u32> {
-        use crate::player::Player;
-        use crate::error::{ERROR_PLAYER_ALREADY_EXISTS};
-        use crate::config::NEW_PLAYER_INITIAL_BALANCE;
-        
-        let player_id = Player::pkey_to_pid(pkey);
-        let player = Player::get_from_pid(&player_id);
-        match player {
-            Some(_) => Err(ERROR_PLAYER_ALREADY_EXISTS), // Player already exists
-            None => {
-                let mut player = Player::new_from_pid(player_id);
-                // Set initial balance for new player
-                player.data.balance = NEW_PLAYER_INITIAL_BALANCE;
-                player.store();
-                Ok(())
-            }
-        }
+    pub fn create_player(&self, player_id: [u64; 2], balance: u64) -> Result<(), u32> {
+        let player = crate::player::install(Player::get_from_pid(&player_id), player_id, balance)?;
+        player.store();
+        Ok(())
     }
 
     pub fn tick(&self) {
@@ -254,7 +816,27 @@ impl Transaction {
         // Emit liquidity history for each market at this counter
         // Note: Market IndexedObject events are emitted directly during operations (bet, sell, resolve)
         for market_id in market_ids {
-            if let Some(market) = MarketManager::get_market(market_id) {
+            if let Some(mut market) = MarketManager::get_market(market_id) {
+                // An oracle-reported outcome settles the market once it is resolvable; markets
+                // that cannot resolve yet or fail their guards are skipped
+                if let Ok(true) = market.try_auto_resolve(new_counter) {
+                    MarketManager::update_market(market_id, &market);
+                    emit_market_indexed_object(&market, market_id);
+                    emit_resolve_event(GLOBAL_STATE.0.borrow().txcounter, market_id, &market, new_counter);
+                }
+                // Markets that opted in also get a snapshot per tick
+                if let Ok(Some(summary)) = market.take_tick_summary() {
+                    let mut data = vec![market_id, new_counter];
+                    data.extend_from_slice(&summary);
+                    insert_event(EVENT_TICK_SUMMARY, &mut data);
+                    MarketManager::update_market(market_id, &market);
+                }
+                emit_market_state_change(
+                    market_id,
+                    Some(market.phase(new_counter - 1)),
+                    market.phase(new_counter),
+                    new_counter
+                );
                 emit_liquidity_history(
                     market_id,
                     new_counter,
@@ -265,6 +847,13 @@ impl Transaction {
         }
     }
 
+    // Only commands that ran and may have changed state take a transaction number; ticks,
+    // queries, failures and replayed tags do not
+    fn counts_as_transaction(&self, result: u32, replayed: bool) -> bool {
+        use crate::command::Command;
+        result == 0 && !replayed && !matches!(self.command, Command::Tick | Command::Query(_))
+    }
+
     pub fn inc_tx_number(&self) {
         let mut global_state = GLOBAL_STATE.0.borrow_mut();
         global_state.txsize += 1;
@@ -273,13 +862,12 @@ impl Transaction {
 
     pub fn process(&self, pkey: &[u64; 4], rand: &[u64; 4]) -> Vec<u64> {
         use crate::command::{Activity, CommandHandler};
-        use crate::config::ADMIN_PUBKEY;
-        use crate::event::clear_events;
-        use crate::player::Player;
+        use crate::event::{clear_events, take_dropped_events};
         use zkwasm_rust_sdk::require;
         
         let pid = Player::pkey_to_pid(pkey);
         let counter = GLOBAL_STATE.0.borrow().counter;
+        let mut replayed = false;
         
         let e = match &self.command {
             crate::command::Command::Tick => {
@@ -287,24 +875,85 @@ impl Transaction {
                 self.tick();
                 0
             },
-            crate::command::Command::InstallPlayer => self.create_player(pkey)
-                .map_or_else(|e| e, |_| 0),
+            crate::command::Command::InstallPlayer(grant) => {
+                let (player_id, balance) = match grant {
+                    Some(grant) => {
+                        unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                        *grant
+                    }
+                    None => (pid, crate::config::NEW_PLAYER_INITIAL_BALANCE),
+                };
+                self.create_player(player_id, balance)
+                    .map_or_else(|e| e, |_| 0)
+            },
             crate::command::Command::Withdraw(cmd) => cmd.handle(&pid, self.nonce, rand, counter)
                 .map_or_else(|e| e, |_| 0),
             crate::command::Command::Activity(cmd) => {
-                // Check admin permissions for resolve, withdraw fees, and create market commands
+                // Check admin permissions for resolve and create market commands
                 if let Activity::Resolve(_, _) = cmd {
                     unsafe { require(*pkey == *ADMIN_PUBKEY) };
                 }
-                if let Activity::WithdrawFees(_) = cmd {
+                if let Activity::ResolveDistribution(_, _) = cmd {
                     unsafe { require(*pkey == *ADMIN_PUBKEY) };
                 }
-                if let Activity::CreateMarket(_, _, _, _, _, _) = cmd {
+                if let Activity::SetOracle(_, _) = cmd {
                     unsafe { require(*pkey == *ADMIN_PUBKEY) };
                 }
-                cmd.handle(&pid, self.nonce, rand, counter)
-                    .map_or_else(|e| e, |_| 0)
+                if let Activity::VoidMarket(_) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::SetOperationFee(_, _) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::OverrideResolution(_, _) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::SweepUnclaimed(_) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::CloseMarket(_) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::SetWithdrawFee(_) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::SetPaused(_, _) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::CreateCategoricalMarket(..) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::ResolveCategorical(_, _) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::SetPlayerFrozen(_, _) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::ResolveBatch(_, _) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::ProposeResolution(_, _) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                if let Activity::CreateMarket(_, _, _, _, _, _, _) = cmd {
+                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                }
+                let (e, was_replayed) = crate::player::run_tagged(
+                    &mut StoredTags(pid),
+                    self.client_tag,
+                    |_| Player::get_from_pid(&pid)
+                        .ok_or(ERROR_PLAYER_NOT_EXIST)
+                        .and_then(|player| cmd.admit(&player, &GLOBAL_STATE.0.borrow(), &pid, self.nonce)),
+                    |_| cmd.handle(&pid, self.nonce, rand, counter).map_or_else(|e| e, |_| 0),
+                );
+                replayed = was_replayed;
+                e
             },
+            crate::command::Command::BatchActivity(activities) => Activity::handle_batch(&pid, self.nonce, activities, counter)
+                .map_or_else(|e| e, |_| 0),
+            // Queries skip the nonce check entirely and leave player and market state untouched
+            crate::command::Command::Query(query) => query.handle(&pid)
+                .map_or_else(|e| e, |_| 0),
             crate::command::Command::Deposit(cmd) => {
                 unsafe { require(*pkey == *ADMIN_PUBKEY) };
                 cmd.handle(&pid, self.nonce, rand, counter)
@@ -312,13 +961,12 @@ impl Transaction {
             },
         };
 
-        if e == 0 {
-            match self.command {
-                crate::command::Command::Tick => (),
-                _ => {
-                    self.inc_tx_number();
-                }
-            }
+        if self.counts_as_transaction(e, replayed) {
+            self.inc_tx_number();
+        }
+        let dropped = take_dropped_events();
+        if dropped > 0 {
+            GLOBAL_STATE.0.borrow_mut().dropped_events += dropped;
         }
         let eventid = {
             let state = GLOBAL_STATE.0.borrow();
@@ -358,6 +1006,7 @@ impl MarketManager {
         Self::store_market(market_id, market);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_market_with_title_u64_and_liquidity(
         title_u64_vec: Vec<u64>, 
         description: String, 
@@ -365,9 +1014,11 @@ impl MarketManager {
         end_time: u64, 
         resolution_time: u64,
         initial_yes_liquidity: u64,
-        initial_no_liquidity: u64
+        initial_no_liquidity: u64,
+        creator: [u64; 2],
+        options: &[(u64, u64)]
     ) -> Result<u64, u32> {
-        let market = MarketData::new_with_title_u64_and_liquidity(
+        let mut market = MarketData::new_with_title_u64_and_liquidity(
             title_u64_vec, 
             description, 
             start_time, 
@@ -376,6 +1027,10 @@ impl MarketManager {
             initial_yes_liquidity,
             initial_no_liquidity
         )?;
+        market.fee_recipient = creator;
+        for (key, value) in options {
+            market.apply_option(*key, *value)?;
+        }
         
         let market_id = {
             let mut global_state = GLOBAL_STATE.0.borrow_mut();
@@ -390,6 +1045,9 @@ impl MarketManager {
         
         // Emit IndexedObject event for new market
         emit_market_indexed_object(&market, market_id);
+        emit_market_created(market_id, &market);
+        let counter = GLOBAL_STATE.0.borrow().counter;
+        emit_market_state_change(market_id, None, market.phase(counter), counter);
         
         Ok(market_id)
     }
@@ -398,3 +1056,135 @@ impl MarketManager {
         GLOBAL_STATE.0.borrow().market_ids.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries_and_ticks_do_not_take_a_transaction_number() {
+        // Queries ignore the nonce they are sent with, so a stale one decodes the same way
+        for nonce in [0, 5] {
+            let query = Transaction::decode(&[(nonce << 16) | QUERY_PLAYER]);
+            assert!(!query.counts_as_transaction(0, false));
+        }
+        let tick = Transaction { command: crate::command::Command::Tick, nonce: 0, client_tag: 0 };
+        assert!(!tick.counts_as_transaction(0, false));
+
+        let bet = Transaction::decode(&[(1 << 16) | BET, 1, 1, 1_000]);
+        assert_eq!(bet.nonce, 1);
+        assert!(bet.counts_as_transaction(0, false));
+        assert!(!bet.counts_as_transaction(crate::error::ERROR_INVALID_NONCE, false));
+        // A replayed tag already took its number the first time
+        let tagged = Transaction::decode(&[(1 << 16) | TAGGED, 42, BET, 1, 1, 1_000]);
+        assert_eq!(tagged.client_tag, 42);
+        assert!(!tagged.counts_as_transaction(0, true));
+    }
+
+    #[test]
+    fn test_batch_activity_decodes_each_entry_like_a_single_command() {
+        use crate::command::{Activity, Command};
+        let batch = Transaction::decode(&[(3 << 16) | BATCH_ACTIVITY, 4, BET, 1, 1, 1_000, 3, CANCEL_ORDER, 1, 7]);
+        assert_eq!(batch.nonce, 3);
+        match batch.command {
+            Command::BatchActivity(activities) => {
+                assert_eq!(activities.len(), 2);
+                assert!(matches!(activities[0], Activity::Bet(1, 1, 1_000)));
+                assert!(matches!(activities[1], Activity::CancelOrder(1, 7)));
+            },
+            _ => panic!("expected a batch"),
+        }
+    }
+
+    #[test]
+    fn test_categorical_market_decodes_a_liquidity_per_outcome() {
+        use crate::command::{Activity, Command};
+        // Title of one word, times 0 / 100 / 120, then three outcomes
+        let create = Transaction::decode(&[CREATE_CATEGORICAL_MARKET, 1, 77, 0, 100, 120, 3, 1_000, 2_000, 3_000]);
+        match create.command {
+            Command::Activity(Activity::CreateCategoricalMarket(title, 0, 100, 120, reserves)) => {
+                assert_eq!((title, reserves), (vec![77], vec![1_000, 2_000, 3_000]));
+            },
+            _ => panic!("expected a categorical market"),
+        }
+    }
+
+    #[test]
+    fn test_bridge_counters_track_held_value() {
+        let mut state = GlobalState::new();
+        state.record_deposit(1000).unwrap();
+        state.record_deposit(500).unwrap();
+        state.record_withdrawal(300).unwrap();
+        assert_eq!(state.total_deposited, 1500);
+        assert_eq!(state.total_withdrawn, 300);
+        assert_eq!(state.bridge_held_value(), Ok(1200));
+
+        state.record_withdrawal(1200).unwrap();
+        assert_eq!(state.bridge_held_value(), Ok(0));
+
+        // Counters survive a storage round trip
+        let mut stored = vec![];
+        state.to_data(&mut stored);
+        let loaded = GlobalState::from_data(&mut stored.iter_mut());
+        assert_eq!(loaded.total_deposited, 1500);
+        assert_eq!(loaded.total_withdrawn, 1500);
+    }
+
+    #[test]
+    fn test_summary_share_totals_follow_bets_and_sells() {
+        let open_market = |name: &str| MarketData::new_with_title_u64_and_liquidity(
+            MarketData::string_to_u64_vec(name), name.to_string(), 0, 1000, 1000, 1_000_000, 1_000_000
+        ).unwrap();
+        let mut state = GlobalState::new();
+        state.txcounter = 42;
+        let mut markets = vec![open_market("A"), open_market("B")];
+        let yes_a = markets[0].place_bet(1, 30_000).unwrap();
+        let no_b = markets[1].place_bet(0, 20_000).unwrap();
+        let yes_b = markets[1].place_bet(1, 10_000).unwrap();
+
+        let summary = state.summary(&markets).unwrap();
+        assert_eq!((summary.txcounter, summary.market_count), (42, 2));
+        assert_eq!((summary.yes_shares, summary.no_shares), (yes_a + yes_b, no_b));
+
+        // A sell takes its shares back out of the totals, and the reserves move with it
+        markets[0].sell_shares(1, yes_a / 2).unwrap();
+        let after = state.summary(&markets).unwrap();
+        assert_eq!(after.yes_shares, yes_a - yes_a / 2 + yes_b);
+        assert_eq!(after.yes_reserves, markets[0].yes_liquidity + markets[1].yes_liquidity);
+        assert_eq!(after.platform_fees, markets[0].total_fees_collected + markets[1].total_fees_collected);
+
+        let mut data = vec![];
+        after.to_data(&mut data);
+        assert_eq!(data.len(), 12);
+        assert_eq!(data[6], after.yes_shares);
+    }
+
+    #[test]
+    fn test_pnl_leaderboard_keeps_top_players_in_order() {
+        let mut state = GlobalState::new();
+        state.update_pnl_leaderboard([3, 0], 500);
+        state.update_pnl_leaderboard([1, 0], -200);
+        state.update_pnl_leaderboard([2, 0], 500);
+        // Equal PnL is ordered by pid
+        assert_eq!(state.pnl_leaderboard, vec![([2, 0], 500), ([3, 0], 500), ([1, 0], -200)]);
+
+        // A player realizing more overtakes the others and keeps a single entry
+        state.update_pnl_leaderboard([1, 0], 900);
+        assert_eq!(state.pnl_leaderboard, vec![([1, 0], 900), ([2, 0], 500), ([3, 0], 500)]);
+
+        // The board stays bounded, dropping the lowest
+        for i in 10..10 + PNL_LEADERBOARD_SIZE as u64 {
+            state.update_pnl_leaderboard([i, 0], 600);
+        }
+        assert_eq!(state.pnl_leaderboard.len(), PNL_LEADERBOARD_SIZE);
+        assert_eq!(state.pnl_leaderboard[0], ([1, 0], 900));
+        assert!(state.pnl_leaderboard.iter().all(|(pid, _)| *pid != [2, 0] && *pid != [3, 0]));
+
+        let mut stored = vec![];
+        state.to_data(&mut stored);
+        state.update_pnl_leaderboard([1, 0], -50);
+        let loaded = GlobalState::from_data(&mut stored.iter_mut());
+        assert_eq!(loaded.pnl_leaderboard[0], ([1, 0], 900));
+        assert_eq!(state.pnl_leaderboard.last(), Some(&([1, 0], -50)));
+    }
+}
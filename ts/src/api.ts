@@ -21,6 +21,10 @@ const CREATE_MARKET = 9;
 const PLATFORM_FEE_RATE = 100n; // 1%
 const FEE_BASIS_POINTS = 10000n;
 
+// The reserve a trade draws from rounds up, matching calculate_new_liquidity_safe,
+// so quoted shares and payouts round down exactly as the contract does
+const divCeil = (a: bigint, b: bigint): bigint => (a + b - 1n) / b;
+
 export class Player extends PlayerConvention {
     constructor(key: string, rpc: ZKWasmAppRpc) {
         super(key, rpc, BigInt(DEPOSIT), BigInt(WITHDRAW));
@@ -82,9 +86,10 @@ export class Player extends PlayerConvention {
     }
 
     // Updated to include market_id
-    async withdrawFees(marketId: bigint) {
+    // Without a target pid the fees go to the market's fee recipient
+    async withdrawFees(marketId: bigint, targetPid?: [bigint, bigint]) {
         let nonce = await this.getNonce();
-        let cmd = createCommand(nonce, BigInt(WITHDRAW_FEES), [marketId]);
+        let cmd = createCommand(nonce, BigInt(WITHDRAW_FEES), targetPid ? [marketId, ...targetPid] : [marketId]);
         return await this.sendTransactionWithCommand(cmd);
     }
 
@@ -275,11 +280,11 @@ export class PredictionMarketAPI {
         
         if (betType === 1) { // YES bet
             const newNoLiquidity = noLiquidity + netAmount;
-            const newYesLiquidity = k / newNoLiquidity;
+            const newYesLiquidity = divCeil(k, newNoLiquidity);
             return yesLiquidity - newYesLiquidity;
         } else { // NO bet
             const newYesLiquidity = yesLiquidity + netAmount;
-            const newNoLiquidity = k / newYesLiquidity;
+            const newNoLiquidity = divCeil(k, newYesLiquidity);
             return noLiquidity - newNoLiquidity;
         }
     }
@@ -293,11 +298,11 @@ export class PredictionMarketAPI {
         let grossAmount: bigint;
         if (sellType === 1) { // Selling YES shares
             const newYesLiquidity = yesLiquidity + sharesToSell;
-            const newNoLiquidity = k / newYesLiquidity;
+            const newNoLiquidity = divCeil(k, newYesLiquidity);
             grossAmount = noLiquidity - newNoLiquidity;
         } else { // Selling NO shares
             const newNoLiquidity = noLiquidity + sharesToSell;
-            const newYesLiquidity = k / newNoLiquidity;
+            const newYesLiquidity = divCeil(k, newNoLiquidity);
             grossAmount = yesLiquidity - newYesLiquidity;
         }
         
@@ -342,10 +347,10 @@ export class PredictionMarketAPI {
         let newYesLiquidity: bigint, newNoLiquidity: bigint;
         if (betType === 1) { // YES bet
             newNoLiquidity = noLiquidity + netAmount;
-            newYesLiquidity = k / newNoLiquidity;
+            newYesLiquidity = divCeil(k, newNoLiquidity);
         } else { // NO bet
             newYesLiquidity = yesLiquidity + netAmount;
-            newNoLiquidity = k / newYesLiquidity;
+            newNoLiquidity = divCeil(k, newYesLiquidity);
         }
         
         const newPrices = this.calculatePrices(newYesLiquidity, newNoLiquidity);
@@ -311,6 +311,15 @@ export class BetEvent {
         return new BetEvent(0n, Array.from(data));
     }
 
+    static fromSellEvent(data: BigUint64Array): BetEvent {
+        // EVENT_SELL_UPDATE carries the raw side:
+        // [txid, pid1, pid2, market_id, sell_type, shares, payout, counter]
+        // Stored bets keep encoding sells as sell_type+10
+        const fields = Array.from(data);
+        fields[4] = fields[4] + 10n;
+        return new BetEvent(0n, fields);
+    }
+
     toObject(): Bet {
         // Add data length validation
         if (this.data.length < 8) {
@@ -287,6 +287,7 @@ service.serve();
 
 const EVENT_BET_UPDATE = 3;
 const EVENT_INDEXED_OBJECT = 4;
+const EVENT_SELL_UPDATE = 15;
 
 async function batchedCallback(_arg: TxWitness[], _preMerkle: string, postMerkle: string) {
     await txStateManager.moveToCommit(postMerkle);
@@ -329,9 +330,10 @@ async function eventCallback(arg: TxWitness, data: BigUint64Array) {
 
         switch (eventType) {
             case EVENT_BET_UPDATE:
+            case EVENT_SELL_UPDATE:
                 {
                     try {
-                        let bet = BetEvent.fromEvent(eventData);
+                        let bet = eventType === EVENT_SELL_UPDATE ? BetEvent.fromSellEvent(eventData) : BetEvent.fromEvent(eventData);
                         let betData = bet.toObject();
                         
                         // Validate bet data